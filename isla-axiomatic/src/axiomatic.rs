@@ -37,6 +37,7 @@ use std::fmt;
 use isla_lib::bitvector::BV;
 use isla_lib::config::ISAConfig;
 use isla_lib::ir::{Name, SharedState, Val};
+use isla_lib::simplify::render_output;
 use isla_lib::smt::{EvPath, Event, register_name_string};
 
 use crate::page_table::VirtualAddress;
@@ -518,6 +519,8 @@ pub struct ExecutionInfo<'ev, B> {
     pub thread_opcodes: Vec<Vec<B>>,
     /// The final write or initial value for each register in each thread
     pub final_writes: HashMap<(Name, ThreadId), &'ev Val<B>>,
+    /// The output from the Sail print builtins for each thread
+    pub thread_output: Vec<String>,
 }
 
 /// An iterator over the base events in a candidate execution
@@ -697,6 +700,7 @@ impl<'ev, B: BV> ExecutionInfo<'ev, B> {
             other_events: Vec::new(),
             thread_opcodes: vec![Vec::new(); candidate.len()],
             final_writes: HashMap::new(),
+            thread_output: candidate.iter().map(|thread| render_output(thread, &shared_state.symtab, None)).collect(),
         };
 
         let rk_ifetch = shared_state.enum_member(isa_config.ifetch_read_kind).expect("Invalid ifetch read kind");
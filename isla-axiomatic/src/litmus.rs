@@ -31,7 +31,7 @@ use std::collections::HashMap;
 use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::sync::Arc;
 use std::error::Error;
@@ -42,7 +42,7 @@ use isla_lib::config::ISAConfig;
 use isla_lib::ir::{Loc, Name, Symtab};
 use isla_lib::lexer::Lexer;
 use isla_lib::log;
-use isla_lib::memory::Region;
+use isla_lib::memory::{device, image, Region};
 use isla_lib::smt::Solver;
 use isla_lib::value_parser::LocParser;
 use isla_lib::zencode;
@@ -651,6 +651,33 @@ fn parse_extra<'v>(extra: (&'v String, &'v Value)) -> Result<UnassembledSection<
     Ok(UnassembledSection { name: &extra.0, address: parse_address(addr)?, code })
 }
 
+/// A section with a `file` key is loaded from a memory image rather
+/// than assembled, producing a section for each contiguous segment
+/// of the image. An `address` is required for raw binary files. A
+/// relative `file` is resolved against `directory`, which should be
+/// the directory containing the litmus file.
+fn load_extra(extra: (&String, &Value), directory: Option<&Path>) -> Result<Vec<AssembledSection>, String> {
+    let file = extra.1.get("file").and_then(|file| file.as_str()).ok_or_else(|| format!("No file in {}", extra.0))?;
+    let base = match extra.1.get("address").and_then(|addr| addr.as_str()) {
+        Some(addr) => Some(parse_address(addr)?),
+        None => None,
+    };
+    let path = match directory {
+        Some(directory) => directory.join(file),
+        None => PathBuf::from(file),
+    };
+    let image = image::load_image(&path, base)?;
+    Ok(image::segments(&image)
+        .drain(..)
+        .map(|(addr, bytes)| AssembledSection {
+            name: extra.0.to_string(),
+            addr,
+            bytes,
+            source: format!("// loaded from {}", file),
+        })
+        .collect())
+}
+
 #[derive(Clone)]
 pub struct AssembledThread {
     pub name: ThreadName,
@@ -686,6 +713,7 @@ pub struct Litmus<B> {
     pub assembled: Vec<AssembledThread>,
     pub sections: Vec<AssembledSection>,
     pub self_modify_regions: Vec<Region<B>>,
+    pub device_regions: Vec<Region<B>>,
     pub objdump: String,
     pub final_assertion: exp::Exp<String>,
 }
@@ -699,7 +727,20 @@ impl<B: BV> Litmus<B> {
         log!(log::LITMUS, &format!("Litmus test final assertion: {:?}", self.final_assertion));
     }
 
+    /// Parse a litmus test. Any memory images referred to by the test
+    /// are loaded relative to the current working directory, use
+    /// [Litmus::parse_in_directory] or [Litmus::from_file] to load
+    /// them relative to the test itself.
     pub fn parse(contents: &str, symtab: &Symtab, isa: &ISAConfig<B>) -> Result<Self, String> {
+        Self::parse_in_directory(contents, None, symtab, isa)
+    }
+
+    pub fn parse_in_directory(
+        contents: &str,
+        directory: Option<&Path>,
+        symtab: &Symtab,
+        isa: &ISAConfig<B>,
+    ) -> Result<Self, String> {
         let litmus_toml = match contents.parse::<Value>() {
             Ok(toml) => toml,
             Err(e) => return Err(format!("Error when parsing litmus: {}", e)),
@@ -767,16 +808,22 @@ impl<B: BV> Litmus<B> {
 
         let empty_table = toml::value::Map::new();
         let sections: &Table = litmus_toml.get("section").and_then(|t| t.as_table()).unwrap_or_else(|| &empty_table);
-        let mut sections: Vec<UnassembledSection<'_>> = sections.iter().map(parse_extra).collect::<Result<_, _>>()?;
+        let (loaded_sections, sections): (Vec<_>, Vec<_>) =
+            sections.iter().partition(|(_, section)| section.get("file").is_some());
+        let mut sections: Vec<UnassembledSection<'_>> =
+            sections.into_iter().map(parse_extra).collect::<Result<_, _>>()?;
         sections.sort_unstable_by_key(|section| section.address);
 
         let (mut assembled, mut assembled_sections, objdump) = assemble(&code, &sections, true, isa)?;
 
-        let sections = assembled_sections
+        let mut sections = assembled_sections
             .drain(..)
             .zip(sections.drain(..))
             .map(|((addr, bytes), unassembled)| AssembledSection { name: unassembled.name.to_string(), addr, bytes, source: unassembled.code.to_string() })
-            .collect();
+            .collect::<Vec<_>>();
+        for section in loaded_sections {
+            sections.append(&mut load_extra(section, directory)?)
+        }
             
         let mut inits: Vec<(Vec<(Name, u64)>, HashMap<Loc<Name>, exp::Exp<String>>)> = threads
             .iter()
@@ -791,6 +838,7 @@ impl<B: BV> Litmus<B> {
             .collect();
 
         let self_modify_regions = parse_self_modify::<B>(&litmus_toml, &objdump)?;
+        let device_regions = device::parse_devices::<B>(&litmus_toml)?;
 
         let fin = litmus_toml.get("final").ok_or("No final section found in litmus file")?;
         let final_assertion = (match fin.get("assertion").and_then(Value::as_str) {
@@ -815,6 +863,7 @@ impl<B: BV> Litmus<B> {
             assembled,
             sections,
             self_modify_regions,
+            device_regions,
             objdump,
             final_assertion,
         })
@@ -837,6 +886,6 @@ impl<B: BV> Litmus<B> {
             Err(e) => return Err(format!("Error when loading litmus '{}': {}", path.as_ref().display(), e)),
         };
 
-        Self::parse(&contents, symtab, isa)
+        Self::parse_in_directory(&contents, path.as_ref().parent(), symtab, isa)
     }
 }
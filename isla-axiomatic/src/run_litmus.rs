@@ -111,6 +111,9 @@ pub struct LitmusRunOpts {
 
 pub struct LitmusRunInfo {
     pub candidates: usize,
+    /// True if any thread's execution assumed an unknown solver
+    /// result was sat or unsat
+    pub assumed_unknown: bool,
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -137,11 +140,20 @@ where
     E: Send,
 {
     let mut memory = Memory::new();
+    memory.set_address_width(isa_config.address_width);
+    memory.set_access_checks(isa_config.access_checks.clone());
 
     for region in &litmus.self_modify_regions {
         memory.add_region(region.clone())
     }
 
+    for region in isa_config.device_regions.iter().chain(litmus.device_regions.iter()) {
+        memory.add_region(region.clone())
+    }
+    for tag_region in &isa_config.tag_regions {
+        memory.add_tag_region(tag_region.clone())
+    }
+
     memory.add_concrete_region(isa_config.thread_base..isa_config.thread_top, HashMap::new());
     // FIXME: Insert a blank exception vector table for AArch64
     memory.add_concrete_region(0x0_u64..0x8000_u64, HashMap::new());
@@ -249,10 +261,12 @@ where
     log!(log::VERBOSE, &format!("Symbolic execution took: {}ms", now.elapsed().as_millis()));
 
     let rk_ifetch = shared_state.enum_member(isa_config.ifetch_read_kind).expect("Invalid ifetch read kind");
+    let mut assumed_unknown = false;
 
     loop {
         match queue.pop() {
             Ok(Ok((task_id, mut events))) => {
+                assumed_unknown |= events.iter().any(|ev| ev.is_assume_unknown());
                 let mut events: EvPath<B> = events
                     .drain(..)
                     .rev()
@@ -322,7 +336,7 @@ where
     }
 
     if callback_errors.is_empty() {
-        Ok(LitmusRunInfo { candidates: num_candidates })
+        Ok(LitmusRunInfo { candidates: num_candidates, assumed_unknown })
     } else {
         Err(LitmusRunError::Callback(callback_errors))
     }
@@ -0,0 +1,149 @@
+// BSD 2-Clause License
+//
+// Copyright (c) 2020 Alasdair Armstrong
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+// notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+// notice, this list of conditions and the following disclaimer in the
+// documentation and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Compares the concrete bitvector implementations. Run with `cargo
+//! bench -p isla-lib`. The `_64` benchmarks use 64-bit values, so
+//! they measure the overhead of the wider representations for the
+//! common case, whereas the `_2048` benchmarks use values that would
+//! otherwise have to be handled by the SMT solver.
+
+use bencher::{benchmark_group, benchmark_main, black_box, Bencher};
+
+use isla_lib::bitvector::b129::B129;
+use isla_lib::bitvector::b64::B64;
+use isla_lib::bitvector::bn::{B2048, B4096};
+use isla_lib::bitvector::BV;
+
+fn arith<B: BV>(bench: &mut Bencher, len: u32) {
+    let x = B::ones(len);
+    let y = B::new(0xCAFE_BABE, len);
+    bench.iter(|| {
+        let x = black_box(x);
+        let y = black_box(y);
+        (x + y) ^ (x - y) & !y
+    })
+}
+
+fn shift<B: BV>(bench: &mut Bencher, len: u32) {
+    let x = B::new(0xCAFE_BABE, len);
+    bench.iter(|| {
+        let x = black_box(x);
+        x.shiftl(black_box(13)).arith_shiftr(black_box(7))
+    })
+}
+
+fn slice_append<B: BV>(bench: &mut Bencher, len: u32) {
+    let x = B::new(0xCAFE_BABE, len);
+    bench.iter(|| {
+        let x = black_box(x);
+        let high = x.slice(len / 2, len / 2).unwrap();
+        let low = x.slice(0, len / 2).unwrap();
+        low.append(high).unwrap()
+    })
+}
+
+fn arith_b64_64(bench: &mut Bencher) {
+    arith::<B64>(bench, 64)
+}
+
+fn arith_b129_64(bench: &mut Bencher) {
+    arith::<B129>(bench, 64)
+}
+
+fn arith_b2048_64(bench: &mut Bencher) {
+    arith::<B2048>(bench, 64)
+}
+
+fn arith_b2048_2048(bench: &mut Bencher) {
+    arith::<B2048>(bench, 2048)
+}
+
+fn arith_b4096_4096(bench: &mut Bencher) {
+    arith::<B4096>(bench, 4096)
+}
+
+fn shift_b64_64(bench: &mut Bencher) {
+    shift::<B64>(bench, 64)
+}
+
+fn shift_b129_64(bench: &mut Bencher) {
+    shift::<B129>(bench, 64)
+}
+
+fn shift_b2048_64(bench: &mut Bencher) {
+    shift::<B2048>(bench, 64)
+}
+
+fn shift_b2048_2048(bench: &mut Bencher) {
+    shift::<B2048>(bench, 2048)
+}
+
+fn shift_b4096_4096(bench: &mut Bencher) {
+    shift::<B4096>(bench, 4096)
+}
+
+fn slice_append_b64_64(bench: &mut Bencher) {
+    slice_append::<B64>(bench, 64)
+}
+
+fn slice_append_b129_64(bench: &mut Bencher) {
+    slice_append::<B129>(bench, 64)
+}
+
+fn slice_append_b2048_64(bench: &mut Bencher) {
+    slice_append::<B2048>(bench, 64)
+}
+
+fn slice_append_b2048_2048(bench: &mut Bencher) {
+    slice_append::<B2048>(bench, 2048)
+}
+
+fn slice_append_b4096_4096(bench: &mut Bencher) {
+    slice_append::<B4096>(bench, 4096)
+}
+
+benchmark_group!(
+    benches,
+    arith_b64_64,
+    arith_b129_64,
+    arith_b2048_64,
+    arith_b2048_2048,
+    arith_b4096_4096,
+    shift_b64_64,
+    shift_b129_64,
+    shift_b2048_64,
+    shift_b2048_2048,
+    shift_b4096_4096,
+    slice_append_b64_64,
+    slice_append_b129_64,
+    slice_append_b2048_64,
+    slice_append_b2048_2048,
+    slice_append_b4096_4096
+);
+benchmark_main!(benches);
@@ -28,11 +28,12 @@
 // OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 //! This module defines the bitvector trait BV, and includes modules
-//! for concrete bitvectors of up to 64-bits, or up to 129-bits. The
-//! 129-bit bitvectors are intended for CHERI architectures as it
-//! allows capabilities to be represented without involving the SMT
-//! solver. Most functions in isla-lib, and dependent libraries will
-//! be parametric over the BV trait.
+//! for concrete bitvectors of up to 64-bits, up to 129-bits, or of
+//! any fixed maximum width. The 129-bit bitvectors are intended for
+//! CHERI architectures as it allows capabilities to be represented
+//! without involving the SMT solver, and the wider bitvectors in
+//! [bn] serve the same purpose for vector registers. Most functions in isla-lib, and dependent
+//! libraries will be parametric over the BV trait.
 //!
 //! The reason for having an upper-bound on the size of concrete
 //! bitvectors is so they can be fixed size, which allows them to be
@@ -72,13 +73,15 @@ macro_rules! write_bits {
 
 pub mod b129;
 pub mod b64;
+pub mod bn;
 
 /// This trait allows us to be generic over the representation of
 /// concrete bitvectors. Specific users of isla-lib may then choose
 /// different representations depending on use case - B64 will likely
 /// be the most efficient for ordinary use, but B129 can represent
 /// [CHERI](https://www.cl.cam.ac.uk/research/security/ctsrd/cheri/)
-/// compressed capabilities concretely.
+/// compressed capabilities concretely, and [bn::B2048] can represent
+/// the widest SVE vector registers.
 pub trait BV
 where
     Self: fmt::Debug + fmt::LowerHex + fmt::UpperHex + fmt::Display,
@@ -253,9 +256,9 @@ where
     }
 
     fn truncate_lsb(self, len: i128) -> Option<Self> {
-        if 0 < len && len <= Self::MAX_WIDTH as i128 {
-            let len = len as u64;
-            (self >> Self::new(64 - len, self.len())).slice(0, len as u32)
+        if 0 < len && len <= self.len() as i128 {
+            let len = len as u32;
+            (self >> Self::new((self.len() - len) as u64, self.len())).slice(0, len)
         } else if len == 0 {
             Some(Self::new(0, 0))
         } else {
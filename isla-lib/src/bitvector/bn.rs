@@ -0,0 +1,597 @@
+// BSD 2-Clause License
+//
+// Copyright (c) 2020 Alasdair Armstrong
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+// notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+// notice, this list of conditions and the following disclaimer in the
+// documentation and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! This module implements [BN], a concrete bitvector with a fixed
+//! maximum width of any number of 64-bit limbs, along with the
+//! [B256], [B2048] and [B4096] aliases for common widths.
+
+use serde::de::{Deserializer, Error as _};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::convert::TryInto;
+use std::fmt;
+use std::ops::{Add, BitAnd, BitOr, BitXor, Neg, Not, Shl, Shr, Sub};
+
+use super::{bzhi_u128, BV};
+use crate::error::ExecError;
+
+/// A concrete bitvector of up to `64 * LIMBS` bits, stored as an
+/// array of 64-bit limbs with the least significant limb first. Bits
+/// above the length are always zero.
+///
+/// The limbs are stored inline rather than on the heap so that `BN`
+/// remains `Copy` like the other [BV] implementations. Every value
+/// occupies the full `LIMBS` words regardless of its length, so this
+/// is slower than [super::b64::B64] for small bitvectors, but avoids
+/// going through the SMT solver for wide concrete values such as SVE
+/// vector registers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BN<const LIMBS: usize> {
+    len: u32,
+    bits: [u64; LIMBS],
+}
+
+/// Wide enough for 256-bit CHERI capability structures.
+pub type B256 = BN<4>;
+
+/// Wide enough for the largest SVE vector length.
+pub type B2048 = BN<32>;
+
+/// Wide enough for concrete values twice the largest SVE vector
+/// length, such as the concatenation of two vector registers.
+pub type B4096 = BN<64>;
+
+impl<const LIMBS: usize> BN<LIMBS> {
+    const fn from_limb(bits: u64, len: u32) -> Self {
+        let mut limbs = [0; LIMBS];
+        limbs[0] = bits;
+        BN { len, bits: limbs }
+    }
+
+    /// Clear any bits above the length of the bitvector.
+    fn mask(mut self) -> Self {
+        let full = (self.len / 64) as usize;
+        let rem = self.len % 64;
+        if full < LIMBS {
+            self.bits[full] &= if rem == 0 { 0 } else { u64::MAX >> (64 - rem) };
+            for limb in &mut self.bits[full + 1..] {
+                *limb = 0
+            }
+        }
+        self
+    }
+
+    fn used_limbs(self) -> usize {
+        self.len.div_ceil(64) as usize
+    }
+
+    fn shift_left(self, n: u32) -> Self {
+        if n >= self.len {
+            return Self::zeros(self.len);
+        }
+        let limbs = (n / 64) as usize;
+        let offset = n % 64;
+        let mut bits = [0; LIMBS];
+        for (i, limb) in bits.iter_mut().enumerate().skip(limbs) {
+            *limb = self.bits[i - limbs] << offset;
+            if offset > 0 && i > limbs {
+                *limb |= self.bits[i - limbs - 1] >> (64 - offset)
+            }
+        }
+        BN { len: self.len, bits }.mask()
+    }
+
+    fn shift_right(self, n: u32) -> Self {
+        if n >= self.len {
+            return Self::zeros(self.len);
+        }
+        let limbs = (n / 64) as usize;
+        let offset = n % 64;
+        let mut bits = [0; LIMBS];
+        for (i, limb) in bits.iter_mut().enumerate().take(LIMBS - limbs) {
+            *limb = self.bits[i + limbs] >> offset;
+            if offset > 0 && i + limbs + 1 < LIMBS {
+                *limb |= self.bits[i + limbs + 1] << (64 - offset)
+            }
+        }
+        BN { len: self.len, bits }
+    }
+
+    /// The value of a bitvector used as a shift amount, saturating
+    /// at `u32::MAX` (which is larger than any bitvector).
+    fn shift_amount(self) -> u32 {
+        if self.bits[1..].iter().any(|limb| *limb != 0) {
+            u32::MAX
+        } else {
+            u32::try_from(self.bits[0]).unwrap_or(u32::MAX)
+        }
+    }
+
+    /// Create a bitvector from the two's complement representation
+    /// of an integer, truncated to `len` bits.
+    fn from_i128(value: i128, len: u32) -> Self {
+        let mut bits = [if value < 0 { u64::MAX } else { 0 }; LIMBS];
+        bits[0] = value as u64;
+        if LIMBS > 1 {
+            bits[1] = (value >> 64) as u64
+        }
+        BN { len, bits }.mask()
+    }
+
+    fn lower_u128(self) -> u128 {
+        let high = if LIMBS > 1 { self.bits[1] } else { 0 };
+        (u128::from(high) << 64) | u128::from(self.bits[0])
+    }
+
+    fn from_digits(digits: &str, radix: u32) -> Option<Self> {
+        let width = if radix == 16 { 4 } else { 1 };
+        let len = u32::try_from(digits.chars().count()).ok()?.checked_mul(width)?;
+        if len > Self::MAX_WIDTH {
+            return None;
+        }
+        let mut bv = Self::zeros(len);
+        for (i, c) in digits.chars().rev().enumerate() {
+            let n = i as u32 * width;
+            bv.bits[(n / 64) as usize] |= u64::from(c.to_digit(radix)?) << (n % 64)
+        }
+        Some(bv)
+    }
+
+    fn write_hex(&self, f: &mut fmt::Formatter<'_>, upper: bool) -> fmt::Result {
+        match (0..LIMBS).rev().find(|i| self.bits[*i] != 0) {
+            None => write!(f, "0"),
+            Some(top) => {
+                for i in (0..=top).rev() {
+                    match (upper, i == top) {
+                        (false, true) => write!(f, "{:x}", self.bits[i])?,
+                        (false, false) => write!(f, "{:016x}", self.bits[i])?,
+                        (true, true) => write!(f, "{:X}", self.bits[i])?,
+                        (true, false) => write!(f, "{:016X}", self.bits[i])?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<const LIMBS: usize> fmt::LowerHex for BN<LIMBS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_hex(f, false)
+    }
+}
+
+impl<const LIMBS: usize> fmt::UpperHex for BN<LIMBS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_hex(f, true)
+    }
+}
+
+impl<const LIMBS: usize> fmt::Display for BN<LIMBS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.len.is_multiple_of(4) {
+            write!(f, "#x")?;
+            for i in (0..self.len / 4).rev() {
+                let n = i * 4;
+                write!(f, "{:x}", (self.bits[(n / 64) as usize] >> (n % 64)) & 0xF)?
+            }
+        } else {
+            write!(f, "#b")?;
+            for n in (0..self.len).rev() {
+                write!(f, "{:b}", (self.bits[(n / 64) as usize] >> (n % 64)) & 0b1)?
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<const LIMBS: usize> Serialize for BN<LIMBS> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.len, &self.bits[..self.used_limbs()]).serialize(serializer)
+    }
+}
+
+impl<'de, const LIMBS: usize> Deserialize<'de> for BN<LIMBS> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (len, limbs): (u32, Vec<u64>) = Deserialize::deserialize(deserializer)?;
+        if len > Self::MAX_WIDTH || limbs.len() > LIMBS {
+            return Err(D::Error::custom(format!("bitvector of length {} is too long", len)));
+        }
+        let mut bits = [0; LIMBS];
+        bits[..limbs.len()].copy_from_slice(&limbs);
+        Ok(BN { len, bits }.mask())
+    }
+}
+
+impl<const LIMBS: usize> TryInto<u64> for BN<LIMBS> {
+    type Error = ExecError;
+
+    fn try_into(self) -> Result<u64, ExecError> {
+        if self.len <= 64 {
+            Ok(self.bits[0])
+        } else {
+            Err(ExecError::Overflow)
+        }
+    }
+}
+
+impl<const LIMBS: usize> Not for BN<LIMBS> {
+    type Output = Self;
+
+    fn not(mut self) -> Self::Output {
+        for limb in &mut self.bits {
+            *limb = !*limb
+        }
+        self.mask()
+    }
+}
+
+impl<const LIMBS: usize> BitXor for BN<LIMBS> {
+    type Output = Self;
+
+    fn bitxor(mut self, rhs: Self) -> Self::Output {
+        for (limb, rhs) in self.bits.iter_mut().zip(rhs.bits.iter()) {
+            *limb ^= rhs
+        }
+        self
+    }
+}
+
+impl<const LIMBS: usize> BitOr for BN<LIMBS> {
+    type Output = Self;
+
+    fn bitor(mut self, rhs: Self) -> Self::Output {
+        for (limb, rhs) in self.bits.iter_mut().zip(rhs.bits.iter()) {
+            *limb |= rhs
+        }
+        self
+    }
+}
+
+impl<const LIMBS: usize> BitAnd for BN<LIMBS> {
+    type Output = Self;
+
+    fn bitand(mut self, rhs: Self) -> Self::Output {
+        for (limb, rhs) in self.bits.iter_mut().zip(rhs.bits.iter()) {
+            *limb &= rhs
+        }
+        self
+    }
+}
+
+impl<const LIMBS: usize> Add for BN<LIMBS> {
+    type Output = Self;
+
+    fn add(mut self, rhs: Self) -> Self::Output {
+        let mut carry = false;
+        for (limb, rhs) in self.bits.iter_mut().zip(rhs.bits.iter()) {
+            let (sum, carry1) = limb.overflowing_add(*rhs);
+            let (sum, carry2) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = carry1 || carry2
+        }
+        self.mask()
+    }
+}
+
+impl<const LIMBS: usize> Neg for BN<LIMBS> {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        !self + Self::from_limb(1, self.len).mask()
+    }
+}
+
+impl<const LIMBS: usize> Sub for BN<LIMBS> {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self + (-rhs)
+    }
+}
+
+impl<const LIMBS: usize> Shl for BN<LIMBS> {
+    type Output = Self;
+
+    fn shl(self, rhs: Self) -> Self::Output {
+        self.shift_left(rhs.shift_amount())
+    }
+}
+
+impl<const LIMBS: usize> Shr for BN<LIMBS> {
+    type Output = Self;
+
+    fn shr(self, rhs: Self) -> Self::Output {
+        self.shift_right(rhs.shift_amount())
+    }
+}
+
+impl<const LIMBS: usize> BV for BN<LIMBS> {
+    const BIT_ONE: Self = Self::from_limb(1, 1);
+    const BIT_ZERO: Self = Self::from_limb(0, 1);
+    const MAX_WIDTH: u32 = 64 * LIMBS as u32;
+
+    fn new(bits: u64, len: u32) -> Self {
+        assert!(len <= Self::MAX_WIDTH);
+        Self::from_limb(bits, len).mask()
+    }
+
+    fn len(self) -> u32 {
+        self.len
+    }
+
+    fn lower_u64(self) -> u64 {
+        self.bits[0]
+    }
+
+    fn lower_u8(self) -> u8 {
+        (self.bits[0] & 0xFF) as u8
+    }
+
+    fn is_zero(self) -> bool {
+        self.bits.iter().all(|limb| *limb == 0)
+    }
+
+    fn zeros(len: u32) -> Self {
+        assert!(len <= Self::MAX_WIDTH);
+        BN { len, bits: [0; LIMBS] }
+    }
+
+    fn ones(len: u32) -> Self {
+        assert!(len <= Self::MAX_WIDTH);
+        BN { len, bits: [u64::MAX; LIMBS] }.mask()
+    }
+
+    fn leading_zeros(self) -> u32 {
+        let mut zeros = 0;
+        for i in (0..self.used_limbs()).rev() {
+            let width = std::cmp::min(64, self.len - i as u32 * 64);
+            if self.bits[i] == 0 {
+                zeros += width
+            } else {
+                return zeros + self.bits[i].leading_zeros() - (64 - width);
+            }
+        }
+        zeros
+    }
+
+    fn from_u8(value: u8) -> Self {
+        Self::from_limb(u64::from(value), 8)
+    }
+
+    fn from_u16(value: u16) -> Self {
+        Self::from_limb(u64::from(value), 16)
+    }
+
+    fn from_u32(value: u32) -> Self {
+        Self::from_limb(u64::from(value), 32)
+    }
+
+    fn from_u64(value: u64) -> Self {
+        Self::from_limb(value, 64)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        assert!(bytes.len() * 8 <= Self::MAX_WIDTH as usize);
+        let mut bv = Self::zeros(bytes.len() as u32 * 8);
+        for (i, byte) in bytes.iter().rev().enumerate() {
+            bv.bits[i / 8] |= u64::from(*byte) << ((i % 8) * 8)
+        }
+        bv
+    }
+
+    fn to_le_bytes(self) -> Vec<u8> {
+        assert!(self.len.is_multiple_of(8));
+        self.bits.iter().flat_map(|limb| limb.to_le_bytes()).take(self.len as usize / 8).collect()
+    }
+
+    fn to_be_bytes(self) -> Vec<u8> {
+        let mut bytes = self.to_le_bytes();
+        bytes.reverse();
+        bytes
+    }
+
+    fn zero_extend(self, new_len: u32) -> Self {
+        assert!(self.len <= new_len && new_len <= Self::MAX_WIDTH);
+        BN { len: new_len, ..self }
+    }
+
+    fn sign_extend(self, new_len: u32) -> Self {
+        assert!(self.len <= new_len && new_len <= Self::MAX_WIDTH);
+        if self.len > 0 && self.leading_zeros() == 0 {
+            BN { len: new_len, ..self } | !Self::ones(self.len).zero_extend(new_len)
+        } else {
+            BN { len: new_len, ..self }
+        }
+    }
+
+    fn unsigned(self) -> i128 {
+        assert!(self.bits.iter().skip(2).all(|limb| *limb == 0));
+        i128::try_from(self.lower_u128()).unwrap()
+    }
+
+    fn signed(self) -> i128 {
+        let bits = self.lower_u128();
+        if self.len == 0 {
+            0
+        } else if self.len < 128 {
+            let shift = 128 - self.len;
+            ((bits << shift) as i128) >> shift
+        } else {
+            assert!(Self::from_i128(bits as i128, self.len) == self);
+            bits as i128
+        }
+    }
+
+    fn slice(self, from: u32, len: u32) -> Option<Self> {
+        if from + len <= self.len {
+            Some(BN { len, ..self.shift_right(from) }.mask())
+        } else {
+            None
+        }
+    }
+
+    fn set_slice(self, n: u32, update: Self) -> Self {
+        let mask = Self::ones(update.len).zero_extend(self.len).shift_left(n);
+        let update = update.zero_extend(self.len).shift_left(n);
+        (self & !mask) | update
+    }
+
+    fn from_str(bv: &str) -> Option<Self> {
+        if bv.len() <= 2 || !(bv.starts_with('#') || bv.starts_with('0')) {
+            return None;
+        }
+
+        match bv.chars().nth(1) {
+            Some('x') => Self::from_digits(&bv[2..], 16),
+            Some('b') => Self::from_digits(&bv[2..], 2),
+            _ => None,
+        }
+    }
+
+    fn add_i128(self, op: i128) -> Self {
+        self + Self::from_i128(op, self.len)
+    }
+
+    fn set_slice_int(int: i128, n: u32, update: Self) -> i128 {
+        assert!(update.len <= 128);
+        let mask = !bzhi_u128(u128::MAX << n, n + update.len);
+        let update = update.lower_u128() << n;
+        ((int as u128 & mask) | update) as i128
+    }
+
+    fn get_slice_int(len: u32, int: i128, n: u32) -> Self {
+        assert!(len <= 128);
+        Self::from_i128(int >> n, len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::b129::B129;
+    use super::*;
+
+    fn bits<B: BV>(bv: B) -> Vec<bool> {
+        (0..bv.len()).map(|n| bv.shiftr(n as i128).lower_u64() & 1 == 1).collect()
+    }
+
+    /// Check that an operation gives the same result for `B2048` as
+    /// for `B129` for a selection of bitvectors at each length up to
+    /// 129 bits. (B129 does not support zero-length bitvectors.)
+    fn agrees_with_b129<F, G>(op: F, same: G)
+    where
+        F: Fn(B129) -> Option<B129>,
+        G: Fn(B2048) -> Option<B2048>,
+    {
+        for len in 1..=129 {
+            let patterns = ["0x0", "0x1", "0xFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF", "0x8000000000000000CAFEBABE12345678"];
+            for pattern in patterns.iter() {
+                let small = B129::from_str(pattern).unwrap().zero_extend(128);
+                let wide = B2048::from_str(pattern).unwrap().zero_extend(128);
+                let (small, wide) = if len <= 128 {
+                    (small.slice(0, len).unwrap(), wide.slice(0, len).unwrap())
+                } else {
+                    (!small.zero_extend(len), !wide.zero_extend(len))
+                };
+                assert_eq!(bits(wide), bits(small));
+                assert_eq!(same(wide).map(bits), op(small).map(bits), "length {} pattern {}", len, pattern)
+            }
+        }
+    }
+
+    #[test]
+    fn test_agrees_with_b129() {
+        agrees_with_b129(|bv| Some(!bv), |bv| Some(!bv));
+        agrees_with_b129(|bv| Some(-bv), |bv| Some(-bv));
+        agrees_with_b129(|bv| Some(bv + bv), |bv| Some(bv + bv));
+        agrees_with_b129(|bv| Some(bv - B129::ones(bv.len())), |bv| Some(bv - B2048::ones(bv.len())));
+        agrees_with_b129(|bv| Some(bv.add_i128(-3)), |bv| Some(bv.add_i128(-3)));
+        agrees_with_b129(|bv| Some(bv.shiftl(3)), |bv| Some(bv.shiftl(3)));
+        agrees_with_b129(|bv| Some(bv.shiftr(65)), |bv| Some(bv.shiftr(65)));
+        agrees_with_b129(|bv| Some(bv.arith_shiftr(7)), |bv| Some(bv.arith_shiftr(7)));
+        agrees_with_b129(
+            |bv| bv.slice(bv.len() / 2, bv.len().div_ceil(2)),
+            |bv| bv.slice(bv.len() / 2, bv.len().div_ceil(2)),
+        );
+        agrees_with_b129(
+            |bv| Some(bv.set_slice(bv.len() / 2, B129::zeros(bv.len().div_ceil(2)))),
+            |bv| Some(bv.set_slice(bv.len() / 2, B2048::zeros(bv.len().div_ceil(2)))),
+        );
+        agrees_with_b129(
+            |bv| Some(B129::new(u64::from(bv.leading_zeros()), 32)),
+            |bv| Some(B2048::new(u64::from(bv.leading_zeros()), 32)),
+        )
+    }
+
+    #[test]
+    fn test_signed() {
+        assert_eq!(B2048::new(0xff, 8).signed(), -1);
+        assert_eq!(B2048::ones(128).signed(), -1);
+        assert_eq!(B2048::ones(2048).signed(), -1);
+        assert_eq!(B2048::new(5, 2048).signed(), 5);
+        assert_eq!(B2048::new(5, 2048).unsigned(), 5);
+        assert_eq!(B2048::new(7, 3).unsigned(), 7)
+    }
+
+    #[test]
+    fn test_wide_arithmetic() {
+        let top = B2048::BIT_ONE.zero_extend(2048).shiftl(2047);
+        assert_eq!(top.leading_zeros(), 0);
+        assert_eq!(top.shiftr(2047), B2048::new(1, 2048));
+        assert_eq!(B2048::ones(2048) + B2048::new(1, 2048), B2048::zeros(2048));
+        assert_eq!(B2048::zeros(2048) - B2048::new(1, 2048), B2048::ones(2048));
+        assert_eq!(B2048::ones(1000).sign_extend(2048), B2048::ones(2048));
+        assert_eq!(top.arith_shiftr(2000), B2048::ones(2001).zero_extend(2048).shiftl(47));
+
+        let wide = B2048::ones(1024).append(B2048::zeros(1024)).unwrap();
+        assert_eq!(wide.slice(1024, 1024), Some(B2048::ones(1024)));
+        assert_eq!(wide.slice(0, 1024), Some(B2048::zeros(1024)));
+        assert!(wide.append(B2048::BIT_ONE).is_none());
+        assert_eq!(B2048::new(0b1011, 4).replicate(512).unwrap().len(), 2048)
+    }
+
+    #[test]
+    fn test_strings_and_bytes() {
+        let hex = format!("#x{}", "0123456789abcdef".repeat(32));
+        let bv = B2048::from_str(&hex).unwrap();
+        assert_eq!(bv.len(), 2048);
+        assert_eq!(format!("{}", bv), hex);
+        assert_eq!(format!("{:x}", bv), &hex[3..]);
+        assert_eq!(format!("{:X}", B256::new(0xab, 256)), "AB");
+        assert_eq!(B256::from_str(&hex), None);
+
+        assert_eq!(B256::from_bytes(&bv.to_be_bytes()[..32]), B256::from_str(&hex[..66]).unwrap());
+        assert_eq!(B2048::from_bytes(&bv.to_be_bytes()), bv);
+        assert_eq!(B2048::new(0x123456, 24).to_le_bytes(), [0x56, 0x34, 0x12]);
+
+        let encoded = bincode::serialize(&bv).unwrap();
+        assert_eq!(bincode::deserialize::<B2048>(&encoded).unwrap(), bv)
+    }
+}
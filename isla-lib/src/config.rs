@@ -43,7 +43,10 @@ use toml::Value;
 use crate::bitvector::BV;
 use crate::ir::{Loc, Name, Reset, Symtab, Val};
 use crate::lexer::Lexer;
+use crate::memory::{device, AccessChecks, CheckMode, Region, TagInit, TagRegion, DEFAULT_ADDRESS_WIDTH};
+use crate::smt::SolverOptions;
 use crate::value_parser::{LocParser, ValParser};
+use crate::watch::Watchpoint;
 use crate::zencode;
 
 /// We make use of various external tools like an assembler/objdump utility. We want to make sure
@@ -447,6 +450,125 @@ fn get_ignored_registers(config: &Value, symtab: &Symtab) -> Result<HashSet<Name
 }
 
 
+fn get_address_width(config: &Value) -> Result<u32, String> {
+    match config.get("address_width") {
+        Some(value) => value
+            .as_integer()
+            .filter(|width| *width > 0 && *width <= 64)
+            .map(|width| width as u32)
+            .ok_or_else(|| "address_width must be an integer between 1 and 64".to_string()),
+        None => Ok(DEFAULT_ADDRESS_WIDTH),
+    }
+}
+
+fn get_access_checks(config: &Value) -> Result<AccessChecks, String> {
+    let mut checks = AccessChecks::default();
+
+    if let Some(table) = config.get("access_checks") {
+        let flag = |key: &str| match table.get(key) {
+            Some(value) => value.as_bool().ok_or_else(|| format!("access_checks.{} must be a boolean", key)),
+            None => Ok(false),
+        };
+        checks.alignment = flag("alignment")?;
+        checks.region_boundaries = flag("region_boundaries")?;
+
+        if let Some(page_size) = table.get("page_size") {
+            let parse = |s: &str| match s.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16).ok(),
+                None => s.parse().ok(),
+            };
+            let page_size = page_size
+                .as_str()
+                .and_then(parse)
+                .filter(|page_size| page_size.is_power_of_two())
+                .ok_or_else(|| "access_checks.page_size must be a power of two".to_string())?;
+            checks.page_size = Some(page_size)
+        }
+
+        checks.mode = match table.get("mode").map(Value::as_str) {
+            Some(Some("error")) | None => CheckMode::Error,
+            Some(Some("fork")) => CheckMode::Fork,
+            _ => return Err("access_checks.mode must be \"error\" or \"fork\"".to_string()),
+        }
+    }
+
+    Ok(checks)
+}
+
+fn get_tag_regions<B: BV>(config: &Value) -> Result<Vec<TagRegion<B>>, String> {
+    let tags = match config.get("tags") {
+        Some(tags) => tags.as_array().ok_or_else(|| "tags must be an array of tables, i.e. [[tags]]".to_string())?,
+        None => return Ok(Vec::new()),
+    };
+
+    tags.iter()
+        .map(|tag_region| {
+            let address = |key: &str, default: Option<u64>| match tag_region.get(key).and_then(Value::as_str) {
+                Some(value) => match value.strip_prefix("0x") {
+                    Some(hex) => u64::from_str_radix(hex, 16),
+                    None => value.parse(),
+                }
+                .map_err(|e| format!("Could not parse tags.{}: {}", key, e)),
+                None => default.ok_or_else(|| format!("tags.{} must be given", key)),
+            };
+
+            let granule = tag_region
+                .get("granule")
+                .and_then(Value::as_integer)
+                .filter(|granule| *granule > 0)
+                .ok_or_else(|| "tags.granule must be a positive integer".to_string())?;
+
+            let initial = match tag_region.get("initial").and_then(Value::as_str) {
+                Some("symbolic") => TagInit::Symbolic(
+                    tag_region.get("bits").and_then(Value::as_integer).filter(|bits| *bits > 0).unwrap_or(1) as u32,
+                ),
+                Some(bv) => TagInit::Concrete(
+                    B::from_str(bv).ok_or_else(|| format!("Could not parse tags.initial {} as a bitvector", bv))?,
+                ),
+                None => TagInit::Concrete(B::zeros(1)),
+            };
+
+            let clear_on_write = tag_region.get("clear_on_write").and_then(Value::as_bool).unwrap_or(false);
+
+            Ok(TagRegion::new(address("base", Some(0))?..address("top", None)?, granule as u64, initial)
+                .clear_on_write(clear_on_write))
+        })
+        .collect()
+}
+
+fn get_solver_options(config: &Value) -> Result<SolverOptions, String> {
+    let mut options = SolverOptions::default();
+
+    if let Some(solver) = config.get("solver") {
+        let solver = solver.as_table().ok_or_else(|| "[solver] must be a table".to_string())?;
+        for (key, value) in solver.iter() {
+            let limit = || {
+                value
+                    .as_integer()
+                    .filter(|i| *i >= 0)
+                    .map(|i| i as u64)
+                    .ok_or_else(|| format!("solver.{} must be a non-negative integer", key))
+            };
+            match key.as_str() {
+                "timeout" => options.timeout = Some(limit()?),
+                "rlimit" => options.rlimit = Some(limit()?),
+                "length_cases" => options.length_cases = Some(limit()? as usize),
+                "symbolic_strings" => {
+                    options.symbolic_strings =
+                        value.as_bool().ok_or_else(|| "solver.symbolic_strings must be a boolean".to_string())?
+                }
+                "unknown" => {
+                    let policy = value.as_str().ok_or_else(|| "solver.unknown must be a string".to_string())?;
+                    options.unknown_policy = policy.parse()?
+                }
+                _ => return Err(format!("Unrecognised option {} in [solver]", key)),
+            }
+        }
+    }
+
+    Ok(options)
+}
+
 /// get the list of cat names for each barrier in the [barriers] section
 fn get_barriers(config: &Value, symtab: &Symtab) -> Result<HashMap<Name, Vec<String>>, String> {
     if let Some(value) = config.get("barriers") {
@@ -534,10 +656,22 @@ pub struct ISAConfig<B> {
     pub ignored_registers: HashSet<Name>,
     /// Print debug information for any function calls in this set during symbolic execution
     pub probes: HashSet<Name>,
+    /// Report memory accesses to these ranges during symbolic execution
+    pub watchpoints: Vec<Watchpoint>,
     /// Trace calls to functions in this set
     pub trace_functions: HashSet<Name>,
     /// Address translation function
     pub translation_function: Option<Name>,
+    /// Resource limits and unknown result handling for the SMT solver
+    pub solver_options: SolverOptions,
+    /// The width of memory addresses in bits
+    pub address_width: u32,
+    /// Memory-mapped devices declared in the `[memory]` table
+    pub device_regions: Vec<Region<B>>,
+    /// Regions of memory with allocation tags
+    pub tag_regions: Vec<TagRegion<B>>,
+    /// Alignment and boundary checks for memory accesses
+    pub access_checks: AccessChecks,
 }
 
 impl<B: BV> ISAConfig<B> {
@@ -583,8 +717,14 @@ impl<B: BV> ISAConfig<B> {
             register_renames: get_register_renames(&config, symtab)?,
             ignored_registers: get_ignored_registers(&config, symtab)?,
             probes: HashSet::new(),
+            watchpoints: Vec::new(),
             trace_functions,
             translation_function,
+            solver_options: get_solver_options(&config)?,
+            address_width: get_address_width(&config)?,
+            device_regions: device::parse_devices(&config)?,
+            tag_regions: get_tag_regions(&config)?,
+            access_checks: get_access_checks(&config)?,
         })
     }
 
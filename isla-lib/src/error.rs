@@ -31,6 +31,8 @@ use std::error::Error;
 use std::fmt;
 
 use crate::ir::source_loc::SourceLoc;
+use crate::smt::smtlib::Exp;
+use crate::smt::Sym;
 
 #[derive(Debug)]
 pub enum ExecError {
@@ -42,8 +44,9 @@ pub enum ExecError {
     /// raised if a bitvector width would become symbolic.
     SymbolicLength(&'static str, SourceLoc),
     /// Returned when there is no symbolic representation for a
-    /// specific type. Certain types like strings are always assumed
-    /// to be concrete.
+    /// specific type. Certain types like strings are assumed to be
+    /// concrete, unless [crate::smt::SolverOptions::symbolic_strings]
+    /// is set.
     NoSymbolicType,
     /// Used for cases that should be unreachable (i.e. are definite
     /// errors).
@@ -53,6 +56,26 @@ pub enum ExecError {
     Unmapped,
     BadRead(&'static str),
     BadWrite(&'static str),
+    /// A memory access used an address narrower than the memory's
+    /// address width, with the width of the address and the address
+    /// width.
+    AddressWidth(u32, u32),
+    /// A memory access failed one of the checks in the memory's
+    /// [crate::memory::AccessChecks], with the name of the check, the
+    /// address, and the size of the access in bytes.
+    AccessCheck(&'static str, String, u32),
+    /// Raised by a memory access at a symbolic address which may or
+    /// may not pass the access checks. The executor handles this for
+    /// every kind of primop by forking on the condition and retrying
+    /// the access, so it is only seen by code which calls
+    /// [crate::memory::Memory::read] or [crate::memory::Memory::write]
+    /// directly with [crate::memory::CheckMode::Fork].
+    AccessFork(Sym),
+    /// Raised by a primop given a symbolic length which can only take
+    /// a few concrete values, as limited by
+    /// [crate::smt::SolverOptions::length_cases]. The executor forks
+    /// for each value and retries the primop.
+    LengthFork(Sym, Vec<Exp>),
     NoElfEntry,
     OutOfBounds(&'static str),
     MatchFailure,
@@ -64,6 +87,54 @@ pub enum ExecError {
     Z3Unknown,
     /// Execution stopped because this function is in the stop_functions set
     Stopped(String),
+    /// The executor reached a state that well-formed IR should never
+    /// produce. Only the path that hit it is abandoned.
+    Malformed(Box<MalformedState>),
+}
+
+/// The ways in which the IR can be malformed at runtime. Names are
+/// stored already decoded, so the error can be reported without a
+/// symbol table.
+#[derive(Debug)]
+pub enum Malformed {
+    UnknownSymbol(String),
+    UnknownEnum(String),
+    NoSuchField(String),
+    NotAStruct(String),
+    NotAReference,
+    BadLocation,
+    BadExpression(String),
+    ConstructorArity(String, usize),
+    NoSuchFunction(String),
+    NoReturnValue,
+}
+
+/// A [Malformed] error along with where it happened. The function,
+/// pc, and backtrace are filled in by the executor once the error
+/// propagates out of the function being executed.
+#[derive(Debug)]
+pub struct MalformedState {
+    pub reason: Malformed,
+    pub info: SourceLoc,
+    pub function: Option<String>,
+    pub pc: usize,
+    pub backtrace: Vec<(String, usize)>,
+}
+
+impl ExecError {
+    pub fn malformed(reason: Malformed, info: SourceLoc) -> Self {
+        ExecError::Malformed(Box::new(MalformedState { reason, info, function: None, pc: 0, backtrace: Vec::new() }))
+    }
+
+    /// The source location where the error was raised, if the error
+    /// records one.
+    pub fn source_loc(&self) -> SourceLoc {
+        match self {
+            ExecError::Type(_, info) | ExecError::SymbolicLength(_, info) => *info,
+            ExecError::Malformed(state) => state.info,
+            _ => SourceLoc::unknown(),
+        }
+    }
 }
 
 impl fmt::Display for ExecError {
@@ -35,6 +35,7 @@ use crossbeam::deque::{Injector, Steal, Stealer, Worker};
 use crossbeam::queue::SegQueue;
 use crossbeam::thread;
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::mem;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
@@ -44,7 +45,7 @@ use std::thread::sleep;
 use std::time::{Duration, Instant};
 
 use crate::bitvector::BV;
-use crate::error::ExecError;
+use crate::error::{ExecError, Malformed, MalformedState};
 use crate::ir::source_loc::SourceLoc;
 use crate::ir::*;
 use crate::log;
@@ -55,8 +56,16 @@ use crate::probe;
 use crate::smt::smtlib::Def;
 use crate::smt::*;
 use crate::smt_parser;
+use crate::watch;
 use crate::zencode;
 
+/// The maximum length of a symbolic list created by [symbolic].
+pub const SYMBOLIC_LIST_LENGTH: usize = 4;
+
+/// The maximum nesting of unions and lists within a value created by
+/// [symbolic]. Recursive unions are only unfolded this many times.
+pub const SYMBOLIC_DEPTH: usize = 4;
+
 /// Create a Symbolic value of a specified type. Can return a concrete value if the type only
 /// permits a single value, such as for the unit type or the zero-length bitvector type (which is
 /// ideal because SMT solvers don't allow zero-length bitvectors). Compound types like structs will
@@ -67,6 +76,16 @@ pub fn symbolic<B: BV>(
     shared_state: &SharedState<B>,
     solver: &mut Solver<B>,
     info: SourceLoc,
+) -> Result<Val<B>, ExecError> {
+    symbolic_with_depth(ty, shared_state, solver, SYMBOLIC_DEPTH, info)
+}
+
+fn symbolic_with_depth<B: BV>(
+    ty: &Ty<Name>,
+    shared_state: &SharedState<B>,
+    solver: &mut Solver<B>,
+    depth: usize,
+    info: SourceLoc,
 ) -> Result<Val<B>, ExecError> {
     let smt_ty = match ty {
         Ty::Unit => return Ok(Val::Unit),
@@ -77,12 +96,13 @@ pub fn symbolic<B: BV>(
         Ty::Bits(sz) => smtlib::Ty::BitVec(*sz),
         Ty::Bool => smtlib::Ty::Bool,
         Ty::Bit => smtlib::Ty::BitVec(1),
+        Ty::String if solver.symbolic_strings() => smtlib::Ty::String,
 
         Ty::Struct(name) => {
             if let Some(field_types) = shared_state.structs.get(name) {
                 let field_values = field_types
                     .iter()
-                    .map(|(f, ty)| match symbolic(ty, shared_state, solver, info) {
+                    .map(|(f, ty)| match symbolic_with_depth(ty, shared_state, solver, depth, info) {
                         Ok(value) => Ok((*f, value)),
                         Err(error) => Err(error),
                     })
@@ -95,16 +115,59 @@ pub fn symbolic<B: BV>(
         }
 
         Ty::Enum(name) => {
-            let enum_size = shared_state.enums.get(name).unwrap().len();
+            let enum_size = match shared_state.enums.get(name) {
+                Some(members) => members.len(),
+                None => {
+                    let name = zencode::decode(shared_state.symtab.to_str(*name));
+                    return Err(ExecError::malformed(Malformed::UnknownEnum(name), info));
+                }
+            };
             let enum_id = solver.get_enum(enum_size);
             return solver.declare_const(smtlib::Ty::Enum(enum_id), info).into();
         }
 
         Ty::FixedVector(sz, ty) => {
-            let values = (0..*sz).map(|_| symbolic(ty, shared_state, solver, info)).collect::<Result<_, _>>()?;
+            let values = (0..*sz)
+                .map(|_| symbolic_with_depth(ty, shared_state, solver, depth, info))
+                .collect::<Result<_, _>>()?;
             return Ok(Val::Vector(values));
         }
 
+        // Beyond the maximum depth we give up, as below
+        Ty::Union(_) | Ty::List(_) if depth == 0 => return Ok(Val::Poison),
+
+        Ty::Union(name) => {
+            let ctors = match shared_state.unions.get(name) {
+                Some(ctors) => ctors,
+                None => {
+                    let name = zencode::decode(shared_state.symtab.to_str(*name));
+                    return Err(ExecError::Unreachable(format!("Union {} does not appear to exist!", name)));
+                }
+            };
+            let mut payloads = ctors
+                .iter()
+                .map(|(ctor, ty)| Ok((*ctor, symbolic_with_depth(ty, shared_state, solver, depth - 1, info)?)))
+                .collect::<Result<Vec<_>, ExecError>>()?;
+            if payloads.len() == 1 {
+                let (ctor, payload) = payloads.pop().unwrap();
+                return Ok(Val::Ctor(ctor, Box::new(payload)));
+            }
+            let enum_id = solver.get_enum(payloads.len());
+            let tag = solver.declare_const(smtlib::Ty::Enum(enum_id), info);
+            return Ok(Val::SymbolicCtor(tag, payloads));
+        }
+
+        Ty::List(ty) => {
+            use smtlib::Exp::{Bvule, Var};
+            let elems = (0..SYMBOLIC_LIST_LENGTH)
+                .map(|_| symbolic_with_depth(ty, shared_state, solver, depth - 1, info))
+                .collect::<Result<_, _>>()?;
+            let len = solver.declare_const(smtlib::Ty::BitVec(64), info);
+            let max_len = smtlib::bits64(SYMBOLIC_LIST_LENGTH as u64, 64);
+            solver.add(Def::Assert(Bvule(Box::new(Var(len)), Box::new(max_len))));
+            return Ok(Val::SymbolicList(len, elems));
+        }
+
         // Some things we just can't represent symbolically, but we can continue in the hope that
         // they never actually get used.
         _ => return Ok(Val::Poison),
@@ -177,13 +240,38 @@ fn get_id_and_initialize<'ir, B: BV>(
                         let enum_id = solver.get_enum(*enum_size);
                         Val::Enum(EnumMember { enum_id, member: *member })
                     }
-                    None => panic!("Symbol {} ({:?}) not found", zencode::decode(shared_state.symtab.to_str(id)), id),
+                    None => {
+                        let symbol = zencode::decode(shared_state.symtab.to_str(id));
+                        return Err(ExecError::malformed(Malformed::UnknownSymbol(symbol), info));
+                    }
                 },
             },
         },
     })
 }
 
+/// The member of the tag enumeration for a [Val::SymbolicCtor]
+/// corresponding to a constructor.
+fn ctor_member<B: BV>(
+    ctor: Name,
+    ctors: &[(Name, Val<B>)],
+    solver: &mut Solver<B>,
+    info: SourceLoc,
+) -> Result<smtlib::Exp, ExecError> {
+    match ctors.iter().position(|(ctor_b, _)| ctor == *ctor_b) {
+        Some(member) => Ok(smtlib::Exp::Enum(EnumMember { enum_id: solver.get_enum(ctors.len()), member })),
+        None => Err(ExecError::Type(format!("Constructor {:?} not in union {:?}", ctor, ctors), info)),
+    }
+}
+
+fn no_such_field<B: BV>(field: Name, shared_state: &SharedState<B>, info: SourceLoc) -> ExecError {
+    ExecError::malformed(Malformed::NoSuchField(zencode::decode(shared_state.symtab.to_str(field))), info)
+}
+
+fn not_a_struct<B: BV>(field: Name, shared_state: &SharedState<B>, info: SourceLoc) -> ExecError {
+    ExecError::malformed(Malformed::NotAStruct(zencode::decode(shared_state.symtab.to_str(field))), info)
+}
+
 fn get_loc_and_initialize<'ir, B: BV>(
     loc: &Loc<Name>,
     local_state: &mut LocalState<'ir, B>,
@@ -201,16 +289,54 @@ fn get_loc_and_initialize<'ir, B: BV>(
             {
                 match members.get(field) {
                     Some(field_value) => field_value.clone(),
-                    None => panic!("No field {:?}", shared_state.symtab.to_str(*field)),
+                    None => return Err(no_such_field(*field, shared_state, info)),
                 }
             } else {
-                panic!("Struct expression did not evaluate to a struct")
+                return Err(not_a_struct(*field, shared_state, info));
             }
         }
-        _ => panic!("Cannot get_loc_and_initialize"),
+        Loc::Addr(_) => return Err(ExecError::malformed(Malformed::BadLocation, info)),
     })
 }
 
+/// Evaluate a built-in IR operation, as used by [Exp::Call], on
+/// already evaluated arguments.
+pub(crate) fn eval_op<B: BV>(
+    op: Op,
+    args: Vec<Val<B>>,
+    solver: &mut Solver<B>,
+    info: SourceLoc,
+) -> Result<Val<B>, ExecError> {
+    match op {
+        Op::Lt => primop::op_lt(args[0].clone(), args[1].clone(), solver, info),
+        Op::Gt => primop::op_gt(args[0].clone(), args[1].clone(), solver, info),
+        Op::Lteq => primop::op_lteq(args[0].clone(), args[1].clone(), solver, info),
+        Op::Gteq => primop::op_gteq(args[0].clone(), args[1].clone(), solver, info),
+        Op::Eq => primop::op_eq(args[0].clone(), args[1].clone(), solver, info),
+        Op::Neq => primop::op_neq(args[0].clone(), args[1].clone(), solver, info),
+        Op::Add => primop::op_add(args[0].clone(), args[1].clone(), solver, info),
+        Op::Sub => primop::op_sub(args[0].clone(), args[1].clone(), solver, info),
+        Op::Bvnot => primop::not_bits(args[0].clone(), solver, info),
+        Op::Bvor => primop::or_bits(args[0].clone(), args[1].clone(), solver, info),
+        Op::Bvxor => primop::xor_bits(args[0].clone(), args[1].clone(), solver, info),
+        Op::Bvand => primop::and_bits(args[0].clone(), args[1].clone(), solver, info),
+        Op::Bvadd => primop::add_bits(args[0].clone(), args[1].clone(), solver, info),
+        Op::Bvsub => primop::sub_bits(args[0].clone(), args[1].clone(), solver, info),
+        Op::Bvaccess => primop::vector_access(args[0].clone(), args[1].clone(), solver, info),
+        Op::Concat => primop::append(args[0].clone(), args[1].clone(), solver, info),
+        Op::Not => primop::not_bool(args[0].clone(), solver, info),
+        Op::And => primop::and_bool(args[0].clone(), args[1].clone(), solver, info),
+        Op::Or => primop::or_bool(args[0].clone(), args[1].clone(), solver, info),
+        Op::Slice(len) => primop::op_slice(args[0].clone(), args[1].clone(), len, solver, info),
+        Op::SetSlice => primop::op_set_slice(args[0].clone(), args[1].clone(), args[2].clone(), solver, info),
+        Op::Unsigned(_) => primop::op_unsigned(args[0].clone(), solver, info),
+        Op::Signed(_) => primop::op_signed(args[0].clone(), solver, info),
+        Op::Head => primop::op_head(args[0].clone(), solver, info),
+        Op::Tail => primop::op_tail(args[0].clone(), solver, info),
+        Op::ZeroExtend(len) => primop::op_zero_extend(args[0].clone(), len, solver, info),
+    }
+}
+
 fn eval_exp_with_accessor<'ir, B: BV>(
     exp: &Exp<Name>,
     local_state: &mut LocalState<'ir, B>,
@@ -238,40 +364,18 @@ fn eval_exp_with_accessor<'ir, B: BV>(
                 .iter()
                 .map(|arg| eval_exp(arg, local_state, shared_state, solver, info))
                 .collect::<Result<_, _>>()?;
-            match op {
-                Op::Lt => primop::op_lt(args[0].clone(), args[1].clone(), solver, info)?,
-                Op::Gt => primop::op_gt(args[0].clone(), args[1].clone(), solver, info)?,
-                Op::Lteq => primop::op_lteq(args[0].clone(), args[1].clone(), solver, info)?,
-                Op::Gteq => primop::op_gteq(args[0].clone(), args[1].clone(), solver, info)?,
-                Op::Eq => primop::op_eq(args[0].clone(), args[1].clone(), solver, info)?,
-                Op::Neq => primop::op_neq(args[0].clone(), args[1].clone(), solver, info)?,
-                Op::Add => primop::op_add(args[0].clone(), args[1].clone(), solver, info)?,
-                Op::Sub => primop::op_sub(args[0].clone(), args[1].clone(), solver, info)?,
-                Op::Bvnot => primop::not_bits(args[0].clone(), solver, info)?,
-                Op::Bvor => primop::or_bits(args[0].clone(), args[1].clone(), solver, info)?,
-                Op::Bvxor => primop::xor_bits(args[0].clone(), args[1].clone(), solver, info)?,
-                Op::Bvand => primop::and_bits(args[0].clone(), args[1].clone(), solver, info)?,
-                Op::Bvadd => primop::add_bits(args[0].clone(), args[1].clone(), solver, info)?,
-                Op::Bvsub => primop::sub_bits(args[0].clone(), args[1].clone(), solver, info)?,
-                Op::Bvaccess => primop::vector_access(args[0].clone(), args[1].clone(), solver, info)?,
-                Op::Concat => primop::append(args[0].clone(), args[1].clone(), solver, info)?,
-                Op::Not => primop::not_bool(args[0].clone(), solver, info)?,
-                Op::And => primop::and_bool(args[0].clone(), args[1].clone(), solver, info)?,
-                Op::Or => primop::or_bool(args[0].clone(), args[1].clone(), solver, info)?,
-                Op::Slice(len) => primop::op_slice(args[0].clone(), args[1].clone(), *len, solver, info)?,
-                Op::SetSlice => primop::op_set_slice(args[0].clone(), args[1].clone(), args[2].clone(), solver, info)?,
-                Op::Unsigned(_) => primop::op_unsigned(args[0].clone(), solver, info)?,
-                Op::Signed(_) => primop::op_signed(args[0].clone(), solver, info)?,
-                Op::Head => primop::op_head(args[0].clone(), solver, info)?,
-                Op::Tail => primop::op_tail(args[0].clone(), solver, info)?,
-                Op::ZeroExtend(len) => primop::op_zero_extend(args[0].clone(), *len, solver, info)?,
-            }
+            eval_op(*op, args, solver, info)?
         }
 
         Kind(ctor_a, exp) => {
             let v = eval_exp(exp, local_state, shared_state, solver, info)?;
             match v {
                 Val::Ctor(ctor_b, _) => Val::Bool(*ctor_a != ctor_b),
+                Val::SymbolicCtor(tag, ref ctors) => {
+                    let member = ctor_member(*ctor_a, ctors, solver, info)?;
+                    let kind = smtlib::Exp::Neq(Box::new(smtlib::Exp::Var(tag)), Box::new(member));
+                    Val::Symbolic(solver.define_const(kind, info))
+                }
                 _ => return Err(ExecError::Type(format!("Kind check on non-constructor {:?}", &v), info)),
             }
         }
@@ -286,6 +390,13 @@ fn eval_exp_with_accessor<'ir, B: BV>(
                         return Err(ExecError::Type(format!("Constructors did not match in unwrap {:?}", &v), info));
                     }
                 }
+                // The IR only unwraps a constructor once it has checked
+                // its kind, so the tag must match on this path
+                Val::SymbolicCtor(tag, mut ctors) => {
+                    let member = ctor_member(*ctor_a, &ctors, solver, info)?;
+                    solver.add(Def::Assert(smtlib::Exp::Eq(Box::new(smtlib::Exp::Var(tag)), Box::new(member))));
+                    ctors.swap_remove(ctors.iter().position(|(ctor_b, _)| ctor_a == ctor_b).unwrap()).1
+                }
                 _ => return Err(ExecError::Type(format!("Tried to unwrap non-constructor {:?}", &v), info)),
             }
         }
@@ -297,16 +408,16 @@ fn eval_exp_with_accessor<'ir, B: BV>(
             {
                 match struct_value.get(field) {
                     Some(field_value) => field_value.clone(),
-                    None => panic!("No field {:?}", shared_state.symtab.to_str(*field)),
+                    None => return Err(no_such_field(*field, shared_state, info)),
                 }
             } else {
-                panic!("Struct expression did not evaluate to a struct")
+                return Err(not_a_struct(*field, shared_state, info));
             }
         }
 
         Ref(reg) => Val::Ref(*reg),
 
-        _ => panic!("Could not evaluate expression {:?}", exp),
+        _ => return Err(ExecError::malformed(Malformed::BadExpression(format!("{:?}", exp)), info)),
     })
 }
 
@@ -366,15 +477,10 @@ fn assign_with_accessor<'ir, B: BV>(
                             info,
                         )?;
                     }
-                    None => panic!("Invalid field assignment"),
+                    None => return Err(no_such_field(*field, shared_state, info)),
                 }
             } else {
-                panic!(
-                    "Cannot assign struct to non-struct {:?}.{:?} ({:?})",
-                    loc,
-                    field,
-                    get_loc_and_initialize(loc, local_state, shared_state, solver, &mut accessor, info)
-                )
+                return Err(not_a_struct(*field, shared_state, info));
             }
         }
 
@@ -382,7 +488,7 @@ fn assign_with_accessor<'ir, B: BV>(
             if let Val::Ref(reg) = get_loc_and_initialize(loc, local_state, shared_state, solver, accessor, info)? {
                 assign_with_accessor(&Loc::Id(reg), v, local_state, shared_state, solver, accessor, info)?
             } else {
-                panic!("Cannot get address of non-reference {:?}", loc)
+                return Err(ExecError::malformed(Malformed::NotAReference, info));
             }
         }
     };
@@ -424,6 +530,42 @@ type Stack<'ir, B> = Option<
 
 pub type Backtrace = Vec<(Name, usize)>;
 
+/// A `PathId` identifies a path by the choice made at each fork along
+/// it. The path which carries on in the same thread after a fork
+/// takes choice 0, and the alternatives queued as new tasks take
+/// choices 1 and up. An identifier taken partway through execution is
+/// a prefix of the identifier of every path that continues from that
+/// point.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PathId(Vec<u32>);
+
+impl PathId {
+    pub fn choices(&self) -> &[u32] {
+        &self.0
+    }
+
+    fn with_choice(&self, choice: u32) -> Self {
+        let mut choices = self.0.clone();
+        choices.push(choice);
+        PathId(choices)
+    }
+}
+
+impl fmt::Display for PathId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "root");
+        }
+        for (i, choice) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ".")?
+            }
+            write!(f, "{}", choice)?
+        }
+        Ok(())
+    }
+}
+
 /// A `Frame` is an immutable snapshot of the program state while it
 /// is being symbolically executed.
 #[derive(Clone)]
@@ -438,6 +580,7 @@ pub struct Frame<'ir, B> {
     stack_vars: Arc<Vec<Bindings<'ir, B>>>,
     stack_call: Stack<'ir, B>,
     backtrace: Arc<Backtrace>,
+    path: PathId,
 }
 
 /// A `LocalFrame` is a mutable frame which is used by a currently
@@ -454,6 +597,7 @@ pub struct LocalFrame<'ir, B> {
     stack_vars: Vec<Bindings<'ir, B>>,
     stack_call: Stack<'ir, B>,
     backtrace: Backtrace,
+    path: PathId,
 }
 
 pub fn unfreeze_frame<'ir, B: BV>(frame: &Frame<'ir, B>) -> LocalFrame<'ir, B> {
@@ -468,6 +612,7 @@ pub fn unfreeze_frame<'ir, B: BV>(frame: &Frame<'ir, B>) -> LocalFrame<'ir, B> {
         stack_vars: (*frame.stack_vars).clone(),
         stack_call: frame.stack_call.clone(),
         backtrace: (*frame.backtrace).clone(),
+        path: frame.path.clone(),
     }
 }
 
@@ -483,9 +628,15 @@ pub fn freeze_frame<'ir, B: BV>(frame: &LocalFrame<'ir, B>) -> Frame<'ir, B> {
         stack_vars: Arc::new(frame.stack_vars.clone()),
         stack_call: frame.stack_call.clone(),
         backtrace: Arc::new(frame.backtrace.clone()),
+        path: frame.path.clone(),
     }
 }
 
+/// Freeze a frame for an alternative path at a fork, see [PathId].
+fn freeze_fork<'ir, B: BV>(frame: &LocalFrame<'ir, B>, choice: u32) -> Frame<'ir, B> {
+    Frame { path: frame.path.with_choice(choice), ..freeze_frame(frame) }
+}
+
 impl<'ir, B: BV> LocalFrame<'ir, B> {
     pub fn vars_mut(&mut self) -> &mut Bindings<'ir, B> {
         &mut self.local_state.vars
@@ -542,6 +693,17 @@ impl<'ir, B: BV> LocalFrame<'ir, B> {
         }
     }
 
+    pub fn path(&self) -> &PathId {
+        &self.path
+    }
+
+    /// The source location of the instruction at the frame's pc, or
+    /// of the closest instruction before it which has one.
+    pub fn source_loc(&self) -> SourceLoc {
+        let end = std::cmp::min(self.pc + 1, self.instrs.len());
+        self.instrs[..end].iter().rev().find_map(Instr::source_loc).unwrap_or_else(SourceLoc::unknown)
+    }
+
     pub fn memory(&self) -> &Memory<B> {
         &self.memory
     }
@@ -594,6 +756,7 @@ impl<'ir, B: BV> LocalFrame<'ir, B> {
             stack_vars: Vec::new(),
             stack_call: None,
             backtrace: Vec::new(),
+            path: PathId::default(),
         }
     }
 
@@ -606,6 +769,7 @@ impl<'ir, B: BV> LocalFrame<'ir, B> {
     ) -> Self {
         let mut new_frame = LocalFrame::new(name, args, vals, instrs);
         new_frame.forks = self.forks;
+        new_frame.path = self.path.clone();
         new_frame.local_state.regs = self.local_state.regs.clone();
         new_frame.local_state.lets = self.local_state.lets.clone();
         new_frame.memory = self.memory.clone();
@@ -694,7 +858,7 @@ pub fn reset_registers<'ir, 'task, B: BV>(
                 .map_err(|e| ExecError::Unreachable(e.to_string()))?;
             solver.add(Def::Assert(assertion_exp));
         }
-        if solver.check_sat().is_unsat()? {
+        if !solver.is_sat(info)? {
             return Err(ExecError::Dead);
         }
     }
@@ -713,15 +877,133 @@ fn run<'ir, 'task, B: BV>(
     solver: &mut Solver<B>,
 ) -> Result<(Val<B>, LocalFrame<'ir, B>), (ExecError, Backtrace)> {
     let mut frame = unfreeze_frame(frame);
+    solver.set_cycle_count(task_state.cycles);
     match run_loop(tid, task_id, timeout, stop_functions, queue, &mut frame, task_state, shared_state, solver) {
         Ok(v) => Ok((v, frame)),
-        Err(err) => {
+        Err(mut err) => {
             frame.backtrace.push((frame.function_name, frame.pc));
+            if let ExecError::Malformed(state) = &mut err {
+                locate_malformed(state, &frame.backtrace, &shared_state.symtab)
+            }
             Err((err, frame.backtrace))
         }
     }
 }
 
+/// Record the function and pc at which a [Malformed] error was raised
+/// (the last entry in the backtrace), along with the rest of the
+/// backtrace, so the error can be reported without the frame.
+fn locate_malformed(state: &mut MalformedState, backtrace: &[(Name, usize)], symtab: &Symtab) {
+    if state.function.is_some() {
+        return;
+    }
+    let mut backtrace = backtrace.iter().map(|(f, pc)| (zencode::decode(symtab.to_str(*f)), *pc));
+    if let Some((function, pc)) = backtrace.next_back() {
+        state.function = Some(function);
+        state.pc = pc
+    }
+    state.backtrace = backtrace.collect()
+}
+
+/// Fork for each value a symbolic length can take, as raised by a
+/// primop with [ExecError::LengthFork]. The current path continues
+/// with the first value, and every path retries the instruction that
+/// raised the error with the length fixed.
+#[allow(clippy::too_many_arguments)]
+fn fork_on_length<'ir, 'task, B: BV>(
+    tid: usize,
+    task_id: usize,
+    v: Sym,
+    mut values: Vec<smtlib::Exp>,
+    stop_functions: Option<&'task HashSet<Name>>,
+    queue: &Worker<Task<'ir, 'task, B>>,
+    frame: &mut LocalFrame<'ir, B>,
+    task_state: &'task TaskState<B>,
+    solver: &mut Solver<B>,
+    info: SourceLoc,
+) {
+    use smtlib::Def::*;
+    use smtlib::Exp::*;
+
+    log_from!(tid, log::FORK, &format!("Symbolic length fork {:?} ({} cases)", info, values.len()));
+    solver.add_event(Event::Fork(frame.forks, v, info));
+    frame.forks += 1;
+
+    let first = values.remove(0);
+    let point = checkpoint(solver);
+    for (choice, value) in values.into_iter().enumerate() {
+        queue.push(Task {
+            id: task_id,
+            frame: freeze_fork(frame, choice as u32 + 1),
+            checkpoint: point.clone(),
+            fork_cond: Some(Assert(Eq(Box::new(Var(v)), Box::new(value)))),
+            state: task_state,
+            stop_functions,
+        })
+    }
+    frame.path = frame.path.with_choice(0);
+    solver.add(Assert(Eq(Box::new(Var(v)), Box::new(first))))
+}
+
+/// Fork on whether a memory access passes its access checks, for a
+/// primop which raised [ExecError::AccessFork]. Every path retries
+/// the instruction that raised the error with the checks decided.
+#[allow(clippy::too_many_arguments)]
+fn fork_on_access<'ir, 'task, B: BV>(
+    tid: usize,
+    task_id: usize,
+    v: Sym,
+    stop_functions: Option<&'task HashSet<Name>>,
+    queue: &Worker<Task<'ir, 'task, B>>,
+    frame: &mut LocalFrame<'ir, B>,
+    task_state: &'task TaskState<B>,
+    solver: &mut Solver<B>,
+    info: SourceLoc,
+) {
+    use smtlib::Def::*;
+    use smtlib::Exp::*;
+
+    log_from!(tid, log::FORK, &format!("Access check fork {:?}", info));
+    solver.add_event(Event::Fork(frame.forks, v, info));
+    frame.forks += 1;
+
+    let point = checkpoint(solver);
+    queue.push(Task {
+        id: task_id,
+        frame: freeze_fork(frame, 1),
+        checkpoint: point,
+        fork_cond: Some(Assert(Not(Box::new(Var(v))))),
+        state: task_state,
+        stop_functions,
+    });
+    frame.path = frame.path.with_choice(0);
+    solver.add(Assert(Var(v)))
+}
+
+/// Check the memory accesses made by a primop, which are the events
+/// added to the trace since `events_before`, against the watchpoints.
+/// All memory accesses are made by primops which take the frame, so
+/// each place such primops are called must check the watchpoints.
+fn check_watchpoints<B: BV>(
+    tid: usize,
+    events_before: usize,
+    frame: &LocalFrame<B>,
+    shared_state: &SharedState<B>,
+    solver: &mut Solver<B>,
+) -> Result<(), ExecError> {
+    if shared_state.watchpoints.is_empty() {
+        return Ok(());
+    }
+    let events = solver.trace().recent().get(events_before..).unwrap_or(&[]).to_vec();
+    let reports =
+        watch::check_events(&events, &frame.path, frame.function_name, &frame.backtrace, shared_state, solver)?;
+    for report in reports {
+        log_from!(tid, log::WATCH, report);
+        solver.add_event(Event::Watch(report))
+    }
+    Ok(())
+}
+
 fn run_loop<'ir, 'task, B: BV>(
     tid: usize,
     task_id: usize,
@@ -765,8 +1047,8 @@ fn run_loop<'ir, 'task, B: BV>(
 
                         let test_true = Var(v);
                         let test_false = Not(Box::new(Var(v)));
-                        let can_be_true = solver.check_sat_with(&test_true).is_sat()?;
-                        let can_be_false = solver.check_sat_with(&test_false).is_sat()?;
+                        let can_be_true = solver.is_sat_with(&test_true, *info)?;
+                        let can_be_false = solver.is_sat_with(&test_false, *info)?;
 
                         if can_be_true && can_be_false {
                             if_logging!(log::FORK, {
@@ -780,7 +1062,7 @@ fn run_loop<'ir, 'task, B: BV>(
                             frame.forks += 1;
 
                             let point = checkpoint(solver);
-                            let frozen = Frame { pc: frame.pc + 1, ..freeze_frame(&frame) };
+                            let frozen = Frame { pc: frame.pc + 1, ..freeze_fork(frame, 1) };
                             queue.push(Task {
                                 id: task_id,
                                 frame: frozen,
@@ -789,6 +1071,7 @@ fn run_loop<'ir, 'task, B: BV>(
                                 state: task_state,
                                 stop_functions,
                             });
+                            frame.path = frame.path.with_choice(0);
                             solver.add(Assert(test_true));
                             frame.pc = *target
                         } else if can_be_true {
@@ -824,7 +1107,28 @@ fn run_loop<'ir, 'task, B: BV>(
 
             Instr::PrimopUnary(loc, f, arg, info) => {
                 let arg = eval_exp(arg, &mut frame.local_state, shared_state, solver, *info)?;
-                let value = f(arg, solver, *info)?;
+                let value = match f(arg, solver, *info) {
+                    Err(ExecError::AccessFork(v)) => {
+                        fork_on_access(tid, task_id, v, stop_functions, queue, frame, task_state, solver, *info);
+                        continue;
+                    }
+                    Err(ExecError::LengthFork(v, values)) => {
+                        fork_on_length(
+                            tid,
+                            task_id,
+                            v,
+                            values,
+                            stop_functions,
+                            queue,
+                            frame,
+                            task_state,
+                            solver,
+                            *info,
+                        );
+                        continue;
+                    }
+                    result => result?,
+                };
                 assign(tid, loc, value, &mut frame.local_state, shared_state, solver, *info)?;
                 frame.pc += 1;
             }
@@ -832,7 +1136,28 @@ fn run_loop<'ir, 'task, B: BV>(
             Instr::PrimopBinary(loc, f, arg1, arg2, info) => {
                 let arg1 = eval_exp(arg1, &mut frame.local_state, shared_state, solver, *info)?;
                 let arg2 = eval_exp(arg2, &mut frame.local_state, shared_state, solver, *info)?;
-                let value = f(arg1, arg2, solver, *info)?;
+                let value = match f(arg1, arg2, solver, *info) {
+                    Err(ExecError::AccessFork(v)) => {
+                        fork_on_access(tid, task_id, v, stop_functions, queue, frame, task_state, solver, *info);
+                        continue;
+                    }
+                    Err(ExecError::LengthFork(v, values)) => {
+                        fork_on_length(
+                            tid,
+                            task_id,
+                            v,
+                            values,
+                            stop_functions,
+                            queue,
+                            frame,
+                            task_state,
+                            solver,
+                            *info,
+                        );
+                        continue;
+                    }
+                    result => result?,
+                };
                 assign(tid, loc, value, &mut frame.local_state, shared_state, solver, *info)?;
                 frame.pc += 1;
             }
@@ -842,7 +1167,33 @@ fn run_loop<'ir, 'task, B: BV>(
                     .iter()
                     .map(|arg| eval_exp(arg, &mut frame.local_state, shared_state, solver, *info))
                     .collect::<Result<_, _>>()?;
-                let value = f(args, solver, frame, *info)?;
+                let events_before = solver.trace().recent().len();
+                let value = match f(args, solver, frame, *info) {
+                    // Memory accesses which may or may not pass the
+                    // access checks fork, and each path retries the
+                    // access with the check either holding or failing
+                    Err(ExecError::AccessFork(v)) => {
+                        fork_on_access(tid, task_id, v, stop_functions, queue, frame, task_state, solver, *info);
+                        continue;
+                    }
+                    Err(ExecError::LengthFork(v, values)) => {
+                        fork_on_length(
+                            tid,
+                            task_id,
+                            v,
+                            values,
+                            stop_functions,
+                            queue,
+                            frame,
+                            task_state,
+                            solver,
+                            *info,
+                        );
+                        continue;
+                    }
+                    result => result?,
+                };
+                check_watchpoints(tid, events_before, frame, shared_state, solver)?;
                 assign(tid, loc, value, &mut frame.local_state, shared_state, solver, *info)?;
                 frame.pc += 1;
             }
@@ -890,6 +1241,10 @@ fn run_loop<'ir, 'task, B: BV>(
                         } else if *f == RESET_REGISTERS {
                             reset_registers(tid, frame, task_state, shared_state, solver, *info)?;
                             frame.pc += 1
+                        } else if *f == SLEEPING {
+                            let sleeping = Val::Bool(task_state.sleeping);
+                            assign(tid, loc, sleeping, &mut frame.local_state, shared_state, solver, *info)?;
+                            frame.pc += 1
                         } else if *f == REG_DEREF && args.len() == 1 {
                             if let Val::Ref(reg) =
                                 eval_exp(&args[0], &mut frame.local_state, shared_state, solver, *info)?
@@ -905,8 +1260,24 @@ fn run_loop<'ir, 'task, B: BV>(
                                 return Err(ExecError::Type(format!("reg_deref (not a register) {:?}", &f), *info));
                             };
                             frame.pc += 1
+                        } else if let Some(closure) = shared_state.closures.get(f) {
+                            let args = args
+                                .iter()
+                                .map(|arg| eval_exp(arg, &mut frame.local_state, shared_state, solver, *info))
+                                .collect::<Result<Vec<Val<B>>, _>>()?;
+                            let events_before = solver.trace().recent().len();
+                            let value = closure(args, solver, frame, *info)?;
+                            check_watchpoints(tid, events_before, frame, shared_state, solver)?;
+                            assign(tid, loc, value, &mut frame.local_state, shared_state, solver, *info)?;
+                            frame.pc += 1
                         } else if shared_state.union_ctors.contains(f) {
-                            assert!(args.len() == 1);
+                            if args.len() != 1 {
+                                let symbol = zencode::decode(shared_state.symtab.to_str(*f));
+                                return Err(ExecError::malformed(
+                                    Malformed::ConstructorArity(symbol, args.len()),
+                                    *info,
+                                ));
+                            }
                             let arg = eval_exp(&args[0], &mut frame.local_state, shared_state, solver, *info)?;
                             assign(
                                 tid,
@@ -920,7 +1291,7 @@ fn run_loop<'ir, 'task, B: BV>(
                             frame.pc += 1
                         } else {
                             let symbol = zencode::decode(shared_state.symtab.to_str(*f));
-                            panic!("Attempted to call non-existent function {} ({:?})", symbol, *f)
+                            return Err(ExecError::malformed(Malformed::NoSuchFunction(symbol), *info));
                         }
                     }
 
@@ -970,7 +1341,7 @@ fn run_loop<'ir, 'task, B: BV>(
             }
 
             Instr::End => match frame.vars().get(&RETURN) {
-                None => panic!("Reached end without assigning to return"),
+                None => return Err(ExecError::malformed(Malformed::NoReturnValue, SourceLoc::unknown())),
                 Some(value) => {
                     let value = match value {
                         UVal::Uninit(ty) => symbolic(ty, shared_state, solver, SourceLoc::unknown())?,
@@ -1024,7 +1395,7 @@ fn run_loop<'ir, 'task, B: BV>(
                     let sym = solver.declare_const(BitVec(len), *info);
                     solver.assert_eq(Var(v), Var(sym));
 
-                    if solver.check_sat().is_unsat()? {
+                    if !solver.is_sat(*info)? {
                         return Err(ExecError::Dead);
                     }
 
@@ -1049,12 +1420,13 @@ fn run_loop<'ir, 'task, B: BV>(
 
                     queue.push(Task {
                         id: task_id,
-                        frame: freeze_frame(&frame),
+                        frame: freeze_fork(frame, 1),
                         checkpoint: point,
                         fork_cond: Some(Assert(Neq(Box::new(Var(v)), Box::new(bits64(result, size))))),
                         state: task_state,
                         stop_functions,
                     });
+                    frame.path = frame.path.with_choice(0);
 
                     solver.assert_eq(Var(v), bits64(result, size));
 
@@ -1113,15 +1485,32 @@ pub type Collector<'ir, B, R> = dyn 'ir
 
 pub struct TaskState<B> {
     reset_registers: HashMap<Loc<Name>, Reset<B>>,
+    sleeping: bool,
+    cycles: i128,
 }
 
 impl<B> TaskState<B> {
     pub fn new() -> Self {
-        TaskState { reset_registers: HashMap::new() }
+        TaskState { reset_registers: HashMap::new(), sleeping: false, cycles: 0 }
     }
 
     pub fn with_reset_registers(reset_registers: HashMap<Loc<Name>, Reset<B>>) -> Self {
-        TaskState { reset_registers }
+        TaskState { reset_registers, sleeping: false, cycles: 0 }
+    }
+
+    /// Set the value returned by the Sail `sleeping` builtin, which
+    /// is false by default.
+    pub fn set_sleeping(&mut self, sleeping: bool) -> &mut Self {
+        self.sleeping = sleeping;
+        self
+    }
+
+    /// Set the initial value of the counter returned by the Sail
+    /// `get_cycle_count` builtin, which is zero by default. Each task
+    /// starts counting from this value.
+    pub fn set_cycle_count(&mut self, cycles: i128) -> &mut Self {
+        self.cycles = cycles;
+        self
     }
 }
 
@@ -1164,6 +1553,7 @@ pub fn start_single<'ir, 'task, B: BV, R>(
     while let Some(task) = queue.pop() {
         let mut cfg = Config::new();
         cfg.set_param_value("model", "true");
+        cfg.set_options(&shared_state.solver_options);
         let ctx = Context::new(cfg);
         let mut solver = Solver::from_checkpoint(&ctx, task.checkpoint);
         if let Some(def) = task.fork_cond {
@@ -1205,7 +1595,8 @@ fn do_work<'ir, 'task, B: BV, R>(
     collected: &R,
     collector: &Collector<'ir, B, R>,
 ) {
-    let cfg = Config::new();
+    let mut cfg = Config::new();
+    cfg.set_options(&shared_state.solver_options);
     let ctx = Context::new(cfg);
     let mut solver = Solver::from_checkpoint(&ctx, task.checkpoint);
     if let Some(def) = task.fork_cond {
@@ -1335,9 +1726,39 @@ pub fn start_multi<'ir, 'task, B: BV, R>(
     .unwrap();
 }
 
-/// This `Collector` is used for boolean Sail functions. It returns
-/// true via an AtomicBool if all reachable paths through the program
-/// are unsatisfiable, which implies that the function always returns
+/// The result of [all_unsat_collector].
+pub struct AllUnsat {
+    all_unsat: AtomicBool,
+    assumed_unknown: AtomicBool,
+}
+
+impl AllUnsat {
+    pub fn new() -> Self {
+        AllUnsat { all_unsat: AtomicBool::new(true), assumed_unknown: AtomicBool::new(false) }
+    }
+
+    /// True if every reachable path was unsatisfiable
+    pub fn all_unsat(&self) -> bool {
+        self.all_unsat.load(Ordering::Acquire)
+    }
+
+    /// True if any path assumed an unknown solver result was sat or
+    /// unsat, in which case [AllUnsat::all_unsat] is only true under
+    /// that assumption.
+    pub fn assumed_unknown(&self) -> bool {
+        self.assumed_unknown.load(Ordering::Acquire)
+    }
+}
+
+impl Default for AllUnsat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// This `Collector` is used for boolean Sail functions. It records
+/// whether all reachable paths through the program are
+/// unsatisfiable, which implies that the function always returns
 /// true.
 pub fn all_unsat_collector<'ir, B: BV>(
     tid: usize,
@@ -1345,15 +1766,19 @@ pub fn all_unsat_collector<'ir, B: BV>(
     result: Result<(Val<B>, LocalFrame<'ir, B>), (ExecError, Backtrace)>,
     shared_state: &SharedState<'ir, B>,
     mut solver: Solver<B>,
-    collected: &AtomicBool,
+    collected: &AllUnsat,
 ) {
+    if solver.trace().to_vec().iter().any(|event| event.is_assume_unknown()) {
+        collected.assumed_unknown.store(true, Ordering::Release)
+    }
+    let collected = &collected.all_unsat;
     match result {
         Ok(value) => match value {
-            (Val::Symbolic(v), _) => {
+            (Val::Symbolic(v), frame) => {
                 use smtlib::Def::*;
                 use smtlib::Exp::*;
                 solver.add(Assert(Not(Box::new(Var(v)))));
-                if solver.check_sat() != SmtResult::Unsat {
+                if solver.check_sat(frame.source_loc()) != SmtResult::Unsat {
                     log_from!(tid, log::VERBOSE, "Got sat");
                     collected.store(false, Ordering::Release)
                 } else {
@@ -1403,7 +1828,7 @@ pub fn trace_collector<'ir, B: BV>(
         }
         Err((ExecError::Dead, _)) => (),
         Err((err, _)) => {
-            if solver.check_sat() == SmtResult::Sat {
+            if solver.check_sat(err.source_loc()) == SmtResult::Sat {
                 let model = Model::new(&solver);
                 collected.push(Err(format!("Error {:?}\n{:?}", err, model)))
             } else {
@@ -1428,7 +1853,7 @@ pub fn trace_value_collector<'ir, B: BV>(
         }
         Err((ExecError::Dead, _)) => (),
         Err((err, _)) => {
-            if solver.check_sat() == SmtResult::Sat {
+            if solver.check_sat(err.source_loc()) == SmtResult::Sat {
                 let model = Model::new(&solver);
                 collected.push(Err(format!("Error {:?}\n{:?}", err, model)))
             } else {
@@ -1480,3 +1905,538 @@ pub fn footprint_collector<'ir, B: BV>(
         Err((err, _)) => collected.push(Err(format!("Error {:?}", err))),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitvector::b64::B64;
+    use crate::error::MalformedState;
+    use crate::init::{initialize_architecture, initialize_architecture_with_primops, Initialized};
+    use crate::primop::Primops;
+    use crate::simplify;
+    use crate::test_util::{isa_config, parse_ir};
+
+    static IR: &str = r#"
+register zR : %bool
+
+val zbad : (%bool) -> %bool
+
+fn zbad(zb) {
+  end
+}
+
+val zmain : (%bool) -> %bool
+
+fn zmain(zb) {
+  jump zb goto 3 ``;
+  return = true;
+  end;
+  return = zbad(zb) ``;
+  end
+}
+
+val zite = "ite" : (%bool, %i, %i) -> %i
+
+val zzeros = "zeros" : (%i) -> %bv
+
+val zlength : (%bool) -> %bv
+
+fn zlength(zb) {
+  zn : %i ``;
+  zn = zite(zb, 8 : %i128, 16 : %i128) ``;
+  return = zzeros(zn) ``;
+  end
+}
+
+union zoption {
+  zSome : %bv8,
+  zNone : %unit
+}
+
+val zopt : (%union zoption) -> %bv8
+
+fn zopt(zx) {
+  jump zx is zSome goto 3 ``;
+  return = zx as zSome;
+  end;
+  return = 0x00;
+  end
+}
+
+val zadd = "add_int" : (%i, %i) -> %i
+
+val zlen : (%list(%bv8)) -> %i
+
+fn zlen(zl) {
+  jump @eq(zl, NULL) goto 5 ``;
+  zn : %i ``;
+  zn = zlen(@tl(zl)) ``;
+  return = zadd(zn, 1 : %i128) ``;
+  end;
+  return = 0 : %i128;
+  end
+}
+
+val zcons = "cons" : (%bv8, %list(%bv8)) -> %list(%bv8)
+
+val zhead_one : (%list(%bv8)) -> %bv8
+
+fn zhead_one(zl) {
+  zm : %list(%bv8) ``;
+  zm = zcons(0x01, NULL) ``;
+  jump @eq(zl, zm) goto 5 ``;
+  return = 0x00;
+  end;
+  jump @eq(@hd(zl), 0x01) goto 8 ``;
+  return = 0xFF;
+  end;
+  return = 0x01;
+  end
+}
+
+union ztree {
+  zLeaf : %unit,
+  zNode : %union ztree
+}
+
+val zleaf : (%union ztree) -> %bool
+
+fn zleaf(zt) {
+  jump zt is zLeaf goto 3 ``;
+  return = true;
+  end;
+  return = false;
+  end
+}
+
+val zstartswith = "string_startswith" : (%string, %string) -> %bool
+
+val zdrop = "string_drop" : (%string, %i) -> %string
+
+val zeq_string = "eq_string" : (%string, %string) -> %bool
+
+val zmnemonic : (%string) -> %string
+
+fn zmnemonic(zs) {
+  zb : %bool ``;
+  zb = zstartswith(zs, "add ") ``;
+  jump zb goto 5 ``;
+  return = "none";
+  end;
+  zt : %string ``;
+  zt = zdrop(zs, 4 : %i128) ``;
+  zb = zeq_string(zt, "x1") ``;
+  jump zb goto 11 ``;
+  return = "other";
+  end;
+  return = zs;
+  end
+}
+
+val zprint_int = "print_int" : (%string, %i) -> %unit
+
+val zgreet : (%bool) -> %unit
+
+fn zgreet(zb) {
+  zn : %i ``;
+  jump zb goto 4 ``;
+  zn = 1 : %i128;
+  goto 5;
+  zn = 2 : %i128;
+  return = zprint_int("n = ", zn) ``;
+  end
+}
+
+val zread_mem = "platform_read_mem" : (%unit, %i, %bv64, %i) -> %bv8
+
+val zwatched : (%bool) -> %bv8
+
+fn zwatched(zb) {
+  jump zb goto 3 ``;
+  return = zread_mem((), 64 : %i128, 0x0000000000001000, 1 : %i128) ``;
+  end;
+  return = zread_mem((), 64 : %i128, 0x0000000000002000, 1 : %i128) ``;
+  end
+}
+"#;
+
+    // Uses a primop which is not built in to isla-lib
+    static PEEK_IR: &str = r#"
+register zR : %bool
+
+val zpeek = "platform_peek" : (%bv64) -> %bv8
+
+val zpeek_test : (%unit) -> %bv8
+
+fn zpeek_test(zu) {
+  return = zpeek(0x0000000000001000) ``;
+  end
+}
+"#;
+
+    #[test]
+    fn malformed_path_is_reported() {
+        let defs = parse_ir(IR);
+        let mut symtab = Symtab::new();
+        let mut arch = symtab.intern_defs(&defs);
+        let isa_config = isa_config(&symtab, "R", "{}");
+        let zmain = symtab.lookup("zmain");
+
+        let Initialized { regs, lets, shared_state } =
+            initialize_architecture(&mut arch, symtab, &isa_config, AssertionMode::Optimistic);
+
+        let (args, _, instrs) = shared_state.functions.get(&zmain).unwrap();
+        let task_state = TaskState::new();
+        let task = LocalFrame::new(zmain, args, None, instrs).add_lets(&lets).add_regs(&regs).task(0, &task_state);
+
+        let queue = SegQueue::new();
+        start_single(task, &shared_state, &queue, &|_, _, result, _, _, collected| match result {
+            Ok((value, _)) => collected.push(Ok(value)),
+            Err((ExecError::Malformed(state), _)) => collected.push(Err(state)),
+            Err((err, _)) => panic!("Unexpected error {:?}", err),
+        });
+
+        let mut ok = 0;
+        let mut malformed = 0;
+        while let Ok(result) = queue.pop() {
+            match result {
+                Ok(Val::Bool(true)) => ok += 1,
+                Ok(value) => panic!("Unexpected value {:?}", value),
+                Err(state) => {
+                    let MalformedState { reason, function, pc, backtrace, .. } = *state;
+                    assert!(matches!(reason, Malformed::NoReturnValue));
+                    assert_eq!(function.as_deref(), Some("bad"));
+                    assert_eq!(pc, 0);
+                    assert_eq!(backtrace, vec![("main".to_string(), 3)]);
+                    malformed += 1
+                }
+            }
+        }
+        assert_eq!((ok, malformed), (1, 1))
+    }
+
+    fn run_length(length_cases: Option<usize>) -> Vec<Result<Val<B64>, ExecError>> {
+        let defs = parse_ir(IR);
+        let mut symtab = Symtab::new();
+        let mut arch = symtab.intern_defs(&defs);
+        let mut isa_config = isa_config(&symtab, "R", "{}");
+        isa_config.solver_options.length_cases = length_cases;
+        let zlength = symtab.lookup("zlength");
+
+        let Initialized { regs, lets, shared_state } =
+            initialize_architecture(&mut arch, symtab, &isa_config, AssertionMode::Optimistic);
+
+        let (args, _, instrs) = shared_state.functions.get(&zlength).unwrap();
+        let task_state = TaskState::new();
+        let task = LocalFrame::new(zlength, args, None, instrs).add_lets(&lets).add_regs(&regs).task(0, &task_state);
+
+        let queue = SegQueue::new();
+        start_single(task, &shared_state, &queue, &|_, _, result, _, _, collected| {
+            collected.push(result.map(|(value, _)| value).map_err(|(err, _)| err))
+        });
+
+        let mut results = Vec::new();
+        while let Ok(result) = queue.pop() {
+            results.push(result)
+        }
+        results
+    }
+
+    #[test]
+    fn symbolic_length_forks() {
+        let results = run_length(None);
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results[0], Err(ExecError::SymbolicLength("zeros", _))));
+
+        let mut lengths: Vec<u32> = run_length(Some(2))
+            .into_iter()
+            .map(|result| match result {
+                Ok(Val::Bits(bv)) if bv.is_zero() => bv.len(),
+                result => panic!("Unexpected result {:?}", result),
+            })
+            .collect();
+        lengths.sort_unstable();
+        assert_eq!(lengths, vec![8, 16]);
+
+        let results = run_length(Some(1));
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results[0], Err(ExecError::SymbolicLength("zeros", _))))
+    }
+
+    fn run_symbolic_arg(f: &str) -> Vec<Val<B64>> {
+        let defs = parse_ir(IR);
+        let mut symtab = Symtab::new();
+        let mut arch = symtab.intern_defs(&defs);
+        let isa_config = isa_config(&symtab, "R", "{}");
+        let f = symtab.lookup(f);
+
+        let Initialized { regs, lets, shared_state } =
+            initialize_architecture(&mut arch, symtab, &isa_config, AssertionMode::Optimistic);
+
+        let (args, _, instrs) = shared_state.functions.get(&f).unwrap();
+        let task_state = TaskState::new();
+        let task = LocalFrame::new(f, args, None, instrs).add_lets(&lets).add_regs(&regs).task(0, &task_state);
+
+        let queue = SegQueue::new();
+        start_single(task, &shared_state, &queue, &|_, _, result, _, _, collected| match result {
+            Ok((value, _)) => collected.push(value),
+            Err((err, _)) => panic!("Unexpected error {:?}", err),
+        });
+
+        let mut results = Vec::new();
+        while let Ok(value) = queue.pop() {
+            results.push(value)
+        }
+        results
+    }
+
+    #[test]
+    fn symbolic_union_forks_per_constructor() {
+        let results = run_symbolic_arg("zopt");
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().any(|v| matches!(v, Val::Symbolic(_))));
+        assert!(results.iter().any(|v| matches!(v, Val::Bits(bv) if bv.is_zero())))
+    }
+
+    #[test]
+    fn symbolic_list_forks_per_length() {
+        let mut lengths: Vec<i128> = run_symbolic_arg("zlen")
+            .into_iter()
+            .map(|v| match v {
+                Val::I128(n) => n,
+                v => panic!("Unexpected result {:?}", v),
+            })
+            .collect();
+        lengths.sort_unstable();
+        assert_eq!(lengths, (0..=SYMBOLIC_LIST_LENGTH as i128).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn symbolic_list_equality() {
+        let mut results: Vec<u64> = run_symbolic_arg("zhead_one")
+            .into_iter()
+            .map(|v| match v {
+                Val::Bits(bv) => bv.lower_u64(),
+                v => panic!("Unexpected result {:?}", v),
+            })
+            .collect();
+        results.sort_unstable();
+        assert_eq!(results, vec![0x00, 0x01])
+    }
+
+    #[test]
+    fn symbolic_recursive_union() {
+        let mut results: Vec<bool> = run_symbolic_arg("zleaf")
+            .into_iter()
+            .map(|v| match v {
+                Val::Bool(b) => b,
+                v => panic!("Unexpected result {:?}", v),
+            })
+            .collect();
+        results.sort_unstable();
+        assert_eq!(results, vec![false, true])
+    }
+
+    #[test]
+    fn symbolic_strings() {
+        let defs = parse_ir(IR);
+        let mut symtab = Symtab::new();
+        let mut arch = symtab.intern_defs(&defs);
+        let mut isa_config = isa_config(&symtab, "R", "{}");
+        isa_config.solver_options.symbolic_strings = true;
+        let zmnemonic = symtab.lookup("zmnemonic");
+
+        let Initialized { regs, lets, shared_state } =
+            initialize_architecture(&mut arch, symtab, &isa_config, AssertionMode::Optimistic);
+
+        let (args, _, instrs) = shared_state.functions.get(&zmnemonic).unwrap();
+        let task_state = TaskState::new();
+        let task = LocalFrame::new(zmnemonic, args, None, instrs).add_lets(&lets).add_regs(&regs).task(0, &task_state);
+
+        let queue = SegQueue::new();
+        start_single(task, &shared_state, &queue, &|_, _, result, _, mut solver, collected| match result {
+            Ok((Val::String(s), _)) => collected.push(s),
+            Ok((Val::Symbolic(v), _)) => {
+                assert!(solver.check_sat(SourceLoc::unknown()) == SmtResult::Sat);
+                match Model::new(&solver).get_var(v) {
+                    Ok(Some(smtlib::Exp::String(s))) => collected.push(s),
+                    model => panic!("Unexpected model value {:?}", model),
+                }
+            }
+            Ok((value, _)) => panic!("Unexpected value {:?}", value),
+            Err((err, _)) => panic!("Unexpected error {:?}", err),
+        });
+
+        let mut results = Vec::new();
+        while let Ok(s) = queue.pop() {
+            results.push(s)
+        }
+        results.sort();
+        assert_eq!(results, vec!["add x1", "none", "other"])
+    }
+
+    #[test]
+    fn print_output_per_path() {
+        let defs = parse_ir(IR);
+        let mut symtab = Symtab::new();
+        let mut arch = symtab.intern_defs(&defs);
+        let isa_config = isa_config(&symtab, "R", "{}");
+        let zgreet = symtab.lookup("zgreet");
+
+        let Initialized { regs, lets, shared_state } =
+            initialize_architecture(&mut arch, symtab, &isa_config, AssertionMode::Optimistic);
+
+        let (args, _, instrs) = shared_state.functions.get(&zgreet).unwrap();
+        let task_state = TaskState::new();
+        let task = LocalFrame::new(zgreet, args, None, instrs).add_lets(&lets).add_regs(&regs).task(0, &task_state);
+
+        let queue = SegQueue::new();
+        start_single(task, &shared_state, &queue, &|_, _, result, shared_state, solver, collected| {
+            assert!(result.is_ok());
+            let events: Vec<&Event<B64>> = solver.trace().to_vec().drain(..).rev().collect();
+            assert!(events.iter().any(|event| matches!(event, Event::Print { stderr: false, .. })));
+            collected.push(simplify::render_output(&events, &shared_state.symtab, None))
+        });
+
+        let mut results = Vec::new();
+        while let Ok(output) = queue.pop() {
+            results.push(output)
+        }
+        results.sort();
+        assert_eq!(results, vec!["n = 1\n", "n = 2\n"])
+    }
+
+    #[test]
+    fn watch_reports_path() {
+        let defs = parse_ir(IR);
+        let mut symtab = Symtab::new();
+        let mut arch = symtab.intern_defs(&defs);
+        let mut isa_config = isa_config(&symtab, "R", "{}");
+        isa_config.watchpoints.push("0x1000:r".parse().unwrap());
+        let zwatched = symtab.lookup("zwatched");
+
+        let Initialized { regs, lets, shared_state } =
+            initialize_architecture(&mut arch, symtab, &isa_config, AssertionMode::Optimistic);
+
+        let (args, _, instrs) = shared_state.functions.get(&zwatched).unwrap();
+        let task_state = TaskState::new();
+        let task = LocalFrame::new(zwatched, args, None, instrs).add_lets(&lets).add_regs(&regs).task(0, &task_state);
+
+        let queue = SegQueue::new();
+        start_single(task, &shared_state, &queue, &|_, _, result, _, solver, collected| {
+            assert!(result.is_ok());
+            for event in solver.trace().to_vec() {
+                if let Event::Watch(report) = event {
+                    collected.push(report.path.to_string())
+                }
+            }
+        });
+
+        // Only the path that did not take the jump reads the watched address
+        let mut results = Vec::new();
+        while let Ok(path) = queue.pop() {
+            results.push(path)
+        }
+        assert_eq!(results, vec!["1"])
+    }
+
+    fn peek<B: BV>(
+        args: Vec<Val<B>>,
+        _: &mut Solver<B>,
+        frame: &mut LocalFrame<B>,
+        info: SourceLoc,
+    ) -> Result<Val<B>, ExecError> {
+        match &args[0] {
+            Val::Bits(addr) => frame.memory().read_initial(addr.lower_u64(), 1),
+            addr => Err(ExecError::Type(format!("platform_peek {:?}", addr), info)),
+        }
+    }
+
+    fn run_peek(primops: &Primops<B64>) {
+        let defs = parse_ir(PEEK_IR);
+        let mut symtab = Symtab::new();
+        let mut arch = symtab.intern_defs(&defs);
+        let isa_config = isa_config(&symtab, "R", "{}");
+        let zpeek_test = symtab.lookup("zpeek_test");
+
+        let Initialized { regs, lets, shared_state } =
+            initialize_architecture_with_primops(&mut arch, symtab, &isa_config, AssertionMode::Optimistic, primops);
+
+        let (args, _, instrs) = shared_state.functions.get(&zpeek_test).unwrap();
+        let task_state = TaskState::new();
+        let mut frame = LocalFrame::new(zpeek_test, args, Some(&[Val::Unit]), instrs);
+        frame.add_lets(&lets).add_regs(&regs);
+        frame.memory_mut().add_concrete_region(0x1000..0x1001, vec![(0x1000, 0x2a)].into_iter().collect());
+        let task = frame.task(0, &task_state);
+
+        let queue = SegQueue::new();
+        start_single(task, &shared_state, &queue, &|_, _, result, _, _, collected| {
+            collected.push(result.map(|(value, _)| value).map_err(|(err, _)| err))
+        });
+
+        match queue.pop() {
+            Ok(Ok(Val::Bits(bv))) => assert_eq!(bv, B64::new(0x2a, 8)),
+            result => panic!("Unexpected result {:?}", result),
+        }
+        assert!(queue.is_empty())
+    }
+
+    #[test]
+    fn user_defined_primop() {
+        let mut primops = Primops::default();
+        primops.register_unary("platform_peek", |_, _, _| Ok(Val::Unit));
+        primops.register_variadic("platform_peek", peek);
+        assert!(!primops.unary.contains_key("platform_peek"));
+        assert!(primops.contains("platform_peek"));
+        run_peek(&primops)
+    }
+
+    #[test]
+    fn closure_primop() {
+        use std::sync::atomic::AtomicUsize;
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut primops = Primops::default();
+        let closure_calls = calls.clone();
+        primops.register_closure("platform_peek", move |args, solver, frame, info| {
+            closure_calls.fetch_add(1, Ordering::SeqCst);
+            peek(args, solver, frame, info)
+        });
+        assert!(primops.contains("platform_peek"));
+        run_peek(&primops);
+        assert_eq!(calls.load(Ordering::SeqCst), 1)
+    }
+
+    #[test]
+    fn closure_watchpoint() {
+        let mut primops = Primops::default();
+        primops.register_closure("platform_peek", |args, solver, frame, _| {
+            frame.memory_mut().read(Val::Unit, args[0].clone(), Val::I128(1), solver, false)
+        });
+
+        let defs = parse_ir(PEEK_IR);
+        let mut symtab = Symtab::new();
+        let mut arch = symtab.intern_defs(&defs);
+        let mut isa_config = isa_config(&symtab, "R", "{}");
+        isa_config.watchpoints.push("0x1000:r".parse().unwrap());
+        let zpeek_test = symtab.lookup("zpeek_test");
+
+        let Initialized { regs, lets, shared_state } =
+            initialize_architecture_with_primops(&mut arch, symtab, &isa_config, AssertionMode::Optimistic, &primops);
+
+        let (args, _, instrs) = shared_state.functions.get(&zpeek_test).unwrap();
+        let task_state = TaskState::new();
+        let task = LocalFrame::new(zpeek_test, args, Some(&[Val::Unit]), instrs)
+            .add_lets(&lets)
+            .add_regs(&regs)
+            .task(0, &task_state);
+
+        // Memory accesses made by closures are checked against the watchpoints
+        let queue = SegQueue::new();
+        start_single(task, &shared_state, &queue, &|_, _, result, _, solver, collected| {
+            assert!(result.is_ok());
+            collected.push(solver.trace().to_vec().iter().filter(|event| matches!(event, Event::Watch(_))).count())
+        });
+        assert_eq!(queue.pop(), Ok(1))
+    }
+}
@@ -50,6 +50,10 @@
 //!
 //! * Finally use the [initialize_architecture] function in this
 //! module to set up everything ready for symbolic execution.
+//!
+//! If the architecture needs primops which are not part of this
+//! library, they can be registered with [Primops] and passed to
+//! [initialize_architecture_with_primops] instead.
 
 use std::collections::HashMap;
 use std::sync::Mutex;
@@ -59,6 +63,7 @@ use crate::config::ISAConfig;
 use crate::executor::{start_single, LocalFrame, TaskState};
 use crate::ir::*;
 use crate::log;
+use crate::primop::Primops;
 use crate::zencode;
 
 fn initialize_letbindings<'ir, B: BV>(
@@ -132,9 +137,22 @@ pub fn initialize_architecture<'ir, B: BV>(
     symtab: Symtab<'ir>,
     isa_config: &ISAConfig<B>,
     mode: AssertionMode,
+) -> Initialized<'ir, B> {
+    initialize_architecture_with_primops(arch, symtab, isa_config, mode, &Primops::default())
+}
+
+/// Like [initialize_architecture], but using the supplied primops,
+/// which can include additional user-defined primops registered
+/// using the methods on [Primops].
+pub fn initialize_architecture_with_primops<'ir, B: BV>(
+    arch: &'ir mut [Def<Name, B>],
+    symtab: Symtab<'ir>,
+    isa_config: &ISAConfig<B>,
+    mode: AssertionMode,
+    primops: &Primops<B>,
 ) -> Initialized<'ir, B> {
     insert_monomorphize(arch);
-    insert_primops(arch, mode);
+    insert_primops(arch, mode, primops);
 
     let regs = initialize_register_state(arch, &isa_config.default_registers, &symtab);
     let lets = Mutex::new(HashMap::new());
@@ -142,9 +160,12 @@ pub fn initialize_architecture<'ir, B: BV>(
         symtab,
         arch,
         isa_config.probes.clone(),
+        isa_config.watchpoints.clone(),
         isa_config.trace_functions.clone(),
         isa_config.reset_registers.clone(),
         isa_config.reset_constraints.clone(),
+        isa_config.solver_options.clone(),
+        primops,
     );
 
     initialize_letbindings(arch, &shared_state, &regs, &lets);
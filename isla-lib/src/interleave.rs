@@ -0,0 +1,540 @@
+// BSD 2-Clause License
+//
+// Copyright (c) 2019, 2020 Alasdair Armstrong
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+// notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+// notice, this list of conditions and the following disclaimer in the
+// documentation and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! A driver which interleaves the execution of several hardware
+//! threads sequentially, one instruction at a time, exploring every
+//! possible schedule. Each thread has its own registers, but memory
+//! is shared between them. For writes to be seen by the other
+//! threads, memory is put in sequential mode (see
+//! [Memory::set_sequential]), so writes to symbolic addresses are
+//! errors.
+//!
+//! The [Event::SleepRequest] and [Event::WakeupRequest] events
+//! produced by the sleep_request and wakeup_request builtins are used
+//! to model WFI/WFE-style low-power states. A thread which requests
+//! to sleep is not scheduled again until another thread requests a
+//! wakeup. The Sail `sleeping` builtin returns true when the thread
+//! being stepped is sleeping, which can only happen if
+//! [InterleaveOpts::step_sleeping] is set. This allows testing the
+//! sleep and event register behaviour of a specification without the
+//! full axiomatic machinery.
+//!
+//! The cycle of each step is the number of [Event::Cycle] events in
+//! the trace so far. The `get_cycle_count` builtin starts each step
+//! from the cycle at which the step starts, so it agrees with
+//! [Step::cycle]. The counter is not restored from a checkpoint, so
+//! if a step forks after a cycle, that cycle is not counted on the
+//! forked paths.
+//!
+//! This module is only a library interface, and is not used by any
+//! of the isla tools.
+
+use crossbeam::queue::SegQueue;
+
+use crate::bitvector::BV;
+use crate::error::{ExecError, Malformed};
+use crate::executor::{start_single, LocalFrame, TaskState};
+use crate::ir::source_loc::SourceLoc;
+use crate::ir::*;
+use crate::memory::Memory;
+use crate::smt;
+use crate::smt::{Checkpoint, Event};
+use crate::zencode;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ThreadStatus {
+    Running,
+    Sleeping,
+    Finished,
+}
+
+#[derive(Clone, Debug)]
+pub struct InterleaveOpts {
+    /// The maximum number of instructions each thread executes
+    pub max_steps: usize,
+    /// If true, a wakeup request received by a thread which is not
+    /// sleeping is remembered, and its next sleep request returns
+    /// immediately (like the Arm event register for WFE). Otherwise
+    /// the wakeup is lost (like WFI).
+    pub latch_wakeups: bool,
+    /// If true, sleeping threads are still stepped (until they reach
+    /// `max_steps`), so the step function can use the `sleeping`
+    /// builtin to decide what to do, for example waking up on a
+    /// pending interrupt. Otherwise sleeping threads are only woken
+    /// by a wakeup request from another thread.
+    pub step_sleeping: bool,
+}
+
+impl Default for InterleaveOpts {
+    fn default() -> Self {
+        InterleaveOpts { max_steps: 10, latch_wakeups: true, step_sleeping: false }
+    }
+}
+
+/// A single instruction executed by one thread in an interleaving.
+#[derive(Clone, Debug)]
+pub struct Step<B> {
+    pub thread: usize,
+    /// The value of the cycle counter after the step
+    pub cycle: i128,
+    /// The events produced by the step, in execution order
+    pub events: Vec<Event<B>>,
+}
+
+/// The result of one complete interleaving, or a partial
+/// interleaving that stopped with an error.
+pub struct Interleaving<B> {
+    pub timeline: Vec<Step<B>>,
+    pub status: Vec<ThreadStatus>,
+    pub checkpoint: Checkpoint<B>,
+}
+
+/// A path which raises an error is returned along with the partial
+/// interleaving leading up to it.
+pub type InterleaveResult<B> = Result<Interleaving<B>, (Interleaving<B>, ExecError)>;
+
+#[derive(Clone)]
+struct ThreadState<'ir, B> {
+    regs: Bindings<'ir, B>,
+    status: ThreadStatus,
+    steps: usize,
+    wakeup_pending: bool,
+}
+
+#[derive(Clone)]
+struct State<'ir, B> {
+    threads: Vec<ThreadState<'ir, B>>,
+    memory: Memory<B>,
+    checkpoint: Checkpoint<B>,
+    timeline: Vec<Step<B>>,
+}
+
+impl<'ir, B: BV> State<'ir, B> {
+    fn interleaving(self) -> Interleaving<B> {
+        Interleaving {
+            timeline: self.timeline,
+            status: self.threads.iter().map(|thread| thread.status).collect(),
+            checkpoint: self.checkpoint,
+        }
+    }
+
+    /// Update the thread states using the sleep and wakeup requests
+    /// made by a step of thread `tid`.
+    fn apply_events(&mut self, tid: usize, events: &[Event<B>], latch_wakeups: bool) {
+        for event in events {
+            match event {
+                Event::SleepRequest => {
+                    let thread = &mut self.threads[tid];
+                    if thread.wakeup_pending {
+                        thread.wakeup_pending = false
+                    } else {
+                        thread.status = ThreadStatus::Sleeping
+                    }
+                }
+                Event::WakeupRequest => {
+                    for thread in self.threads.iter_mut() {
+                        if thread.status == ThreadStatus::Sleeping {
+                            thread.status = ThreadStatus::Running
+                        } else if latch_wakeups && thread.status == ThreadStatus::Running {
+                            thread.wakeup_pending = true
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+/// All the events in a checkpoint's trace, newest first.
+fn trace_events<B: BV>(checkpoint: &Checkpoint<B>) -> Vec<&Event<B>> {
+    checkpoint.trace().as_ref().map(|trace| trace.to_vec()).unwrap_or_default()
+}
+
+type StepQueue<'ir, B> = SegQueue<(Result<(Val<B>, LocalFrame<'ir, B>), ExecError>, Checkpoint<B>)>;
+
+/// Execute every interleaving of the given threads, where each step
+/// calls the function `step` (which should take a single unit
+/// argument and execute one instruction) for a thread which is
+/// running, or sleeping if `opts.step_sleeping` is set. A running
+/// thread finishes after `opts.max_steps` steps, or when `step`
+/// returns false. Each thread starts with the given registers, and
+/// execution starts with the given memory and solver checkpoint. The
+/// memory is put in sequential mode if it is not already.
+///
+/// Paths which are found to be unreachable are discarded. An error is
+/// returned if `step` is not a function in the architecture.
+pub fn interleave<'ir, B: BV>(
+    step: Name,
+    threads: Vec<Bindings<'ir, B>>,
+    lets: &Bindings<'ir, B>,
+    mut memory: Memory<B>,
+    checkpoint: Checkpoint<B>,
+    shared_state: &SharedState<'ir, B>,
+    opts: &InterleaveOpts,
+) -> Result<Vec<InterleaveResult<B>>, ExecError> {
+    let (args, _, instrs) = match shared_state.functions.get(&step) {
+        Some(function) => function,
+        None => {
+            let symbol = zencode::decode(shared_state.symtab.to_str(step));
+            return Err(ExecError::malformed(Malformed::NoSuchFunction(symbol), SourceLoc::unknown()));
+        }
+    };
+
+    if !memory.is_sequential() {
+        memory.set_sequential(true)
+    }
+
+    let threads = threads
+        .into_iter()
+        .map(|regs| ThreadState { regs, status: ThreadStatus::Running, steps: 0, wakeup_pending: false })
+        .collect();
+    let mut stack = vec![State { threads, memory, checkpoint, timeline: Vec::new() }];
+    let mut interleavings = Vec::new();
+
+    while let Some(state) = stack.pop() {
+        let runnable: Vec<usize> = (0..state.threads.len())
+            .filter(|tid| {
+                let thread = &state.threads[*tid];
+                match thread.status {
+                    ThreadStatus::Running => true,
+                    ThreadStatus::Sleeping => opts.step_sleeping && thread.steps < opts.max_steps,
+                    ThreadStatus::Finished => false,
+                }
+            })
+            .collect();
+        if runnable.is_empty() {
+            interleavings.push(Ok(state.interleaving()));
+            continue;
+        }
+
+        let before = trace_events(&state.checkpoint);
+        let events_before = before.len();
+        let cycles_before = before.iter().filter(|event| event.is_cycle()).count() as i128;
+
+        for tid in runnable {
+            let mut task_state = TaskState::new();
+            task_state.set_sleeping(state.threads[tid].status == ThreadStatus::Sleeping).set_cycle_count(cycles_before);
+
+            let mut frame = LocalFrame::new(step, args, Some(&[Val::Unit]), instrs);
+            frame.add_lets(lets).add_regs(&state.threads[tid].regs).set_memory(state.memory.clone());
+            let task = frame.task_with_checkpoint(0, &task_state, state.checkpoint.clone());
+
+            let queue: StepQueue<'ir, B> = SegQueue::new();
+            start_single(task, shared_state, &queue, &|_, _, result, _, mut solver, collected| match result {
+                Err((ExecError::Dead, _)) => (),
+                Ok(result) => collected.push((Ok(result), smt::checkpoint(&mut solver))),
+                Err((err, _)) => collected.push((Err(err), smt::checkpoint(&mut solver))),
+            });
+
+            while let Ok((result, checkpoint)) = queue.pop() {
+                let mut next = state.clone();
+                let all_events = trace_events(&checkpoint);
+                let cycle = all_events.iter().filter(|event| event.is_cycle()).count() as i128;
+                let events: Vec<Event<B>> =
+                    all_events[..all_events.len() - events_before].iter().rev().map(|event| (*event).clone()).collect();
+
+                match result {
+                    Ok((value, frame)) => {
+                        next.apply_events(tid, &events, opts.latch_wakeups);
+                        next.memory = frame.memory().clone();
+                        let thread = &mut next.threads[tid];
+                        thread.regs = frame.regs().clone();
+                        thread.steps += 1;
+                        if thread.status == ThreadStatus::Running
+                            && (thread.steps >= opts.max_steps || matches!(value, Val::Bool(false)))
+                        {
+                            thread.status = ThreadStatus::Finished
+                        }
+                        next.timeline.push(Step { thread: tid, cycle, events });
+                        next.checkpoint = checkpoint;
+                        stack.push(next)
+                    }
+                    Err(err) => {
+                        next.timeline.push(Step { thread: tid, cycle, events });
+                        next.checkpoint = checkpoint;
+                        interleavings.push(Err((next.interleaving(), err)))
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(interleavings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitvector::b64::B64;
+    use crate::init::{initialize_architecture, Initialized};
+    use crate::test_util::{isa_config, parse_ir};
+
+    // Thread 0 sleeps on its first step and finishes on the next,
+    // while thread 1 sends a wakeup and finishes.
+    static IR: &str = r#"
+register zR : %bool
+
+register zwaker : %bool
+
+register zslept : %bool
+
+val zcycle_count = "cycle_count" : (%unit) -> %unit
+
+val zsleep_request = "sleep_request" : (%unit) -> %unit
+
+val zwakeup_request = "wakeup_request" : (%unit) -> %unit
+
+val zstep : (%unit) -> %bool
+
+fn zstep(zu) {
+  zu = zcycle_count(()) ``;
+  jump zwaker goto 7 ``;
+  jump zslept goto 8 ``;
+  zslept = true;
+  zu = zsleep_request(()) ``;
+  return = true;
+  end;
+  zu = zwakeup_request(()) ``;
+  return = false;
+  end
+}
+
+val zsleeping = "sleeping" : (%unit) -> %bool
+
+val zsleepy_step : (%unit) -> %bool
+
+fn zsleepy_step(zu) {
+  zb : %bool ``;
+  zb = zsleeping(()) ``;
+  jump zb goto 5 ``;
+  zu = zsleep_request(()) ``;
+  goto 6;
+  zslept = true;
+  return = true;
+  end
+}
+
+register zwriter : %bool
+
+register zseen : %bv8
+
+register zcycles : %i
+
+val zget_cycle_count = "get_cycle_count" : (%unit) -> %i
+
+val zread_mem = "platform_read_mem" : (%unit, %i, %bv64, %i) -> %bv8
+
+val zwrite_mem = "platform_write_mem" : (%unit, %i, %bv64, %i, %bv8) -> %bool
+
+val zshared_step : (%unit) -> %bool
+
+fn zshared_step(zu) {
+  zb : %bool ``;
+  zu = zcycle_count(()) ``;
+  zcycles = zget_cycle_count(()) ``;
+  jump zwriter goto 7 ``;
+  zseen = zread_mem((), 64 : %i128, 0x0000000000001000, 1 : %i128) ``;
+  return = false;
+  end;
+  zb = zwrite_mem((), 64 : %i128, 0x0000000000001000, 1 : %i128, 0x2A) ``;
+  return = false;
+  end
+}
+"#;
+
+    fn schedules(latch_wakeups: bool) -> Vec<(Vec<usize>, Vec<i128>, Vec<ThreadStatus>)> {
+        let defs = parse_ir(IR);
+        let mut symtab = Symtab::new();
+        let mut arch = symtab.intern_defs(&defs);
+        let isa_config = isa_config(&symtab, "R", "{}");
+        let zstep = symtab.lookup("zstep");
+        let zwaker = symtab.lookup("zwaker");
+        let zslept = symtab.lookup("zslept");
+
+        let Initialized { regs, lets, shared_state } =
+            initialize_architecture(&mut arch, symtab, &isa_config, AssertionMode::Optimistic);
+
+        let threads = [false, true]
+            .iter()
+            .map(|waker| {
+                let mut regs = regs.clone();
+                regs.insert(zwaker, UVal::Init(Val::Bool(*waker)));
+                regs.insert(zslept, UVal::Init(Val::Bool(false)));
+                regs
+            })
+            .collect();
+
+        let opts = InterleaveOpts { latch_wakeups, ..InterleaveOpts::default() };
+        let mut schedules: Vec<(Vec<usize>, Vec<i128>, Vec<ThreadStatus>)> =
+            interleave(zstep, threads, &lets, Memory::new(), Checkpoint::new(), &shared_state, &opts)
+                .unwrap()
+                .drain(..)
+                .map(|result| match result {
+                    Ok(interleaving) => {
+                        assert!(interleaving.timeline.iter().all(|step| step.events.iter().any(Event::is_cycle)));
+                        (
+                            interleaving.timeline.iter().map(|step| step.thread).collect(),
+                            interleaving.timeline.iter().map(|step| step.cycle).collect(),
+                            interleaving.status,
+                        )
+                    }
+                    Err((_, err)) => panic!("Unexpected error {:?}", err),
+                })
+                .collect();
+        schedules.sort_by(|a, b| a.0.cmp(&b.0));
+        schedules
+    }
+
+    #[test]
+    fn sleep_and_wakeup() {
+        use ThreadStatus::*;
+        assert_eq!(
+            schedules(true),
+            vec![
+                (vec![0, 1, 0], vec![1, 2, 3], vec![Finished, Finished]),
+                (vec![1, 0, 0], vec![1, 2, 3], vec![Finished, Finished])
+            ]
+        );
+        // Without latching, a wakeup sent before the other thread
+        // sleeps is lost
+        assert_eq!(
+            schedules(false),
+            vec![
+                (vec![0, 1, 0], vec![1, 2, 3], vec![Finished, Finished]),
+                (vec![1, 0], vec![1, 2], vec![Sleeping, Finished])
+            ]
+        );
+    }
+
+    #[test]
+    fn sleeping_status() {
+        let defs = parse_ir(IR);
+        let mut symtab = Symtab::new();
+        let mut arch = symtab.intern_defs(&defs);
+        let isa_config = isa_config(&symtab, "R", "{}");
+        let zsleepy_step = symtab.lookup("zsleepy_step");
+        let zslept = symtab.lookup("zslept");
+
+        let Initialized { mut regs, lets, shared_state } =
+            initialize_architecture(&mut arch, symtab, &isa_config, AssertionMode::Optimistic);
+        regs.insert(zslept, UVal::Init(Val::Bool(false)));
+
+        // Returns which steps saw the thread sleeping, and its final status
+        let run = |step_sleeping| {
+            let opts = InterleaveOpts { max_steps: 3, step_sleeping, ..InterleaveOpts::default() };
+            let mut results = interleave(
+                zsleepy_step,
+                vec![regs.clone()],
+                &lets,
+                Memory::new(),
+                Checkpoint::new(),
+                &shared_state,
+                &opts,
+            )
+            .unwrap();
+            assert_eq!(results.len(), 1);
+            match results.pop().unwrap() {
+                Ok(interleaving) => (
+                    interleaving.timeline.iter().map(|step| step.events.iter().any(Event::is_write_reg)).collect(),
+                    interleaving.status,
+                ),
+                Err((_, err)) => panic!("Unexpected error {:?}", err),
+            }
+        };
+
+        assert_eq!(run(false), (vec![false], vec![ThreadStatus::Sleeping]));
+        assert_eq!(run(true), (vec![false, true, true], vec![ThreadStatus::Sleeping]));
+
+        let opts = InterleaveOpts::default();
+        let result =
+            interleave(zslept, vec![regs.clone()], &lets, Memory::new(), Checkpoint::new(), &shared_state, &opts);
+        assert!(matches!(result, Err(ExecError::Malformed(_))))
+    }
+
+    #[test]
+    fn shared_memory_and_cycles() {
+        let defs = parse_ir(IR);
+        let mut symtab = Symtab::new();
+        let mut arch = symtab.intern_defs(&defs);
+        let isa_config = isa_config(&symtab, "R", "{}");
+        let zshared_step = symtab.lookup("zshared_step");
+        let zwriter = symtab.lookup("zwriter");
+        let zseen = symtab.lookup("zseen");
+        let zcycles = symtab.lookup("zcycles");
+
+        let Initialized { regs, lets, shared_state } =
+            initialize_architecture(&mut arch, symtab, &isa_config, AssertionMode::Optimistic);
+
+        let threads = [true, false]
+            .iter()
+            .map(|writer| {
+                let mut regs = regs.clone();
+                regs.insert(zwriter, UVal::Init(Val::Bool(*writer)));
+                regs
+            })
+            .collect();
+
+        let opts = InterleaveOpts::default();
+        let results =
+            interleave(zshared_step, threads, &lets, Memory::new(), Checkpoint::new(), &shared_state, &opts).unwrap();
+        assert_eq!(results.len(), 2);
+
+        let mut checked = 0;
+        for result in results {
+            let interleaving = match result {
+                Ok(interleaving) => interleaving,
+                Err((_, err)) => panic!("Unexpected error {:?}", err),
+            };
+            let schedule: Vec<usize> = interleaving.timeline.iter().map(|step| step.thread).collect();
+            for step in &interleaving.timeline {
+                for event in &step.events {
+                    match event {
+                        // get_cycle_count agrees with the cycle of the step
+                        Event::WriteReg(reg, _, value) if *reg == zcycles => {
+                            assert_eq!(*value, Val::I128(step.cycle));
+                            checked += 1
+                        }
+                        // The reader sees the write only if the writer went first
+                        Event::WriteReg(reg, _, value) if *reg == zseen => {
+                            if schedule == vec![0, 1] {
+                                assert_eq!(*value, Val::Bits(B64::new(0x2A, 8)))
+                            } else {
+                                assert!(matches!(value, Val::Symbolic(_)))
+                            }
+                            checked += 1
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+        assert_eq!(checked, 6)
+    }
+}
@@ -50,13 +50,18 @@ use std::sync::Arc;
 use crate::bitvector::{b64::B64, BV};
 use crate::error::ExecError;
 use crate::memory::Memory;
-use crate::primop::{Binary, Primops, Unary, Variadic};
-use crate::smt::{Solver, Sym};
+use crate::primop::{Binary, Closure, Primops, Unary, Variadic};
+use crate::smt::{Solver, SolverOptions, Sym};
+use crate::watch::Watchpoint;
 use crate::zencode;
 
+pub mod checker;
 pub mod linearize;
+pub mod optimize;
+pub mod pretty;
 pub mod serialize;
 pub mod source_loc;
+pub mod specialize;
 pub mod ssa;
 
 use source_loc::SourceLoc;
@@ -188,6 +193,14 @@ pub enum Val<B> {
     Enum(EnumMember),
     Struct(HashMap<Name, Val<B>>),
     Ctor(Name, Box<Val<B>>),
+    /// A union value with a symbolic constructor. The tag is an SMT
+    /// enumeration with a member for each constructor, in the order
+    /// they are given here, and each constructor has its own payload.
+    SymbolicCtor(Sym, Vec<(Name, Val<B>)>),
+    /// A list with a symbolic length, which is at most the number of
+    /// elements. As with [Val::List] the elements are stored in
+    /// reverse, so a list of length `n` is the last `n` elements.
+    SymbolicList(Sym, Vec<Val<B>>),
     Ref(Name),
     Poison,
 }
@@ -222,6 +235,14 @@ impl<B: BV> Val<B> {
             Vector(vals) | List(vals) => vals.iter().for_each(|val| val.collect_symbolic_variables(vars)),
             Struct(vals) => vals.iter().for_each(|(_, val)| val.collect_symbolic_variables(vars)),
             Ctor(_, val) => val.collect_symbolic_variables(vars),
+            SymbolicCtor(tag, ctors) => {
+                vars.insert(*tag);
+                ctors.iter().for_each(|(_, val)| val.collect_symbolic_variables(vars))
+            }
+            SymbolicList(len, vals) => {
+                vars.insert(*len);
+                vals.iter().for_each(|val| val.collect_symbolic_variables(vars))
+            }
         }
     }
 
@@ -309,6 +330,17 @@ impl<B: BV> Val<B> {
                 format!("(_ struct {})", fields)
             }
             Ctor(ctor, v) => format!("(|{}| {})", zencode::decode(symtab.to_str(*ctor)), v.to_string(symtab)),
+            SymbolicCtor(tag, ctors) => {
+                let ctors: Vec<_> = ctors
+                    .iter()
+                    .map(|(ctor, v)| format!("(|{}| {})", zencode::decode(symtab.to_str(*ctor)), v.to_string(symtab)))
+                    .collect();
+                format!("(_ union v{} {})", tag, ctors.join(" "))
+            }
+            SymbolicList(len, vec) => {
+                let vec: Vec<_> = vec.iter().map(|elem| elem.to_string(symtab)).collect();
+                format!("(_ list v{} {})", len, vec.join(" "))
+            }
             Ref(reg) => format!("(_ reg |{}|)", zencode::decode(symtab.to_str(*reg))),
             Poison => "(_ poison)".to_string(),
         }
@@ -338,6 +370,8 @@ impl<B: BV> Val<B> {
             (Val::Ctor(_, _), _) => Ok(()),            // TODO
             (Val::Ref(_), _) => Ok(()),                // TODO
             (Val::Poison, _) => Ok(()),
+            (Val::SymbolicCtor(_, _), Ty::Union(_)) => Ok(()),
+            (Val::SymbolicList(_, _), Ty::List(_)) => Ok(()),
             (_, _) => Err(format!("value {} doesn't appear to match type {:?}", self.to_string(symtab), ty)),
         }
     }
@@ -398,6 +432,31 @@ pub enum Exp<A> {
     Call(Op, Vec<Exp<A>>),
 }
 
+impl<A> Exp<A> {
+    /// Parse a hexadecimal (`0x`) or binary (`0b`) bitvector
+    /// literal. Literals are stored as [B64], so literals wider than
+    /// 64 bits become a concatenation of chunks of at most 64 bits,
+    /// most significant first.
+    pub fn bits_literal(literal: &str) -> Option<Self> {
+        let (prefix, digits, digits_per_chunk) = match literal.strip_prefix("0x") {
+            Some(digits) => ("0x", digits, 16),
+            None => ("0b", literal.strip_prefix("0b")?, 64),
+        };
+
+        let mut chunks = Vec::new();
+        let mut end = digits.len();
+        while end > 0 {
+            let start = end.saturating_sub(digits_per_chunk);
+            chunks.push(B64::from_str(&format!("{}{}", prefix, digits.get(start..end)?))?);
+            end = start
+        }
+
+        let mut chunks = chunks.drain(..).rev().map(Exp::Bits);
+        let first = chunks.next()?;
+        Some(chunks.fold(first, |higher, lower| Exp::Call(Op::Concat, vec![higher, lower])))
+    }
+}
+
 impl<A: Hash + Eq + Clone> Exp<A> {
     fn collect_ids(&self, ids: &mut HashSet<A>) {
         use Exp::*;
@@ -470,6 +529,25 @@ impl<A: fmt::Debug, B: fmt::Debug> fmt::Debug for Instr<A, B> {
     }
 }
 
+impl<A, B> Instr<A, B> {
+    /// The source location of an instruction, if it has one.
+    pub fn source_loc(&self) -> Option<SourceLoc> {
+        use Instr::*;
+        match self {
+            Decl(_, _, info)
+            | Init(_, _, _, info)
+            | Jump(_, _, info)
+            | Copy(_, _, info)
+            | Monomorphize(_, info)
+            | Call(_, _, _, _, info)
+            | PrimopUnary(_, _, _, info)
+            | PrimopBinary(_, _, _, _, info)
+            | PrimopVariadic(_, _, _, info) => Some(*info),
+            Goto(_) | Failure | Arbitrary | End => None,
+        }
+    }
+}
+
 /// Append instructions from rhs into the lhs vector, leaving rhs
 /// empty (the same behavior as `Vec::append`).
 pub fn append_instrs<A, B>(lhs: &mut Vec<Instr<A, B>>, rhs: &mut Vec<Instr<A, B>>) {
@@ -576,6 +654,10 @@ pub const BV_BIT_RIGHT: Name = Name { id: 16 };
 /// values according to the ISA config
 pub const RESET_REGISTERS: Name = Name { id: 17 };
 
+/// [SLEEPING] is a special function that returns true if the task is
+/// sleeping, as set by [crate::executor::TaskState::set_sleeping]
+pub const SLEEPING: Name = Name { id: 18 };
+
 static GENSYM: &str = "|GENSYM|";
 
 impl<'ir> Symtab<'ir> {
@@ -648,6 +730,7 @@ impl<'ir> Symtab<'ir> {
         symtab.intern("ztuplez3z5bv_z5bit0");
         symtab.intern("ztuplez3z5bv_z5bit1");
         symtab.intern("reset_registers");
+        symtab.intern("sleeping");
         symtab
     }
 
@@ -772,8 +855,10 @@ impl<'ir> Symtab<'ir> {
                 Struct(self.intern(s), fields)
             }
             Union(u, ctors) => {
+                // The union is interned first, as it may be recursive
+                let u = self.intern(u);
                 let ctors = ctors.iter().map(|(ctor, ty)| (self.intern(ctor), self.intern_ty(ty))).collect();
-                Union(self.intern(u), ctors)
+                Union(u, ctors)
             }
             Val(f, args, ret) => {
                 Val(self.intern(f), args.iter().map(|ty| self.intern_ty(ty)).collect(), self.intern_ty(ret))
@@ -843,10 +928,15 @@ pub struct SharedState<'ir, B> {
     pub enum_members: HashMap<Name, (usize, usize)>,
     /// `union_ctors` is a set of all union constructor identifiers
     pub union_ctors: HashSet<Name>,
+    /// `unions` maps each union identifier to its constructors and
+    /// their types, in the order they were declared
+    pub unions: HashMap<Name, Vec<(Name, Ty<Name>)>>,
     /// `registers` is a set of all registers and their types
     pub registers: HashMap<Name, Ty<Name>>,
     /// `probes` is a set of function/location identifers to print debug information for when called
     pub probes: HashSet<Name>,
+    /// `watchpoints` are ranges of memory for which accesses are reported
+    pub watchpoints: Vec<Watchpoint>,
     /// `trace_functions` defines a set of functions which we include
     /// in the traces as function call and return events
     pub trace_functions: HashSet<Name>,
@@ -856,16 +946,26 @@ pub struct SharedState<'ir, B> {
     /// `reset_constraints` are added as assertions at the reset_registers builtin
     /// derived from the ISA config
     pub reset_constraints: Vec<String>,
+    /// `solver_options` are the resource limits and unknown result
+    /// policy used for each solver created by the executor
+    pub solver_options: SolverOptions,
+    /// `closures` maps functions to the user-defined primops
+    /// matching their extern names
+    pub closures: HashMap<Name, Closure<B>>,
 }
 
 impl<'ir, B: BV> SharedState<'ir, B> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         symtab: Symtab<'ir>,
         defs: &'ir [Def<Name, B>],
         probes: HashSet<Name>,
+        watchpoints: Vec<Watchpoint>,
         trace_functions: HashSet<Name>,
         reset_registers: HashMap<Loc<Name>, Reset<B>>,
         reset_constraints: Vec<String>,
+        solver_options: SolverOptions,
+        primops: &Primops<B>,
     ) -> Self {
         let mut vals = HashMap::new();
         let mut functions: HashMap<Name, FnDecl<'ir, B>> = HashMap::new();
@@ -873,8 +973,10 @@ impl<'ir, B: BV> SharedState<'ir, B> {
         let mut enums: HashMap<Name, HashSet<Name>> = HashMap::new();
         let mut enum_members: HashMap<Name, (usize, usize)> = HashMap::new();
         let mut union_ctors: HashSet<Name> = HashSet::new();
+        let mut unions: HashMap<Name, Vec<(Name, Ty<Name>)>> = HashMap::new();
         let mut registers: HashMap<Name, Ty<Name>> = HashMap::new();
-        
+        let mut closures: HashMap<Name, Closure<B>> = HashMap::new();
+
         for def in defs {
             match def {
                 Def::Val(f, arg_tys, ret_ty) => {
@@ -903,16 +1005,23 @@ impl<'ir, B: BV> SharedState<'ir, B> {
                     enums.insert(*name, members);
                 }
 
-                Def::Union(_, ctors) => {
+                Def::Union(name, ctors) => {
                     for (ctor, _) in ctors {
                         union_ctors.insert(*ctor);
                     }
+                    unions.insert(*name, ctors.clone());
                 }
 
                 Def::Register(name, ty) => {
                     registers.insert(*name, ty.clone());
                 }
 
+                Def::Extern(f, ext, _, _) => {
+                    if let Some(closure) = primops.closures.get(ext) {
+                        closures.insert(*f, closure.clone());
+                    }
+                }
+
                 _ => (),
             }
         }
@@ -924,11 +1033,15 @@ impl<'ir, B: BV> SharedState<'ir, B> {
             enums,
             enum_members,
             union_ctors,
+            unions,
             registers,
             probes,
+            watchpoints,
             trace_functions,
             reset_registers,
             reset_constraints,
+            solver_options,
+            closures,
         }
     }
 
@@ -962,6 +1075,11 @@ fn insert_instr_primops<B: BV>(
                     Instr::Call(loc.clone(), false, REG_DEREF, args.clone(), *info)
                 } else if name == "reset_registers" {
                     Instr::Call(loc.clone(), false, RESET_REGISTERS, args.clone(), *info)
+                } else if name == "sleeping" {
+                    Instr::Call(loc.clone(), false, SLEEPING, args.clone(), *info)
+                } else if primops.closures.contains_key(name) {
+                    // Closures are called by the executor
+                    instr
                 } else {
                     // Currently we just warn when we don't have a
                     // primop. This happens for softfloat based
@@ -988,7 +1106,7 @@ pub enum AssertionMode {
 }
 
 /// Change Calls without implementations into Primops
-pub(crate) fn insert_primops<B: BV>(defs: &mut [Def<Name, B>], mode: AssertionMode) {
+pub(crate) fn insert_primops<B: BV>(defs: &mut [Def<Name, B>], mode: AssertionMode, primops: &Primops<B>) {
     let mut externs: HashMap<Name, String> = HashMap::new();
     for def in defs.iter() {
         if let Def::Extern(f, ext, _, _) = def {
@@ -1003,21 +1121,19 @@ pub(crate) fn insert_primops<B: BV>(defs: &mut [Def<Name, B>], mode: AssertionMo
     externs.insert(SAIL_ASSUME, "assume".to_string());
     externs.insert(BITVECTOR_UPDATE, "bitvector_update".to_string());
 
-    let primops = Primops::default();
-
     for def in defs.iter_mut() {
         match def {
             Def::Fn(f, args, body) => {
                 *def = Def::Fn(
                     *f,
                     args.to_vec(),
-                    body.to_vec().into_iter().map(|instr| insert_instr_primops(instr, &externs, &primops)).collect(),
+                    body.to_vec().into_iter().map(|instr| insert_instr_primops(instr, &externs, primops)).collect(),
                 )
             }
             Def::Let(bindings, setup) => {
                 *def = Def::Let(
                     bindings.clone(),
-                    setup.to_vec().into_iter().map(|instr| insert_instr_primops(instr, &externs, &primops)).collect(),
+                    setup.to_vec().into_iter().map(|instr| insert_instr_primops(instr, &externs, primops)).collect(),
                 )
             }
             _ => (),
@@ -0,0 +1,858 @@
+// BSD 2-Clause License
+//
+// Copyright (c) 2020 Alasdair Armstrong
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+// notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+// notice, this list of conditions and the following disclaimer in the
+// documentation and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+//! This module implements a static checker for the IR, which is
+//! intended to be run on the architecture after it has been interned
+//! with [Symtab::intern_defs], but before any execution has
+//! started. Without it, many mistakes in the IR only surface at run
+//! time as an [crate::error::ExecError::Type] or a panic in the
+//! executor, often after a long time spent executing.
+//!
+//! The checker looks for:
+//!
+//! - Variables which are used without any declaration that could
+//!   reach the use, and identifiers that are not defined at all.
+//!
+//! - Struct fields, union constructors, enums, and functions that do
+//!   not exist.
+//!
+//! - Calls with the wrong number of arguments, and jumps to
+//!   instructions outside the function body.
+//!
+//! - Type errors, both in built-in operations and in the arguments
+//!   passed to functions and primops (via their `val` declarations).
+//!
+//! Type checking is deliberately permissive, so that it only reports
+//! types which could never be compatible at run time: the integer
+//! types `%i64` and `%i` are interchangeable, `%bv` is compatible with
+//! any bitvector, and `%bit` with `%bv1`. Any expression whose type
+//! cannot be determined is compatible with everything.
+
+use super::pretty::ty_string;
+use super::source_loc::SourceLoc;
+use super::*;
+
+/// A problem found in the IR by [check_defs].
+#[derive(Clone, Debug)]
+pub struct CheckError {
+    /// The function in which the problem occurs, or [TOP_LEVEL_LET]
+    /// for the setup code of a top-level let binding
+    pub function: Name,
+    /// The index of the instruction containing the problem, if the
+    /// problem is within a function body
+    pub instr: Option<usize>,
+    pub info: SourceLoc,
+    pub message: String,
+}
+
+impl CheckError {
+    /// Format the error, using the symbol table to print the function
+    /// name and the source file containing the problem.
+    pub fn describe(&self, symtab: &Symtab) -> String {
+        let mut location = format!("In {}", zencode::decode(symtab.to_str(self.function)));
+        if let Some(instr) = self.instr {
+            location.push_str(&format!(" at instruction {}", instr))
+        }
+        if !self.info.is_unknown() {
+            location.push_str(&format!(" ({})", self.info.location_string(symtab.files())))
+        }
+        format!("{}: {}", location, self.message)
+    }
+}
+
+fn is_int(ty: &Ty<Name>) -> bool {
+    matches!(ty, Ty::I64 | Ty::I128)
+}
+
+fn is_bits(ty: &Ty<Name>) -> bool {
+    matches!(ty, Ty::AnyBits | Ty::Bits(_) | Ty::Bit)
+}
+
+fn compatible(lhs: &Ty<Name>, rhs: &Ty<Name>) -> bool {
+    use Ty::*;
+    match (lhs, rhs) {
+        (Bits(n), Bits(m)) => n == m,
+        (Bit, Bits(n)) | (Bits(n), Bit) => *n == 1,
+        (Bit, Bit) => true,
+        (AnyBits, _) | (_, AnyBits) => is_bits(lhs) && is_bits(rhs),
+        (Vector(lhs), Vector(rhs))
+        | (Vector(lhs), FixedVector(_, rhs))
+        | (FixedVector(_, lhs), Vector(rhs))
+        | (List(lhs), List(rhs))
+        | (Ref(lhs), Ref(rhs)) => compatible(lhs, rhs),
+        (FixedVector(n, lhs), FixedVector(m, rhs)) => n == m && compatible(lhs, rhs),
+        (Enum(lhs), Enum(rhs)) | (Struct(lhs), Struct(rhs)) | (Union(lhs), Union(rhs)) => lhs == rhs,
+        (Unit, Unit) | (Bool, Bool) | (String, String) | (Real, Real) => true,
+        (_, _) => is_int(lhs) && is_int(rhs),
+    }
+}
+
+/// Everything that is defined at the top level of the architecture.
+struct Globals {
+    signatures: HashMap<Name, (Vec<Ty<Name>>, Ty<Name>)>,
+    structs: HashMap<Name, HashMap<Name, Ty<Name>>>,
+    enums: HashSet<Name>,
+    enum_members: HashMap<Name, Name>,
+    unions: HashSet<Name>,
+    ctors: HashMap<Name, (Name, Ty<Name>)>,
+    registers: HashMap<Name, Ty<Name>>,
+    lets: HashMap<Name, Option<Ty<Name>>>,
+}
+
+impl Globals {
+    fn new<B: BV>(defs: &[Def<Name, B>]) -> Self {
+        let mut globals = Globals {
+            signatures: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashSet::new(),
+            enum_members: HashMap::new(),
+            unions: HashSet::new(),
+            ctors: HashMap::new(),
+            registers: HashMap::new(),
+            lets: HashMap::new(),
+        };
+
+        // These are always created by the executor
+        globals.lets.insert(HAVE_EXCEPTION, Some(Ty::Bool));
+        globals.lets.insert(CURRENT_EXCEPTION, Some(Ty::Union(SAIL_EXCEPTION)));
+        globals.lets.insert(THROW_LOCATION, Some(Ty::String));
+        globals.lets.insert(NULL, None);
+        globals.lets.insert(ELF_ENTRY, None);
+
+        for def in defs {
+            match def {
+                Def::Val(f, arg_tys, ret_ty) | Def::Extern(f, _, arg_tys, ret_ty) => {
+                    globals.signatures.insert(*f, (arg_tys.clone(), ret_ty.clone()));
+                }
+                Def::Struct(s, fields) => {
+                    globals.structs.insert(*s, fields.iter().cloned().collect());
+                }
+                Def::Enum(e, members) => {
+                    globals.enums.insert(*e);
+                    for member in members {
+                        globals.enum_members.insert(*member, *e);
+                    }
+                }
+                Def::Union(u, ctors) => {
+                    globals.unions.insert(*u);
+                    for (ctor, ty) in ctors {
+                        globals.ctors.insert(*ctor, (*u, ty.clone()));
+                    }
+                }
+                Def::Register(reg, ty) => {
+                    globals.registers.insert(*reg, ty.clone());
+                }
+                Def::Let(bindings, _) => {
+                    for (id, ty) in bindings {
+                        globals.lets.insert(*id, Some(ty.clone()));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        globals
+    }
+}
+
+/// The special functions handled directly by the executor, and how
+/// many arguments they take (if fixed).
+fn builtin_arity(f: Name) -> Option<Option<usize>> {
+    match f {
+        SAIL_ASSERT => Some(Some(2)),
+        SAIL_ASSUME | REG_DEREF | INTERNAL_VECTOR_INIT => Some(Some(1)),
+        INTERNAL_VECTOR_UPDATE | BITVECTOR_UPDATE => Some(Some(3)),
+        SAIL_EXIT | RESET_REGISTERS => Some(None),
+        _ => None,
+    }
+}
+
+fn successors<B: BV>(instrs: &[Instr<Name, B>], i: usize) -> Vec<usize> {
+    let succs = match &instrs[i] {
+        Instr::Goto(target) => vec![*target],
+        Instr::Jump(_, target, _) => vec![*target, i + 1],
+        Instr::End | Instr::Failure | Instr::Arbitrary => vec![],
+        _ => vec![i + 1],
+    };
+    succs.into_iter().filter(|j| *j < instrs.len()).collect()
+}
+
+/// For each instruction, computes the set of local variables which
+/// may have been declared before it is executed, or `None` if the
+/// instruction is unreachable.
+fn reaching_declarations<B: BV>(instrs: &[Instr<Name, B>], initial: &HashSet<Name>) -> Vec<Option<HashSet<Name>>> {
+    let mut declared: Vec<Option<HashSet<Name>>> = vec![None; instrs.len()];
+    if instrs.is_empty() {
+        return declared;
+    }
+    declared[0] = Some(initial.clone());
+
+    let mut worklist = vec![0];
+    while let Some(i) = worklist.pop() {
+        let mut out = declared[i].clone().unwrap();
+        if let Instr::Decl(id, _, _) | Instr::Init(id, _, _, _) = &instrs[i] {
+            out.insert(*id);
+        }
+        for j in successors(instrs, i) {
+            match &mut declared[j] {
+                Some(vars) => {
+                    let before = vars.len();
+                    vars.extend(out.iter().copied());
+                    if vars.len() != before {
+                        worklist.push(j)
+                    }
+                }
+                None => {
+                    declared[j] = Some(out.clone());
+                    worklist.push(j)
+                }
+            }
+        }
+    }
+
+    declared
+}
+
+struct Checker<'a, 'ir> {
+    globals: &'a Globals,
+    symtab: &'a Symtab<'ir>,
+    function: Name,
+    /// The type of each local variable, or `None` if it is declared
+    /// with different types in different places
+    locals: HashMap<Name, Option<Ty<Name>>>,
+    /// The local variables which might be declared at the current
+    /// instruction, or `None` if it is unreachable
+    declared: Option<HashSet<Name>>,
+    instr: Option<usize>,
+    info: SourceLoc,
+    errors: Vec<CheckError>,
+}
+
+impl<'a, 'ir> Checker<'a, 'ir> {
+    fn new(globals: &'a Globals, symtab: &'a Symtab<'ir>, function: Name) -> Self {
+        Checker {
+            globals,
+            symtab,
+            function,
+            locals: HashMap::new(),
+            declared: None,
+            instr: None,
+            info: SourceLoc::unknown(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn error(&mut self, message: String) {
+        self.errors.push(CheckError { function: self.function, instr: self.instr, info: self.info, message })
+    }
+
+    fn name(&self, n: Name) -> String {
+        // Symtab::lookup returns this if a symbol was never interned
+        if n.id == u32::MAX {
+            "<unknown>".to_string()
+        } else {
+            zencode::decode(self.symtab.to_str(n))
+        }
+    }
+
+    fn expect(&mut self, expected: &Ty<Name>, found: &Option<Ty<Name>>, what: &str) {
+        if let Some(found) = found {
+            if !compatible(expected, found) {
+                self.error(format!(
+                    "Type mismatch in {}: expected {}, found {}",
+                    what,
+                    ty_string(expected, self.symtab),
+                    ty_string(found, self.symtab)
+                ))
+            }
+        }
+    }
+
+    fn expect_class(&mut self, found: &Option<Ty<Name>>, class: fn(&Ty<Name>) -> bool, expected: &str, what: &str) {
+        if let Some(found) = found {
+            if !class(found) {
+                self.error(format!(
+                    "Type mismatch in {}: expected {}, found {}",
+                    what,
+                    expected,
+                    ty_string(found, self.symtab)
+                ))
+            }
+        }
+    }
+
+    fn check_ty(&mut self, ty: &Ty<Name>) {
+        match ty {
+            Ty::Enum(e) if !self.globals.enums.contains(e) => self.error(format!("Enum {} not found", self.name(*e))),
+            Ty::Struct(s) if !self.globals.structs.contains_key(s) => {
+                self.error(format!("Struct {} not found", self.name(*s)))
+            }
+            Ty::Union(u) if !self.globals.unions.contains(u) => {
+                self.error(format!("Union {} not found", self.name(*u)))
+            }
+            Ty::Vector(ty) | Ty::FixedVector(_, ty) | Ty::List(ty) | Ty::Ref(ty) => self.check_ty(ty),
+            _ => (),
+        }
+    }
+
+    fn declare(&mut self, id: Name, ty: &Ty<Name>) {
+        match self.locals.get(&id) {
+            Some(Some(prev)) if !compatible(prev, ty) => {
+                self.locals.insert(id, None);
+            }
+            Some(_) => (),
+            None => {
+                self.locals.insert(id, Some(ty.clone()));
+            }
+        }
+    }
+
+    fn check_declared(&mut self, id: Name) {
+        if let Some(declared) = &self.declared {
+            if !declared.contains(&id) {
+                self.error(format!("Variable {} is used before it is declared", self.name(id)))
+            }
+        }
+    }
+
+    fn id_ty(&mut self, id: Name) -> Option<Ty<Name>> {
+        if let Some(ty) = self.locals.get(&id).cloned() {
+            self.check_declared(id);
+            ty
+        } else if let Some(ty) = self.globals.registers.get(&id) {
+            Some(ty.clone())
+        } else if let Some(ty) = self.globals.lets.get(&id) {
+            ty.clone()
+        } else if let Some(e) = self.globals.enum_members.get(&id) {
+            Some(Ty::Enum(*e))
+        } else {
+            self.error(format!("Symbol {} not found", self.name(id)));
+            None
+        }
+    }
+
+    fn field_ty(&mut self, ty: &Option<Ty<Name>>, field: Name) -> Option<Ty<Name>> {
+        match ty {
+            Some(Ty::Struct(s)) => match self.globals.structs.get(s).map(|fields| fields.get(&field)) {
+                Some(Some(ty)) => Some(ty.clone()),
+                Some(None) => {
+                    self.error(format!("Struct {} has no field {}", self.name(*s), self.name(field)));
+                    None
+                }
+                None => None,
+            },
+            Some(ty) => {
+                self.error(format!(
+                    "Field {} accessed on a value of type {}, which is not a struct",
+                    self.name(field),
+                    ty_string(ty, self.symtab)
+                ));
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn op_ty(&mut self, op: Op, args: &[Exp<Name>]) -> Option<Ty<Name>> {
+        use Op::*;
+        let tys: Vec<Option<Ty<Name>>> = args.iter().map(|arg| self.exp_ty(arg)).collect();
+
+        let arity = match op {
+            Not | Signed(_) | Unsigned(_) | ZeroExtend(_) | Bvnot | Head | Tail => 1,
+            SetSlice => 3,
+            _ => 2,
+        };
+        if tys.len() != arity {
+            self.error(format!("Operator {:?} expects {} arguments, got {}", op, arity, tys.len()));
+            return None;
+        }
+
+        let what = format!("operator {:?}", op);
+        match op {
+            Not | And | Or => {
+                for ty in &tys {
+                    self.expect(&Ty::Bool, ty, &what)
+                }
+                Some(Ty::Bool)
+            }
+            Eq | Neq => {
+                if let (Some(lhs), Some(rhs)) = (&tys[0], &tys[1]) {
+                    if !compatible(lhs, rhs) {
+                        self.error(format!(
+                            "Type mismatch in {}: cannot compare {} with {}",
+                            what,
+                            ty_string(lhs, self.symtab),
+                            ty_string(rhs, self.symtab)
+                        ))
+                    }
+                }
+                Some(Ty::Bool)
+            }
+            Lteq | Lt | Gteq | Gt => {
+                for ty in &tys {
+                    self.expect_class(ty, is_int, "an integer", &what)
+                }
+                Some(Ty::Bool)
+            }
+            Add | Sub => {
+                for ty in &tys {
+                    self.expect_class(ty, is_int, "an integer", &what)
+                }
+                tys[0].clone()
+            }
+            Slice(len) | ZeroExtend(len) => {
+                self.expect_class(&tys[0], is_bits, "a bitvector", &what);
+                if tys.len() > 1 {
+                    self.expect_class(&tys[1], is_int, "an integer", &what)
+                }
+                Some(Ty::Bits(len))
+            }
+            SetSlice => {
+                self.expect_class(&tys[0], is_bits, "a bitvector", &what);
+                self.expect_class(&tys[1], is_int, "an integer", &what);
+                self.expect_class(&tys[2], is_bits, "a bitvector", &what);
+                tys[0].clone()
+            }
+            Signed(_) | Unsigned(_) => {
+                self.expect_class(&tys[0], is_bits, "a bitvector", &what);
+                Some(Ty::I64)
+            }
+            Bvnot => {
+                self.expect_class(&tys[0], is_bits, "a bitvector", &what);
+                tys[0].clone()
+            }
+            Bvor | Bvxor | Bvand | Bvadd | Bvsub => {
+                for ty in &tys {
+                    self.expect_class(ty, is_bits, "a bitvector", &what)
+                }
+                match (&tys[0], &tys[1]) {
+                    (Some(lhs), Some(rhs)) if !compatible(lhs, rhs) => {
+                        self.error(format!(
+                            "Type mismatch in {}: bitvectors {} and {} have different lengths",
+                            what,
+                            ty_string(lhs, self.symtab),
+                            ty_string(rhs, self.symtab)
+                        ));
+                        None
+                    }
+                    (Some(Ty::AnyBits), rhs) => rhs.clone(),
+                    (lhs, _) => lhs.clone(),
+                }
+            }
+            Bvaccess => {
+                self.expect_class(&tys[0], is_bits, "a bitvector", &what);
+                self.expect_class(&tys[1], is_int, "an integer", &what);
+                Some(Ty::Bit)
+            }
+            Concat => {
+                for ty in &tys {
+                    self.expect_class(ty, is_bits, "a bitvector", &what)
+                }
+                match (&tys[0], &tys[1]) {
+                    (Some(Ty::Bits(n)), Some(Ty::Bits(m))) => Some(Ty::Bits(n + m)),
+                    _ => Some(Ty::AnyBits),
+                }
+            }
+            Head | Tail => match &tys[0] {
+                Some(Ty::List(ty)) if matches!(op, Head) => Some((**ty).clone()),
+                Some(ty @ Ty::List(_)) => Some(ty.clone()),
+                Some(ty) => {
+                    self.error(format!(
+                        "Type mismatch in {}: expected a list, found {}",
+                        what,
+                        ty_string(ty, self.symtab)
+                    ));
+                    None
+                }
+                None => None,
+            },
+        }
+    }
+
+    fn exp_ty(&mut self, exp: &Exp<Name>) -> Option<Ty<Name>> {
+        use Exp::*;
+        match exp {
+            Id(id) => self.id_ty(*id),
+            Ref(reg) => match self.globals.registers.get(reg) {
+                Some(ty) => Some(Ty::Ref(Box::new(ty.clone()))),
+                None => {
+                    self.error(format!("Reference to {}, which is not a register", self.name(*reg)));
+                    None
+                }
+            },
+            Bool(_) => Some(Ty::Bool),
+            Bits(bv) => Some(Ty::Bits(bv.len())),
+            String(_) => Some(Ty::String),
+            Unit => Some(Ty::Unit),
+            I64(_) => Some(Ty::I64),
+            I128(_) => Some(Ty::I128),
+            Undefined(ty) => {
+                self.check_ty(ty);
+                Some(ty.clone())
+            }
+            Struct(s, fields) => {
+                for (field, exp) in fields {
+                    let found = self.exp_ty(exp);
+                    match self.globals.structs.get(s).map(|fields| fields.get(field)) {
+                        Some(Some(ty)) => {
+                            let what = format!("field {}", self.name(*field));
+                            self.expect(ty, &found, &what)
+                        }
+                        Some(None) => {
+                            self.error(format!("Struct {} has no field {}", self.name(*s), self.name(*field)))
+                        }
+                        None => (),
+                    }
+                }
+                if self.globals.structs.contains_key(s) {
+                    Some(Ty::Struct(*s))
+                } else {
+                    self.error(format!("Struct {} not found", self.name(*s)));
+                    None
+                }
+            }
+            Kind(ctor, arg) | Unwrap(ctor, arg) => {
+                let found = self.exp_ty(arg);
+                match self.globals.ctors.get(ctor) {
+                    Some((u, ty)) => {
+                        let what = format!("constructor {}", self.name(*ctor));
+                        self.expect(&Ty::Union(*u), &found, &what);
+                        if let Kind(_, _) = exp {
+                            Some(Ty::Bool)
+                        } else {
+                            Some(ty.clone())
+                        }
+                    }
+                    None => {
+                        self.error(format!("Union constructor {} not found", self.name(*ctor)));
+                        None
+                    }
+                }
+            }
+            Field(exp, field) => {
+                let ty = self.exp_ty(exp);
+                self.field_ty(&ty, *field)
+            }
+            Call(op, args) => self.op_ty(*op, args),
+        }
+    }
+
+    fn loc_ty(&mut self, loc: &Loc<Name>) -> Option<Ty<Name>> {
+        match loc {
+            Loc::Id(id) => {
+                if self.locals.contains_key(id)
+                    || self.globals.registers.contains_key(id)
+                    || self.globals.lets.contains_key(id)
+                {
+                    self.id_ty(*id)
+                } else {
+                    self.error(format!("Assignment to {}, which is not a variable or register", self.name(*id)));
+                    None
+                }
+            }
+            Loc::Field(loc, field) => {
+                let ty = self.loc_ty(loc);
+                self.field_ty(&ty, *field)
+            }
+            Loc::Addr(loc) => match self.loc_ty(loc) {
+                Some(Ty::Ref(ty)) => Some(*ty),
+                Some(ty) => {
+                    self.error(format!("Dereference of {}, which is not a reference", ty_string(&ty, self.symtab)));
+                    None
+                }
+                None => None,
+            },
+        }
+    }
+
+    fn check_call(&mut self, loc: &Loc<Name>, f: Name, args: &[Exp<Name>]) {
+        let loc_ty = self.loc_ty(loc);
+        let tys: Vec<Option<Ty<Name>>> = args.iter().map(|arg| self.exp_ty(arg)).collect();
+
+        if let Some((arg_tys, ret_ty)) = self.globals.signatures.get(&f) {
+            if arg_tys.len() != tys.len() {
+                self.error(format!("Function {} expects {} arguments, got {}", self.name(f), arg_tys.len(), tys.len()))
+            } else {
+                for (i, (arg_ty, ty)) in arg_tys.iter().zip(tys.iter()).enumerate() {
+                    let what = format!("argument {} of {}", i + 1, self.name(f));
+                    self.expect(arg_ty, ty, &what)
+                }
+            }
+            if let Some(loc_ty) = loc_ty {
+                let what = format!("result of {}", self.name(f));
+                self.expect(&loc_ty, &Some(ret_ty.clone()), &what)
+            }
+        } else if let Some((u, ty)) = self.globals.ctors.get(&f) {
+            if tys.len() != 1 {
+                self.error(format!("Union constructor {} expects 1 argument, got {}", self.name(f), tys.len()))
+            } else {
+                let what = format!("constructor {}", self.name(f));
+                self.expect(ty, &tys[0], &what)
+            }
+            if let Some(loc_ty) = loc_ty {
+                let what = format!("constructor {}", self.name(f));
+                self.expect(&loc_ty, &Some(Ty::Union(*u)), &what)
+            }
+        } else {
+            match builtin_arity(f) {
+                Some(Some(arity)) if arity != tys.len() => {
+                    self.error(format!("Function {} expects {} arguments, got {}", self.name(f), arity, tys.len()))
+                }
+                Some(_) => (),
+                None => self.error(format!("Function {} not found", self.name(f))),
+            }
+        }
+    }
+
+    fn check_instr<B: BV>(&mut self, instr: &Instr<Name, B>, len: usize) {
+        use Instr::*;
+        match instr {
+            Decl(_, ty, info) => {
+                self.info = *info;
+                self.check_ty(ty)
+            }
+            Init(id, ty, exp, info) => {
+                self.info = *info;
+                self.check_ty(ty);
+                let found = self.exp_ty(exp);
+                let what = format!("initialization of {}", self.name(*id));
+                self.expect(ty, &found, &what)
+            }
+            Jump(exp, target, info) => {
+                self.info = *info;
+                let found = self.exp_ty(exp);
+                self.expect(&Ty::Bool, &found, "jump condition");
+                if *target >= len {
+                    self.error(format!("Jump target {} is out of range", target))
+                }
+            }
+            Goto(target) => {
+                if *target >= len {
+                    self.error(format!("Goto target {} is out of range", target))
+                }
+            }
+            Copy(loc, exp, info) => {
+                self.info = *info;
+                let loc_ty = self.loc_ty(loc);
+                let found = self.exp_ty(exp);
+                if let Some(loc_ty) = loc_ty {
+                    self.expect(&loc_ty, &found, "assignment")
+                }
+            }
+            Monomorphize(id, info) => {
+                self.info = *info;
+                self.id_ty(*id);
+            }
+            Call(loc, _, f, args, info) => {
+                self.info = *info;
+                self.check_call(loc, *f, args)
+            }
+            PrimopUnary(loc, _, exp, info) => {
+                self.info = *info;
+                self.loc_ty(loc);
+                self.exp_ty(exp);
+            }
+            PrimopBinary(loc, _, lhs, rhs, info) => {
+                self.info = *info;
+                self.loc_ty(loc);
+                self.exp_ty(lhs);
+                self.exp_ty(rhs);
+            }
+            PrimopVariadic(loc, _, args, info) => {
+                self.info = *info;
+                self.loc_ty(loc);
+                args.iter().for_each(|arg| {
+                    self.exp_ty(arg);
+                })
+            }
+            Failure | Arbitrary | End => (),
+        }
+    }
+
+    fn check_body<B: BV>(&mut self, params: &[(Name, Ty<Name>)], body: &[Instr<Name, B>]) {
+        for (id, ty) in params {
+            self.check_ty(ty);
+            self.declare(*id, ty)
+        }
+        for instr in body {
+            if let Instr::Decl(id, ty, _) | Instr::Init(id, ty, _, _) = instr {
+                self.declare(*id, ty)
+            }
+        }
+
+        let initial = params.iter().map(|(id, _)| *id).collect();
+        let declared = reaching_declarations(body, &initial);
+
+        for (i, (instr, declared)) in body.iter().zip(declared).enumerate() {
+            self.instr = Some(i);
+            self.declared = declared;
+            self.check_instr(instr, body.len())
+        }
+    }
+}
+
+/// Check an architecture for problems, returning every problem found.
+/// This should be run after [Symtab::intern_defs], and before the
+/// primops are inserted by [crate::init::initialize_architecture].
+pub fn check_defs<B: BV>(defs: &[Def<Name, B>], symtab: &Symtab) -> Vec<CheckError> {
+    let globals = Globals::new(defs);
+    let mut errors = Vec::new();
+
+    for def in defs {
+        match def {
+            Def::Fn(f, args, body) => {
+                let mut checker = Checker::new(&globals, symtab, *f);
+                match globals.signatures.get(f) {
+                    Some((arg_tys, ret_ty)) if arg_tys.len() == args.len() => {
+                        let mut params: Vec<(Name, Ty<Name>)> =
+                            args.iter().copied().zip(arg_tys.iter().cloned()).collect();
+                        params.push((RETURN, ret_ty.clone()));
+                        checker.check_body(&params, body)
+                    }
+                    Some((arg_tys, _)) => checker.error(format!(
+                        "Function has {} parameters, but its type signature has {}",
+                        args.len(),
+                        arg_tys.len()
+                    )),
+                    None => checker.error("Function has no type signature".to_string()),
+                }
+                errors.append(&mut checker.errors)
+            }
+            Def::Let(bindings, setup) => {
+                let mut checker = Checker::new(&globals, symtab, TOP_LEVEL_LET);
+                for (_, ty) in bindings {
+                    checker.check_ty(ty)
+                }
+                checker.check_body(&[], setup);
+                errors.append(&mut checker.errors)
+            }
+            Def::Register(_, ty) | Def::Val(_, _, ty) | Def::Extern(_, _, _, ty) => {
+                let mut checker = Checker::new(&globals, symtab, TOP_LEVEL_LET);
+                checker.check_ty(ty);
+                if let Def::Val(_, arg_tys, _) | Def::Extern(_, _, arg_tys, _) = def {
+                    arg_tys.iter().for_each(|ty| checker.check_ty(ty))
+                }
+                errors.append(&mut checker.errors)
+            }
+            Def::Struct(_, fields) | Def::Union(_, fields) => {
+                let mut checker = Checker::new(&globals, symtab, TOP_LEVEL_LET);
+                fields.iter().for_each(|(_, ty)| checker.check_ty(ty));
+                errors.append(&mut checker.errors)
+            }
+            _ => (),
+        }
+    }
+
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse_ir;
+
+    static IR: &str = r#"
+register zR : %bv8
+
+struct zS {
+  zf : %bv8
+}
+
+val zg : (%bv8) -> %bv8
+
+fn zg(zx) {
+  return = zx;
+  end
+}
+
+val zadd_bits = "add_bits" : (%bv, %bv) -> %bv
+
+val zok : (%bv8) -> %bool
+
+fn zok(zx) {
+  zs : %struct zS ``;
+  zs.zf = zx;
+  zy : %bv8 ``;
+  zy = zg(zx) ``;
+  zy = zadd_bits(zy, zR) ``;
+  jump @eq(zs.zf, zy) goto 8 ``;
+  return = false;
+  goto 9;
+  return = @eq(zy, zR);
+  end
+}
+
+val zbad : (%bv8) -> %bool
+
+fn zbad(zx) {
+  goto 2;
+  zflag : %bool = true ``;
+  jump zflag goto 3 ``;
+  zy : %bv16 = @bvadd(zx, 0x01) ``;
+  zz : %bv8 = zundefined ``;
+  zs : %struct zS = struct zS {zh = zx} ``;
+  zy = zg(zx, zx) ``;
+  zy = zh(zx) ``;
+  zy = zadd_bits(zx, true) ``;
+  goto 42;
+  return = zx;
+  end
+}
+"#;
+
+    fn check(ir: &str) -> Vec<String> {
+        let defs = parse_ir(ir);
+        let mut symtab = Symtab::new();
+        let defs = symtab.intern_defs(&defs);
+        check_defs(&defs, &symtab).iter().map(|err| err.describe(&symtab)).collect()
+    }
+
+    #[test]
+    fn check_ok() {
+        let errors = check(IR);
+        assert!(errors.iter().all(|err| !err.starts_with("In ok")), "{:?}", errors)
+    }
+
+    #[test]
+    fn check_bad() {
+        let errors = check(IR);
+        let expected = [
+            "In bad at instruction 2: Variable flag is used before it is declared",
+            "In bad at instruction 3: Type mismatch in initialization of y: expected %bv16, found %bv8",
+            "In bad at instruction 4: Symbol <unknown> not found",
+            "In bad at instruction 5: Struct S has no field <unknown>",
+            "In bad at instruction 6: Function g expects 1 arguments, got 2",
+            "In bad at instruction 6: Type mismatch in result of g: expected %bv16, found %bv8",
+            "In bad at instruction 7: Function <unknown> not found",
+            "In bad at instruction 8: Type mismatch in argument 2 of add_bits: expected %bv, found %bool",
+            "In bad at instruction 9: Goto target 42 is out of range",
+            "In bad at instruction 10: Type mismatch in assignment: expected %bool, found %bv8",
+        ];
+        assert_eq!(errors, expected)
+    }
+}
@@ -71,6 +71,14 @@
 //! needs to be pure (it can only read architectural state), and its
 //! control flow graph must be acyclic so it can be placed into a
 //! topological order.
+//!
+//! [linearize_function] relaxes the second restriction somewhat. Loops
+//! whose bounds are concrete are unrolled by repeatedly peeling off
+//! their first iteration and propagating constants until the
+//! remaining loop becomes unreachable, and calls to other functions
+//! which can themselves be linearized are replaced by their
+//! linearized bodies. Finally, [candidates] uses a simple heuristic to
+//! pick out functions which are likely to be worth linearizing.
 
 use petgraph::algo;
 use petgraph::graph::{EdgeIndex, NodeIndex};
@@ -78,11 +86,13 @@ use petgraph::Direction;
 use std::cmp;
 use std::ops::{BitAnd, BitOr};
 
+use super::optimize::{collect_refs, constant_propagation, inline_call};
 use super::source_loc::SourceLoc;
 use super::ssa::{unssa_ty, BlockInstr, BlockLoc, Edge, SSAName, Terminator, CFG};
 use super::*;
 use crate::config::ISAConfig;
-use crate::primop::{binary_primops, variadic_primops};
+use crate::primop::{binary_primops, variadic_primops, Primops};
+use crate::smt::{Config, Context};
 
 /// The reachability of a node in an SSA graph is determined by a
 /// boolean formula over edges which can be taken to reach that node.
@@ -430,16 +440,320 @@ pub fn linearize<B: BV>(instrs: Vec<Instr<Name, B>>, ret_ty: &Ty<Name>, symtab:
     }
 }
 
+/// The default maximum number of loop iterations that will be
+/// unrolled by [linearize_function].
+pub const DEFAULT_UNROLL_BOUND: usize = 64;
+
+/// Functions with more instructions than this are never chosen by
+/// [candidates].
+pub const CANDIDATE_SIZE_LIMIT: usize = 256;
+
+/// Functions with more jumps than this are never chosen by
+/// [candidates], as the `ite` chains produced by linearization grow
+/// with the number of paths through the function.
+pub const CANDIDATE_BRANCH_LIMIT: usize = 32;
+
+fn jump_target<B: BV>(instr: &Instr<Name, B>) -> Option<usize> {
+    match instr {
+        Instr::Goto(target) | Instr::Jump(_, target, _) => Some(*target),
+        _ => None,
+    }
+}
+
+fn retarget<B: BV>(instr: &Instr<Name, B>, f: impl Fn(usize) -> usize) -> Instr<Name, B> {
+    match instr {
+        Instr::Goto(target) => Instr::Goto(f(*target)),
+        Instr::Jump(exp, target, info) => Instr::Jump(exp.clone(), f(*target), *info),
+        _ => instr.clone(),
+    }
+}
+
+/// Find the outermost loop in a function body, returned as the index
+/// of the loop header and the index of the last back-edge to it.
+fn find_loop<B: BV>(instrs: &[Instr<Name, B>]) -> Option<(usize, usize)> {
+    let mut found: Option<(usize, usize)> = None;
+    for (j, instr) in instrs.iter().enumerate() {
+        match (jump_target(instr), found) {
+            (Some(h), Some((header, _))) if h <= j && h <= header => found = Some((h, j)),
+            (Some(h), None) if h <= j => found = Some((h, j)),
+            _ => (),
+        }
+    }
+    found
+}
+
+/// Peel the first iteration off the loop between `h` and `j`, by
+/// placing a copy of the loop body before the loop, where the copy
+/// branches to the original loop rather than back to itself. Only
+/// loops with a single entry point at the header and an
+/// unconditional back-edge at the end can be peeled.
+fn peel_loop<B: BV>(instrs: &[Instr<Name, B>], h: usize, j: usize) -> Option<Vec<Instr<Name, B>>> {
+    if !matches!(instrs[j], Instr::Goto(target) if target == h) {
+        return None;
+    }
+
+    for (i, instr) in instrs.iter().enumerate() {
+        if i < h || i > j {
+            match jump_target(instr) {
+                Some(target) if target > h && target <= j => return None,
+                Some(target) if i > j && target <= h => return None,
+                _ => (),
+            }
+        }
+    }
+
+    let len = j - h + 1;
+    let mut peeled = Vec::with_capacity(instrs.len() + len);
+    peeled.extend(instrs[..h].iter().map(|instr| retarget(instr, |t| if t > h { t + len } else { t })));
+    peeled.extend(instrs[h..=j].iter().map(|instr| retarget(instr, |t| if t == h || t > j { t + len } else { t })));
+    peeled.extend(instrs[h..].iter().map(|instr| retarget(instr, |t| if t >= h { t + len } else { t })));
+    Some(peeled)
+}
+
+/// Unroll every loop in a function body, peeling off at most
+/// `max_unroll` iterations in total. Returns `None` if any loop
+/// remains, which will happen if a loop does not have concrete
+/// bounds. The solver is used for constant propagation (see
+/// [constant_propagation]).
+pub fn unroll_loops<B: BV>(
+    instrs: &[Instr<Name, B>],
+    args: &[Name],
+    max_unroll: usize,
+    solver: &mut Solver<B>,
+) -> Option<Vec<Instr<Name, B>>> {
+    if find_loop(instrs).is_none() {
+        return Some(instrs.to_vec());
+    }
+
+    let registers = HashMap::new();
+    let (mut instrs, _) = constant_propagation(instrs, args, &registers, solver);
+
+    for _ in 0..max_unroll {
+        match find_loop(&instrs) {
+            None => return Some(instrs),
+            Some((h, j)) => instrs = constant_propagation(&peel_loop(&instrs, h, j)?, args, &registers, solver).0,
+        }
+    }
+
+    if find_loop(&instrs).is_none() {
+        Some(instrs)
+    } else {
+        None
+    }
+}
+
+/// Primops which are safe to execute unconditionally in a linearized
+/// function body. Anything which prints, touches memory, or otherwise
+/// has an effect on the outside world is excluded, as are closures,
+/// which can do any of these things.
+fn pure_primop<B>(name: &str, primops: &Primops<B>) -> bool {
+    static IMPURE_PREFIXES: [&str; 14] = [
+        "print",
+        "prerr",
+        "sail_putchar",
+        "platform_",
+        "sleep",
+        "wakeup_request",
+        "mark_register",
+        "cycle_count",
+        "get_cycle_count",
+        "bad_",
+        "internal_pick",
+        "elf_entry",
+        "assume",
+        "optimistic_assert",
+    ];
+    name != "pessimistic_assert"
+        && !IMPURE_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+        && (primops.unary.contains_key(name)
+            || primops.binary.contains_key(name)
+            || primops.variadic.contains_key(name))
+}
+
+/// The arguments and body of a function
+type FnBody<'a, B> = (&'a [Name], &'a [Instr<Name, B>]);
+
+/// The functions, signatures, and externs of an architecture, indexed
+/// by name.
+struct Functions<'a, B> {
+    signatures: HashMap<Name, (&'a [Ty<Name>], &'a Ty<Name>)>,
+    bodies: HashMap<Name, FnBody<'a, B>>,
+    externs: HashMap<Name, &'a str>,
+}
+
+impl<'a, B: BV> Functions<'a, B> {
+    fn new(defs: &'a [Def<Name, B>]) -> Self {
+        let mut functions = Functions { signatures: HashMap::new(), bodies: HashMap::new(), externs: HashMap::new() };
+        for def in defs {
+            match def {
+                Def::Val(f, arg_tys, ret_ty) => {
+                    functions.signatures.insert(*f, (arg_tys, ret_ty));
+                }
+                Def::Fn(f, args, body) => {
+                    functions.bodies.insert(*f, (args, body));
+                }
+                Def::Extern(f, ext, _, _) => {
+                    functions.externs.insert(*f, ext);
+                }
+                _ => (),
+            }
+        }
+        functions
+    }
+
+    fn linearize(
+        &self,
+        f: Name,
+        max_unroll: usize,
+        visiting: &mut HashSet<Name>,
+        done: &mut HashMap<Name, Option<Vec<Instr<Name, B>>>>,
+        symtab: &mut Symtab,
+        solver: &mut Solver<B>,
+    ) -> Option<Vec<Instr<Name, B>>> {
+        if let Some(result) = done.get(&f) {
+            return result.clone();
+        }
+        let (args, body) = *self.bodies.get(&f)?;
+        let (_, ret_ty) = *self.signatures.get(&f)?;
+        // Recursive functions cannot be linearized
+        if !visiting.insert(f) {
+            return None;
+        }
+
+        let mut inlined = Vec::new();
+        for instr in label_instrs(body.to_vec()) {
+            let (mut label, instr) = match instr {
+                LabeledInstr::Labeled(label, instr) => (Some(label), instr),
+                LabeledInstr::Unlabeled(instr) => (None, instr),
+            };
+            match instr {
+                Instr::Call(loc, ext, g, call_args, info) if self.signatures.contains_key(&g) => {
+                    if let Some(callee) = self.linearize(g, max_unroll, visiting, done, symtab, solver) {
+                        let (params, _) = self.bodies[&g];
+                        let (arg_tys, callee_ret_ty) = self.signatures[&g];
+                        for instr in inline_call(loc, call_args, info, params, arg_tys, callee_ret_ty, &callee, symtab)
+                        {
+                            inlined.push(apply_label(&mut label, instr))
+                        }
+                    } else {
+                        inlined.push(apply_label(&mut label, Instr::Call(loc, ext, g, call_args, info)))
+                    }
+                }
+                instr => inlined.push(apply_label(&mut label, instr)),
+            }
+        }
+
+        visiting.remove(&f);
+
+        let result = unroll_loops(&unlabel_instrs(inlined), args, max_unroll, solver)
+            .map(|body| linearize(body, ret_ty, symtab))
+            .filter(|body| body.iter().all(|instr| jump_target(instr).is_none()));
+        done.insert(f, result.clone());
+        result
+    }
+
+    fn is_pure(&self, f: Name, primops: &Primops<B>, memo: &mut HashMap<Name, bool>) -> bool {
+        if let Some(pure) = memo.get(&f) {
+            return *pure;
+        }
+        // Recursive functions are treated as impure
+        memo.insert(f, false);
+
+        let pure = if let Some((args, body)) = self.bodies.get(&f) {
+            let mut locals: HashSet<Name> = args.iter().copied().collect();
+            locals.insert(RETURN);
+            for instr in body.iter() {
+                if let Instr::Decl(id, _, _) | Instr::Init(id, _, _, _) = instr {
+                    locals.insert(*id);
+                }
+            }
+            let local_loc = |loc: &Loc<Name>| !matches!(loc, Loc::Addr(_)) && locals.contains(&loc.id());
+            let no_refs = |exp: &Exp<Name>| {
+                let mut refs = HashSet::new();
+                collect_refs(exp, &mut refs);
+                refs.is_empty()
+            };
+
+            body.iter().all(|instr| match instr {
+                Instr::Decl(_, _, _) | Instr::Goto(_) | Instr::Monomorphize(_, _) | Instr::End => true,
+                Instr::Init(_, _, exp, _) | Instr::Jump(exp, _, _) => no_refs(exp),
+                Instr::Copy(loc, exp, _) => local_loc(loc) && no_refs(exp),
+                Instr::Call(loc, _, g, args, _) => {
+                    local_loc(loc) && args.iter().all(no_refs) && self.is_pure(*g, primops, memo)
+                }
+                _ => false,
+            })
+        } else {
+            self.externs.get(&f).is_some_and(|ext| pure_primop(ext, primops))
+        };
+
+        memo.insert(f, pure);
+        pure
+    }
+}
+
+/// Linearize a function, unrolling at most `max_unroll` loop
+/// iterations, and inlining the linearized bodies of any functions
+/// it calls which can also be linearized. Returns `None` if the
+/// function still contains control flow afterwards. As with
+/// [linearize()], the function must be pure, which can be checked
+/// with [self_test_function].
+pub fn linearize_function<B: BV>(
+    defs: &[Def<Name, B>],
+    f: Name,
+    max_unroll: usize,
+    symtab: &mut Symtab,
+) -> Option<Vec<Instr<Name, B>>> {
+    let ctx = Context::new(Config::new());
+    let mut solver = Solver::<B>::new(&ctx);
+    Functions::new(defs).linearize(f, max_unroll, &mut HashSet::new(), &mut HashMap::new(), symtab, &mut solver)
+}
+
+/// Returns functions which are likely to benefit from linearization,
+/// in the order they appear in the architecture. A candidate must
+/// contain at least one branch, be below the [CANDIDATE_SIZE_LIMIT]
+/// and [CANDIDATE_BRANCH_LIMIT], and be pure, meaning it only writes
+/// to its own local variables and only calls other pure functions or
+/// primops. Functions in `keep`, such as those which are probed or
+/// traced, are never chosen. The `primops` should be those the
+/// architecture will be initialized with, so that any user-defined
+/// primops replacing pure built-in ones are taken into account.
+pub fn candidates<B: BV>(defs: &[Def<Name, B>], keep: &HashSet<Name>, primops: &Primops<B>) -> Vec<Name> {
+    let functions = Functions::new(defs);
+    let mut memo = HashMap::new();
+
+    defs.iter()
+        .filter_map(|def| match def {
+            Def::Fn(f, _, body) => {
+                let jumps = body.iter().filter(|instr| matches!(instr, Instr::Jump(_, _, _))).count();
+                if !keep.contains(f)
+                    && functions.signatures.contains_key(f)
+                    && body.len() <= CANDIDATE_SIZE_LIMIT
+                    && jumps > 0
+                    && jumps <= CANDIDATE_BRANCH_LIMIT
+                    && functions.is_pure(*f, primops, &mut memo)
+                {
+                    Some(*f)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        })
+        .collect()
+}
+
 /// Test that a rewritten function body is equivalent to the original
 /// body by constructing a symbolic execution problem that proves
 /// this. Note that this function should called with an uninitialized
-/// architecture.
+/// architecture, which is initialized with the given `primops`.
 #[allow(clippy::too_many_arguments)]
 pub fn self_test<'ir, B: BV>(
     num_threads: usize,
     mut arch: Vec<Def<Name, B>>,
     mut symtab: Symtab<'ir>,
     isa_config: &ISAConfig<B>,
+    primops: &Primops<B>,
     args: &[Name],
     arg_tys: &[Ty<Name>],
     ret_ty: &Ty<Name>,
@@ -447,8 +761,7 @@ pub fn self_test<'ir, B: BV>(
     instrs2: Vec<Instr<Name, B>>,
 ) -> bool {
     use crate::executor;
-    use crate::init::{initialize_architecture, Initialized};
-    use std::sync::atomic::{AtomicBool, Ordering};
+    use crate::init::{initialize_architecture_with_primops, Initialized};
 
     let fn1 = symtab.intern("self_test_fn1#");
     let fn2 = symtab.intern("self_test_fn2#");
@@ -477,15 +790,169 @@ pub fn self_test<'ir, B: BV>(
     }));
 
     let Initialized { regs, lets, shared_state } =
-        initialize_architecture(&mut arch, symtab, isa_config, AssertionMode::Optimistic);
+        initialize_architecture_with_primops(&mut arch, symtab, isa_config, AssertionMode::Optimistic, primops);
 
     let (args, _, instrs) = shared_state.functions.get(&comparison).unwrap();
     let task_state = executor::TaskState::new();
     let task =
         executor::LocalFrame::new(comparison, args, None, instrs).add_lets(&lets).add_regs(&regs).task(0, &task_state);
-    let result = Arc::new(AtomicBool::new(true));
+    let result = Arc::new(executor::AllUnsat::new());
 
     executor::start_multi(num_threads, None, vec![task], &shared_state, result.clone(), &executor::all_unsat_collector);
 
-    result.load(Ordering::Acquire)
+    // An equivalence which only holds when assuming unknown solver
+    // results is not a proof
+    result.all_unsat() && !result.assumed_unknown()
+}
+
+/// As [self_test], but looks up the arguments, types, and original
+/// body of the function `f` in the architecture. Returns false if `f`
+/// does not have both a type signature and a body.
+pub fn self_test_function<'ir, B: BV>(
+    num_threads: usize,
+    arch: Vec<Def<Name, B>>,
+    symtab: Symtab<'ir>,
+    isa_config: &ISAConfig<B>,
+    primops: &Primops<B>,
+    f: Name,
+    rewritten: Vec<Instr<Name, B>>,
+) -> bool {
+    let function = {
+        let functions = Functions::new(&arch);
+        match (functions.signatures.get(&f), functions.bodies.get(&f)) {
+            (Some((arg_tys, ret_ty)), Some((args, body))) => {
+                Some((args.to_vec(), arg_tys.to_vec(), (*ret_ty).clone(), body.to_vec()))
+            }
+            _ => None,
+        }
+    };
+
+    if let Some((args, arg_tys, ret_ty, body)) = function {
+        self_test(num_threads, arch, symtab, isa_config, primops, &args, &arg_tys, &ret_ty, body, rewritten)
+    } else {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitvector::b64::B64;
+    use crate::test_util::{isa_config, parse_ir};
+
+    static IR: &str = r#"
+register zR : %bv8
+
+val zinc : (%bv8) -> %bv8
+
+fn zinc(zx) {
+  jump @eq(zx, 0xFF) goto 3 ``;
+  return = @bvadd(zx, 0x01);
+  goto 4;
+  return = 0x00;
+  end
+}
+
+val zsum : (%bv8) -> %bv8
+
+fn zsum(zx) {
+  zi : %i64 = 0 ``;
+  zacc : %bv8 = zx ``;
+  jump @gteq(zi, 3) goto 6 ``;
+  zacc = zinc(zacc) ``;
+  zi = @iadd(zi, 1);
+  goto 2;
+  return = zacc;
+  end
+}
+
+val zsymbolic_loop : (%i64) -> %i64
+
+fn zsymbolic_loop(zn) {
+  zi : %i64 = 0 ``;
+  jump @gteq(zi, zn) goto 4 ``;
+  zi = @iadd(zi, 1);
+  goto 1;
+  return = zi;
+  end
+}
+
+val zwrites_r : (%bv8) -> %unit
+
+fn zwrites_r(zx) {
+  jump @eq(zx, 0x00) goto 2 ``;
+  zR = zx;
+  return = ();
+  end
+}
+"#;
+
+    fn is_linear(body: &[Instr<Name, B64>]) -> bool {
+        body.iter().all(|instr| !matches!(instr, Instr::Goto(_) | Instr::Jump(_, _, _) | Instr::Call(_, _, _, _, _)))
+    }
+
+    #[test]
+    fn unroll_and_inline() {
+        let defs = parse_ir(IR);
+        let mut symtab = Symtab::new();
+        let arch = symtab.intern_defs(&defs);
+        let isa_config = isa_config(&symtab, "R", "{}");
+        let zsum = symtab.lookup("zsum");
+
+        let body = linearize_function(&arch, zsum, DEFAULT_UNROLL_BOUND, &mut symtab).unwrap();
+        assert!(is_linear(&body));
+        assert!(self_test_function(1, arch.clone(), symtab.clone(), &isa_config, &Primops::default(), zsum, body));
+
+        // Not enough iterations to fully unroll the loop
+        assert!(linearize_function(&arch, zsum, 2, &mut symtab).is_none())
+    }
+
+    #[test]
+    fn symbolic_loop() {
+        let defs = parse_ir(IR);
+        let mut symtab = Symtab::new();
+        let arch = symtab.intern_defs(&defs);
+        let f = symtab.lookup("zsymbolic_loop");
+        assert!(linearize_function(&arch, f, DEFAULT_UNROLL_BOUND, &mut symtab).is_none())
+    }
+
+    #[test]
+    fn auto_candidates() {
+        let defs = parse_ir(IR);
+        let mut symtab = Symtab::new();
+        let arch = symtab.intern_defs(&defs);
+
+        let found = candidates(&arch, &HashSet::new(), &Primops::default());
+        assert_eq!(found, vec![symtab.lookup("zinc"), symtab.lookup("zsum"), symtab.lookup("zsymbolic_loop")]);
+
+        let keep = vec![symtab.lookup("zinc")].into_iter().collect();
+        assert!(!candidates(&arch, &keep, &Primops::default()).contains(&symtab.lookup("zinc")))
+    }
+
+    #[test]
+    fn user_primops_not_pure() {
+        let defs = parse_ir(
+            r#"
+val zadd_bits = "add_bits" : (%bv8, %bv8) -> %bv8
+
+val zinc : (%bv8) -> %bv8
+
+fn zinc(zx) {
+  jump @eq(zx, 0xFF) goto 3 ``;
+  return = zadd_bits(zx, 0x01) ``;
+  goto 4;
+  return = 0x00;
+  end
+}
+"#,
+        );
+        let mut symtab = Symtab::new();
+        let arch = symtab.intern_defs(&defs);
+        let zinc = symtab.lookup("zinc");
+
+        let mut primops = Primops::default();
+        assert_eq!(candidates(&arch, &HashSet::new(), &primops), vec![zinc]);
+        primops.register_closure("add_bits", |_, _, _, _| Ok(Val::Unit));
+        assert!(candidates(&arch, &HashSet::new(), &primops).is_empty())
+    }
 }
@@ -0,0 +1,1203 @@
+// BSD 2-Clause License
+//
+// Copyright (c) 2020 Alasdair Armstrong
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+// notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+// notice, this list of conditions and the following disclaimer in the
+// documentation and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+//! This module implements some simple optimisation passes over
+//! function bodies, which are run before primops are inserted:
+//!
+//! - [inline_small_functions] replaces calls to small functions with
+//!   no internal control flow, calls, or operations that can fail
+//!   with their bodies.
+//!
+//! - [constant_propagation] propagates literal values through a
+//!   function body, folding any operations whose arguments become
+//!   literals, and any jumps whose condition becomes known. Registers
+//!   with a concrete default value in the ISA config that are never
+//!   written (or referenced) by the IR are also treated as
+//!   constants. Note that this means reads of those registers no
+//!   longer appear as events in traces.
+//!
+//! - [dead_code_elimination] removes assignments to local variables
+//!   whose values are never used, and unused declarations.
+//!
+//! Both constant propagation and dead code elimination work by
+//! placing the function body into SSA form using [super::ssa::CFG],
+//! and then rewriting the basic blocks in place. As no code is moved
+//! between blocks, the SSA numbers can simply be dropped afterwards.
+//!
+//! Much like [super::linearize], each pass can be checked by using
+//! [super::linearize::self_test] to prove that the function bodies
+//! before and after the pass are equivalent.
+
+use petgraph::graph::NodeIndex;
+use std::fmt;
+use std::str::FromStr;
+
+use super::source_loc::SourceLoc;
+use super::ssa::{unssa_ty, BlockInstr, BlockLoc, SSAName, Terminator, CFG};
+use super::*;
+use crate::config::ISAConfig;
+use crate::executor::eval_op;
+use crate::log;
+use crate::primop::Primops;
+use crate::smt::{Config, Context};
+
+/// Functions with at most this many instructions are considered for
+/// inlining.
+pub const INLINE_THRESHOLD: usize = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Pass {
+    Inline,
+    ConstantPropagation,
+    DeadCode,
+}
+
+/// All the passes, in the order they are run by default.
+pub static ALL_PASSES: [Pass; 3] = [Pass::Inline, Pass::ConstantPropagation, Pass::DeadCode];
+
+impl fmt::Display for Pass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pass::Inline => write!(f, "inline"),
+            Pass::ConstantPropagation => write!(f, "const-prop"),
+            Pass::DeadCode => write!(f, "dead-code"),
+        }
+    }
+}
+
+impl FromStr for Pass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "inline" => Ok(Pass::Inline),
+            "const-prop" => Ok(Pass::ConstantPropagation),
+            "dead-code" => Ok(Pass::DeadCode),
+            _ => Err(format!("Unknown optimisation pass {}, expected inline, const-prop, or dead-code", s)),
+        }
+    }
+}
+
+/// Parse a comma separated list of passes, where `all` stands for
+/// [ALL_PASSES].
+pub fn parse_passes(s: &str) -> Result<Vec<Pass>, String> {
+    if s == "all" {
+        Ok(ALL_PASSES.to_vec())
+    } else {
+        s.split(',').map(|pass| pass.trim().parse()).collect()
+    }
+}
+
+pub(super) fn is_literal<A>(exp: &Exp<A>) -> bool {
+    matches!(exp, Exp::Bool(_) | Exp::Bits(_) | Exp::String(_) | Exp::Unit | Exp::I64(_) | Exp::I128(_))
+}
+
+fn same_literal<A>(lhs: &Exp<A>, rhs: &Exp<A>) -> bool {
+    use Exp::*;
+    match (lhs, rhs) {
+        (Bool(x), Bool(y)) => x == y,
+        (Bits(x), Bits(y)) => x == y,
+        (String(x), String(y)) => x == y,
+        (Unit, Unit) => true,
+        (I64(x), I64(y)) => x == y,
+        (I128(x), I128(y)) => x == y,
+        (_, _) => false,
+    }
+}
+
+fn cast_literal<A, C>(exp: &Exp<A>) -> Option<Exp<C>> {
+    use Exp::*;
+    match exp {
+        Bool(b) => Some(Bool(*b)),
+        Bits(bv) => Some(Bits(*bv)),
+        String(s) => Some(String(s.clone())),
+        Unit => Some(Unit),
+        I64(n) => Some(I64(*n)),
+        I128(n) => Some(I128(*n)),
+        _ => None,
+    }
+}
+
+fn literal_to_val<A, B: BV>(exp: &Exp<A>) -> Option<Val<B>> {
+    match exp {
+        Exp::Bool(b) => Some(Val::Bool(*b)),
+        Exp::Bits(bv) => Some(Val::Bits(B::new(bv.lower_u64(), bv.len()))),
+        Exp::String(s) => Some(Val::String(s.clone())),
+        Exp::Unit => Some(Val::Unit),
+        Exp::I64(n) => Some(Val::I64(*n)),
+        Exp::I128(n) => Some(Val::I128(*n)),
+        _ => None,
+    }
+}
+
+fn val_to_literal<A, B: BV>(val: &Val<B>) -> Option<Exp<A>> {
+    match val {
+        Val::Bool(b) => Some(Exp::Bool(*b)),
+        Val::Bits(bv) if bv.len() <= 64 => Some(Exp::Bits(B64::new(bv.lower_u64(), bv.len()))),
+        Val::String(s) => Some(Exp::String(s.clone())),
+        Val::Unit => Some(Exp::Unit),
+        Val::I64(n) => Some(Exp::I64(*n)),
+        Val::I128(n) => Some(Exp::I128(*n)),
+        _ => None,
+    }
+}
+
+/// Substitute constants for variables in an expression, and evaluate
+/// any operations that have only literal arguments. Operations are
+/// evaluated using the same code as the executor, so folding can
+/// never change their meaning. Sets `folded` if anything was
+/// substituted or evaluated.
+fn fold_exp<B: BV>(
+    exp: &Exp<SSAName>,
+    consts: &HashMap<SSAName, Exp<SSAName>>,
+    folded: &mut bool,
+    solver: &mut Solver<B>,
+) -> Exp<SSAName> {
+    use Exp::*;
+    match exp {
+        Id(id) => match consts.get(id) {
+            Some(value) => {
+                *folded = true;
+                value.clone()
+            }
+            None => Id(*id),
+        },
+        Call(op, args) => {
+            let args: Vec<_> = args.iter().map(|arg| fold_exp(arg, consts, folded, solver)).collect();
+            let vals: Option<Vec<Val<B>>> = args.iter().map(literal_to_val).collect();
+            if let Some(vals) = vals {
+                if let Ok(result) = eval_op(*op, vals, solver, SourceLoc::unknown()) {
+                    if let Some(exp) = val_to_literal(&result) {
+                        *folded = true;
+                        return exp;
+                    }
+                }
+            }
+            Call(*op, args)
+        }
+        Struct(s, fields) => {
+            Struct(*s, fields.iter().map(|(field, exp)| (*field, fold_exp(exp, consts, folded, solver))).collect())
+        }
+        Kind(ctor, exp) => Kind(*ctor, Box::new(fold_exp(exp, consts, folded, solver))),
+        Unwrap(ctor, exp) => Unwrap(*ctor, Box::new(fold_exp(exp, consts, folded, solver))),
+        Field(exp, field) => Field(Box::new(fold_exp(exp, consts, folded, solver)), *field),
+        _ => exp.clone(),
+    }
+}
+
+fn loc_id(loc: &BlockLoc) -> SSAName {
+    match loc {
+        BlockLoc::Id(id) => *id,
+        BlockLoc::Field(loc, _, _) | BlockLoc::Addr(loc) => loc_id(loc),
+    }
+}
+
+fn has_addr(loc: &BlockLoc) -> bool {
+    match loc {
+        BlockLoc::Id(_) => false,
+        BlockLoc::Field(loc, _, _) => has_addr(loc),
+        BlockLoc::Addr(_) => true,
+    }
+}
+
+fn strip_loc(loc: &BlockLoc) -> Loc<Name> {
+    match loc {
+        BlockLoc::Id(id) => Loc::Id(id.base_name()),
+        BlockLoc::Field(loc, _, field) => Loc::Field(Box::new(strip_loc(loc)), field.base_name()),
+        BlockLoc::Addr(loc) => Loc::Addr(Box::new(strip_loc(loc))),
+    }
+}
+
+fn strip_exp(exp: &Exp<SSAName>) -> Exp<Name> {
+    use Exp::*;
+    match exp {
+        Id(id) => Id(id.base_name()),
+        Ref(reg) => Ref(reg.base_name()),
+        Bool(b) => Bool(*b),
+        Bits(bv) => Bits(*bv),
+        String(s) => String(s.clone()),
+        Unit => Unit,
+        I64(n) => I64(*n),
+        I128(n) => I128(*n),
+        Undefined(ty) => Undefined(unssa_ty(ty)),
+        Struct(s, fields) => {
+            Struct(s.base_name(), fields.iter().map(|(field, exp)| (field.base_name(), strip_exp(exp))).collect())
+        }
+        Kind(ctor, exp) => Kind(ctor.base_name(), Box::new(strip_exp(exp))),
+        Unwrap(ctor, exp) => Unwrap(ctor.base_name(), Box::new(strip_exp(exp))),
+        Field(exp, field) => Field(Box::new(strip_exp(exp)), field.base_name()),
+        Call(op, args) => Call(*op, args.iter().map(strip_exp).collect()),
+    }
+}
+
+fn strip_instr<B: BV>(instr: &BlockInstr<B>) -> Instr<Name, B> {
+    use BlockInstr::*;
+    match instr {
+        Decl(id, ty, info) => Instr::Decl(id.base_name(), unssa_ty(ty), *info),
+        Init(id, ty, exp, info) => Instr::Init(id.base_name(), unssa_ty(ty), strip_exp(exp), *info),
+        Copy(loc, exp, info) => Instr::Copy(strip_loc(loc), strip_exp(exp), *info),
+        Monomorphize(id, info) => Instr::Monomorphize(id.base_name(), *info),
+        Call(loc, ext, f, args, info) => {
+            Instr::Call(strip_loc(loc), *ext, *f, args.iter().map(strip_exp).collect(), *info)
+        }
+        PrimopUnary(loc, fptr, exp, info) => Instr::PrimopUnary(strip_loc(loc), *fptr, strip_exp(exp), *info),
+        PrimopBinary(loc, fptr, lhs, rhs, info) => {
+            Instr::PrimopBinary(strip_loc(loc), *fptr, strip_exp(lhs), strip_exp(rhs), *info)
+        }
+        PrimopVariadic(loc, fptr, args, info) => {
+            Instr::PrimopVariadic(strip_loc(loc), *fptr, args.iter().map(strip_exp).collect(), *info)
+        }
+    }
+}
+
+fn to_ssa<B: BV>(instrs: &[Instr<Name, B>]) -> CFG<B> {
+    let labeled = prune_labels(label_instrs(instrs.to_vec()));
+    let mut cfg = CFG::new(&labeled);
+    cfg.ssa();
+    cfg
+}
+
+/// Turn a CFG produced by [to_ssa] back into a sequence of
+/// instructions by dropping the SSA numbers. The nodes are in the same
+/// order as the original instructions, so fallthrough between blocks
+/// is preserved. Any blocks that have become unreachable are removed.
+fn from_ssa<B: BV>(cfg: &CFG<B>) -> Vec<Instr<Name, B>> {
+    let num_blocks = cfg.graph.node_count();
+    let block = |i: usize| &cfg.graph[NodeIndex::new(i)];
+
+    let mut targets: HashMap<usize, usize> = HashMap::new();
+    for i in 0..num_blocks {
+        if let Some(label) = block(i).label {
+            targets.insert(label, i);
+        }
+    }
+
+    let mut reachable = vec![false; num_blocks];
+    let mut stack = vec![cfg.root.index()];
+    while let Some(i) = stack.pop() {
+        if i >= num_blocks || reachable[i] {
+            continue;
+        }
+        reachable[i] = true;
+        match &block(i).terminator {
+            Terminator::Continue => stack.push(i + 1),
+            Terminator::Goto(target) => stack.push(targets[target]),
+            Terminator::Jump(_, target, _) => {
+                stack.push(i + 1);
+                stack.push(targets[target])
+            }
+            Terminator::Failure | Terminator::Arbitrary | Terminator::End => (),
+        }
+    }
+
+    let mut instrs = Vec::new();
+    let mut jump_table: HashMap<usize, usize> = HashMap::new();
+
+    let blocks: Vec<usize> = (0..num_blocks).filter(|i| reachable[*i]).collect();
+    for (n, i) in blocks.iter().enumerate() {
+        let block = block(*i);
+        if let Some(label) = block.label {
+            jump_table.insert(label, instrs.len());
+        }
+        instrs.extend(block.instrs.iter().map(strip_instr));
+        match &block.terminator {
+            Terminator::Continue => (),
+            // A goto to the following block is redundant
+            Terminator::Goto(target) if blocks.get(n + 1) == Some(&targets[target]) => (),
+            Terminator::Goto(target) => instrs.push(Instr::Goto(*target)),
+            Terminator::Jump(exp, target, info) => instrs.push(Instr::Jump(strip_exp(exp), *target, *info)),
+            Terminator::Failure => instrs.push(Instr::Failure),
+            Terminator::Arbitrary => instrs.push(Instr::Arbitrary),
+            Terminator::End => instrs.push(Instr::End),
+        }
+    }
+
+    for instr in instrs.iter_mut() {
+        match instr {
+            Instr::Goto(target) | Instr::Jump(_, target, _) => *target = jump_table[target],
+            _ => (),
+        }
+    }
+
+    instrs
+}
+
+/// Propagate constants through a function body with the given
+/// arguments. The `registers` map contains registers which are known
+/// to always have a constant value. The solver is only used to
+/// evaluate operations on literals, so a single solver can be shared
+/// by every call. Returns the new body, and whether anything in it
+/// was folded.
+pub fn constant_propagation<B: BV>(
+    instrs: &[Instr<Name, B>],
+    args: &[Name],
+    registers: &HashMap<Name, Exp<Name>>,
+    solver: &mut Solver<B>,
+) -> (Vec<Instr<Name, B>>, bool) {
+    if instrs.is_empty() {
+        return (Vec::new(), false);
+    }
+
+    let mut cfg = to_ssa(instrs);
+
+    // Registers and arguments are not renamed by the SSA transform,
+    // so we can only use a register's value if no argument shadows it.
+    let mut consts: HashMap<SSAName, Exp<SSAName>> = registers
+        .iter()
+        .filter(|(reg, _)| !args.contains(reg))
+        .filter_map(|(reg, exp)| Some((SSAName::new(*reg), cast_literal(exp)?)))
+        .collect();
+
+    loop {
+        let mut changed = false;
+
+        for ix in cfg.graph.node_indices() {
+            let block = &cfg.graph[ix];
+
+            for (id, args) in &block.phis {
+                if consts.contains_key(id) {
+                    continue;
+                }
+                if let Some((first, rest)) = args.split_first() {
+                    if let Some(value) = consts.get(first) {
+                        if rest.iter().all(|arg| consts.get(arg).is_some_and(|other| same_literal(value, other))) {
+                            consts.insert(*id, value.clone());
+                            changed = true
+                        }
+                    }
+                }
+            }
+
+            for instr in &block.instrs {
+                let (id, exp) = match instr {
+                    BlockInstr::Init(id, _, exp, _) | BlockInstr::Copy(BlockLoc::Id(id), exp, _) => (id, exp),
+                    _ => continue,
+                };
+                if id.ssa_number() < 0 || consts.contains_key(id) {
+                    continue;
+                }
+                let exp = fold_exp(exp, &consts, &mut false, solver);
+                if is_literal(&exp) {
+                    consts.insert(*id, exp);
+                    changed = true
+                }
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    let mut folded = false;
+
+    for ix in cfg.graph.node_indices().collect::<Vec<_>>() {
+        let block = &mut cfg.graph[ix];
+
+        for instr in block.instrs.iter_mut() {
+            use BlockInstr::*;
+            match instr {
+                Init(_, _, exp, _) | Copy(_, exp, _) | PrimopUnary(_, _, exp, _) => {
+                    *exp = fold_exp(exp, &consts, &mut folded, solver)
+                }
+                PrimopBinary(_, _, lhs, rhs, _) => {
+                    *lhs = fold_exp(lhs, &consts, &mut folded, solver);
+                    *rhs = fold_exp(rhs, &consts, &mut folded, solver)
+                }
+                Call(_, _, _, args, _) | PrimopVariadic(_, _, args, _) => {
+                    for arg in args.iter_mut() {
+                        *arg = fold_exp(arg, &consts, &mut folded, solver)
+                    }
+                }
+                Decl(_, _, _) | Monomorphize(_, _) => (),
+            }
+        }
+
+        if let Terminator::Jump(exp, target, _) = &block.terminator {
+            match fold_exp(exp, &consts, &mut folded, solver) {
+                Exp::Bool(true) => block.terminator = Terminator::Goto(*target),
+                Exp::Bool(false) => block.terminator = Terminator::Continue,
+                exp => {
+                    if let Terminator::Jump(cond, _, _) = &mut block.terminator {
+                        *cond = exp
+                    }
+                }
+            }
+        }
+    }
+
+    (from_ssa(&cfg), folded)
+}
+
+/// Returns true if evaluating the expression might fail, in which
+/// case it cannot be removed even if its value is never used.
+fn may_fail<A>(exp: &Exp<A>) -> bool {
+    use Exp::*;
+    match exp {
+        Unwrap(_, _) | Call(Op::Head, _) | Call(Op::Tail, _) => true,
+        Kind(_, exp) | Field(exp, _) => may_fail(exp),
+        Struct(_, fields) => fields.iter().any(|(_, exp)| may_fail(exp)),
+        Call(_, args) => args.iter().any(may_fail),
+        _ => false,
+    }
+}
+
+fn loc_uses(loc: &BlockLoc, uses: &mut HashSet<SSAName>) {
+    match loc {
+        BlockLoc::Id(_) => (),
+        BlockLoc::Field(loc, base, _) => {
+            uses.insert(*base);
+            loc_uses(loc, uses)
+        }
+        BlockLoc::Addr(loc) => loc_uses(loc, uses),
+    }
+}
+
+fn instr_uses<B: BV>(instr: &BlockInstr<B>, uses: &mut HashSet<SSAName>) {
+    use BlockInstr::*;
+    match instr {
+        Decl(_, _, _) => (),
+        Init(_, _, exp, _) => exp.collect_ids(uses),
+        Monomorphize(id, _) => {
+            uses.insert(*id);
+        }
+        Copy(loc, exp, _) | PrimopUnary(loc, _, exp, _) => {
+            loc_uses(loc, uses);
+            exp.collect_ids(uses)
+        }
+        PrimopBinary(loc, _, lhs, rhs, _) => {
+            loc_uses(loc, uses);
+            lhs.collect_ids(uses);
+            rhs.collect_ids(uses)
+        }
+        Call(loc, _, _, args, _) | PrimopVariadic(loc, _, args, _) => {
+            loc_uses(loc, uses);
+            args.iter().for_each(|arg| arg.collect_ids(uses))
+        }
+    }
+}
+
+fn instr_names<B: BV>(instr: &BlockInstr<B>, names: &mut HashSet<Name>) {
+    let mut uses = HashSet::new();
+    instr_uses(instr, &mut uses);
+    if let Some(id) = instr.write() {
+        names.insert(id);
+    }
+    names.extend(uses.iter().map(|id| id.base_name()))
+}
+
+enum Definition {
+    Instr(NodeIndex, usize),
+    Phi(NodeIndex, usize),
+}
+
+/// Remove assignments to local variables that are never used, as
+/// well as declarations of variables that are no longer mentioned.
+/// Returns the new body, and whether anything was removed from it.
+pub fn dead_code_elimination<B: BV>(instrs: &[Instr<Name, B>]) -> (Vec<Instr<Name, B>>, bool) {
+    use BlockInstr::*;
+
+    if instrs.is_empty() {
+        return (Vec::new(), false);
+    }
+
+    let mut cfg = to_ssa(instrs);
+
+    // Writes via a reference are treated as declarations by the SSA
+    // transform, so we keep every write to such variables.
+    let mut pinned = HashSet::new();
+    let mut definitions = HashMap::new();
+    for ix in cfg.graph.node_indices() {
+        for (k, (id, _)) in cfg.graph[ix].phis.iter().enumerate() {
+            definitions.insert(*id, Definition::Phi(ix, k));
+        }
+        for (k, instr) in cfg.graph[ix].instrs.iter().enumerate() {
+            if let Some((id, _)) = instr.write_ssa() {
+                definitions.insert(id, Definition::Instr(ix, k));
+            }
+            match instr {
+                Copy(loc, _, _) | Call(loc, _, _, _, _) if has_addr(loc) => {
+                    pinned.insert(loc_id(loc).base_name());
+                }
+                _ => (),
+            }
+        }
+    }
+
+    let is_critical = |instr: &BlockInstr<B>| match instr {
+        Decl(_, _, _) => false,
+        Init(id, _, exp, _) => id.ssa_number() < 0 || pinned.contains(&id.base_name()) || may_fail(exp),
+        Copy(loc, exp, _) => {
+            let id = loc_id(loc);
+            id.ssa_number() < 0
+                || id.base_name() == RETURN
+                || has_addr(loc)
+                || pinned.contains(&id.base_name())
+                || may_fail(exp)
+        }
+        _ => true,
+    };
+
+    let mut live: HashSet<(NodeIndex, usize)> = HashSet::new();
+    let mut worklist: HashSet<SSAName> = HashSet::new();
+    for ix in cfg.graph.node_indices() {
+        let block = &cfg.graph[ix];
+        for (k, instr) in block.instrs.iter().enumerate() {
+            if is_critical(instr) {
+                live.insert((ix, k));
+                instr_uses(instr, &mut worklist)
+            }
+        }
+        if let Terminator::Jump(exp, _, _) = &block.terminator {
+            exp.collect_ids(&mut worklist)
+        }
+    }
+
+    let mut live_names: HashSet<SSAName> = HashSet::new();
+    let mut worklist: Vec<SSAName> = worklist.into_iter().collect();
+    while let Some(id) = worklist.pop() {
+        if !live_names.insert(id) {
+            continue;
+        }
+        match definitions.get(&id) {
+            Some(Definition::Instr(ix, k)) => {
+                live.insert((*ix, *k));
+                let mut uses = HashSet::new();
+                instr_uses(&cfg.graph[*ix].instrs[*k], &mut uses);
+                worklist.extend(uses)
+            }
+            Some(Definition::Phi(ix, k)) => worklist.extend(cfg.graph[*ix].phis[*k].1.iter().copied()),
+            None => (),
+        }
+    }
+
+    let mut removed = false;
+
+    // An unused Init still declares its variable, so it becomes a Decl
+    // which is removed below if nothing else mentions the variable.
+    for ix in cfg.graph.node_indices().collect::<Vec<_>>() {
+        let instrs = std::mem::take(&mut cfg.graph[ix].instrs);
+        cfg.graph[ix].instrs = instrs
+            .into_iter()
+            .enumerate()
+            .filter_map(|(k, instr)| match instr {
+                _ if live.contains(&(ix, k)) => Some(instr),
+                Decl(_, _, _) => Some(instr),
+                Init(id, ty, _, info) => {
+                    removed = true;
+                    Some(Decl(id, ty, info))
+                }
+                _ => {
+                    removed = true;
+                    None
+                }
+            })
+            .collect()
+    }
+
+    let mut mentioned = HashSet::new();
+    mentioned.insert(RETURN);
+    for ix in cfg.graph.node_indices() {
+        let block = &cfg.graph[ix];
+        for instr in &block.instrs {
+            if !matches!(instr, Decl(_, _, _)) {
+                instr_names(instr, &mut mentioned)
+            }
+        }
+        if let Terminator::Jump(exp, _, _) = &block.terminator {
+            let mut uses = HashSet::new();
+            exp.collect_ids(&mut uses);
+            mentioned.extend(uses.iter().map(|id: &SSAName| id.base_name()))
+        }
+    }
+
+    for ix in cfg.graph.node_indices().collect::<Vec<_>>() {
+        cfg.graph[ix].instrs.retain(|instr| match instr {
+            Decl(id, _, _) if !mentioned.contains(&id.base_name()) => {
+                removed = true;
+                false
+            }
+            _ => true,
+        })
+    }
+
+    (from_ssa(&cfg), removed)
+}
+
+fn rename_exp(exp: &Exp<Name>, renaming: &HashMap<Name, Name>) -> Exp<Name> {
+    use Exp::*;
+    match exp {
+        Id(id) => Id(*renaming.get(id).unwrap_or(id)),
+        Struct(s, fields) => {
+            Struct(*s, fields.iter().map(|(field, exp)| (*field, rename_exp(exp, renaming))).collect())
+        }
+        Kind(ctor, exp) => Kind(*ctor, Box::new(rename_exp(exp, renaming))),
+        Unwrap(ctor, exp) => Unwrap(*ctor, Box::new(rename_exp(exp, renaming))),
+        Field(exp, field) => Field(Box::new(rename_exp(exp, renaming)), *field),
+        Call(op, args) => Call(*op, args.iter().map(|arg| rename_exp(arg, renaming)).collect()),
+        _ => exp.clone(),
+    }
+}
+
+fn rename_loc(loc: &Loc<Name>, renaming: &HashMap<Name, Name>) -> Loc<Name> {
+    match loc {
+        Loc::Id(id) => Loc::Id(*renaming.get(id).unwrap_or(id)),
+        Loc::Field(loc, field) => Loc::Field(Box::new(rename_loc(loc, renaming)), *field),
+        Loc::Addr(loc) => Loc::Addr(Box::new(rename_loc(loc, renaming))),
+    }
+}
+
+fn rename_instr<B: BV>(instr: &Instr<Name, B>, renaming: &HashMap<Name, Name>) -> Instr<Name, B> {
+    use Instr::*;
+    let rename = |id: &Name| *renaming.get(id).unwrap_or(id);
+    match instr {
+        Decl(id, ty, info) => Decl(rename(id), ty.clone(), *info),
+        Init(id, ty, exp, info) => Init(rename(id), ty.clone(), rename_exp(exp, renaming), *info),
+        Copy(loc, exp, info) => Copy(rename_loc(loc, renaming), rename_exp(exp, renaming), *info),
+        Monomorphize(id, info) => Monomorphize(rename(id), *info),
+        Call(loc, ext, f, args, info) => {
+            Call(rename_loc(loc, renaming), *ext, *f, args.iter().map(|arg| rename_exp(arg, renaming)).collect(), *info)
+        }
+        PrimopUnary(loc, fptr, exp, info) => {
+            PrimopUnary(rename_loc(loc, renaming), *fptr, rename_exp(exp, renaming), *info)
+        }
+        PrimopBinary(loc, fptr, lhs, rhs, info) => {
+            PrimopBinary(rename_loc(loc, renaming), *fptr, rename_exp(lhs, renaming), rename_exp(rhs, renaming), *info)
+        }
+        PrimopVariadic(loc, fptr, args, info) => PrimopVariadic(
+            rename_loc(loc, renaming),
+            *fptr,
+            args.iter().map(|arg| rename_exp(arg, renaming)).collect(),
+            *info,
+        ),
+        _ => instr.clone(),
+    }
+}
+
+fn writes_return<B: BV>(instr: &Instr<Name, B>) -> bool {
+    use Instr::*;
+    match instr {
+        Copy(loc, _, _)
+        | Call(loc, _, _, _, _)
+        | PrimopUnary(loc, _, _, _)
+        | PrimopBinary(loc, _, _, _, _)
+        | PrimopVariadic(loc, _, _, _) => loc.id() == RETURN,
+        _ => false,
+    }
+}
+
+/// The arguments and body of a function
+type FnBody<B> = (Vec<Name>, Vec<Instr<Name, B>>);
+
+/// A function can be inlined if it is small, has no control flow
+/// other than the final `end`, and sets its return value. As an
+/// inlined body does not get its own stack frame, it must also be
+/// unable to fail or call anything, so any backtrace reported by the
+/// executor is the same with or without inlining.
+fn inlinable<B: BV>(body: &[Instr<Name, B>]) -> bool {
+    use Instr::*;
+    match body.split_last() {
+        Some((End, body)) => {
+            body.len() < INLINE_THRESHOLD
+                && body.iter().any(writes_return)
+                && body.iter().all(|instr| match instr {
+                    Decl(_, _, _) => true,
+                    Init(_, _, exp, _) | Copy(_, exp, _) => !may_fail(exp),
+                    _ => false,
+                })
+        }
+        _ => false,
+    }
+}
+
+/// Returns the instructions that replace a call `loc = f(args)` when
+/// the body of `f` is inlined. The body must be straight-line code
+/// ending in `end`. The parameters, return value, and local variables
+/// of the callee are all renamed to fresh names.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn inline_call<B: BV>(
+    loc: Loc<Name>,
+    args: Vec<Exp<Name>>,
+    info: SourceLoc,
+    params: &[Name],
+    arg_tys: &[Ty<Name>],
+    ret_ty: &Ty<Name>,
+    callee: &[Instr<Name, B>],
+    symtab: &mut Symtab,
+) -> Vec<Instr<Name, B>> {
+    let mut renaming = HashMap::new();
+    for id in params.iter().chain(std::iter::once(&RETURN)) {
+        renaming.insert(*id, symtab.gensym());
+    }
+    for instr in callee {
+        if let Instr::Decl(id, _, _) | Instr::Init(id, _, _, _) = instr {
+            renaming.entry(*id).or_insert_with(|| symtab.gensym());
+        }
+    }
+
+    let mut inlined = Vec::new();
+    for ((param, ty), arg) in params.iter().zip(arg_tys).zip(args) {
+        inlined.push(Instr::Init(renaming[param], ty.clone(), arg, info))
+    }
+    inlined.push(Instr::Decl(renaming[&RETURN], ret_ty.clone(), info));
+    inlined.extend(callee[..callee.len() - 1].iter().map(|instr| rename_instr(instr, &renaming)));
+    inlined.push(Instr::Copy(loc, Exp::Id(renaming[&RETURN]), info));
+    inlined
+}
+
+/// Inline calls to small functions (see [INLINE_THRESHOLD]) which
+/// contain no control flow. Functions in `keep` are never inlined,
+/// which should include any functions that are probed, traced, or
+/// that execution should stop at. Only
+/// the original bodies are inlined, so calls within an inlined body
+/// remain as calls. Returns the names of the functions which were
+/// changed.
+pub fn inline_small_functions<B: BV>(
+    defs: &mut [Def<Name, B>],
+    keep: &HashSet<Name>,
+    symtab: &mut Symtab,
+) -> HashSet<Name> {
+    let mut signatures: HashMap<Name, (Vec<Ty<Name>>, Ty<Name>)> = HashMap::new();
+    for def in defs.iter() {
+        if let Def::Val(f, arg_tys, ret_ty) = def {
+            signatures.insert(*f, (arg_tys.clone(), ret_ty.clone()));
+        }
+    }
+
+    let mut inlinable_fns: HashMap<Name, FnBody<B>> = HashMap::new();
+    for def in defs.iter() {
+        if let Def::Fn(f, args, body) = def {
+            if !keep.contains(f) && signatures.contains_key(f) && inlinable(body) {
+                inlinable_fns.insert(*f, (args.clone(), body.clone()));
+            }
+        }
+    }
+
+    let mut changed = HashSet::new();
+
+    for def in defs.iter_mut() {
+        if let Def::Fn(caller, _, body) = def {
+            if !body.iter().any(|instr| matches!(instr, Instr::Call(_, _, f, _, _) if inlinable_fns.contains_key(f))) {
+                continue;
+            }
+
+            let mut new_body = Vec::new();
+            for instr in label_instrs(std::mem::take(body)) {
+                let (label, instr) = match instr {
+                    LabeledInstr::Labeled(label, instr) => (Some(label), instr),
+                    LabeledInstr::Unlabeled(instr) => (None, instr),
+                };
+                match instr {
+                    Instr::Call(loc, _, f, args, info) if inlinable_fns.contains_key(&f) && f != *caller => {
+                        let (params, callee) = &inlinable_fns[&f];
+                        let (arg_tys, ret_ty) = &signatures[&f];
+                        let inlined = inline_call(loc, args, info, params, arg_tys, ret_ty, callee, symtab);
+
+                        for (i, instr) in inlined.into_iter().enumerate() {
+                            match label {
+                                Some(label) if i == 0 => new_body.push(LabeledInstr::Labeled(label, instr)),
+                                _ => new_body.push(LabeledInstr::Unlabeled(instr)),
+                            }
+                        }
+                        changed.insert(*caller);
+                    }
+                    instr => match label {
+                        Some(label) => new_body.push(LabeledInstr::Labeled(label, instr)),
+                        None => new_body.push(LabeledInstr::Unlabeled(instr)),
+                    },
+                }
+            }
+            *body = unlabel_instrs(new_body)
+        }
+    }
+
+    changed
+}
+
+fn collect_writes<B: BV>(instr: &Instr<Name, B>, written: &mut HashSet<Name>) {
+    use Instr::*;
+    let mut exps: Vec<&Exp<Name>> = Vec::new();
+    match instr {
+        Decl(id, _, _) => {
+            written.insert(*id);
+        }
+        Init(id, _, exp, _) => {
+            written.insert(*id);
+            exps.push(exp)
+        }
+        Copy(loc, exp, _) | PrimopUnary(loc, _, exp, _) => {
+            written.insert(loc.id());
+            exps.push(exp)
+        }
+        PrimopBinary(loc, _, lhs, rhs, _) => {
+            written.insert(loc.id());
+            exps.push(lhs);
+            exps.push(rhs)
+        }
+        Call(loc, _, _, args, _) | PrimopVariadic(loc, _, args, _) => {
+            written.insert(loc.id());
+            exps.extend(args)
+        }
+        Jump(exp, _, _) => exps.push(exp),
+        _ => (),
+    }
+    // Any register that has a reference taken might be written via
+    // that reference.
+    for exp in exps {
+        collect_refs(exp, written)
+    }
+}
+
+pub(super) fn collect_refs(exp: &Exp<Name>, refs: &mut HashSet<Name>) {
+    use Exp::*;
+    match exp {
+        Ref(reg) => {
+            refs.insert(*reg);
+        }
+        Struct(_, fields) => fields.iter().for_each(|(_, exp)| collect_refs(exp, refs)),
+        Kind(_, exp) | Unwrap(_, exp) | Field(exp, _) => collect_refs(exp, refs),
+        Call(_, args) => args.iter().for_each(|exp| collect_refs(exp, refs)),
+        _ => (),
+    }
+}
+
+/// Returns the registers with a concrete default value in the ISA
+/// config that can be treated as constants, i.e. those that are not
+/// written by the IR, have no references taken to them, are not reset
+/// by the config, and are not shadowed by any local variable or
+/// argument. Registers reset or initialised for each task, as litmus
+/// tests do for each thread, are not known here, so front-ends which
+/// do so must not treat registers as constants.
+pub fn constant_registers<B: BV>(defs: &[Def<Name, B>], isa_config: &ISAConfig<B>) -> HashMap<Name, Exp<Name>> {
+    let mut written: HashSet<Name> = isa_config.reset_registers.keys().map(Loc::id).collect();
+
+    for def in defs {
+        match def {
+            Def::Fn(_, args, body) => {
+                written.extend(args);
+                body.iter().for_each(|instr| collect_writes(instr, &mut written))
+            }
+            Def::Let(bindings, setup) => {
+                written.extend(bindings.iter().map(|(id, _)| *id));
+                setup.iter().for_each(|instr| collect_writes(instr, &mut written))
+            }
+            _ => (),
+        }
+    }
+
+    isa_config
+        .default_registers
+        .iter()
+        .filter(|(reg, _)| !written.contains(reg))
+        .filter_map(|(reg, value)| Some((*reg, val_to_literal(value)?)))
+        .collect()
+}
+
+fn signature<B: BV>(defs: &[Def<Name, B>], f: Name) -> Option<(&[Ty<Name>], &Ty<Name>)> {
+    defs.iter().find_map(|def| match def {
+        Def::Val(g, arg_tys, ret_ty) if *g == f => Some((arg_tys.as_slice(), ret_ty)),
+        _ => None,
+    })
+}
+
+/// Run the given optimisation passes over every function body in the
+/// architecture. Functions in `keep` are never inlined (see
+/// [inline_small_functions]). If `self_test` is set to a number of
+/// threads, then every function changed by a pass is checked to be
+/// equivalent to the original using [super::linearize::self_test],
+/// with `primops` available to the executor.
+/// This can be very slow for a full architecture, and only compares
+/// return values, so it is intended for debugging the passes
+/// themselves.
+pub fn optimize<B: BV>(
+    arch: &mut [Def<Name, B>],
+    symtab: &mut Symtab,
+    isa_config: &ISAConfig<B>,
+    primops: &Primops<B>,
+    keep: &HashSet<Name>,
+    passes: &[Pass],
+    self_test: Option<usize>,
+) -> Result<(), String> {
+    for pass in passes {
+        let before = arch.to_vec();
+
+        let changed: HashSet<Name> = match pass {
+            Pass::Inline => inline_small_functions(arch, keep, symtab),
+            Pass::ConstantPropagation | Pass::DeadCode => {
+                let registers = constant_registers(arch, isa_config);
+                let ctx = Context::new(Config::new());
+                let mut solver = Solver::<B>::new(&ctx);
+                let mut changed = HashSet::new();
+                for def in arch.iter_mut() {
+                    if let Def::Fn(f, args, body) = def {
+                        let (new_body, body_changed) = match pass {
+                            Pass::ConstantPropagation => constant_propagation(body, args, &registers, &mut solver),
+                            _ => dead_code_elimination(body),
+                        };
+                        if body_changed {
+                            *body = new_body;
+                            changed.insert(*f);
+                        }
+                    }
+                }
+                changed
+            }
+        };
+
+        log!(log::VERBOSE, &format!("Optimisation pass {} changed {} functions", pass, changed.len()));
+
+        if let Some(num_threads) = self_test {
+            for def in arch.iter() {
+                if let Def::Fn(f, args, body) = def {
+                    if !changed.contains(f) {
+                        continue;
+                    }
+                    let original = before.iter().find_map(|def| match def {
+                        Def::Fn(g, _, body) if g == f => Some(body),
+                        _ => None,
+                    });
+                    if let (Some(original), Some((arg_tys, ret_ty))) = (original, signature(arch, *f)) {
+                        let success = linearize::self_test(
+                            num_threads,
+                            before.clone(),
+                            symtab.clone(),
+                            isa_config,
+                            primops,
+                            args,
+                            arg_tys,
+                            ret_ty,
+                            original.clone(),
+                            body.clone(),
+                        );
+                        if !success {
+                            return Err(format!(
+                                "Optimisation pass {} changed the behaviour of {}",
+                                pass,
+                                zencode::decode(symtab.to_str(*f))
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitvector::b64::B64;
+    use crate::ir::pretty::ir_string;
+    use crate::test_util::{isa_config, parse_ir};
+
+    static IR: &str = r#"
+register zR : %bv8
+
+register zS : %bv8
+
+val zg : (%bv8) -> %bv8
+
+fn zg(zx) {
+  return = @bvadd(zx, 0x01);
+  end
+}
+
+val zf : (%bv8) -> %bool
+
+fn zf(zy) {
+  za : %bv8 ``;
+  za = zg(zy) `0 1:1-1:10;
+  zb : %bv8 = 0x02 ``;
+  zc : %bv8 = @bvadd(zb, zR) ``;
+  zunused : %bv8 = @bvadd(za, zS) ``;
+  jump @eq(zc, 0x05) goto 8 ``;
+  return = false;
+  goto 9;
+  return = @eq(za, zy);
+  end
+}
+
+val zloop : (%i64) -> %i64
+
+fn zloop(zn) {
+  zi : %i64 = 0 ``;
+  jump @gteq(zi, zn) goto 4 ``;
+  zi = @iadd(zi, 1);
+  goto 1;
+  return = zi;
+  end
+}
+
+val zwrites_s : (%bv8) -> %unit
+
+fn zwrites_s(zx) {
+  zS = zx;
+  return = ();
+  end
+}
+"#;
+
+    fn function<'a>(defs: &'a [Def<Name, B64>], symtab: &Symtab, f: &str) -> &'a [Instr<Name, B64>] {
+        defs.iter()
+            .find_map(|def| match def {
+                Def::Fn(g, _, body) if *g == symtab.lookup(f) => Some(body.as_slice()),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    fn config(symtab: &Symtab) -> ISAConfig<B64> {
+        isa_config(symtab, "R", r#"{ R = "0x03", S = "0x04" }"#)
+    }
+
+    #[test]
+    fn constant_registers_not_written() {
+        let defs = parse_ir(IR);
+        let mut symtab = Symtab::new();
+        let defs = symtab.intern_defs(&defs);
+        let registers = constant_registers(&defs, &config(&symtab));
+        assert!(registers.contains_key(&symtab.lookup("zR")));
+        assert!(!registers.contains_key(&symtab.lookup("zS")))
+    }
+
+    #[test]
+    fn passes() {
+        let defs = parse_ir(IR);
+        let mut symtab = Symtab::new();
+        let mut defs = symtab.intern_defs(&defs);
+        let isa_config = config(&symtab);
+        let primops = Primops::default();
+
+        optimize(&mut defs, &mut symtab, &isa_config, &primops, &HashSet::new(), &[Pass::Inline], None).unwrap();
+        let f = ir_string(&defs, &symtab).unwrap();
+        assert!(!f.contains("zg(zy)"));
+
+        optimize(&mut defs, &mut symtab, &isa_config, &primops, &HashSet::new(), &[Pass::ConstantPropagation], None)
+            .unwrap();
+        let f = ir_string(&defs, &symtab).unwrap();
+        // zc is known to be 5, so the jump is always taken
+        assert!(f.contains("zc : %bv8 = 0x05"));
+        assert!(!f.contains("jump @eq"));
+        assert!(!f.contains("return = false"));
+        // zi is not constant due to the loop
+        assert!(f.contains("jump @gteq(zi, zn) goto"));
+
+        optimize(&mut defs, &mut symtab, &isa_config, &primops, &HashSet::new(), &[Pass::DeadCode], None).unwrap();
+        let f = ir_string(&defs, &symtab).unwrap();
+        assert!(!f.contains("zunused"));
+        assert!(!f.contains("zb"));
+        assert!(!f.contains("zc"));
+        assert!(f.contains("return = @eq(za, zy)"));
+        assert!(f.contains("zi = @iadd(zi, 1)"));
+
+        // The result should still be valid IR
+        parse_ir(&f);
+    }
+
+    #[test]
+    fn inline_keep() {
+        let ir = r#"
+register zR : %bv8
+
+register zS : %bv8
+
+val zg : (%bv8) -> %bv8
+
+fn zg(zx) {
+  return = @bvadd(zx, 0x01);
+  end
+}
+
+val zh : (%bv8) -> %bv8
+
+fn zh(zx) {
+  return = zg(zx) ``;
+  end
+}
+
+val zf : (%bv8) -> %bv8
+
+fn zf(zy) {
+  za : %bv8 ``;
+  za = zg(zy) ``;
+  zb : %bv8 ``;
+  zb = zh(zy) ``;
+  return = @bvadd(za, zb);
+  end
+}
+"#;
+        let defs = parse_ir(ir);
+        let mut symtab = Symtab::new();
+        let defs = symtab.intern_defs(&defs);
+        let isa_config = config(&symtab);
+        let primops = Primops::default();
+
+        // zh makes a call, so inlining it would lose its frame from backtraces
+        let mut inlined = defs.clone();
+        optimize(&mut inlined, &mut symtab, &isa_config, &primops, &HashSet::new(), &[Pass::Inline], None).unwrap();
+        let f = ir_string(&inlined, &symtab).unwrap();
+        assert!(!f.contains("za = zg(zy)"));
+        assert!(f.contains("zb = zh(zy)"));
+
+        let mut kept = defs.clone();
+        let keep = [symtab.lookup("zg")].iter().copied().collect();
+        optimize(&mut kept, &mut symtab, &isa_config, &primops, &keep, &[Pass::Inline], None).unwrap();
+        let f = ir_string(&kept, &symtab).unwrap();
+        assert!(f.contains("za = zg(zy)"));
+        assert!(f.contains("return = zg(zx)"))
+    }
+
+    #[test]
+    fn unchanged_not_reported() {
+        let defs = parse_ir(IR);
+        let mut symtab = Symtab::new();
+        let defs = symtab.intern_defs(&defs);
+        let registers = HashMap::new();
+        let ctx = Context::new(Config::new());
+        let mut solver = Solver::<B64>::new(&ctx);
+        let (_, changed) = constant_propagation(function(&defs, &symtab, "zloop"), &[], &registers, &mut solver);
+        assert!(!changed);
+        let (_, changed) = dead_code_elimination(function(&defs, &symtab, "zloop"));
+        assert!(!changed);
+        let (_, changed) = dead_code_elimination(function(&defs, &symtab, "zf"));
+        assert!(changed)
+    }
+
+    #[test]
+    fn passes_self_test() {
+        let defs = parse_ir(IR);
+        let mut symtab = Symtab::new();
+        let mut defs = symtab.intern_defs(&defs);
+        let isa_config = config(&symtab);
+        let primops = Primops::default();
+        optimize(&mut defs, &mut symtab, &isa_config, &primops, &HashSet::new(), &ALL_PASSES, Some(1)).unwrap();
+        assert_eq!(function(&defs, &symtab, "zf").len(), 7)
+    }
+}
@@ -0,0 +1,571 @@
+// BSD 2-Clause License
+//
+// Copyright (c) 2020 Alasdair Armstrong
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+// notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+// notice, this list of conditions and the following disclaimer in the
+// documentation and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+//! This module provides a pretty-printer for the IR, producing text
+//! that can be read back in by the IR parser. This allows IR that has
+//! been transformed (for example by [super::linearize]) to be
+//! inspected, diffed, and used as input to the other tools.
+//!
+//! Primops inserted by `insert_primops` are printed as calls to the
+//! `val` declaration that introduced them, so the output can be
+//! parsed and have primops inserted again. Identifiers that cannot be
+//! written in the concrete syntax (such as those created by
+//! [Symtab::gensym]) are given a unique printable name based on their
+//! symbol number. Source locations on `Copy` and `Monomorphize`
+//! instructions are not part of the concrete syntax and are dropped.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use super::*;
+use crate::bitvector::BV;
+use crate::ir_lexer::Tok;
+use crate::lexer::Lexer;
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Returns true if the string would be lexed as a single identifier
+/// token, rather than a keyword or some other token sequence.
+fn is_identifier(s: &str) -> bool {
+    let mut lexer = Lexer::new(s);
+    match lexer.next() {
+        Some(Ok((0, Tok::Id(_), end))) => end == s.len(),
+        _ => false,
+    }
+}
+
+fn name<'ir>(symtab: &Symtab<'ir>, n: Name) -> Cow<'ir, str> {
+    let s = symtab.to_str(n);
+    if is_identifier(s) {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(format!("gensym_{}", n.id))
+    }
+}
+
+/// The bits of a literal, most significant first. This includes
+/// literals wider than 64 bits, which [Exp::bits_literal] splits into
+/// concatenated chunks.
+fn literal_bits(exp: &Exp<Name>) -> Option<Vec<bool>> {
+    match exp {
+        Exp::Bits(bv) => Some((0..bv.len()).rev().map(|i| bv.extract(i, i).unwrap().lower_u64() == 1).collect()),
+        Exp::Call(Op::Concat, args) if args.len() == 2 => {
+            let mut bits = literal_bits(&args[0])?;
+            bits.append(&mut literal_bits(&args[1])?);
+            Some(bits)
+        }
+        _ => None,
+    }
+}
+
+fn write_literal(buf: &mut dyn Write, bits: &[bool]) -> io::Result<()> {
+    let nibbles = bits.chunks_exact(4);
+    if bits.is_empty() {
+        write!(buf, "UINT64_C(0)")
+    } else if nibbles.remainder().is_empty() {
+        write!(buf, "0x")?;
+        for nibble in nibbles {
+            write!(buf, "{:x}", nibble.iter().fold(0, |n, bit| n << 1 | *bit as u8))?
+        }
+        Ok(())
+    } else {
+        write!(buf, "0b")?;
+        for bit in bits {
+            write!(buf, "{}", *bit as u8)?
+        }
+        Ok(())
+    }
+}
+
+fn write_ty(buf: &mut dyn Write, ty: &Ty<Name>, symtab: &Symtab) -> io::Result<()> {
+    use Ty::*;
+    match ty {
+        I64 => write!(buf, "%i64"),
+        I128 => write!(buf, "%i"),
+        AnyBits => write!(buf, "%bv"),
+        Bits(sz) => write!(buf, "%bv{}", sz),
+        Unit => write!(buf, "%unit"),
+        Bool => write!(buf, "%bool"),
+        Bit => write!(buf, "%bit"),
+        String => write!(buf, "%string"),
+        Real => write!(buf, "%real"),
+        Enum(id) => write!(buf, "%enum {}", name(symtab, *id)),
+        Struct(id) => write!(buf, "%struct {}", name(symtab, *id)),
+        Union(id) => write!(buf, "%union {}", name(symtab, *id)),
+        Vector(ty) => {
+            write!(buf, "%vec(")?;
+            write_ty(buf, ty, symtab)?;
+            write!(buf, ")")
+        }
+        FixedVector(sz, ty) => {
+            write!(buf, "%fvec({}, ", sz)?;
+            write_ty(buf, ty, symtab)?;
+            write!(buf, ")")
+        }
+        List(ty) => {
+            write!(buf, "%list(")?;
+            write_ty(buf, ty, symtab)?;
+            write!(buf, ")")
+        }
+        Ref(ty) => {
+            write!(buf, "&(")?;
+            write_ty(buf, ty, symtab)?;
+            write!(buf, ")")
+        }
+    }
+}
+
+struct Printer<'a, 'ir> {
+    symtab: &'a Symtab<'ir>,
+    unary: HashMap<usize, Name>,
+    binary: HashMap<usize, Name>,
+    variadic: HashMap<usize, Name>,
+}
+
+impl<'a, 'ir> Printer<'a, 'ir> {
+    fn new<B: BV>(defs: &[Def<Name, B>], symtab: &'a Symtab<'ir>) -> Self {
+        let primops = Primops::<B>::default();
+        let mut printer = Printer { symtab, unary: HashMap::new(), binary: HashMap::new(), variadic: HashMap::new() };
+
+        let mut add_extern = |f: Name, ext: &str| {
+            if let Some(unop) = primops.unary.get(ext) {
+                printer.unary.entry(*unop as usize).or_insert(f);
+            } else if let Some(binop) = primops.binary.get(ext) {
+                printer.binary.entry(*binop as usize).or_insert(f);
+            } else if let Some(varop) = primops.variadic.get(ext) {
+                printer.variadic.entry(*varop as usize).or_insert(f);
+            }
+        };
+
+        // These are the externs that insert_primops adds itself
+        add_extern(SAIL_ASSERT, "optimistic_assert");
+        add_extern(SAIL_ASSERT, "pessimistic_assert");
+        add_extern(SAIL_ASSUME, "assume");
+        add_extern(BITVECTOR_UPDATE, "bitvector_update");
+
+        for def in defs {
+            if let Def::Extern(f, ext, _, _) = def {
+                add_extern(*f, ext)
+            }
+        }
+
+        printer
+    }
+
+    fn name(&self, n: Name) -> Cow<'ir, str> {
+        name(self.symtab, n)
+    }
+
+    fn write_comma<T, F>(&self, buf: &mut dyn Write, xs: &[T], mut f: F) -> io::Result<()>
+    where
+        F: FnMut(&mut dyn Write, &T) -> io::Result<()>,
+    {
+        for (i, x) in xs.iter().enumerate() {
+            if i != 0 {
+                write!(buf, ", ")?
+            }
+            f(buf, x)?
+        }
+        Ok(())
+    }
+
+    fn write_ty(&self, buf: &mut dyn Write, ty: &Ty<Name>) -> io::Result<()> {
+        write_ty(buf, ty, self.symtab)
+    }
+
+    fn write_loc(&self, buf: &mut dyn Write, loc: &Loc<Name>) -> io::Result<()> {
+        match loc {
+            Loc::Id(id) => write!(buf, "{}", self.name(*id)),
+            Loc::Field(loc, field) => {
+                self.write_loc(buf, loc)?;
+                write!(buf, ".{}", self.name(*field))
+            }
+            Loc::Addr(loc) => {
+                self.write_loc(buf, loc)?;
+                write!(buf, "*")
+            }
+        }
+    }
+
+    fn write_op(&self, buf: &mut dyn Write, op: Op) -> io::Result<()> {
+        use Op::*;
+        match op {
+            Not => write!(buf, "@not"),
+            Or => write!(buf, "@or"),
+            And => write!(buf, "@and"),
+            Eq => write!(buf, "@eq"),
+            Neq => write!(buf, "@neq"),
+            Lteq => write!(buf, "@lteq"),
+            Lt => write!(buf, "@lt"),
+            Gteq => write!(buf, "@gteq"),
+            Gt => write!(buf, "@gt"),
+            Add => write!(buf, "@iadd"),
+            Sub => write!(buf, "@isub"),
+            Slice(n) => write!(buf, "@slice::<{}>", n),
+            SetSlice => write!(buf, "@set_slice"),
+            Signed(n) => write!(buf, "@signed::<{}>", n),
+            Unsigned(n) => write!(buf, "@unsigned::<{}>", n),
+            ZeroExtend(n) => write!(buf, "@zero_extend::<{}>", n),
+            Bvnot => write!(buf, "@bvnot"),
+            Bvor => write!(buf, "@bvor"),
+            Bvxor => write!(buf, "@bvxor"),
+            Bvand => write!(buf, "@bvand"),
+            Bvadd => write!(buf, "@bvadd"),
+            Bvsub => write!(buf, "@bvsub"),
+            Bvaccess => write!(buf, "@bvaccess"),
+            Concat => write!(buf, "@concat"),
+            Head => write!(buf, "@hd"),
+            Tail => write!(buf, "@tl"),
+        }
+    }
+
+    fn write_exp(&self, buf: &mut dyn Write, exp: &Exp<Name>) -> io::Result<()> {
+        use Exp::*;
+        match exp {
+            Id(id) => write!(buf, "{}", self.name(*id)),
+            Ref(reg) => write!(buf, "&{}", self.name(*reg)),
+            Bool(b) => write!(buf, "{}", b),
+            Bits(_) => write_literal(buf, &literal_bits(exp).unwrap()),
+            String(s) if s.contains('"') => {
+                Err(invalid_data(format!("string literal {:?} cannot be printed as IR", s)))
+            }
+            String(s) => write!(buf, "\"{}\"", s),
+            Unit => write!(buf, "()"),
+            I64(n) => write!(buf, "{}", n),
+            I128(n) => write!(buf, "{} : %i128", n),
+            Undefined(ty) => {
+                write!(buf, "undefined : ")?;
+                self.write_ty(buf, ty)
+            }
+            Struct(s, fields) => {
+                write!(buf, "struct {} {{", self.name(*s))?;
+                self.write_comma(buf, fields, |buf, (field, exp)| {
+                    write!(buf, "{} = ", self.name(*field))?;
+                    self.write_exp(buf, exp)
+                })?;
+                write!(buf, "}}")
+            }
+            Kind(ctor, exp) => {
+                self.write_exp(buf, exp)?;
+                write!(buf, " is {}", self.name(*ctor))
+            }
+            Unwrap(ctor, exp) => {
+                self.write_exp(buf, exp)?;
+                write!(buf, " as {}", self.name(*ctor))
+            }
+            Field(exp, field) => {
+                self.write_exp(buf, exp)?;
+                write!(buf, ".{}", self.name(*field))
+            }
+            Call(Op::Concat, _) if matches!(literal_bits(exp), Some(bits) if bits.len() > 64) => {
+                write_literal(buf, &literal_bits(exp).unwrap())
+            }
+            Call(op, args) => {
+                self.write_op(buf, *op)?;
+                self.write_args(buf, args)
+            }
+        }
+    }
+
+    fn write_args(&self, buf: &mut dyn Write, args: &[Exp<Name>]) -> io::Result<()> {
+        // The concrete syntax has no nullary calls, instead a single
+        // unit argument is used.
+        if args.is_empty() {
+            return write!(buf, "(())");
+        }
+        write!(buf, "(")?;
+        self.write_comma(buf, args, |buf, exp| self.write_exp(buf, exp))?;
+        write!(buf, ")")
+    }
+
+    fn write_call(
+        &self,
+        buf: &mut dyn Write,
+        loc: &Loc<Name>,
+        ext: bool,
+        f: Name,
+        args: &[Exp<Name>],
+        info: SourceLoc,
+    ) -> io::Result<()> {
+        self.write_loc(buf, loc)?;
+        write!(buf, " = {}{}", if ext { "$" } else { "" }, self.name(f))?;
+        self.write_args(buf, args)?;
+        write!(buf, " `{}", info.ir_string())
+    }
+
+    fn primop_name(&self, table: &HashMap<usize, Name>, fptr: usize) -> io::Result<Name> {
+        table
+            .get(&fptr)
+            .copied()
+            .ok_or_else(|| invalid_data("primop has no corresponding extern declaration".to_string()))
+    }
+
+    fn write_instr<B: BV>(&self, buf: &mut dyn Write, instr: &Instr<Name, B>) -> io::Result<()> {
+        use Instr::*;
+        match instr {
+            Decl(id, ty, info) => {
+                write!(buf, "{} : ", self.name(*id))?;
+                self.write_ty(buf, ty)?;
+                write!(buf, " `{}", info.ir_string())
+            }
+            Init(id, ty, exp, info) => {
+                write!(buf, "{} : ", self.name(*id))?;
+                self.write_ty(buf, ty)?;
+                write!(buf, " = ")?;
+                self.write_exp(buf, exp)?;
+                write!(buf, " `{}", info.ir_string())
+            }
+            Jump(exp, target, info) => {
+                write!(buf, "jump ")?;
+                self.write_exp(buf, exp)?;
+                write!(buf, " goto {} `{}", target, info.ir_string())
+            }
+            Goto(target) => write!(buf, "goto {}", target),
+            Copy(loc, exp, _) => {
+                self.write_loc(buf, loc)?;
+                write!(buf, " = ")?;
+                self.write_exp(buf, exp)
+            }
+            Monomorphize(id, _) => write!(buf, "mono {}", self.name(*id)),
+            Call(loc, ext, f, args, info) => self.write_call(buf, loc, *ext, *f, args, *info),
+            PrimopUnary(loc, fptr, exp, info) => {
+                let f = self.primop_name(&self.unary, *fptr as usize)?;
+                self.write_call(buf, loc, false, f, std::slice::from_ref(exp), *info)
+            }
+            PrimopBinary(loc, fptr, lhs, rhs, info) => {
+                let f = self.primop_name(&self.binary, *fptr as usize)?;
+                self.write_call(buf, loc, false, f, &[lhs.clone(), rhs.clone()], *info)
+            }
+            PrimopVariadic(loc, fptr, args, info) => {
+                let f = self.primop_name(&self.variadic, *fptr as usize)?;
+                self.write_call(buf, loc, false, f, args, *info)
+            }
+            Failure => write!(buf, "failure"),
+            Arbitrary => write!(buf, "arbitrary"),
+            End => write!(buf, "end"),
+        }
+    }
+
+    fn write_body<B: BV>(&self, buf: &mut dyn Write, instrs: &[Instr<Name, B>]) -> io::Result<()> {
+        writeln!(buf, " {{")?;
+        for instr in instrs {
+            write!(buf, "  ")?;
+            self.write_instr(buf, instr)?;
+            writeln!(buf, ";")?
+        }
+        writeln!(buf, "}}")
+    }
+
+    fn write_arg(&self, buf: &mut dyn Write, (id, ty): &(Name, Ty<Name>)) -> io::Result<()> {
+        write!(buf, "{}: ", self.name(*id))?;
+        self.write_ty(buf, ty)
+    }
+
+    fn write_def<B: BV>(&self, buf: &mut dyn Write, def: &Def<Name, B>) -> io::Result<()> {
+        use Def::*;
+        match def {
+            Register(id, ty) => {
+                write!(buf, "register {} : ", self.name(*id))?;
+                self.write_ty(buf, ty)?;
+                writeln!(buf)
+            }
+            Let(bindings, setup) => {
+                write!(buf, "let (")?;
+                self.write_comma(buf, bindings, |buf, arg| self.write_arg(buf, arg))?;
+                write!(buf, ")")?;
+                self.write_body(buf, setup)
+            }
+            Enum(id, members) => {
+                write!(buf, "enum {} {{ ", self.name(*id))?;
+                self.write_comma(buf, members, |buf, member| write!(buf, "{}", self.name(*member)))?;
+                writeln!(buf, " }}")
+            }
+            Struct(id, fields) => {
+                write!(buf, "struct {} {{ ", self.name(*id))?;
+                self.write_comma(buf, fields, |buf, arg| self.write_arg(buf, arg))?;
+                writeln!(buf, " }}")
+            }
+            Union(id, ctors) => {
+                write!(buf, "union {} {{ ", self.name(*id))?;
+                self.write_comma(buf, ctors, |buf, arg| self.write_arg(buf, arg))?;
+                writeln!(buf, " }}")
+            }
+            Val(id, arg_tys, ret_ty) => {
+                write!(buf, "val {} : (", self.name(*id))?;
+                self.write_comma(buf, arg_tys, |buf, ty| self.write_ty(buf, ty))?;
+                write!(buf, ") -> ")?;
+                self.write_ty(buf, ret_ty)?;
+                writeln!(buf)
+            }
+            Extern(id, ext, arg_tys, ret_ty) => {
+                write!(buf, "val {} = \"{}\" : (", self.name(*id), ext)?;
+                self.write_comma(buf, arg_tys, |buf, ty| self.write_ty(buf, ty))?;
+                write!(buf, ") -> ")?;
+                self.write_ty(buf, ret_ty)?;
+                writeln!(buf)
+            }
+            Fn(id, args, body) => {
+                write!(buf, "fn {}(", self.name(*id))?;
+                self.write_comma(buf, args, |buf, arg| write!(buf, "{}", self.name(*arg)))?;
+                write!(buf, ")")?;
+                self.write_body(buf, body)
+            }
+            Files(files) => {
+                write!(buf, "files")?;
+                for file in files {
+                    write!(buf, " \"{}\"", file)?
+                }
+                writeln!(buf)
+            }
+        }
+    }
+}
+
+/// Write a list of definitions as textual IR that can be read back in
+/// by the IR parser. An error is returned if a definition cannot be
+/// represented in the concrete syntax, for example if it contains a
+/// primop without a corresponding `val` declaration.
+pub fn write_ir<B: BV>(buf: &mut dyn Write, defs: &[Def<Name, B>], symtab: &Symtab) -> io::Result<()> {
+    let printer = Printer::new(defs, symtab);
+    for (i, def) in defs.iter().enumerate() {
+        if i != 0 {
+            writeln!(buf)?
+        }
+        printer.write_def(buf, def)?
+    }
+    Ok(())
+}
+
+/// Returns a type in the same syntax used by the IR parser.
+pub fn ty_string(ty: &Ty<Name>, symtab: &Symtab) -> String {
+    let mut buf = Vec::new();
+    write_ty(&mut buf, ty, symtab).unwrap();
+    String::from_utf8(buf).unwrap()
+}
+
+/// Like [write_ir], but returns the IR as a string.
+pub fn ir_string<B: BV>(defs: &[Def<Name, B>], symtab: &Symtab) -> io::Result<String> {
+    let mut buf = Vec::new();
+    write_ir(&mut buf, defs, symtab)?;
+    Ok(String::from_utf8(buf).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitvector::b64::B64;
+    use crate::test_util::parse_ir;
+
+    static IR: &str = r#"
+files "test.sail"
+
+enum zE { zA, zB }
+
+struct zS { zf: %bv8, zg: %bool }
+
+union zU { zC: %i64, zD: %unit }
+
+register zR : %bv64
+
+val zadd_bits = "add_bits" : (%bv, %bv) -> %bv
+
+val zxor_vec = "xor_bits" : (%bv, %bv) -> %bv
+
+val zf : (%bv64, %i) -> %bv64
+
+fn zf(zx, zn) {
+  zy : %bv64 `0 1:2-3:4;
+  zs : %struct zS = struct zS {zf = 0x0F, zg = true} ``;
+  zy = zadd_bits(zx, 0x0000000000000001) `0 2:1-2:9;
+  zy = zxor_vec(zy, zR) `0 3:1-3:9;
+  zb : %bool = @bvaccess(zy, 3) ``;
+  jump @not(zb) goto 9 `0 4:1-4:9;
+  zs.zf = @bvxor(@slice::<8>(zy, 0), 0b10101010);
+  zu : %union zU = undefined : %union zU ``;
+  zy = @zero_extend::<64>(@concat(zs.zf, 0b101));
+  zn2 : %i = -5 : %i128 ``;
+  return = zy;
+  end
+}
+"#;
+
+    #[test]
+    fn round_trip() {
+        let defs = parse_ir(IR);
+        let mut symtab = Symtab::new();
+        let defs = symtab.intern_defs(&defs);
+        let printed = ir_string(&defs, &symtab).unwrap();
+
+        let reparsed = parse_ir(&printed);
+        let mut symtab = Symtab::new();
+        let reparsed = symtab.intern_defs(&reparsed);
+        assert_eq!(printed, ir_string(&reparsed, &symtab).unwrap());
+        assert!(printed.contains("@bvxor(@slice::<8>(zy, 0), 0xaa)"));
+        assert!(printed.contains("@concat(zs.zf, 0b101)"));
+        assert!(printed.contains("zy : %bv64 `0 1:2-3:4"))
+    }
+
+    #[test]
+    fn round_trip_wide_literal() {
+        let wide = format!("0b1{}", "01".repeat(64));
+        let ir = format!("val zwide : (%unit) -> %bv129\n\nfn zwide(zu) {{\n  return = {};\n  end\n}}\n", wide);
+        let defs = parse_ir(&ir);
+        let mut symtab = Symtab::new();
+        let defs = symtab.intern_defs(&defs);
+        let printed = ir_string(&defs, &symtab).unwrap();
+        assert!(printed.contains(&format!("return = {};", wide)));
+
+        let reparsed = parse_ir(&printed);
+        let mut symtab = Symtab::new();
+        let reparsed = symtab.intern_defs(&reparsed);
+        assert_eq!(printed, ir_string(&reparsed, &symtab).unwrap())
+    }
+
+    #[test]
+    fn round_trip_primops() {
+        let defs = parse_ir(IR);
+        let mut symtab = Symtab::new();
+        let mut defs = symtab.intern_defs(&defs);
+        let before = ir_string(&defs, &symtab).unwrap();
+        insert_primops(&mut defs, AssertionMode::Optimistic, &Primops::default());
+        assert_eq!(before, ir_string(&defs, &symtab).unwrap())
+    }
+
+    #[test]
+    fn gensyms() {
+        let mut symtab = Symtab::new();
+        let x = symtab.gensym();
+        let defs: Vec<Def<Name, B64>> = vec![Def::Register(x, Ty::Bool), Def::Register(TOP_LEVEL_LET, Ty::Bool)];
+        let printed = ir_string(&defs, &symtab).unwrap();
+        assert_eq!(printed, format!("register gensym_{} : %bool\n\nregister gensym_14 : %bool\n", x.id));
+        parse_ir(&printed);
+    }
+}
@@ -61,6 +61,10 @@ impl SourceLoc {
         }
     }
 
+    pub fn is_unknown(self) -> bool {
+        self.file < 0
+    }
+
     fn canonicalize(self) -> Self {
         if self.line1 > self.line2 {
             SourceLoc { line1: self.line2, line2: self.line1, ..self }
@@ -207,6 +211,16 @@ impl SourceLoc {
         }
     }
 
+    /// The location in the form accepted by the IR parser, i.e.
+    /// `file line:char-line:char`, or a single backtick if unknown.
+    pub fn ir_string(self) -> String {
+        if self.file < 0 {
+            "`".to_string()
+        } else {
+            format!("{} {}:{}-{}:{}", self.file, self.line1, self.char1, self.line2, self.char2)
+        }
+    }
+
     /// Print a message associated with an original source code
     /// location. It takes a base directory and a list of source file
     /// paths relative to that base directory. The file index in the
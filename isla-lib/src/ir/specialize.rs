@@ -0,0 +1,319 @@
+// BSD 2-Clause License
+//
+// Copyright (c) 2020 Alasdair Armstrong
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+// notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+// notice, this list of conditions and the following disclaimer in the
+// documentation and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+//! This module specialises an architecture to a fixed ISA
+//! configuration. Registers with a concrete default value in the
+//! config which are never written by the IR (see
+//! [super::optimize::constant_registers]) are treated as constants
+//! and folded through every function body, so checks on
+//! configuration registers such as `__v81_implemented` are resolved
+//! once up front rather than on every instruction.
+//!
+//! Specialisation repeatedly runs constant propagation and dead code
+//! elimination over the architecture. After each round, any function
+//! whose body has been reduced to returning a literal has its calls
+//! replaced by that literal, which allows further folding in the
+//! callers. As specialising a full architecture can take some time,
+//! the result can be cached using [crate::cache].
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+use super::optimize::{constant_propagation, constant_registers, dead_code_elimination, is_literal};
+use super::serialize;
+use super::*;
+use crate::cache::{Cacheable, Cachekey};
+use crate::config::ISAConfig;
+use crate::log;
+use crate::smt::{Config, Context};
+
+/// The maximum number of rounds of folding performed by [specialize].
+pub const SPECIALIZE_ROUNDS: usize = 8;
+
+#[derive(Clone)]
+pub struct Specializationkey {
+    hash: String,
+}
+
+impl Cachekey for Specializationkey {
+    fn key(&self) -> String {
+        format!("specialized_{}", self.hash)
+    }
+}
+
+/// A specialised architecture in its serialized form.
+#[derive(Serialize, Deserialize)]
+pub struct Specialized {
+    ir: Vec<u8>,
+}
+
+impl Cacheable for Specialized {
+    type Key = Specializationkey;
+}
+
+/// If a function body does nothing but return a literal, return
+/// that literal.
+fn constant_return<B: BV>(body: &[Instr<Name, B>]) -> Option<&Exp<Name>> {
+    match body {
+        [Instr::Copy(Loc::Id(RETURN), exp, _), Instr::End] if is_literal(exp) => Some(exp),
+        _ => None,
+    }
+}
+
+/// Replace calls to functions that always return the same literal
+/// with that literal. Functions in `keep` are left alone, as calls to
+/// them may be observed by probes or traces, or stop execution.
+/// Returns the names of the functions which were changed.
+fn fold_constant_calls<B: BV>(defs: &mut [Def<Name, B>], keep: &HashSet<Name>) -> HashSet<Name> {
+    let constants: HashMap<Name, Exp<Name>> = defs
+        .iter()
+        .filter_map(|def| match def {
+            Def::Fn(f, _, body) if !keep.contains(f) => Some((*f, constant_return(body)?.clone())),
+            _ => None,
+        })
+        .collect();
+
+    let mut changed = HashSet::new();
+
+    for def in defs.iter_mut() {
+        if let Def::Fn(f, _, body) = def {
+            for instr in body.iter_mut() {
+                if let Instr::Call(loc, false, g, _, info) = instr {
+                    if let Some(exp) = constants.get(g) {
+                        *instr = Instr::Copy(loc.clone(), exp.clone(), *info);
+                        changed.insert(*f);
+                    }
+                }
+            }
+        }
+    }
+
+    changed
+}
+
+/// Partially evaluate the architecture with respect to the default
+/// register values in the ISA config. Calls to functions in `keep`
+/// are never replaced, so it should contain any functions which are
+/// probed, traced, or where execution stops. Returns the names of the
+/// functions which were changed.
+pub fn specialize<B: BV>(arch: &mut [Def<Name, B>], isa_config: &ISAConfig<B>, keep: &HashSet<Name>) -> HashSet<Name> {
+    let registers = constant_registers(arch, isa_config);
+    let ctx = Context::new(Config::new());
+    let mut solver = Solver::<B>::new(&ctx);
+
+    let mut changed = HashSet::new();
+
+    for round in 0..SPECIALIZE_ROUNDS {
+        let mut changed_this_round = fold_constant_calls(arch, keep);
+
+        for def in arch.iter_mut() {
+            if let Def::Fn(f, args, body) = def {
+                let (new_body, folded) = constant_propagation(body, args, &registers, &mut solver);
+                if folded {
+                    *body = new_body;
+                    changed_this_round.insert(*f);
+                }
+                let (new_body, removed) = dead_code_elimination(body);
+                if removed {
+                    *body = new_body;
+                    changed_this_round.insert(*f);
+                }
+            }
+        }
+
+        log!(log::VERBOSE, &format!("Specialisation round {} changed {} functions", round, changed_this_round.len()));
+
+        if changed_this_round.is_empty() {
+            break;
+        }
+        changed.extend(changed_this_round)
+    }
+
+    changed
+}
+
+/// The key under which the specialisation of an architecture is
+/// cached. The hasher should already contain the architecture and
+/// config files (as when using [crate::config::ISAConfig::from_file]),
+/// to which we add the constant registers and the functions in
+/// `keep`, as these can also be changed on the command line.
+fn specialization_key<B: BV>(
+    hasher: &Sha256,
+    arch: &[Def<Name, B>],
+    isa_config: &ISAConfig<B>,
+    keep: &HashSet<Name>,
+) -> Specializationkey {
+    let mut hasher = hasher.clone();
+    let mut registers: Vec<String> =
+        constant_registers(arch, isa_config).iter().map(|(reg, exp)| format!("{:?} = {:?}", reg, exp)).collect();
+    registers.sort();
+    for reg in registers {
+        hasher.input(reg)
+    }
+    let mut keep: Vec<String> = keep.iter().map(|f| format!("keep {:?}", f)).collect();
+    keep.sort();
+    for f in keep {
+        hasher.input(f)
+    }
+    Specializationkey { hash: format!("{:x}", hasher.result()) }
+}
+
+/// As [specialize], but first checks whether a specialisation of the
+/// same architecture and configuration is in the cache directory,
+/// and otherwise adds the result to it.
+pub fn specialize_cached<B: BV, P: AsRef<Path>>(
+    arch: &mut Vec<Def<Name, B>>,
+    isa_config: &ISAConfig<B>,
+    keep: &HashSet<Name>,
+    hasher: &Sha256,
+    cache: P,
+) {
+    let key = specialization_key(hasher, arch, isa_config, keep);
+
+    if let Some(specialized) = Specialized::from_cache(key.clone(), &cache) {
+        if let Some(defs) = serialize::deserialize(&specialized.ir) {
+            log!(log::VERBOSE, &format!("Loaded specialised architecture from cache: {}", key.key()));
+            *arch = defs;
+            return;
+        }
+    }
+
+    specialize(arch, isa_config, keep);
+
+    if let Some(ir) = serialize::serialize(arch.clone()) {
+        Specialized { ir }.cache(key, &cache)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitvector::b64::B64;
+    use crate::ir::pretty::ir_string;
+    use crate::test_util::{isa_config, parse_ir};
+
+    static IR: &str = r#"
+register zCFG_X : %bool
+
+register zY : %bv8
+
+val zhave_x : (%unit) -> %bool
+
+fn zhave_x(zgsz30) {
+  return = zCFG_X;
+  end
+}
+
+val zexecute : (%bv8) -> %unit
+
+fn zexecute(zx) {
+  zb : %bool ``;
+  zb = zhave_x(()) ``;
+  jump zb goto 5 ``;
+  zY = zx;
+  goto 6;
+  zY = @bvadd(zx, 0x01);
+  return = ();
+  end
+}
+"#;
+
+    fn config(symtab: &Symtab) -> ISAConfig<B64> {
+        isa_config(symtab, "Y", r#"{ CFG_X = "true" }"#)
+    }
+
+    #[test]
+    fn specialize_feature_check() {
+        let defs = parse_ir(IR);
+        let mut symtab = Symtab::new();
+        let mut arch = symtab.intern_defs(&defs);
+        let isa_config = config(&symtab);
+
+        let changed = specialize(&mut arch, &isa_config, &HashSet::new());
+        assert!(changed.contains(&symtab.lookup("zexecute")));
+
+        let ir = ir_string(&arch, &symtab).unwrap();
+        assert!(!ir.contains("zhave_x(())"));
+        assert!(!ir.contains("jump"));
+        assert!(!ir.contains("zY = zx"));
+        assert!(ir.contains("zY = @bvadd(zx, 0x01)"))
+    }
+
+    #[test]
+    fn specialize_fixpoint() {
+        let defs = parse_ir(IR);
+        let mut symtab = Symtab::new();
+        let mut arch = symtab.intern_defs(&defs);
+        let isa_config = config(&symtab);
+
+        specialize(&mut arch, &isa_config, &HashSet::new());
+        assert!(specialize(&mut arch, &isa_config, &HashSet::new()).is_empty())
+    }
+
+    #[test]
+    fn specialize_keep() {
+        let defs = parse_ir(IR);
+        let mut symtab = Symtab::new();
+        let mut arch = symtab.intern_defs(&defs);
+        let isa_config = config(&symtab);
+        let keep = std::iter::once(symtab.lookup("zhave_x")).collect();
+
+        specialize(&mut arch, &isa_config, &keep);
+        let ir = ir_string(&arch, &symtab).unwrap();
+        assert!(ir.contains("zhave_x(())"))
+    }
+
+    #[test]
+    fn specialize_cache() {
+        let cache = std::env::temp_dir().join(format!("isla_specialize_test_{}", std::process::id()));
+        std::fs::create_dir_all(&cache).unwrap();
+
+        let defs = parse_ir(IR);
+        let mut symtab = Symtab::new();
+        let original = symtab.intern_defs(&defs);
+        let isa_config = config(&symtab);
+        let mut hasher = Sha256::new();
+        hasher.input(IR);
+
+        let mut arch1 = original.clone();
+        specialize_cached(&mut arch1, &isa_config, &HashSet::new(), &hasher, &cache);
+        let key = specialization_key(&hasher, &original, &isa_config, &HashSet::new());
+        assert!(cache.join(key.key()).is_file());
+
+        // Functions which are kept are part of the key
+        let keep = std::iter::once(symtab.lookup("zhave_x")).collect();
+        assert_ne!(key.key(), specialization_key(&hasher, &original, &isa_config, &keep).key());
+
+        let mut arch2 = original.clone();
+        specialize_cached(&mut arch2, &isa_config, &HashSet::new(), &hasher, &cache);
+        assert_eq!(ir_string(&arch1, &symtab).unwrap(), ir_string(&arch2, &symtab).unwrap());
+
+        std::fs::remove_dir_all(&cache).unwrap()
+    }
+}
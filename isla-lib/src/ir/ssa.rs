@@ -329,6 +329,9 @@ impl<B: BV> Block<B> {
         for instr in self.instrs.iter_mut() {
             for variable_use in instr.variables() {
                 match variable_use {
+                    // Only local variables are renamed, writes to
+                    // registers and arguments keep their original names.
+                    Variable::Declaration(id) if !stacks.contains_key(&id.name) => (),
                     Variable::Declaration(id) => {
                         let i = counts.entry(id.name).or_default();
                         *i += 1;
@@ -686,7 +689,12 @@ impl<B: BV> CFG<B> {
         }
 
         for a in all_vars {
-            let mut worklist: Vec<NodeIndex> = defsites.get_mut(a).unwrap().drain().collect();
+            // The RETURN variable might never be written, e.g. if
+            // the function always fails.
+            let mut worklist: Vec<NodeIndex> = match defsites.get_mut(a) {
+                Some(sites) => sites.drain().collect(),
+                None => continue,
+            };
 
             while !worklist.is_empty() {
                 let n = worklist.pop().unwrap();
@@ -797,3 +805,79 @@ impl<B: BV> CFG<B> {
         writeln!(output, "}}")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitvector::b64::B64;
+    use crate::test_util::parse_ir;
+
+    fn ssa_cfg(defs: &[Def<Name, B64>], f: Name) -> CFG<B64> {
+        let body = defs
+            .iter()
+            .find_map(|def| match def {
+                Def::Fn(g, _, body) if *g == f => Some(body.clone()),
+                _ => None,
+            })
+            .unwrap();
+        let mut cfg = CFG::new(&label_instrs(body));
+        cfg.ssa();
+        cfg
+    }
+
+    fn writes(cfg: &CFG<B64>) -> Vec<SSAName> {
+        cfg.graph
+            .node_indices()
+            .flat_map(|ix| cfg.graph[ix].instrs.iter().filter_map(|instr| instr.write_ssa()).map(|(id, _)| id))
+            .collect()
+    }
+
+    #[test]
+    fn registers_and_arguments_not_renamed() {
+        let defs = parse_ir(
+            r#"
+register zR : %bv8
+
+val zf : (%bv8) -> %bv8
+
+fn zf(zx) {
+  zR = zx;
+  zx = @bvadd(zx, 0x01);
+  zy : %bv8 = zx ``;
+  return = zy;
+  end
+}
+"#,
+        );
+        let mut symtab = Symtab::new();
+        let defs = symtab.intern_defs(&defs);
+        let cfg = ssa_cfg(&defs, symtab.lookup("zf"));
+
+        for id in writes(&cfg) {
+            if id.base_name() == symtab.lookup("zR") || id.base_name() == symtab.lookup("zx") {
+                assert!(id.ssa_number() < 0)
+            } else {
+                assert!(id.ssa_number() >= 0)
+            }
+        }
+    }
+
+    #[test]
+    fn return_never_written() {
+        let defs = parse_ir(
+            r#"
+val zf : (%bv8) -> %bv8
+
+fn zf(zx) {
+  zy : %bv8 = zx ``;
+  failure;
+  end
+}
+"#,
+        );
+        let mut symtab = Symtab::new();
+        let defs = symtab.intern_defs(&defs);
+        let cfg = ssa_cfg(&defs, symtab.lookup("zf"));
+        assert!(writes(&cfg).iter().all(|id| id.base_name() != RETURN))
+    }
+}
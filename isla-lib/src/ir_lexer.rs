@@ -202,7 +202,7 @@ lazy_static! {
         table.push(Keyword::new("@neq", OpNeq));
         table.push(Keyword::new("@bvnot", OpBvnot));
         table.push(Keyword::new("@bvor", OpBvor));
-        table.push(Keyword::new("@bvxor", OpBvor));
+        table.push(Keyword::new("@bvxor", OpBvxor));
         table.push(Keyword::new("@bvand", OpBvand));
         table.push(Keyword::new("@bvadd", OpBvadd));
         table.push(Keyword::new("@bvsub", OpBvsub));
@@ -47,6 +47,7 @@ pub mod config;
 pub mod error;
 pub mod executor;
 pub mod init;
+pub mod interleave;
 pub mod ir;
 pub mod ir_lexer;
 pub mod lexer;
@@ -55,4 +56,7 @@ pub mod primop;
 mod probe;
 pub mod simplify;
 pub mod smt;
+#[cfg(test)]
+mod test_util;
+pub mod watch;
 pub mod zencode;
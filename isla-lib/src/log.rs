@@ -58,6 +58,7 @@ pub const LITMUS: u32 = 8u32;
 pub const PROBE: u32 = 16u32;
 pub const CACHE: u32 = 32u32;
 pub const GRAPH: u32 = 64u32;
+pub const WATCH: u32 = 128u32;
 
 pub fn set_flags(flags: u32) {
     FLAGS.store(flags, SeqCst);
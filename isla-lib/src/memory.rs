@@ -36,7 +36,8 @@
 //! so the bevhaior can be imposed later as part of the concurrency
 //! model.
 
-use std::collections::HashMap;
+use std::cmp;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryFrom;
 use std::fmt;
 use std::ops::Range;
@@ -51,13 +52,24 @@ use crate::log;
 use crate::probe;
 use crate::smt::smtlib::{bits64, Def, Exp};
 use crate::smt::{Event, Model, SmtResult, Solver, Sym};
+use pages::Pages;
 
-/// For now, we assume that we only deal with 64-bit architectures.
+pub mod device;
+pub mod image;
+pub mod pages;
+
+/// Concrete addresses are represented as 64-bit integers, so address
+/// widths of up to 64 bits are supported. The width used for a
+/// particular architecture is set using [Memory::set_address_width].
 pub type Address = u64;
 
+pub const DEFAULT_ADDRESS_WIDTH: u32 = 64;
+
 pub trait CustomRegion<B> {
+    /// Reads take `&mut self` so that devices can have side effects
+    /// on read, such as a FIFO dequeuing a value.
     fn read(
-        &self,
+        &mut self,
         read_kind: Val<B>,
         address: Address,
         bytes: u32,
@@ -89,6 +101,10 @@ pub trait CustomRegion<B> {
     fn clone_dyn(&self) -> Box<dyn Send + Sync + CustomRegion<B>>;
 }
 
+/// A region of memory. Each region covers an exclusive range of
+/// addresses, except that a range ending at `Address::MAX` also
+/// contains `Address::MAX`, so a region can cover the whole 64-bit
+/// address space.
 pub enum Region<B> {
     /// A region with a symbolic value constrained by a symbolic
     /// variable generated by an arbitrary function. The region should
@@ -99,8 +115,15 @@ pub enum Region<B> {
     Symbolic(Range<Address>),
     /// A read only region of arbitrary symbolic locations intended for code
     SymbolicCode(Range<Address>),
-    /// A region of concrete read-only memory
-    Concrete(Range<Address>, HashMap<Address, u8>),
+    /// A region of symbolic locations represented as an SMT array
+    /// from addresses to bytes, so reads and writes at symbolic
+    /// addresses within a single thread are resolved by the
+    /// solver. The array is declared on first use, and each write
+    /// replaces it with an updated array.
+    SymbolicArray(Range<Address>, Option<Sym>),
+    /// A region of concrete read-only memory, stored as copy-on-write
+    /// pages so it can be shared cheaply between frames
+    Concrete(Range<Address>, Pages),
     /// A custom region
     Custom(Range<Address>, Box<dyn Send + Sync + CustomRegion<B>>),
 }
@@ -112,6 +135,7 @@ impl<B> Clone for Region<B> {
             Constrained(r, contents) => Constrained(r.clone(), contents.clone()),
             Symbolic(r) => Symbolic(r.clone()),
             SymbolicCode(r) => SymbolicCode(r.clone()),
+            SymbolicArray(r, array) => SymbolicArray(r.clone(), *array),
             Concrete(r, contents) => Concrete(r.clone(), contents.clone()),
             Custom(r, contents) => Custom(r.clone(), contents.clone_dyn()),
         }
@@ -131,6 +155,7 @@ impl<B> fmt::Debug for Region<B> {
             Constrained(r, _) => write!(f, "Constrained({:?}, <closure>)", r),
             Symbolic(r) => write!(f, "Symbolic({:?})", r),
             SymbolicCode(r) => write!(f, "SymbolicCode({:?})", r),
+            SymbolicArray(r, array) => write!(f, "SymbolicArray({:?}, {:?})", r, array),
             Concrete(r, locs) => write!(f, "Concrete({:?}, {:?})", r, locs),
             Custom(r, _) => write!(f, "Custom({:?}, <trait object>)", r),
         }
@@ -143,6 +168,7 @@ impl<B> Region<B> {
             Region::Constrained(_, _) => "constrained",
             Region::Symbolic(_) => "symbolic",
             Region::SymbolicCode(_) => "symbolic code",
+            Region::SymbolicArray(_, _) => "symbolic array",
             Region::Concrete(_, _) => "concrete",
             Region::Custom(_, contents) => contents.memory_kind(),
         }
@@ -153,10 +179,15 @@ impl<B> Region<B> {
             Region::Constrained(r, _) => r,
             Region::Symbolic(r) => r,
             Region::SymbolicCode(r) => r,
+            Region::SymbolicArray(r, _) => r,
             Region::Concrete(r, _) => r,
             Region::Custom(r, _) => r,
         }
     }
+
+    fn contains(&self, address: Address) -> bool {
+        range_contains(self.region_range(), address)
+    }
 }
 
 // Optional client interface.  At the time of writing this is only
@@ -216,22 +247,305 @@ fn make_bv_bit_pair<B>(left: Val<B>, right: Val<B>) -> Val<B> {
     Val::Struct(fields)
 }
 
-#[derive(Clone, Debug, Default)]
+/// The initial value of the allocation tags in a tag region
+#[derive(Clone, Debug)]
+pub enum TagInit<B> {
+    Concrete(B),
+    /// Each granule starts with a fresh symbolic tag of the given width
+    Symbolic(u32),
+}
+
+/// A tag region tracks an allocation tag for each granule of memory
+/// in its range, such as MTE allocation tags or CHERI capability
+/// tags. Tags are read by tagged reads and set by tagged writes to
+/// concrete addresses, independently of the regions holding the data.
+#[derive(Clone, Debug)]
+pub struct TagRegion<B> {
+    range: Range<Address>,
+    granule: u64,
+    initial: TagInit<B>,
+    clear_on_write: bool,
+    tags: HashMap<u64, Val<B>>,
+}
+
+impl<B: BV> TagRegion<B> {
+    /// Create a new tag region with a granule size in bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the granule size is zero.
+    pub fn new(range: Range<Address>, granule: u64, initial: TagInit<B>) -> Self {
+        assert!(granule > 0, "Tag granule size must be non-zero");
+        TagRegion { range, granule, initial, clear_on_write: false, tags: HashMap::new() }
+    }
+
+    /// If set, untagged writes clear the tags of the granules they
+    /// write, as for CHERI capability tags. MTE allocation tags are
+    /// not affected by data writes, which is the default.
+    pub fn clear_on_write(mut self, clear: bool) -> Self {
+        self.clear_on_write = clear;
+        self
+    }
+
+    fn width(&self) -> u32 {
+        match self.initial {
+            TagInit::Concrete(bv) => bv.len(),
+            TagInit::Symbolic(width) => width,
+        }
+    }
+
+    fn granules(&self, address: Address, bytes: u32) -> Range<u64> {
+        (address / self.granule)..((address + u64::from(cmp::max(bytes, 1)) - 1) / self.granule + 1)
+    }
+
+    fn read(&mut self, granule: u64, solver: &mut Solver<B>) -> Val<B> {
+        let initial = &self.initial;
+        self.tags
+            .entry(granule)
+            .or_insert_with(|| match initial {
+                TagInit::Concrete(bv) => Val::Bits(*bv),
+                TagInit::Symbolic(width) => {
+                    Val::Symbolic(solver.declare_const(crate::smt::smtlib::Ty::BitVec(*width), SourceLoc::unknown()))
+                }
+            })
+            .clone()
+    }
+}
+
+/// How memory accesses which could fail an [AccessChecks] check are
+/// handled
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CheckMode {
+    /// Raise [ExecError::AccessCheck] if the access could fail
+    #[default]
+    Error,
+    /// Fork on whether an access at a symbolic address passes the
+    /// check, so only the failing path raises [ExecError::AccessCheck]
+    Fork,
+}
+
+/// Checks performed on the address and size of each memory access
+/// before it is passed to a region.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccessChecks {
+    /// Require accesses with a power of two size to be naturally aligned
+    pub alignment: bool,
+    /// Forbid accesses which cross the boundary of a memory region
+    pub region_boundaries: bool,
+    /// Forbid accesses which cross a boundary between pages of this
+    /// size, which must be a power of two
+    pub page_size: Option<u64>,
+    pub mode: CheckMode,
+}
+
+impl AccessChecks {
+    pub fn is_enabled(&self) -> bool {
+        self.alignment || self.region_boundaries || self.page_size.is_some()
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Memory<B> {
     regions: Vec<Region<B>>,
+    tag_regions: Vec<TagRegion<B>>,
     client_info: Option<Box<dyn MemoryCallbacks<B>>>,
+    address_width: u32,
+    // Shared between forks until one of them writes, like the pages of
+    // a concrete region, and ordered so overlays are deterministic
+    write_back: Option<Arc<BTreeMap<Address, Val<B>>>>,
+    access_checks: AccessChecks,
+}
+
+impl<B: BV> Default for Memory<B> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 static DEFAULT_MEMORY_KIND: &str = "default";
 
 impl<B: BV> Memory<B> {
     pub fn new() -> Self {
-        Memory { regions: Vec::new(), client_info: None }
+        Memory {
+            regions: Vec::new(),
+            tag_regions: Vec::new(),
+            client_info: None,
+            address_width: DEFAULT_ADDRESS_WIDTH,
+            write_back: None,
+            access_checks: AccessChecks::default(),
+        }
+    }
+
+    pub fn set_access_checks(&mut self, access_checks: AccessChecks) {
+        self.access_checks = access_checks
+    }
+
+    fn region_index(&self, address: Address) -> Option<usize> {
+        self.regions.iter().position(|region| region.contains(address))
+    }
+
+    /// Check the address and size of an access against the access
+    /// checks. Accesses at concrete addresses either pass or fail, but
+    /// an access at a symbolic address may do either, in which case it
+    /// fails or forks depending on the [CheckMode].
+    fn check_access(&self, address: &Val<B>, bytes: u32, solver: &mut Solver<B>) -> Result<(), ExecError> {
+        use crate::smt::smtlib::Exp::*;
+
+        let checks = &self.access_checks;
+        if !checks.is_enabled() || bytes == 0 {
+            return Ok(());
+        }
+
+        match address {
+            Val::Bits(address) => {
+                let address = address.lower_u64();
+                let last = address.wrapping_add(u64::from(bytes) - 1);
+                let error = |check| Err(ExecError::AccessCheck(check, format!("0x{:x}", address), bytes));
+                if checks.alignment && bytes.is_power_of_two() && address % u64::from(bytes) != 0 {
+                    return error("alignment");
+                }
+                if let Some(page_size) = checks.page_size {
+                    if address / page_size != last / page_size {
+                        return error("page boundary");
+                    }
+                }
+                if checks.region_boundaries && self.region_index(address) != self.region_index(last) {
+                    return error("region boundary");
+                }
+                Ok(())
+            }
+
+            Val::Symbolic(address) => {
+                let width = self.address_width;
+                let mut conditions: Vec<(&'static str, Exp)> = Vec::new();
+
+                if checks.alignment && bytes.is_power_of_two() && bytes > 1 {
+                    let low_bits = bytes.trailing_zeros();
+                    let aligned =
+                        Eq(Box::new(Extract(low_bits - 1, 0, Box::new(Var(*address)))), Box::new(bits64(0, low_bits)));
+                    conditions.push(("alignment", aligned))
+                }
+
+                if let Some(page_size) = checks.page_size {
+                    let page_bits = page_size.trailing_zeros();
+                    if page_bits < width {
+                        let page = |exp| Extract(width - 1, page_bits, Box::new(exp));
+                        let last = Bvadd(Box::new(Var(*address)), Box::new(bits64(u64::from(bytes) - 1, width)));
+                        conditions.push(("page boundary", Eq(Box::new(page(Var(*address))), Box::new(page(last)))))
+                    }
+                }
+
+                if checks.region_boundaries {
+                    // The address one past the end of the access, with an extra bit to prevent wrapping
+                    let end = Bvadd(
+                        Box::new(ZeroExtend(1, Box::new(Var(*address)))),
+                        Box::new(bits64(bytes.into(), width + 1)),
+                    );
+                    for region in &self.regions {
+                        let range = region.region_range();
+                        if range.start > max_address(width) {
+                            continue;
+                        }
+                        // An access must either lie entirely within the region, or
+                        // neither start within it nor overlap its start
+                        let starts_within = smt_within_range(range, *address, 1, width);
+                        let within = smt_within_range(range, *address, bytes, width);
+                        let overlaps_start = And(
+                            Box::new(Bvult(Box::new(Var(*address)), Box::new(bits64(range.start, width)))),
+                            Box::new(Bvult(Box::new(bits64(range.start, width + 1)), Box::new(end.clone()))),
+                        );
+                        let outside =
+                            And(Box::new(Not(Box::new(starts_within))), Box::new(Not(Box::new(overlaps_start))));
+                        conditions.push(("region boundary", Or(Box::new(within), Box::new(outside))))
+                    }
+                }
+
+                for (check, passes) in conditions {
+                    let fails = Not(Box::new(passes.clone()));
+                    if solver.is_sat_with(&fails, SourceLoc::unknown())? {
+                        if checks.mode == CheckMode::Fork && solver.is_sat_with(&passes, SourceLoc::unknown())? {
+                            return Err(ExecError::AccessFork(solver.define_const(passes, SourceLoc::unknown())));
+                        }
+                        return Err(ExecError::AccessCheck(check, format!("v{}", address), bytes));
+                    }
+                }
+                Ok(())
+            }
+
+            _ => Ok(()),
+        }
+    }
+
+    /// In sequential mode each write to a concrete address is stored
+    /// byte by byte, and later reads in the same frame see the written
+    /// bytes in place of the contents of the underlying region, even
+    /// when the accesses only partially overlap. Reads still produce
+    /// the same events as they would otherwise. Writes to custom and
+    /// symbolic array regions are not stored, as those regions track
+    /// their own contents, and writes to symbolic addresses are an
+    /// error.
+    pub fn set_sequential(&mut self, sequential: bool) {
+        self.write_back = if sequential { Some(Arc::new(BTreeMap::new())) } else { None }
+    }
+
+    pub fn is_sequential(&self) -> bool {
+        self.write_back.is_some()
+    }
+
+    /// Set the width of addresses in bits. Addresses passed to
+    /// [Memory::read] and [Memory::write] which are wider than this,
+    /// such as the 129-bit capabilities used by CHERI, carry the
+    /// address in their low bits. Narrower addresses are an
+    /// [ExecError::AddressWidth] error.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the width is zero or greater than 64 bits.
+    pub fn set_address_width(&mut self, width: u32) {
+        assert!(width > 0 && width <= 64, "Address width must be between 1 and 64 bits");
+        self.address_width = width
+    }
+
+    pub fn address_width(&self) -> u32 {
+        self.address_width
+    }
+
+    /// The range of every address representable at the memory's
+    /// address width.
+    pub fn address_space(&self) -> Range<Address> {
+        0..max_address(self.address_width).saturating_add(1)
+    }
+
+    /// Extract the address bits of an address argument, which are
+    /// its low bits up to the memory's address width.
+    fn normalize_address(&self, address: Val<B>, solver: &mut Solver<B>) -> Result<Val<B>, ExecError> {
+        use crate::smt::smtlib::Exp::*;
+        let width = self.address_width;
+        match address {
+            Val::Bits(bv) if bv.len() == width => Ok(Val::Bits(bv)),
+            Val::Bits(bv) if bv.len() > width => {
+                bv.extract(width - 1, 0).map(Val::Bits).ok_or(ExecError::AddressWidth(bv.len(), width))
+            }
+            Val::Bits(bv) => Err(ExecError::AddressWidth(bv.len(), width)),
+            Val::Symbolic(v) => {
+                let length = crate::primop::length_bits(&address, solver, SourceLoc::unknown())?;
+                if length == width {
+                    Ok(address)
+                } else if length > width {
+                    Ok(Val::Symbolic(
+                        solver.define_const(Extract(width - 1, 0, Box::new(Var(v))), SourceLoc::unknown()),
+                    ))
+                } else {
+                    Err(ExecError::AddressWidth(length, width))
+                }
+            }
+            _ => Ok(address),
+        }
     }
 
     pub fn kind_at(&self, addr: Address) -> &'static str {
         for region in &self.regions {
-            if region.region_range().contains(&addr) {
+            if region.contains(addr) {
                 return region.memory_kind();
             }
         }
@@ -250,6 +564,9 @@ impl<B: BV> Memory<B> {
                 Region::SymbolicCode(range) => {
                     log!(log::MEMORY, &format!("Memory range: [0x{:x}, 0x{:x}) symbolic code", range.start, range.end))
                 }
+                Region::SymbolicArray(range, _) => {
+                    log!(log::MEMORY, &format!("Memory range: [0x{:x}, 0x{:x}) symbolic array", range.start, range.end))
+                }
                 Region::Concrete(range, _) => {
                     log!(log::MEMORY, &format!("Memory range: [0x{:x}, 0x{:x}) concrete", range.start, range.end))
                 }
@@ -269,7 +586,7 @@ impl<B: BV> Memory<B> {
     pub fn in_custom_region(&self, addr: Address) -> Option<&dyn CustomRegion<B>> {
         for region in &self.regions {
             match region {
-                Region::Custom(range, mem) if range.contains(&addr) => return Some(mem.as_ref()),
+                Region::Custom(range, mem) if range_contains(range, addr) => return Some(mem.as_ref()),
                 _ => (),
             }
         }
@@ -288,12 +605,82 @@ impl<B: BV> Memory<B> {
         self.regions.push(Region::SymbolicCode(range))
     }
 
+    pub fn add_symbolic_array_region(&mut self, range: Range<Address>) {
+        self.regions.push(Region::SymbolicArray(range, None))
+    }
+
     pub fn add_concrete_region(&mut self, range: Range<Address>, contents: HashMap<Address, u8>) {
-        self.regions.push(Region::Concrete(range, contents))
+        self.regions.push(Region::Concrete(range, contents.into()))
+    }
+
+    /// Add the contents of a memory image, creating a concrete region
+    /// for each contiguous range of the image. The regions share the
+    /// same pages.
+    pub fn add_image(&mut self, image: &image::Image) {
+        let (pages, ranges) = image::to_pages(image);
+        for range in ranges {
+            self.regions.push(Region::Concrete(*range.start()..range.end().saturating_add(1), pages.clone()))
+        }
     }
 
     pub fn add_zero_region(&mut self, range: Range<Address>) {
-        self.regions.push(Region::Concrete(range, HashMap::new()))
+        self.regions.push(Region::Concrete(range, Pages::new()))
+    }
+
+    pub fn add_tag_region(&mut self, tag_region: TagRegion<B>) {
+        self.tag_regions.push(tag_region)
+    }
+
+    /// Read the tag for an access, if it is within a tag region. The
+    /// access must lie within a single tag granule.
+    fn read_tag(&mut self, address: Address, bytes: u32, solver: &mut Solver<B>) -> Result<Option<Val<B>>, ExecError> {
+        for tag_region in self.tag_regions.iter_mut() {
+            if tag_region.range.contains(&address) {
+                let granules = tag_region.granules(address, bytes);
+                if granules.end - granules.start != 1 {
+                    return Err(ExecError::BadRead("tagged read crosses a tag granule boundary"));
+                }
+                return Ok(Some(tag_region.read(granules.start, solver)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Update the tags for each granule touched by a write, if it is
+    /// within a tag region.
+    fn write_tag(&mut self, address: Address, bytes: u32, tag: &Option<Val<B>>) {
+        for tag_region in self.tag_regions.iter_mut() {
+            if tag_region.range.contains(&address) {
+                let tag_value = match tag {
+                    Some(tag_value) => tag_value.clone(),
+                    None if tag_region.clear_on_write => Val::Bits(B::zeros(tag_region.width())),
+                    None => return,
+                };
+                for granule in tag_region.granules(address, bytes) {
+                    tag_region.tags.insert(granule, tag_value.clone());
+                }
+                return;
+            }
+        }
+    }
+
+    /// Tags are only tracked for concrete addresses, so accesses at
+    /// symbolic addresses which could touch a tag region are
+    /// rejected.
+    fn check_tag_overlap(
+        &self,
+        address: Sym,
+        bytes: u32,
+        error: ExecError,
+        solver: &mut Solver<B>,
+    ) -> Result<(), ExecError> {
+        for tag_region in &self.tag_regions {
+            let overlaps = smt_overlaps_range(&tag_region.range, address, bytes, self.address_width);
+            if solver.is_sat_with(&overlaps, SourceLoc::unknown())? {
+                return Err(error);
+            }
+        }
+        Ok(())
     }
 
     pub fn set_client_info(&mut self, info: Box<dyn MemoryCallbacks<B>>) {
@@ -303,27 +690,27 @@ impl<B: BV> Memory<B> {
     pub fn write_byte(&mut self, address: Address, byte: u8) {
         for region in &mut self.regions {
             match region {
-                Region::Concrete(range, contents) if range.contains(&address) => {
+                Region::Concrete(range, contents) if range_contains(range, address) => {
                     contents.insert(address, byte);
                     return;
                 }
                 _ => (),
             }
         }
-        self.regions.push(Region::Concrete(address..address, vec![(address, byte)].into_iter().collect()))
+        self.regions.push(Region::Concrete(address..address, Pages::from_bytes(address, &[byte])))
     }
 
     fn read_initial_byte(&self, address: Address) -> Result<u8, ExecError> {
         use Region::*;
         for region in &self.regions {
             match region {
-                Constrained(range, _) | Symbolic(range) | SymbolicCode(range) if range.contains(&address) => {
+                Constrained(range, _) | Symbolic(range) | SymbolicCode(range) | SymbolicArray(range, _)
+                    if range_contains(range, address) =>
+                {
                     return Err(ExecError::BadRead("symbolic initial byte"))
                 }
-                Concrete(range, contents) if range.contains(&address) => {
-                    return Ok(contents.get(&address).copied().unwrap_or(0))
-                }
-                Custom(range, contents) if range.contains(&address) => {
+                Concrete(range, contents) if range_contains(range, address) => return Ok(contents.get(address)),
+                Custom(range, contents) if range_contains(range, address) => {
                     return contents
                         .initial_value(address, 1)
                         .map(B::lower_u8)
@@ -357,24 +744,35 @@ impl<B: BV> Memory<B> {
         let mut region_constraints = Vec::new();
 
         for region in &self.regions {
-            let Range { start, end } = region.region_range();
+            let width = self.address_width;
+            let Range { start, .. } = region.region_range();
+            if *start > max_address(width) {
+                continue;
+            }
 
             region_constraints.push(And(
-                Box::new(Bvule(Box::new(bits64(*start, 64)), Box::new(Var(address)))),
-                Box::new(Bvult(Box::new(Var(address)), Box::new(bits64(*end, 64)))),
+                Box::new(Bvule(Box::new(bits64(*start, width)), Box::new(Var(address)))),
+                Box::new(Bvult(
+                    Box::new(ZeroExtend(1, Box::new(Var(address)))),
+                    Box::new(smt_range_end(region.region_range(), width)),
+                )),
             ))
         }
 
         if let Some(r) = region_constraints.pop() {
             let constraint = region_constraints.drain(..).fold(r, |r1, r2| Or(Box::new(r1), Box::new(r2)));
-            match solver.check_sat_with(&constraint) {
+            match solver.check_sat_with(&constraint, SourceLoc::unknown()) {
                 Sat => {
                     let mut model = Model::new(&solver);
                     log!(log::MEMORY, &format!("Overlapping satisfiable address: {:?}", model.get_var(address)?));
                     probe::taint_info(log::MEMORY, address, None, solver);
                     return Err(error);
                 }
-                Unknown => return Err(ExecError::Z3Unknown),
+                Unknown => {
+                    if solver.resolve_unknown(SourceLoc::unknown())? {
+                        return Err(error);
+                    }
+                }
                 Unsat => (),
             }
         }
@@ -391,7 +789,7 @@ impl<B: BV> Memory<B> {
     /// Panics if the number of bytes to read is concrete but does not fit
     /// in a u32, which should never be the case.
     pub fn read(
-        &self,
+        &mut self,
         read_kind: Val<B>,
         address: Val<B>,
         bytes: Val<B>,
@@ -400,60 +798,34 @@ impl<B: BV> Memory<B> {
     ) -> Result<Val<B>, ExecError> {
         log!(log::MEMORY, &format!("Read: {:?} {:?} {:?} {:?}", read_kind, address, bytes, tag));
 
+        let address = self.normalize_address(address, solver)?;
+
         if let Val::I128(bytes) = bytes {
             let bytes = u32::try_from(bytes).expect("Bytes did not fit in u32 in memory read");
+            self.check_access(&address, bytes, solver)?;
 
             match address {
                 Val::Bits(concrete_addr) => {
-                    for region in &self.regions {
-                        match region {
-                            Region::Constrained(range, generator) if range.contains(&concrete_addr.lower_u64()) => {
-                                return read_constrained(
-                                    range,
-                                    generator.as_ref(),
-                                    read_kind,
-                                    concrete_addr.lower_u64(),
-                                    bytes,
-                                    solver,
-                                    tag,
-                                    region.memory_kind(),
-                                )
-                            }
-
-                            Region::Symbolic(range) if range.contains(&concrete_addr.lower_u64()) => {
-                                return self.read_symbolic(read_kind, address, bytes, solver, tag, region.memory_kind())
-                            }
-
-                            Region::SymbolicCode(range) if range.contains(&concrete_addr.lower_u64()) => {
-                                return self.read_symbolic(read_kind, address, bytes, solver, tag, region.memory_kind())
-                            }
-
-                            Region::Concrete(range, contents) if range.contains(&concrete_addr.lower_u64()) => {
-                                return read_concrete(
-                                    contents,
-                                    read_kind,
-                                    concrete_addr.lower_u64(),
-                                    bytes,
-                                    solver,
-                                    tag,
-                                    region.memory_kind(),
-                                )
-                            }
-
-                            Region::Custom(range, contents) if range.contains(&concrete_addr.lower_u64()) => {
-                                return contents.read(read_kind, concrete_addr.lower_u64(), bytes, solver, tag)
-                            }
-
-                            _ => continue,
-                        }
-                    }
-
-                    self.read_symbolic(read_kind, address, bytes, solver, tag, DEFAULT_MEMORY_KIND)
+                    let tracked_tag = if tag { self.read_tag(concrete_addr.lower_u64(), bytes, solver)? } else { None };
+                    let value =
+                        self.read_concrete_address(read_kind, concrete_addr, bytes, solver, tag, tracked_tag)?;
+                    self.overlay_written(value, &address, bytes, solver)
                 }
 
                 Val::Symbolic(symbolic_addr) => {
+                    self.check_tag_overlap(
+                        symbolic_addr,
+                        bytes,
+                        ExecError::BadRead("possible symbolic address in tag region"),
+                        solver,
+                    )?;
+                    if let Some(region) = self.array_region_containing(symbolic_addr, bytes, solver)? {
+                        return self.read_array(region, read_kind, address, bytes, solver, tag);
+                    }
                     self.check_overlap(symbolic_addr, ExecError::BadRead("possible symbolic address overlap"), solver)?;
-                    self.read_symbolic(read_kind, address, bytes, solver, tag, DEFAULT_MEMORY_KIND)
+                    let value =
+                        self.read_symbolic(read_kind, address.clone(), bytes, solver, tag, None, DEFAULT_MEMORY_KIND)?;
+                    self.overlay_written(value, &address, bytes, solver)
                 }
 
                 _ => Err(ExecError::Type("Non bitvector address in read".to_string(), SourceLoc::unknown())),
@@ -463,6 +835,51 @@ impl<B: BV> Memory<B> {
         }
     }
 
+    /// Read from a concrete address. If the address is in a tag
+    /// region, `tracked_tag` is the current tag for the address,
+    /// which is returned in place of the tag from the memory region
+    /// itself.
+    fn read_concrete_address(
+        &mut self,
+        read_kind: Val<B>,
+        address: B,
+        bytes: u32,
+        solver: &mut Solver<B>,
+        tag: bool,
+        tracked_tag: Option<Val<B>>,
+    ) -> Result<Val<B>, ExecError> {
+        let concrete_addr = address.lower_u64();
+        let region_tag = tag && tracked_tag.is_none();
+
+        let value = if let Some(region) = self.array_region_at(concrete_addr) {
+            self.read_array(region, read_kind, Val::Bits(address), bytes, solver, region_tag)?
+        } else if let Some(Region::Custom(range, contents)) = self.region_at_mut(concrete_addr) {
+            if !range_contains_access(range, concrete_addr, bytes) {
+                return Err(ExecError::BadRead("read crosses the end of a custom region"));
+            }
+            contents.read(read_kind, concrete_addr, bytes, solver, region_tag)?
+        } else {
+            let region = self.regions.iter().find(|region| region.contains(concrete_addr));
+            let kind = region.map(Region::memory_kind).unwrap_or(DEFAULT_MEMORY_KIND);
+            match region {
+                Some(Region::Constrained(range, generator)) => {
+                    read_constrained(range, generator.as_ref(), read_kind, address, bytes, solver, region_tag, kind)?
+                }
+
+                Some(Region::Concrete(_, contents)) => {
+                    read_concrete(contents, read_kind, address, bytes, solver, region_tag, kind)?
+                }
+
+                _ => return self.read_symbolic(read_kind, Val::Bits(address), bytes, solver, tag, tracked_tag, kind),
+            }
+        };
+
+        match tracked_tag {
+            Some(tag_value) => Ok(make_bv_bit_pair(value, tag_value)),
+            None => Ok(value),
+        }
+    }
+
     pub fn write(
         &mut self,
         write_kind: Val<B>,
@@ -473,22 +890,39 @@ impl<B: BV> Memory<B> {
     ) -> Result<Val<B>, ExecError> {
         log!(log::MEMORY, &format!("Write: {:?} {:?} {:?} {:?}", write_kind, address, data, tag));
 
+        let address = self.normalize_address(address, solver)?;
+
+        if self.access_checks.is_enabled() {
+            let bytes = crate::primop::length_bits(&data, solver, SourceLoc::unknown())? / 8;
+            self.check_access(&address, bytes, solver)?
+        }
+
         match address {
             Val::Bits(concrete_addr) => {
-                for region in self.regions.iter_mut() {
-                    match region {
-                        Region::Custom(range, contents) if range.contains(&concrete_addr.lower_u64()) => {
-                            return contents.write(write_kind, concrete_addr.lower_u64(), data, solver, tag)
-                        }
-
-                        _ => continue,
-                    }
+                if self.tag_regions.is_empty() {
+                    return self.write_concrete_address(write_kind, concrete_addr, data, solver, tag);
                 }
-
-                self.write_symbolic(write_kind, address, data, solver, tag, DEFAULT_MEMORY_KIND)
+                // Only update the tags once the write has succeeded
+                let bytes = crate::primop::length_bits(&data, solver, SourceLoc::unknown())? / 8;
+                let result = self.write_concrete_address(write_kind, concrete_addr, data, solver, tag.clone())?;
+                self.write_tag(concrete_addr.lower_u64(), bytes, &tag);
+                Ok(result)
             }
 
             Val::Symbolic(symbolic_addr) => {
+                let bytes = crate::primop::length_bits(&data, solver, SourceLoc::unknown())? / 8;
+                self.check_tag_overlap(
+                    symbolic_addr,
+                    bytes,
+                    ExecError::BadWrite("possible symbolic address in tag region"),
+                    solver,
+                )?;
+                if let Some(region) = self.array_region_containing(symbolic_addr, bytes, solver)? {
+                    return self.write_array(region, write_kind, address, data, solver, tag);
+                }
+                if self.write_back.is_some() {
+                    return Err(ExecError::BadWrite("symbolic address write in sequential memory"));
+                }
                 self.check_overlap(symbolic_addr, ExecError::BadWrite("possible symbolic address overlap"), solver)?;
                 self.write_symbolic(write_kind, address, data, solver, tag, DEFAULT_MEMORY_KIND)
             }
@@ -497,10 +931,142 @@ impl<B: BV> Memory<B> {
         }
     }
 
+    fn write_concrete_address(
+        &mut self,
+        write_kind: Val<B>,
+        concrete_addr: B,
+        data: Val<B>,
+        solver: &mut Solver<B>,
+        tag: Option<Val<B>>,
+    ) -> Result<Val<B>, ExecError> {
+        if let Some(region) = self.array_region_at(concrete_addr.lower_u64()) {
+            return self.write_array(region, write_kind, Val::Bits(concrete_addr), data, solver, tag);
+        }
+
+        for region in self.regions.iter_mut() {
+            match region {
+                Region::Custom(range, contents) if range_contains(range, concrete_addr.lower_u64()) => {
+                    let bytes = crate::primop::length_bits(&data, solver, SourceLoc::unknown())? / 8;
+                    if !range_contains_access(range, concrete_addr.lower_u64(), bytes) {
+                        return Err(ExecError::BadWrite("write crosses the end of a custom region"));
+                    }
+                    return contents.write(write_kind, concrete_addr.lower_u64(), data, solver, tag);
+                }
+
+                _ => continue,
+            }
+        }
+
+        if self.write_back.is_some() {
+            self.store_written(concrete_addr.lower_u64(), &data, solver)?
+        }
+
+        self.write_symbolic(write_kind, Val::Bits(concrete_addr), data, solver, tag, DEFAULT_MEMORY_KIND)
+    }
+
+    /// Store each byte of a write in the sequential mode write-back
+    /// store. Writes must be a whole number of bytes.
+    fn store_written(&mut self, address: Address, data: &Val<B>, solver: &mut Solver<B>) -> Result<(), ExecError> {
+        let bits = crate::primop::length_bits(data, solver, SourceLoc::unknown())?;
+        if bits % 8 != 0 {
+            return Err(ExecError::BadWrite("partial byte write in sequential memory"));
+        }
+        let bytes = bits / 8;
+        let data_exp = crate::primop::smt_value(data)?;
+        for i in 0..bytes {
+            let byte = match data {
+                Val::Bits(bv) => Val::Bits(bv.extract(i * 8 + 7, i * 8).unwrap()),
+                _ => {
+                    let byte_exp = Exp::Extract(i * 8 + 7, i * 8, Box::new(data_exp.clone()));
+                    Val::Symbolic(solver.define_const(byte_exp, SourceLoc::unknown()))
+                }
+            };
+            if let Some(store) = &mut self.write_back {
+                Arc::make_mut(store).insert(address + u64::from(i), byte);
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the bytes of a value read from memory with any bytes
+    /// held in the sequential mode write-back store for the same
+    /// addresses. For symbolic addresses each byte becomes a chain of
+    /// if-then-else expressions over the stored bytes.
+    fn overlay_written(
+        &self,
+        value: Val<B>,
+        address: &Val<B>,
+        bytes: u32,
+        solver: &mut Solver<B>,
+    ) -> Result<Val<B>, ExecError> {
+        let store = match &self.write_back {
+            Some(store) if !store.is_empty() => store,
+            _ => return Ok(value),
+        };
+
+        if let Val::Struct(mut fields) = value {
+            if let Some(bits) = fields.remove(&ir::BV_BIT_LEFT) {
+                fields.insert(ir::BV_BIT_LEFT, self.overlay_written(bits, address, bytes, solver)?);
+            }
+            return Ok(Val::Struct(fields));
+        }
+
+        if let Val::Bits(address) = address {
+            let address = address.lower_u64();
+            let stored: Vec<Option<&Val<B>>> = (0..bytes).map(|i| store.get(&(address + u64::from(i)))).collect();
+            if stored.iter().all(Option::is_none) {
+                return Ok(value);
+            }
+
+            let concrete_byte = |i: usize, byte: &Option<&Val<B>>| match (byte, &value) {
+                (Some(Val::Bits(byte)), _) => Some(byte.lower_u8()),
+                (None, Val::Bits(bv)) => bv.extract(i as u32 * 8 + 7, i as u32 * 8).map(B::lower_u8),
+                _ => None,
+            };
+            if bytes <= 8 {
+                if let Some(mut byte_vec) =
+                    stored.iter().enumerate().map(|(i, byte)| concrete_byte(i, byte)).collect::<Option<Vec<u8>>>()
+                {
+                    byte_vec.reverse();
+                    return Ok(Val::Bits(B::from_bytes(&byte_vec)));
+                }
+            }
+
+            let value_exp = crate::primop::smt_value(&value)?;
+            let byte_exps = stored
+                .iter()
+                .enumerate()
+                .map(|(i, byte)| match byte {
+                    Some(byte) => crate::primop::smt_value(byte),
+                    None => Ok(Exp::Extract(i as u32 * 8 + 7, i as u32 * 8, Box::new(value_exp.clone()))),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(Val::Symbolic(solver.define_const(concat_bytes(byte_exps), SourceLoc::unknown())));
+        }
+
+        let address_exp = crate::primop::smt_value(address)?;
+        let value_exp = crate::primop::smt_value(&value)?;
+        let byte_exps = (0..bytes)
+            .map(|i| {
+                let byte_address = array_offset(&address_exp, i, self.address_width);
+                store.iter().try_fold(Exp::Extract(i * 8 + 7, i * 8, Box::new(value_exp.clone())), |rest, (a, byte)| {
+                    Ok(Exp::Ite(
+                        Box::new(Exp::Eq(Box::new(byte_address.clone()), Box::new(bits64(*a, self.address_width)))),
+                        Box::new(crate::primop::smt_value(byte)?),
+                        Box::new(rest),
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>, ExecError>>()?;
+        Ok(Val::Symbolic(solver.define_const(concat_bytes(byte_exps), SourceLoc::unknown())))
+    }
+
     /// The simplest read is to symbolically read a memory location. In
     /// that case we just return a fresh SMT bitvector of the appropriate
     /// size, and add a ReadMem event to the trace. For this we need the
-    /// number of bytes to be non-symbolic.
+    /// number of bytes to be non-symbolic. If the read is tagged the
+    /// tag is also fresh, unless it is tracked by a tag region.
+    #[allow(clippy::too_many_arguments)]
     fn read_symbolic(
         &self,
         read_kind: Val<B>,
@@ -508,6 +1074,7 @@ impl<B: BV> Memory<B> {
         bytes: u32,
         solver: &mut Solver<B>,
         tag: bool,
+        tracked_tag: Option<Val<B>>,
         kind: &'static str,
     ) -> Result<Val<B>, ExecError> {
         use crate::smt::smtlib::*;
@@ -515,14 +1082,15 @@ impl<B: BV> Memory<B> {
         let value = solver.fresh();
         solver.add(Def::DeclareConst(value, Ty::BitVec(8 * bytes)));
 
-        let tag_value = if tag {
-            let v = solver.fresh();
-            solver.add(Def::DeclareConst(v, Ty::BitVec(1)));
-            Some(v)
-        } else {
-            None
+        let tag_ir_value = match tracked_tag {
+            Some(tag_value) => Some(tag_value),
+            None if tag => {
+                let v = solver.fresh();
+                solver.add(Def::DeclareConst(v, Ty::BitVec(1)));
+                Some(Val::Symbolic(v))
+            }
+            None => None,
         };
-        let tag_ir_value = tag_value.map(Val::Symbolic);
         match &self.client_info {
             Some(c) => c.symbolic_read(
                 &self.regions,
@@ -544,7 +1112,7 @@ impl<B: BV> Memory<B> {
             kind,
         });
 
-        log!(log::MEMORY, &format!("Read symbolic: {} {:?}", value, tag_value));
+        log!(log::MEMORY, &format!("Read symbolic: {} {:?}", value, tag_ir_value));
 
         let return_value = match tag_ir_value {
             Some(v) => make_bv_bit_pair(Val::Symbolic(value), v),
@@ -587,6 +1155,152 @@ impl<B: BV> Memory<B> {
         Ok(Val::Symbolic(value))
     }
 
+    fn region_at_mut(&mut self, address: Address) -> Option<&mut Region<B>> {
+        self.regions.iter_mut().find(|region| region.contains(address))
+    }
+
+    /// Returns the index of the region containing a concrete
+    /// address, if that region is a symbolic array region.
+    fn array_region_at(&self, address: Address) -> Option<usize> {
+        let region = self.regions.iter().position(|region| region.contains(address))?;
+        if let Region::SymbolicArray(_, _) = self.regions[region] {
+            Some(region)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the index of a symbolic array region which must
+    /// contain every byte of an access at a symbolic address, if
+    /// there is such a region.
+    fn array_region_containing(
+        &self,
+        address: Sym,
+        bytes: u32,
+        solver: &mut Solver<B>,
+    ) -> Result<Option<usize>, ExecError> {
+        for (i, region) in self.regions.iter().enumerate() {
+            if let Region::SymbolicArray(range, _) = region {
+                let outside = Exp::Not(Box::new(smt_within_range(range, address, bytes, self.address_width)));
+                if !solver.is_sat_with(&outside, SourceLoc::unknown())? {
+                    return Ok(Some(i));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the current SMT array for a symbolic array region,
+    /// declaring an unconstrained array if the region has not been
+    /// used yet.
+    fn current_array(&mut self, region: usize, solver: &mut Solver<B>) -> Result<Sym, ExecError> {
+        use crate::smt::smtlib::*;
+
+        match &mut self.regions[region] {
+            Region::SymbolicArray(_, Some(array)) => Ok(*array),
+            Region::SymbolicArray(_, array) => {
+                let v = solver.fresh();
+                let address_ty = Ty::BitVec(self.address_width);
+                solver.add(Def::DeclareConst(v, Ty::Array(Box::new(address_ty), Box::new(Ty::BitVec(8)))));
+                *array = Some(v);
+                Ok(v)
+            }
+            _ => Err(ExecError::Unreachable("current_array called on non-array region".to_string())),
+        }
+    }
+
+    /// Read from a symbolic array region. The bytes are selected from
+    /// the region's current array and concatenated in little-endian
+    /// order. Allocation tags are not modelled by array regions, so
+    /// tag reads always return zero.
+    fn read_array(
+        &mut self,
+        region: usize,
+        read_kind: Val<B>,
+        address: Val<B>,
+        bytes: u32,
+        solver: &mut Solver<B>,
+        tag: bool,
+    ) -> Result<Val<B>, ExecError> {
+        use crate::smt::smtlib::*;
+
+        let array = self.current_array(region, solver)?;
+        let address_exp = crate::primop::smt_value(&address)?;
+        let value_exp = (0..bytes)
+            .map(|i| array_byte(array, &address_exp, i, self.address_width))
+            .reduce(|lower, higher| Exp::Concat(Box::new(higher), Box::new(lower)))
+            .ok_or(ExecError::BadRead("zero byte read from symbolic array"))?;
+
+        let value = solver.define_const(value_exp, SourceLoc::unknown());
+        solver.add_event(Event::ReadMem {
+            value: Val::Symbolic(value),
+            read_kind,
+            address,
+            bytes,
+            tag_value: None,
+            kind: self.regions[region].memory_kind(),
+        });
+
+        log!(log::MEMORY, &format!("Read symbolic array: {}", value));
+
+        if tag {
+            Ok(make_bv_bit_pair(Val::Symbolic(value), Val::Bits(B::zeros(1))))
+        } else {
+            Ok(Val::Symbolic(value))
+        }
+    }
+
+    /// Write to a symbolic array region, storing each byte of the data
+    /// into the region's array to create its new current array.
+    fn write_array(
+        &mut self,
+        region: usize,
+        write_kind: Val<B>,
+        address: Val<B>,
+        data: Val<B>,
+        solver: &mut Solver<B>,
+        tag: Option<Val<B>>,
+    ) -> Result<Val<B>, ExecError> {
+        use crate::smt::smtlib::*;
+
+        let data_length = crate::primop::length_bits(&data, solver, SourceLoc::unknown())?;
+        if data_length % 8 != 0 {
+            return Err(ExecError::Type(format!("write_array {:?}", &data_length), SourceLoc::unknown()));
+        };
+        let bytes = data_length / 8;
+
+        let array = self.current_array(region, solver)?;
+        let address_exp = crate::primop::smt_value(&address)?;
+        let data_exp = crate::primop::smt_value(&data)?;
+        let array_exp = (0..bytes).fold(Exp::Var(array), |array_exp, i| {
+            Exp::Store(
+                Box::new(array_exp),
+                Box::new(array_offset(&address_exp, i, self.address_width)),
+                Box::new(Exp::Extract(i * 8 + 7, i * 8, Box::new(data_exp.clone()))),
+            )
+        });
+
+        let new_array = solver.define_const(array_exp, SourceLoc::unknown());
+        if let Region::SymbolicArray(_, current) = &mut self.regions[region] {
+            *current = Some(new_array)
+        }
+
+        let value = solver.define_const(Exp::Bool(true), SourceLoc::unknown());
+        solver.add_event(Event::WriteMem {
+            value,
+            write_kind,
+            address,
+            data,
+            bytes,
+            tag_value: tag,
+            kind: self.regions[region].memory_kind(),
+        });
+
+        log!(log::MEMORY, &format!("Write symbolic array: {}", new_array));
+
+        Ok(Val::Bool(true))
+    }
+
     pub fn smt_address_constraint(
         &self,
         address: &Exp,
@@ -595,7 +1309,7 @@ impl<B: BV> Memory<B> {
         solver: &mut Solver<B>,
         tag: Option<&Exp>,
     ) -> Exp {
-        smt_address_constraint(&self.regions, address, bytes, kind, solver, tag)
+        smt_address_constraint(&self.regions, address, bytes, kind, solver, tag, self.address_width)
     }
 }
 
@@ -606,6 +1320,7 @@ pub fn smt_address_constraint<B: BV>(
     kind: SmtKind,
     solver: &mut Solver<B>,
     tag: Option<&Exp>,
+    address_width: u32,
 ) -> Exp {
     use crate::smt::smtlib::Exp::*;
     let addr_var = match address {
@@ -626,17 +1341,7 @@ pub fn smt_address_constraint<B: BV>(
         .map(|r| (r.region_range(), matches!(r, Region::Symbolic(_))))
         .filter(|(r, _k)| r.end - r.start >= bytes as u64)
         .map(|(r, k)| {
-            let in_range = And(
-                Box::new(Bvule(Box::new(bits64(r.start, 64)), Box::new(Var(addr_var)))),
-                // Use an extra bit to prevent wrapping
-                Box::new(Bvult(
-                    Box::new(Bvadd(
-                        Box::new(ZeroExtend(65, Box::new(Var(addr_var)))),
-                        Box::new(ZeroExtend(65, Box::new(bits64(bytes as u64, 64)))),
-                    )),
-                    Box::new(ZeroExtend(65, Box::new(bits64(r.end, 64)))),
-                )),
-            );
+            let in_range = smt_in_range(r, addr_var, bytes, address_width);
             // If we're not in a normal Symbolic region tags must be clear
             if let (false, Some(tag)) = (k, tag) {
                 And(Box::new(in_range), Box::new(Eq(Box::new(tag.clone()), Box::new(bits64(0, 1)))))
@@ -650,6 +1355,116 @@ pub fn smt_address_constraint<B: BV>(
         })
 }
 
+/// The largest address representable with the given address width
+fn max_address(width: u32) -> Address {
+    if width >= 64 {
+        Address::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
+
+/// Whether a region's range contains a concrete address, see
+/// [Region] for why a range ending at `Address::MAX` contains it.
+fn range_contains(range: &Range<Address>, address: Address) -> bool {
+    range.contains(&address) || (range.end == Address::MAX && address == Address::MAX)
+}
+
+/// Whether every byte of an access of `bytes` bytes at a concrete
+/// address lies within a region's range.
+fn range_contains_access(range: &Range<Address>, address: Address, bytes: u32) -> bool {
+    match address.checked_add(u64::from(cmp::max(bytes, 1)) - 1) {
+        Some(last) => range_contains(range, address) && range_contains(range, last),
+        None => false,
+    }
+}
+
+/// The exclusive end of `range` as a `width + 1` bit value, clamped
+/// to the top of the address space.
+fn smt_range_end(range: &Range<Address>, width: u32) -> Exp {
+    if range.end <= max_address(width) && range.end != Address::MAX {
+        bits64(range.end, width + 1)
+    } else if width < 64 {
+        bits64(1 << width, width + 1)
+    } else {
+        let mut top = vec![false; width as usize + 1];
+        top[width as usize] = true;
+        Exp::Bits(top)
+    }
+}
+
+/// An SMT constraint that an access of `bytes` bytes at a `width` bit
+/// `address` lies within `range`, and does not end at the last byte
+/// of the range. This is the constraint [smt_address_constraint] has
+/// always used for symbolic regions, see [smt_within_range] for one
+/// which allows the access to end at the last byte.
+fn smt_in_range(range: &Range<Address>, address: Sym, bytes: u32, width: u32) -> Exp {
+    use crate::smt::smtlib::Exp::*;
+    if range.start > max_address(width) {
+        return Bool(false);
+    }
+    And(
+        Box::new(Bvule(Box::new(bits64(range.start, width)), Box::new(Var(address)))),
+        // Use an extra bit to prevent wrapping
+        Box::new(Bvult(
+            Box::new(Bvadd(Box::new(ZeroExtend(1, Box::new(Var(address)))), Box::new(bits64(bytes as u64, width + 1)))),
+            Box::new(smt_range_end(range, width)),
+        )),
+    )
+}
+
+/// An SMT constraint that every byte of an access of `bytes` bytes
+/// at a `width` bit `address` lies within `range`.
+fn smt_within_range(range: &Range<Address>, address: Sym, bytes: u32, width: u32) -> Exp {
+    use crate::smt::smtlib::Exp::*;
+    if range.start > max_address(width) {
+        return Bool(false);
+    }
+    And(
+        Box::new(Bvule(Box::new(bits64(range.start, width)), Box::new(Var(address)))),
+        // Use an extra bit to prevent wrapping
+        Box::new(Bvule(
+            Box::new(Bvadd(Box::new(ZeroExtend(1, Box::new(Var(address)))), Box::new(bits64(bytes as u64, width + 1)))),
+            Box::new(smt_range_end(range, width)),
+        )),
+    )
+}
+
+/// An SMT constraint that some byte of an access of `bytes` bytes at
+/// a `width` bit `address` lies within `range`.
+fn smt_overlaps_range(range: &Range<Address>, address: Sym, bytes: u32, width: u32) -> Exp {
+    use crate::smt::smtlib::Exp::*;
+    if range.start > max_address(width) {
+        return Bool(false);
+    }
+    // Use an extra bit to prevent wrapping
+    let wide = |exp: Exp| ZeroExtend(1, Box::new(exp));
+    And(
+        Box::new(Bvult(
+            Box::new(bits64(range.start, width + 1)),
+            Box::new(Bvadd(Box::new(wide(Var(address))), Box::new(bits64(u64::from(cmp::max(bytes, 1)), width + 1)))),
+        )),
+        Box::new(Bvult(Box::new(wide(Var(address))), Box::new(smt_range_end(range, width)))),
+    )
+}
+
+fn array_offset(address: &Exp, offset: u32, width: u32) -> Exp {
+    if offset == 0 {
+        address.clone()
+    } else {
+        Exp::Bvadd(Box::new(address.clone()), Box::new(bits64(offset as u64, width)))
+    }
+}
+
+/// Concatenate a little-endian sequence of bytes
+fn concat_bytes(bytes: Vec<Exp>) -> Exp {
+    bytes.into_iter().reduce(|lower, higher| Exp::Concat(Box::new(higher), Box::new(lower))).unwrap()
+}
+
+fn array_byte(array: Sym, address: &Exp, offset: u32, width: u32) -> Exp {
+    Exp::Select(Box::new(Exp::Var(array)), Box::new(array_offset(address, offset, width)))
+}
+
 fn reverse_endianness(bytes: &mut [u8]) {
     if bytes.len() <= 2 {
         bytes.reverse()
@@ -665,18 +1480,18 @@ fn read_constrained<B: BV>(
     range: &Range<Address>,
     generator: &(dyn Fn(&mut Solver<B>) -> Sym),
     read_kind: Val<B>,
-    address: Address,
+    address: B,
     bytes: u32,
     solver: &mut Solver<B>,
     tag: bool,
     kind: &'static str,
 ) -> Result<Val<B>, ExecError> {
     let region = generator(solver);
-    if address == range.start && address + bytes as u64 == range.end {
+    if address.lower_u64() == range.start && address.lower_u64() + bytes as u64 == range.end {
         solver.add_event(Event::ReadMem {
             value: Val::Symbolic(region),
             read_kind,
-            address: Val::Bits(B::from_u64(address)),
+            address: Val::Bits(address),
             bytes,
             tag_value: None,
             kind,
@@ -692,17 +1507,17 @@ fn read_constrained<B: BV>(
 }
 
 fn read_concrete<B: BV>(
-    region: &HashMap<Address, u8>,
+    region: &Pages,
     read_kind: Val<B>,
-    address: Address,
+    address: B,
     bytes: u32,
     solver: &mut Solver<B>,
     tag: bool,
     kind: &'static str,
 ) -> Result<Val<B>, ExecError> {
     let mut byte_vec: Vec<u8> = Vec::with_capacity(bytes as usize);
-    for i in address..(address + u64::from(bytes)) {
-        byte_vec.push(*region.get(&i).unwrap_or(&0))
+    for i in address.lower_u64()..(address.lower_u64() + u64::from(bytes)) {
+        byte_vec.push(region.get(i))
     }
 
     reverse_endianness(&mut byte_vec);
@@ -714,7 +1529,7 @@ fn read_concrete<B: BV>(
         solver.add_event(Event::ReadMem {
             value,
             read_kind,
-            address: Val::Bits(B::from_u64(address)),
+            address: Val::Bits(address),
             bytes,
             tag_value: None,
             kind,
@@ -729,3 +1544,296 @@ fn read_concrete<B: BV>(
         Err(ExecError::BadRead("concrete read more than 8 bytes"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitvector::b64::B64;
+    use crate::smt::smtlib::Ty;
+    use crate::smt::{Config, Context};
+
+    #[test]
+    fn symbolic_array_read_after_write() {
+        let ctx = Context::new(Config::new());
+        let mut solver = Solver::<B64>::new(&ctx);
+        let mut memory = Memory::new();
+        memory.add_symbolic_array_region(0x1000..0x2000);
+
+        let addr = solver.declare_const(Ty::BitVec(64), SourceLoc::unknown());
+        let in_region = smt_in_range(&(0x1000..0x1ff0), addr, 8, 64);
+        solver.add(Def::Assert(in_region));
+
+        let data = Val::Bits(B64::new(0xdead_beef, 32));
+        memory.write(Val::Unit, Val::Symbolic(addr), data, &mut solver, None).unwrap();
+        let value = memory.read(Val::Unit, Val::Symbolic(addr), Val::I128(4), &mut solver, false).unwrap();
+        let byte = memory.read(Val::Unit, Val::Symbolic(addr), Val::I128(1), &mut solver, false).unwrap();
+
+        match (value, byte) {
+            (Val::Symbolic(value), Val::Symbolic(byte)) => {
+                let neq = Exp::Neq(Box::new(Exp::Var(value)), Box::new(bits64(0xdead_beef, 32)));
+                assert!(solver.check_sat_with(&neq, SourceLoc::unknown()) == SmtResult::Unsat);
+                let neq = Exp::Neq(Box::new(Exp::Var(byte)), Box::new(bits64(0xef, 8)));
+                assert!(solver.check_sat_with(&neq, SourceLoc::unknown()) == SmtResult::Unsat)
+            }
+            _ => panic!("Expected symbolic reads from symbolic array region"),
+        }
+
+        // Addresses which may lie outside every array region fall back to the usual behaviour
+        let unconstrained = solver.declare_const(Ty::BitVec(64), SourceLoc::unknown());
+        assert!(memory.read(Val::Unit, Val::Symbolic(unconstrained), Val::I128(4), &mut solver, false).is_err())
+    }
+
+    #[test]
+    fn tag_regions() {
+        let ctx = Context::new(Config::new());
+        let mut solver = Solver::<B64>::new(&ctx);
+        let mut memory = Memory::new();
+        memory.add_zero_region(0x0..0x1000);
+        memory.add_tag_region(TagRegion::new(0x0..0x1000, 16, TagInit::Symbolic(1)).clear_on_write(true));
+
+        let addr = |a| Val::Bits(B64::from_u64(a));
+        let tag_of = |value: Val<B64>| match value {
+            Val::Struct(fields) => fields.get(&ir::BV_BIT_RIGHT).cloned().unwrap(),
+            _ => panic!("Expected a tagged value"),
+        };
+
+        // The initial tag is symbolic, but each granule only has a single tag
+        let tag1 = tag_of(memory.read(Val::Unit, addr(0x20), Val::I128(8), &mut solver, true).unwrap());
+        let tag2 = tag_of(memory.read(Val::Unit, addr(0x28), Val::I128(8), &mut solver, true).unwrap());
+        assert!(matches!((&tag1, &tag2), (Val::Symbolic(v1), Val::Symbolic(v2)) if v1 == v2));
+
+        let data = Val::Bits(B64::new(0, 64));
+        memory.write(Val::Unit, addr(0x20), data.clone(), &mut solver, Some(Val::Bits(B64::new(1, 1)))).unwrap();
+        let tag = tag_of(memory.read(Val::Unit, addr(0x20), Val::I128(8), &mut solver, true).unwrap());
+        assert!(matches!(tag, Val::Bits(bv) if bv == B64::new(1, 1)));
+
+        memory.write(Val::Unit, addr(0x28), data, &mut solver, None).unwrap();
+        let tag = tag_of(memory.read(Val::Unit, addr(0x20), Val::I128(8), &mut solver, true).unwrap());
+        assert!(matches!(tag, Val::Bits(bv) if bv == B64::new(0, 1)));
+
+        assert!(memory.read(Val::Unit, addr(0x2c), Val::I128(8), &mut solver, true).is_err());
+
+        // A write which fails leaves the tags unchanged
+        memory.set_sequential(true);
+        let nibble = Val::Bits(B64::new(0, 4));
+        assert!(memory.write(Val::Unit, addr(0x20), nibble, &mut solver, Some(Val::Bits(B64::new(1, 1)))).is_err());
+        let tag = tag_of(memory.read(Val::Unit, addr(0x20), Val::I128(8), &mut solver, true).unwrap());
+        assert!(matches!(tag, Val::Bits(bv) if bv == B64::new(0, 1)));
+
+        // Symbolic accesses which could touch any byte of a tag region are rejected
+        let mut memory = Memory::new();
+        memory.add_tag_region(TagRegion::new(0x1000..0x2000, 16, TagInit::Symbolic(1)));
+        let sym_addr =
+            |a, solver: &mut Solver<B64>| Val::Symbolic(solver.define_const(bits64(a, 64), SourceLoc::unknown()));
+        for a in [0xffe, 0x1ffc] {
+            let address = sym_addr(a, &mut solver);
+            assert!(memory.read(Val::Unit, address, Val::I128(4), &mut solver, false).is_err())
+        }
+        let address = sym_addr(0x2000, &mut solver);
+        assert!(memory.read(Val::Unit, address, Val::I128(4), &mut solver, false).is_ok())
+    }
+
+    #[test]
+    fn address_width() {
+        let ctx = Context::new(Config::new());
+        let mut solver = Solver::<B64>::new(&ctx);
+        let mut memory = Memory::new();
+        memory.set_address_width(32);
+        memory.add_symbolic_array_region(0x8000_0000..0x1_0000_0000);
+
+        let addr = solver.declare_const(Ty::BitVec(32), SourceLoc::unknown());
+        solver.add(Def::Assert(smt_in_range(&(0x8000_0000..0x9000_0000), addr, 4, 32)));
+        let data = Val::Bits(B64::new(0x1234, 16));
+        memory.write(Val::Unit, Val::Symbolic(addr), data, &mut solver, None).unwrap();
+
+        match memory.read(Val::Unit, Val::Symbolic(addr), Val::I128(2), &mut solver, false).unwrap() {
+            Val::Symbolic(value) => {
+                let neq = Exp::Neq(Box::new(Exp::Var(value)), Box::new(bits64(0x1234, 16)));
+                assert!(solver.check_sat_with(&neq, SourceLoc::unknown()) == SmtResult::Unsat)
+            }
+            _ => panic!("Expected symbolic read from symbolic array region"),
+        }
+
+        // Addresses wider than the address width carry the address in their low bits
+        let wide_addr = solver.define_const(
+            Exp::Concat(Box::new(bits64(0xffff_ffff, 32)), Box::new(Exp::Var(addr))),
+            SourceLoc::unknown(),
+        );
+        match memory.read(Val::Unit, Val::Symbolic(wide_addr), Val::I128(2), &mut solver, false).unwrap() {
+            Val::Symbolic(value) => {
+                let neq = Exp::Neq(Box::new(Exp::Var(value)), Box::new(bits64(0x1234, 16)));
+                assert!(solver.check_sat_with(&neq, SourceLoc::unknown()) == SmtResult::Unsat)
+            }
+            _ => panic!("Expected symbolic read from symbolic array region"),
+        }
+        assert!(memory.read(Val::Unit, Val::Bits(B64::new(0x10, 64)), Val::I128(4), &mut solver, false).is_ok());
+
+        // Narrower addresses are rejected rather than zero extended
+        assert!(matches!(
+            memory.read(Val::Unit, Val::Bits(B64::new(0x10, 16)), Val::I128(4), &mut solver, false),
+            Err(ExecError::AddressWidth(16, 32))
+        ));
+        assert!(memory.read(Val::Unit, Val::Bits(B64::new(0x10, 32)), Val::I128(4), &mut solver, false).is_ok())
+    }
+
+    #[test]
+    fn address_space() {
+        let ctx = Context::new(Config::new());
+        let mut solver = Solver::<B64>::new(&ctx);
+        let mut memory = Memory::<B64>::new();
+        memory.set_address_width(32);
+        assert_eq!(memory.address_space(), 0..0x1_0000_0000);
+
+        // A 64-bit address space includes the last address
+        memory.set_address_width(64);
+        memory.add_symbolic_region(memory.address_space());
+        assert_eq!(memory.kind_at(Address::MAX), "symbolic");
+        memory.set_access_checks(AccessChecks { region_boundaries: true, ..AccessChecks::default() });
+        let last = solver.define_const(bits64(Address::MAX, 64), SourceLoc::unknown());
+        assert!(memory.check_access(&Val::Symbolic(last), 1, &mut solver).is_ok());
+        assert!(memory.check_access(&Val::Symbolic(last), 2, &mut solver).is_err())
+    }
+
+    #[test]
+    fn access_checks() {
+        let ctx = Context::new(Config::new());
+        let mut solver = Solver::<B64>::new(&ctx);
+        let mut memory = Memory::new();
+        memory.add_zero_region(0x1001..0x2000);
+        memory.set_access_checks(AccessChecks {
+            alignment: true,
+            region_boundaries: true,
+            page_size: Some(0x100),
+            mode: CheckMode::Error,
+        });
+
+        let mut read = |memory: &mut Memory<B64>, address, bytes| {
+            memory.read(Val::Unit, address, Val::I128(bytes), &mut solver, false)
+        };
+        assert!(read(&mut memory, Val::Bits(B64::new(0x1008, 64)), 8).is_ok());
+        assert!(matches!(
+            read(&mut memory, Val::Bits(B64::new(0x1002, 64)), 4),
+            Err(ExecError::AccessCheck("alignment", _, 4))
+        ));
+        assert!(matches!(
+            read(&mut memory, Val::Bits(B64::new(0x10FF, 64)), 3),
+            Err(ExecError::AccessCheck("page boundary", _, 3))
+        ));
+        assert!(read(&mut memory, Val::Bits(B64::new(0x1FFF, 64)), 1).is_ok());
+        assert!(matches!(
+            read(&mut memory, Val::Bits(B64::new(0x1000, 64)), 2),
+            Err(ExecError::AccessCheck("region boundary", _, 2))
+        ));
+
+        let addr = solver.declare_const(Ty::BitVec(64), SourceLoc::unknown());
+        solver.add(Def::Assert(smt_in_range(&(0x3000..0x4000), addr, 4, 64)));
+        assert!(matches!(
+            memory.read(Val::Unit, Val::Symbolic(addr), Val::I128(4), &mut solver, false),
+            Err(ExecError::AccessCheck("alignment", _, 4))
+        ));
+
+        memory.set_access_checks(AccessChecks { alignment: true, mode: CheckMode::Fork, ..AccessChecks::default() });
+        match memory.read(Val::Unit, Val::Symbolic(addr), Val::I128(4), &mut solver, false) {
+            Err(ExecError::AccessFork(aligned)) => {
+                solver.add(Def::Assert(Exp::Var(aligned)));
+                assert!(memory.read(Val::Unit, Val::Symbolic(addr), Val::I128(4), &mut solver, false).is_ok())
+            }
+            _ => panic!("Expected access check fork"),
+        }
+    }
+
+    #[test]
+    fn in_range_boundary() {
+        let ctx = Context::new(Config::new());
+        let mut solver = Solver::<B64>::new(&ctx);
+        let mut memory = Memory::new();
+        memory.add_zero_region(0x1000..0x2000);
+        memory.set_access_checks(AccessChecks { region_boundaries: true, ..AccessChecks::default() });
+
+        let last_word = solver.declare_const(Ty::BitVec(64), SourceLoc::unknown());
+        solver.add(Def::Assert(Exp::Eq(Box::new(Exp::Var(last_word)), Box::new(bits64(0x1FFC, 64)))));
+
+        // An access may end at the last byte of the range
+        let within = smt_within_range(&(0x1000..0x2000), last_word, 4, 64);
+        assert!(solver.is_sat_with(&within, SourceLoc::unknown()).unwrap());
+        let within = smt_within_range(&(0x1000..0x2000), last_word, 5, 64);
+        assert!(!solver.is_sat_with(&within, SourceLoc::unknown()).unwrap());
+
+        assert!(memory.check_access(&Val::Symbolic(last_word), 4, &mut solver).is_ok());
+        assert!(matches!(
+            memory.check_access(&Val::Symbolic(last_word), 5, &mut solver),
+            Err(ExecError::AccessCheck("region boundary", _, 5))
+        ));
+
+        // Including accesses to the last word of a symbolic array region
+        let mut memory = Memory::new();
+        memory.add_symbolic_array_region(0x1000..0x2000);
+        assert_eq!(memory.array_region_containing(last_word, 4, &mut solver).unwrap(), Some(0));
+        assert_eq!(memory.array_region_containing(last_word, 5, &mut solver).unwrap(), None)
+    }
+
+    #[test]
+    fn sequential() {
+        let ctx = Context::new(Config::new());
+        let mut solver = Solver::<B64>::new(&ctx);
+        let mut memory = Memory::new();
+        memory.set_sequential(true);
+
+        let data = Val::Bits(B64::new(0xDEAD_BEEF, 32));
+        memory.write(Val::Unit, Val::Bits(B64::new(0x1000, 64)), data, &mut solver, None).unwrap();
+
+        let read = memory.read(Val::Unit, Val::Bits(B64::new(0x1001, 64)), Val::I128(2), &mut solver, false).unwrap();
+        assert_eq!(read, Val::Bits(B64::new(0xADBE, 16)));
+
+        // A partially overlapping read only has its lower bytes determined by the write
+        match memory.read(Val::Unit, Val::Bits(B64::new(0x1002, 64)), Val::I128(4), &mut solver, false).unwrap() {
+            Val::Symbolic(value) => {
+                let lower = Exp::Extract(15, 0, Box::new(Exp::Var(value)));
+                let neq = Exp::Neq(Box::new(lower), Box::new(bits64(0xDEAD, 16)));
+                assert!(solver.check_sat_with(&neq, SourceLoc::unknown()) == SmtResult::Unsat)
+            }
+            _ => panic!("Expected symbolic read"),
+        }
+
+        // A read at a symbolic address sees the write if it is at the same address
+        let addr = solver.declare_const(Ty::BitVec(64), SourceLoc::unknown());
+        solver.add(Def::Assert(Exp::Eq(Box::new(Exp::Var(addr)), Box::new(bits64(0x1000, 64)))));
+        match memory.read(Val::Unit, Val::Symbolic(addr), Val::I128(1), &mut solver, false).unwrap() {
+            Val::Symbolic(value) => {
+                let neq = Exp::Neq(Box::new(Exp::Var(value)), Box::new(bits64(0xEF, 8)));
+                assert!(solver.check_sat_with(&neq, SourceLoc::unknown()) == SmtResult::Unsat)
+            }
+            _ => panic!("Expected symbolic read"),
+        }
+
+        let data = Val::Bits(B64::new(0, 8));
+        assert!(memory.write(Val::Unit, Val::Symbolic(addr), data, &mut solver, None).is_err());
+
+        let data = Val::Bits(B64::new(0, 12));
+        assert!(memory.write(Val::Unit, Val::Bits(B64::new(0x2000, 64)), data, &mut solver, None).is_err())
+    }
+
+    #[test]
+    fn sequential_fork() {
+        let ctx = Context::new(Config::new());
+        let mut solver = Solver::<B64>::new(&ctx);
+        let mut memory = Memory::new();
+        memory.set_sequential(true);
+
+        let data = Val::Bits(B64::new(0xAB, 8));
+        memory.write(Val::Unit, Val::Bits(B64::new(0x1000, 64)), data, &mut solver, None).unwrap();
+
+        // Forks share the write-back store until one of them writes
+        let mut fork = memory.clone();
+        assert!(Arc::ptr_eq(memory.write_back.as_ref().unwrap(), fork.write_back.as_ref().unwrap()));
+
+        let data = Val::Bits(B64::new(0xCD, 8));
+        fork.write(Val::Unit, Val::Bits(B64::new(0x1000, 64)), data, &mut solver, None).unwrap();
+        assert!(!Arc::ptr_eq(memory.write_back.as_ref().unwrap(), fork.write_back.as_ref().unwrap()));
+
+        let read = memory.read(Val::Unit, Val::Bits(B64::new(0x1000, 64)), Val::I128(1), &mut solver, false).unwrap();
+        assert_eq!(read, Val::Bits(B64::new(0xAB, 8)));
+        let read = fork.read(Val::Unit, Val::Bits(B64::new(0x1000, 64)), Val::I128(1), &mut solver, false).unwrap();
+        assert_eq!(read, Val::Bits(B64::new(0xCD, 8)))
+    }
+}
@@ -0,0 +1,360 @@
+// BSD 2-Clause License
+//
+// Copyright (c) 2019, 2020 Alasdair Armstrong
+// Copyright (c) 2020 Brian Campbell
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+// notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+// notice, this list of conditions and the following disclaimer in the
+// documentation and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! This module implements simple memory-mapped devices as
+//! [CustomRegion]s, so they can be declared in a `[memory]` table of
+//! either the ISA configuration or a litmus test, rather than
+//! requiring a new custom region to be written in Rust. Each entry in
+//! the table is a device, for example:
+//!
+//! ```toml
+//! [memory.uart]
+//! kind = "log"
+//! address = "0x10000000"
+//! bytes = 8
+//!
+//! [memory.id_registers]
+//! kind = "constant"
+//! address = "0x10001000"
+//! bytes = 16
+//! values = { "0x0" = "0x00000001", "0x4" = "0xdeadbeef" }
+//! ```
+//!
+//! The keys of the `values` table are offsets from the base address
+//! of the device, and each value is stored in little-endian order.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use toml::Value;
+
+use crate::bitvector::BV;
+use crate::error::ExecError;
+use crate::ir::source_loc::SourceLoc;
+use crate::ir::Val;
+use crate::log;
+use crate::primop::length_bits;
+use crate::smt::smtlib::{Exp, Ty};
+use crate::smt::{Event, Solver};
+
+use super::{make_bv_bit_pair, reverse_endianness, Address, CustomRegion, Region};
+
+#[derive(Clone, Debug)]
+enum DeviceKind {
+    /// A write-only device, like a UART transmit register, where each
+    /// write is recorded in the trace.
+    Log,
+    /// A bank of read-only registers with constant values, where
+    /// writes are ignored. Bytes without a specified value read as
+    /// zero.
+    Constant(HashMap<Address, u8>),
+    /// A read-only FIFO where each read returns a fresh symbolic
+    /// value, which can only be read once. If the FIFO has a depth,
+    /// reads fail after that many values have been read.
+    Fifo { depth: Option<u32>, reads: u32 },
+    /// Reads as zero, with writes ignored.
+    WriteIgnored,
+}
+
+#[derive(Clone, Debug)]
+pub struct Device {
+    kind: DeviceKind,
+}
+
+impl Device {
+    pub fn log() -> Self {
+        Device { kind: DeviceKind::Log }
+    }
+
+    pub fn constant(values: HashMap<Address, u8>) -> Self {
+        Device { kind: DeviceKind::Constant(values) }
+    }
+
+    pub fn fifo(depth: Option<u32>) -> Self {
+        Device { kind: DeviceKind::Fifo { depth, reads: 0 } }
+    }
+
+    pub fn write_ignored() -> Self {
+        Device { kind: DeviceKind::WriteIgnored }
+    }
+
+    /// Read a concrete value from a device which has one, as for a
+    /// concrete region this is limited to 8 bytes.
+    fn concrete_value<B: BV>(&self, address: Address, bytes: u32) -> Option<B> {
+        let mut byte_vec: Vec<u8> = Vec::with_capacity(bytes as usize);
+        for i in address..(address + u64::from(bytes)) {
+            match &self.kind {
+                DeviceKind::Constant(values) => byte_vec.push(values.get(&i).copied().unwrap_or(0)),
+                DeviceKind::WriteIgnored => byte_vec.push(0),
+                DeviceKind::Log | DeviceKind::Fifo { .. } => return None,
+            }
+        }
+        reverse_endianness(&mut byte_vec);
+        if byte_vec.len() <= 8 {
+            Some(B::from_bytes(&byte_vec))
+        } else {
+            None
+        }
+    }
+}
+
+impl<B: BV> CustomRegion<B> for Device {
+    fn read(
+        &mut self,
+        read_kind: Val<B>,
+        address: Address,
+        bytes: u32,
+        solver: &mut Solver<B>,
+        tag: bool,
+    ) -> Result<Val<B>, ExecError> {
+        log!(log::MEMORY, &format!("Device read: 0x{:x} {}", address, bytes));
+
+        let value = match &mut self.kind {
+            DeviceKind::Log => return Err(ExecError::BadRead("read from write-only log device")),
+            DeviceKind::Fifo { depth, reads } => {
+                if depth.map(|depth| *reads >= depth).unwrap_or(false) {
+                    return Err(ExecError::BadRead("read from empty FIFO device"));
+                }
+                *reads += 1;
+                Val::Symbolic(solver.declare_const(Ty::BitVec(8 * bytes), SourceLoc::unknown()))
+            }
+            DeviceKind::Constant(_) | DeviceKind::WriteIgnored => match self.concrete_value(address, bytes) {
+                Some(bv) => Val::Bits(bv),
+                None => return Err(ExecError::BadRead("device read more than 8 bytes")),
+            },
+        };
+
+        solver.add_event(Event::ReadMem {
+            value: value.clone(),
+            read_kind,
+            address: Val::Bits(B::from_u64(address)),
+            bytes,
+            tag_value: None,
+            kind: CustomRegion::<B>::memory_kind(self),
+        });
+
+        if tag {
+            Ok(make_bv_bit_pair(value, Val::Bits(B::zeros(1))))
+        } else {
+            Ok(value)
+        }
+    }
+
+    fn write(
+        &mut self,
+        write_kind: Val<B>,
+        address: Address,
+        data: Val<B>,
+        solver: &mut Solver<B>,
+        tag: Option<Val<B>>,
+    ) -> Result<Val<B>, ExecError> {
+        log!(log::MEMORY, &format!("Device write: 0x{:x} <- {:?}", address, data));
+
+        if let DeviceKind::Fifo { .. } = self.kind {
+            return Err(ExecError::BadWrite("write to read-only FIFO device"));
+        }
+
+        let data_length = length_bits(&data, solver, SourceLoc::unknown())?;
+        if data_length % 8 != 0 {
+            return Err(ExecError::Type(format!("device write {:?}", &data_length), SourceLoc::unknown()));
+        };
+
+        let value = solver.define_const(Exp::Bool(true), SourceLoc::unknown());
+        solver.add_event(Event::WriteMem {
+            value,
+            write_kind,
+            address: Val::Bits(B::from_u64(address)),
+            data,
+            bytes: data_length / 8,
+            tag_value: tag,
+            kind: CustomRegion::<B>::memory_kind(self),
+        });
+
+        Ok(Val::Bool(true))
+    }
+
+    fn initial_value(&self, address: Address, bytes: u32) -> Option<B> {
+        self.concrete_value(address, bytes)
+    }
+
+    fn memory_kind(&self) -> &'static str {
+        match self.kind {
+            DeviceKind::Log => "log device",
+            DeviceKind::Constant(_) => "constant device",
+            DeviceKind::Fifo { .. } => "fifo device",
+            DeviceKind::WriteIgnored => "write ignored device",
+        }
+    }
+
+    fn clone_dyn(&self) -> Box<dyn Send + Sync + CustomRegion<B>> {
+        Box::new(self.clone())
+    }
+}
+
+fn parse_address(value: &Value) -> Option<u64> {
+    match value {
+        Value::Integer(i) => u64::try_from(*i).ok(),
+        Value::String(s) if s.len() >= 2 && &s[0..2] == "0x" => u64::from_str_radix(&s[2..], 16).ok(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+fn parse_constant_values<B: BV>(name: &str, base: Address, values: &Value) -> Result<HashMap<Address, u8>, String> {
+    let values = values.as_table().ok_or_else(|| format!("memory.{}.values must be a table", name))?;
+    let mut contents = HashMap::new();
+    for (offset, value) in values {
+        let offset = parse_address(&Value::String(offset.clone()))
+            .ok_or_else(|| format!("Could not parse offset {} in memory.{}.values", offset, name))?;
+        let bv = value.as_str().and_then(B::from_str).filter(|bv| bv.len() % 8 == 0).ok_or_else(|| {
+            format!("Values in memory.{}.values must be bitvectors with a whole number of bytes", name)
+        })?;
+        for (i, byte) in bv.to_le_bytes().iter().enumerate() {
+            contents.insert(base + offset + i as u64, *byte);
+        }
+    }
+    Ok(contents)
+}
+
+fn parse_device<B: BV>(name: &str, device: &Value) -> Result<Region<B>, String> {
+    let device = device.as_table().ok_or_else(|| format!("memory.{} must be a table", name))?;
+    let field = |key: &str| device.get(key).ok_or_else(|| format!("memory.{} must have a `{}` field", name, key));
+
+    let address = parse_address(field("address")?)
+        .ok_or_else(|| format!("Could not parse memory.{}.address as a 64-bit unsigned integer", name))?;
+    let bytes = parse_address(field("bytes")?)
+        .ok_or_else(|| format!("Could not parse memory.{}.bytes as a 64-bit unsigned integer", name))?;
+
+    let device = match field("kind")?.as_str() {
+        Some("log") => Device::log(),
+        Some("constant") => match device.get("values") {
+            Some(values) => Device::constant(parse_constant_values::<B>(name, address, values)?),
+            None => Device::constant(HashMap::new()),
+        },
+        Some("fifo") => match device.get("depth") {
+            Some(depth) => Device::fifo(Some(
+                depth
+                    .as_integer()
+                    .and_then(|depth| u32::try_from(depth).ok())
+                    .ok_or_else(|| format!("memory.{}.depth must be a non-negative integer", name))?,
+            )),
+            None => Device::fifo(None),
+        },
+        Some("ignore") => Device::write_ignored(),
+        _ => return Err(format!("memory.{}.kind must be one of log, constant, fifo, or ignore", name)),
+    };
+
+    let end = address
+        .checked_add(bytes)
+        .ok_or_else(|| format!("memory.{} extends past the end of the 64-bit address space", name))?;
+
+    Ok(Region::Custom(address..end, Box::new(device)))
+}
+
+/// Parse the devices declared in the `[memory]` table of a TOML
+/// configuration or litmus file, returning an empty vector if there is
+/// no such table.
+pub fn parse_devices<B: BV>(toml: &Value) -> Result<Vec<Region<B>>, String> {
+    match toml.get("memory") {
+        Some(memory) => {
+            let memory = memory.as_table().ok_or_else(|| "[memory] must be a table".to_string())?;
+            memory.iter().map(|(name, device)| parse_device(name, device)).collect()
+        }
+        None => Ok(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitvector::b64::B64;
+    use crate::memory::Memory;
+    use crate::smt::{Config, Context};
+
+    #[test]
+    fn devices_from_toml() {
+        let toml = r#"
+            [memory.uart]
+            kind = "log"
+            address = "0x1000"
+            bytes = 8
+
+            [memory.id]
+            kind = "constant"
+            address = "0x2000"
+            bytes = 8
+            values = { "0x4" = "0xdeadbeef" }
+
+            [memory.input]
+            kind = "fifo"
+            address = "0x3000"
+            bytes = 4
+            depth = 1
+        "#
+        .parse::<Value>()
+        .unwrap();
+
+        let ctx = Context::new(Config::new());
+        let mut solver = Solver::<B64>::new(&ctx);
+        let mut memory = Memory::new();
+        for region in parse_devices::<B64>(&toml).unwrap() {
+            memory.add_region(region)
+        }
+        assert_eq!(memory.kind_at(0x1004), "log device");
+
+        let addr = |a| Val::Bits(B64::from_u64(a));
+        let data = Val::Bits(B64::new(0x41, 8));
+        assert!(memory.write(Val::Unit, addr(0x1000), data.clone(), &mut solver, None).is_ok());
+        assert!(memory.read(Val::Unit, addr(0x1000), Val::I128(1), &mut solver, false).is_err());
+
+        let value = memory.read(Val::Unit, addr(0x2004), Val::I128(4), &mut solver, false).unwrap();
+        assert!(matches!(value, Val::Bits(bv) if bv == B64::new(0xdead_beef, 32)));
+        assert!(memory.write(Val::Unit, addr(0x2004), data.clone(), &mut solver, None).is_ok());
+
+        // Accesses must not run past the end of the device
+        assert!(memory.read(Val::Unit, addr(0x2006), Val::I128(4), &mut solver, false).is_err());
+        let word = Val::Bits(B64::new(0x41, 32));
+        assert!(memory.write(Val::Unit, addr(0x2006), word, &mut solver, None).is_err());
+
+        assert!(matches!(memory.read(Val::Unit, addr(0x3000), Val::I128(4), &mut solver, false), Ok(Val::Symbolic(_))));
+        assert!(memory.read(Val::Unit, addr(0x3000), Val::I128(4), &mut solver, false).is_err());
+        assert!(memory.write(Val::Unit, addr(0x3000), data, &mut solver, None).is_err());
+
+        assert!(solver.trace().to_vec().iter().filter(|event| event.is_memory()).count() == 4);
+
+        let bad_kind = "[memory.dev]\nkind = \"disk\"\naddress = 0\nbytes = 1".parse::<Value>().unwrap();
+        assert!(parse_devices::<B64>(&bad_kind).is_err());
+
+        let overflow = "[memory.dev]\nkind = \"log\"\naddress = \"0xffffffffffffff00\"\nbytes = \"0x200\""
+            .parse::<Value>()
+            .unwrap();
+        assert!(parse_devices::<B64>(&overflow).is_err())
+    }
+}
@@ -0,0 +1,301 @@
+// BSD 2-Clause License
+//
+// Copyright (c) 2019, 2020 Alasdair Armstrong
+// Copyright (c) 2020 Brian Campbell
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+// notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+// notice, this list of conditions and the following disclaimer in the
+// documentation and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! This module contains loaders for memory images, so memory can be
+//! initialised from a raw binary file placed at a base address, or
+//! from an Intel HEX or Motorola S-record file, which contain their
+//! own addresses. An image is a list of segments, which can be added
+//! to memory with [super::Memory::add_image].
+
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use super::pages::Pages;
+use super::Address;
+
+/// A memory image, as a list of segments each containing the bytes
+/// starting at an address. Where segments overlap, later segments
+/// take precedence.
+pub type Image = Vec<(Address, Vec<u8>)>;
+
+/// Check that a segment does not run past the end of the address
+/// space.
+fn segment_fits(address: Address, bytes: &[u8]) -> bool {
+    bytes.is_empty() || address.checked_add(bytes.len() as u64 - 1).is_some()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Binary,
+    IntelHex,
+    SRecord,
+}
+
+impl ImageFormat {
+    /// Guess the format of an image from its file extension. Files
+    /// with an unrecognised extension are treated as raw binaries.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        match path.as_ref().extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("hex") | Some("ihex") | Some("ihx") => ImageFormat::IntelHex,
+            Some("srec") | Some("s19") | Some("s28") | Some("s37") | Some("mot") => ImageFormat::SRecord,
+            _ => ImageFormat::Binary,
+        }
+    }
+}
+
+pub fn parse_binary(bytes: &[u8], base: Address) -> Result<Image, String> {
+    if !segment_fits(base, bytes) {
+        return Err(format!("Binary image at 0x{:x} extends past the end of the address space", base));
+    }
+    Ok(vec![(base, bytes.to_vec())])
+}
+
+fn parse_hex_bytes(line: &str, line_number: usize) -> Result<Vec<u8>, String> {
+    if line.len() % 2 == 1 {
+        return Err(format!("Odd number of hex digits on line {}", line_number));
+    }
+    // Work on bytes rather than slicing the string, so non-ASCII
+    // input is reported as an error rather than splitting a character
+    line.as_bytes()
+        .chunks(2)
+        .map(|pair| match ((pair[0] as char).to_digit(16), (pair[1] as char).to_digit(16)) {
+            (Some(hi), Some(lo)) => Ok((hi << 4 | lo) as u8),
+            _ => Err(format!("Invalid hex digits {} on line {}", String::from_utf8_lossy(pair), line_number)),
+        })
+        .collect()
+}
+
+/// Parse an Intel HEX file, supporting both extended segment and
+/// extended linear addresses.
+pub fn parse_intel_hex(contents: &str) -> Result<Image, String> {
+    let mut image = Vec::new();
+    let mut offset: u64 = 0;
+
+    for (i, line) in contents.lines().enumerate().map(|(i, line)| (i + 1, line.trim())) {
+        if line.is_empty() {
+            continue;
+        }
+        if !line.starts_with(':') {
+            return Err(format!("Intel HEX record on line {} does not start with ':'", i));
+        }
+
+        let record = parse_hex_bytes(&line[1..], i)?;
+        if record.len() < 5 || record.len() != record[0] as usize + 5 {
+            return Err(format!("Intel HEX record on line {} has the wrong length", i));
+        }
+        if record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(format!("Bad checksum for Intel HEX record on line {}", i));
+        }
+
+        let address = u64::from(record[1]) << 8 | u64::from(record[2]);
+        let data = &record[4..record.len() - 1];
+        match record[3] {
+            0x00 => image.push((offset + address, data.to_vec())),
+            0x01 => break,
+            0x02 if data.len() == 2 => offset = (u64::from(data[0]) << 8 | u64::from(data[1])) << 4,
+            0x04 if data.len() == 2 => offset = (u64::from(data[0]) << 8 | u64::from(data[1])) << 16,
+            // Start addresses do not affect the contents of memory
+            0x03 | 0x05 => (),
+            record_type => return Err(format!("Invalid Intel HEX record type {:02x} on line {}", record_type, i)),
+        }
+    }
+
+    Ok(image)
+}
+
+/// Parse a Motorola S-record file with 16, 24, or 32-bit addresses.
+pub fn parse_srecord(contents: &str) -> Result<Image, String> {
+    let mut image = Vec::new();
+
+    for (i, line) in contents.lines().enumerate().map(|(i, line)| (i + 1, line.trim())) {
+        if line.is_empty() {
+            continue;
+        }
+        if line.len() < 2 || !line.starts_with('S') {
+            return Err(format!("S-record on line {} does not start with 'S'", i));
+        }
+
+        let record_type = line.as_bytes()[1];
+        let data = line.get(2..).ok_or_else(|| format!("Invalid S-record type on line {}", i))?;
+        let record = parse_hex_bytes(data, i)?;
+        if record.is_empty() || record.len() != record[0] as usize + 1 {
+            return Err(format!("S-record on line {} has the wrong length", i));
+        }
+        if record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0xFF {
+            return Err(format!("Bad checksum for S-record on line {}", i));
+        }
+
+        let address_bytes = match record_type {
+            b'1' => 2,
+            b'2' => 3,
+            b'3' => 4,
+            // Headers, record counts, and start addresses do not affect the contents of memory
+            b'0' | b'5' | b'6' | b'7' | b'8' | b'9' => continue,
+            _ => return Err(format!("Invalid S-record type on line {}", i)),
+        };
+        if record.len() < address_bytes + 2 {
+            return Err(format!("S-record on line {} is too short", i));
+        }
+
+        let address = record[1..=address_bytes].iter().fold(0u64, |address, byte| address << 8 | u64::from(*byte));
+        image.push((address, record[address_bytes + 1..record.len() - 1].to_vec()));
+    }
+
+    Ok(image)
+}
+
+/// Load an image from a file, using the file extension to determine
+/// its format. Raw binary files require a base address. For Intel HEX
+/// and S-record files, any base address is added to the addresses in
+/// the file.
+pub fn load_image<P: AsRef<Path>>(path: P, base: Option<Address>) -> Result<Image, String> {
+    let path = path.as_ref();
+    let read_error = |e| format!("Could not read memory image {}: {}", path.display(), e);
+
+    let image = match ImageFormat::from_path(path) {
+        ImageFormat::Binary => {
+            let base = base.ok_or_else(|| format!("Raw binary image {} requires a base address", path.display()))?;
+            return parse_binary(&fs::read(path).map_err(read_error)?, base)
+                .map_err(|e| format!("{}: {}", path.display(), e));
+        }
+        ImageFormat::IntelHex => parse_intel_hex(&fs::read_to_string(path).map_err(read_error)?),
+        ImageFormat::SRecord => parse_srecord(&fs::read_to_string(path).map_err(read_error)?),
+    }
+    .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    match base {
+        Some(base) => image
+            .into_iter()
+            .map(|(address, bytes)| match address.checked_add(base) {
+                Some(address) if segment_fits(address, &bytes) => Ok((address, bytes)),
+                _ => Err(format!(
+                    "{}: segment at 0x{:x} plus base 0x{:x} extends past the end of the address space",
+                    path.display(),
+                    address,
+                    base
+                )),
+            })
+            .collect(),
+        None => Ok(image),
+    }
+}
+
+/// Load an image from a command line argument of the form
+/// `<file>[@<address>]`.
+pub fn load_image_argument(arg: &str) -> Result<Image, String> {
+    match arg.rsplit_once('@') {
+        Some((file, addr)) => {
+            let base = if let Some(hex) = addr.strip_prefix("0x") {
+                u64::from_str_radix(hex, 16)
+            } else {
+                addr.parse::<u64>()
+            };
+            let base = base.map_err(|_| format!("Invalid base address {} for memory image {}", addr, file))?;
+            load_image(file, Some(base))
+        }
+        None => load_image(arg, None),
+    }
+}
+
+/// Write the segments of an image into pages, returning the pages
+/// along with the contiguous ranges of addresses the image covers in
+/// order of address.
+pub fn to_pages(image: &Image) -> (Pages, Vec<RangeInclusive<Address>>) {
+    let mut pages = Pages::new();
+    let mut ranges = Vec::new();
+    for (address, bytes) in image.iter().filter(|(_, bytes)| !bytes.is_empty()) {
+        pages.write_bytes(*address, bytes);
+        ranges.push(*address..=address.wrapping_add(bytes.len() as u64 - 1))
+    }
+    ranges.sort_unstable_by_key(|range| *range.start());
+
+    let mut merged: Vec<RangeInclusive<Address>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start().saturating_sub(1) <= *last.end() => {
+                *last = *last.start()..=*range.end().max(last.end())
+            }
+            _ => merged.push(range),
+        }
+    }
+    (pages, merged)
+}
+
+/// Split an image into its contiguous segments, in order of address.
+pub fn segments(image: &Image) -> Vec<(Address, Vec<u8>)> {
+    let (pages, ranges) = to_pages(image);
+    ranges.into_iter().map(|range| (*range.start(), range.map(|address| pages.get(address)).collect())).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intel_hex() {
+        let hex = ":020000040001F9\n:0400100001020304E2\n:00000001FF\n";
+        let image = parse_intel_hex(hex).unwrap();
+        assert_eq!(segments(&image), vec![(0x10010, vec![1, 2, 3, 4])]);
+        assert!(parse_intel_hex(":0400100001020304E3\n").is_err())
+    }
+
+    #[test]
+    fn srecord() {
+        let srec = "S00600004844521B\nS1070100AABBCCDDE9\nS5030001FB\nS9030000FC\n";
+        let image = parse_srecord(srec).unwrap();
+        assert_eq!(segments(&image), vec![(0x100, vec![0xAA, 0xBB, 0xCC, 0xDD])]);
+        assert!(parse_srecord("S1070100AABBCCDDEA\n").is_err())
+    }
+
+    #[test]
+    fn non_ascii() {
+        assert!(parse_intel_hex(":\u{e9}00").is_err());
+        assert!(parse_intel_hex(":\u{e9}\n").is_err());
+        assert!(parse_srecord("S\u{e9}0701\n").is_err());
+        assert!(parse_srecord("S10\u{e9}\n").is_err())
+    }
+
+    #[test]
+    fn overlapping_segments() {
+        let image = vec![(0x1002, vec![5, 6]), (0x1000, vec![1, 2, 3]), (0x1004, vec![7])];
+        assert_eq!(segments(&image), vec![(0x1000, vec![1, 2, 3, 6, 7])])
+    }
+
+    #[test]
+    fn binary_segments() {
+        let mut image = parse_binary(&[1, 2], 0x1000).unwrap();
+        image.extend(parse_binary(&[3], 0x2000).unwrap());
+        assert_eq!(segments(&image), vec![(0x1000, vec![1, 2]), (0x2000, vec![3])]);
+        assert!(parse_binary(&[1, 2], u64::MAX - 1).is_ok());
+        assert_eq!(segments(&parse_binary(&[1, 2], u64::MAX - 1).unwrap()), vec![(u64::MAX - 1, vec![1, 2])]);
+        assert!(parse_binary(&[1, 2, 3], u64::MAX - 1).is_err())
+    }
+}
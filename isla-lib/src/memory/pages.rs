@@ -0,0 +1,149 @@
+// BSD 2-Clause License
+//
+// Copyright (c) 2019, 2020 Alasdair Armstrong
+// Copyright (c) 2020 Brian Campbell
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+// notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+// notice, this list of conditions and the following disclaimer in the
+// documentation and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! This module implements the storage for concrete memory regions as
+//! a persistent map of fixed-size pages. Both the page table and the
+//! individual pages are reference counted and copied on write, so
+//! cloning memory when a frame is frozen is cheap regardless of how
+//! much concrete memory has been loaded, and each path only copies
+//! the pages it actually modifies.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::FromIterator;
+use std::sync::Arc;
+
+use super::Address;
+
+pub const PAGE_BITS: u32 = 12;
+pub const PAGE_SIZE: usize = 1 << PAGE_BITS;
+
+type Page = [u8; PAGE_SIZE];
+
+fn split_address(address: Address) -> (u64, usize) {
+    (address >> PAGE_BITS, (address & (PAGE_SIZE as u64 - 1)) as usize)
+}
+
+/// Concrete memory contents. Bytes which have never been written
+/// read as zero.
+#[derive(Clone, Default)]
+pub struct Pages {
+    pages: Arc<HashMap<u64, Arc<Page>>>,
+}
+
+impl Pages {
+    pub fn new() -> Self {
+        Pages::default()
+    }
+
+    /// Create a set of pages containing the bytes starting at address.
+    pub fn from_bytes(address: Address, bytes: &[u8]) -> Self {
+        let mut pages = Pages::new();
+        pages.write_bytes(address, bytes);
+        pages
+    }
+
+    pub fn get(&self, address: Address) -> u8 {
+        let (page, offset) = split_address(address);
+        self.pages.get(&page).map(|page| page[offset]).unwrap_or(0)
+    }
+
+    fn page_mut(&mut self, page: u64) -> &mut Page {
+        Arc::make_mut(Arc::make_mut(&mut self.pages).entry(page).or_insert_with(|| Arc::new([0; PAGE_SIZE])))
+    }
+
+    pub fn insert(&mut self, address: Address, byte: u8) {
+        let (page, offset) = split_address(address);
+        self.page_mut(page)[offset] = byte
+    }
+
+    pub fn write_bytes(&mut self, address: Address, bytes: &[u8]) {
+        let mut written = 0;
+        while written < bytes.len() {
+            let (page, offset) = split_address(address.wrapping_add(written as u64));
+            let n = usize::min(PAGE_SIZE - offset, bytes.len() - written);
+            self.page_mut(page)[offset..offset + n].copy_from_slice(&bytes[written..written + n]);
+            written += n
+        }
+    }
+
+    /// The number of pages which have been allocated.
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+}
+
+impl FromIterator<(Address, u8)> for Pages {
+    fn from_iter<I: IntoIterator<Item = (Address, u8)>>(iter: I) -> Self {
+        let mut pages = Pages::new();
+        for (address, byte) in iter {
+            pages.insert(address, byte)
+        }
+        pages
+    }
+}
+
+impl From<HashMap<Address, u8>> for Pages {
+    fn from(contents: HashMap<Address, u8>) -> Self {
+        contents.into_iter().collect()
+    }
+}
+
+impl fmt::Debug for Pages {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{} pages>", self.pages.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_on_write() {
+        let mut pages = Pages::from_bytes(0xFFE, &[1, 2, 3, 4]);
+        assert_eq!(pages.page_count(), 2);
+        assert_eq!((pages.get(0xFFD), pages.get(0xFFF), pages.get(0x1001)), (0, 2, 4));
+
+        let snapshot = pages.clone();
+        assert!(Arc::ptr_eq(&pages.pages, &snapshot.pages));
+
+        pages.insert(0x1000, 5);
+        assert_eq!((pages.get(0x1000), snapshot.get(0x1000)), (5, 3));
+        assert!(Arc::ptr_eq(&pages.pages[&0], &snapshot.pages[&0]));
+        assert!(!Arc::ptr_eq(&pages.pages[&1], &snapshot.pages[&1]))
+    }
+
+    #[test]
+    fn write_at_end() {
+        let pages = Pages::from_bytes(u64::MAX - 1, &[1, 2]);
+        assert_eq!((pages.get(u64::MAX - 1), pages.get(u64::MAX)), (1, 2))
+    }
+}
@@ -46,12 +46,13 @@ use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
 use std::ops::{BitAnd, BitOr, Not, Shl, Shr};
 use std::str::FromStr;
+use std::sync::Arc;
 
 use crate::bitvector::b64::B64;
 use crate::bitvector::BV;
 use crate::error::ExecError;
 use crate::executor::LocalFrame;
-use crate::ir::{source_loc::SourceLoc, BitsSegment, UVal, Val, ELF_ENTRY};
+use crate::ir::{source_loc::SourceLoc, BitsSegment, EnumMember, UVal, Val, ELF_ENTRY};
 use crate::smt::smtlib::*;
 use crate::smt::*;
 
@@ -59,6 +60,14 @@ pub type Unary<B> = fn(Val<B>, solver: &mut Solver<B>, info: SourceLoc) -> Resul
 pub type Binary<B> = fn(Val<B>, Val<B>, solver: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError>;
 pub type Variadic<B> =
     fn(Vec<Val<B>>, solver: &mut Solver<B>, frame: &mut LocalFrame<B>, info: SourceLoc) -> Result<Val<B>, ExecError>;
+/// A user-defined primop which can capture its environment, see
+/// [Primops::register_closure].
+pub type Closure<B> = Arc<
+    dyn 'static
+        + Send
+        + Sync
+        + Fn(Vec<Val<B>>, &mut Solver<B>, &mut LocalFrame<B>, SourceLoc) -> Result<Val<B>, ExecError>,
+>;
 
 #[allow(clippy::needless_range_loop)]
 pub fn smt_i128(i: i128) -> Exp {
@@ -289,7 +298,7 @@ fn optimistic_assert<B: BV>(
     match x {
         Val::Symbolic(v) => {
             let test_true = Box::new(Exp::Var(v));
-            let can_be_true = solver.check_sat_with(&test_true).is_sat()?;
+            let can_be_true = solver.is_sat_with(&test_true, info)?;
             if can_be_true {
                 solver.add(Def::Assert(Exp::Var(v)));
                 Ok(Val::Unit)
@@ -322,7 +331,7 @@ fn pessimistic_assert<B: BV>(
     match x {
         Val::Symbolic(v) => {
             let test_false = Exp::Not(Box::new(Exp::Var(v)));
-            let can_be_false = solver.check_sat_with(&test_false).is_sat()?;
+            let can_be_false = solver.is_sat_with(&test_false, info)?;
             if can_be_false {
                 Err(ExecError::AssertionFailed(message))
             } else {
@@ -399,22 +408,47 @@ pub(crate) fn op_neq<B: BV>(
     }
 }
 
-pub(crate) fn op_head<B: BV>(xs: Val<B>, _: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
+/// Taking the head or tail of a list is only valid if the list is
+/// non-empty, which the code will have checked by comparing it
+/// against the empty list, so for a symbolic list this must hold on
+/// the current path.
+fn assert_symbolic_list_non_empty<B: BV>(len: Sym, solver: &mut Solver<B>) {
+    solver.add(Def::Assert(Exp::Neq(Box::new(Exp::Var(len)), Box::new(bits64(0, 64)))))
+}
+
+pub(crate) fn op_head<B: BV>(xs: Val<B>, solver: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
     match xs {
         Val::List(mut xs) => match xs.pop() {
             Some(x) => Ok(x),
             None => Err(ExecError::Type(format!("op_head (list empty) {:?}", &xs), info)),
         },
+        Val::SymbolicList(len, mut xs) => match xs.pop() {
+            Some(x) => {
+                assert_symbolic_list_non_empty(len, solver);
+                Ok(x)
+            }
+            None => Err(ExecError::Type(format!("op_head (list empty) {:?}", &xs), info)),
+        },
         _ => Err(ExecError::Type(format!("op_head {:?}", &xs), info)),
     }
 }
 
-pub(crate) fn op_tail<B: BV>(xs: Val<B>, _: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
+pub(crate) fn op_tail<B: BV>(xs: Val<B>, solver: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
     match xs {
         Val::List(mut xs) => {
             xs.pop();
             Ok(Val::List(xs))
         }
+        Val::SymbolicList(len, mut xs) => {
+            assert_symbolic_list_non_empty(len, solver);
+            xs.pop();
+            if xs.is_empty() {
+                Ok(Val::List(xs))
+            } else {
+                let len = solver.define_const(Exp::Bvsub(Box::new(Exp::Var(len)), Box::new(bits64(1, 64))), info);
+                Ok(Val::SymbolicList(len, xs))
+            }
+        }
         _ => Err(ExecError::Type(format!("op_tail {:?}", &xs), info)),
     }
 }
@@ -710,6 +744,16 @@ fn add_bits_int<B: BV>(bits: Val<B>, n: Val<B>, solver: &mut Solver<B>, info: So
             ));
             Ok(Val::Symbolic(result))
         }
+        (Val::Bits(bits), Val::Symbolic(n)) => {
+            let len = bits.len();
+            assert!(len <= 128);
+            solver
+                .define_const(
+                    Exp::Bvadd(Box::new(smt_sbits(bits)), Box::new(Exp::Extract(len - 1, 0, Box::new(Exp::Var(n))))),
+                    info,
+                )
+                .into()
+        }
         (bits, n) => Err(ExecError::Type(format!("add_bits_int {:?} {:?}", &bits, &n), info)),
     }
 }
@@ -753,10 +797,63 @@ fn sub_bits_int<B: BV>(bits: Val<B>, n: Val<B>, solver: &mut Solver<B>, info: So
             ));
             Ok(Val::Symbolic(result))
         }
+        (Val::Bits(bits), Val::Symbolic(n)) => {
+            let len = bits.len();
+            assert!(len <= 128);
+            solver
+                .define_const(
+                    Exp::Bvsub(Box::new(smt_sbits(bits)), Box::new(Exp::Extract(len - 1, 0, Box::new(Exp::Var(n))))),
+                    info,
+                )
+                .into()
+        }
         (bits, n) => Err(ExecError::Type(format!("sub_bits_int {:?} {:?}", &bits, &n), info)),
     }
 }
 
+/// Convert a bitvector value from a model into a signed integer.
+pub(crate) fn model_i128(exp: &Exp) -> Option<i128> {
+    match exp {
+        Exp::Bits64(bv) => Some(bv.signed()),
+        Exp::Bits(bits) if !bits.is_empty() && bits.len() <= 128 => {
+            let n = bits.iter().rev().fold(0, |n: i128, bit| (n << 1) | *bit as i128);
+            if bits.len() < 128 && bits[bits.len() - 1] {
+                Some(n - (1 << bits.len()))
+            } else {
+                Some(n)
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Called when a primop is given a symbolic length. If case
+/// splitting on symbolic lengths is enabled (see
+/// [crate::smt::SolverOptions::length_cases]), and the length can
+/// only take a single value, that value is returned so the primop
+/// can continue concretely. If it can take a few values, we raise
+/// [ExecError::LengthFork] so the executor can fork on them.
+fn symbolic_length<B: BV>(
+    len: Sym,
+    name: &'static str,
+    solver: &mut Solver<B>,
+    info: SourceLoc,
+) -> Result<Val<B>, ExecError> {
+    let limit = match solver.length_cases() {
+        Some(limit) => limit,
+        None => return Err(ExecError::SymbolicLength(name, info)),
+    };
+    match solver.possible_values(len, limit, info)? {
+        Some(values) if values.len() == 1 => match model_i128(&values[0]) {
+            Some(n) => Ok(Val::I128(n)),
+            None => Err(ExecError::Type(format!("{} (length is not an integer) {:?}", name, &values[0]), info)),
+        },
+        Some(values) if values.is_empty() => Err(ExecError::Dead),
+        Some(values) => Err(ExecError::LengthFork(len, values)),
+        None => Err(ExecError::SymbolicLength(name, info)),
+    }
+}
+
 fn zeros<B: BV>(len: Val<B>, solver: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
     match len {
         Val::I128(len) => {
@@ -766,7 +863,7 @@ fn zeros<B: BV>(len: Val<B>, solver: &mut Solver<B>, info: SourceLoc) -> Result<
                 solver.define_const(smt_zeros(len), info).into()
             }
         }
-        Val::Symbolic(_) => Err(ExecError::SymbolicLength("zeros", info)),
+        Val::Symbolic(len) => zeros(symbolic_length(len, "zeros", solver, info)?, solver, info),
         _ => Err(ExecError::Type(format!("zeros {:?}", &len), info)),
     }
 }
@@ -780,7 +877,7 @@ fn ones<B: BV>(len: Val<B>, solver: &mut Solver<B>, info: SourceLoc) -> Result<V
                 solver.define_const(smt_ones(len), info).into()
             }
         }
-        Val::Symbolic(_) => Err(ExecError::SymbolicLength("ones", info)),
+        Val::Symbolic(len) => ones(symbolic_length(len, "ones", solver, info)?, solver, info),
         _ => Err(ExecError::Type(format!("ones {:?}", &len), info)),
     }
 }
@@ -812,7 +909,7 @@ macro_rules! extension {
                     };
                     solver.define_const($smt_extension(ext, Box::new(Exp::Var(bits))), info).into()
                 }
-                (_, Val::Symbolic(_)) => Err(ExecError::SymbolicLength("extension", info)),
+                (bits, Val::Symbolic(len)) => $id(bits, symbolic_length(len, "extension", solver, info)?, solver, info),
                 (_, _) => Err(ExecError::Type($name, info)),
             }
         }
@@ -883,6 +980,9 @@ fn replicate_bits<B: BV>(
                 solver.define_const(replicate_exp(Exp::Var(bits), times), info).into()
             }
         }
+        (bits, Val::Symbolic(times)) => {
+            replicate_bits(bits, symbolic_length(times, "replicate_bits", solver, info)?, solver, info)
+        }
         (bits, times) => Err(ExecError::Type(format!("replicate_bits {:?} {:?}", &bits, &times), info)),
     }
 }
@@ -1067,7 +1167,7 @@ fn slice_internal<B: BV>(
                         }
                     }
                 },
-                _ if bits.is_zero() => Ok(Val::Bits(B::zeros(bits_length))),
+                _ if bits.is_zero() => Ok(Val::Bits(B::zeros(length as u32))),
                 _ => slice!(bits_length, smt_sbits(bits), from, length, solver, info),
             },
             Val::MixedBits(ref segments) => match from {
@@ -1079,7 +1179,10 @@ fn slice_internal<B: BV>(
             },
             _ => Err(ExecError::Type(format!("slice_internal {:?}", &bits), info)),
         },
-        Val::Symbolic(_) => Err(ExecError::SymbolicLength("slice_internal", info)),
+        Val::Symbolic(length) => {
+            let length = symbolic_length(length, "slice_internal", solver, info)?;
+            slice_internal(bits, from, length, solver, info)
+        }
         _ => Err(ExecError::Type(format!("slice_internal {:?}", &length), info)),
     }
 }
@@ -1115,8 +1218,14 @@ pub fn subrange_internal<B: BV>(
             let bits_length = segments_length(segments, solver, info)?;
             mixed_bits_slice(segments, bits_length, low as u32, (high - low + 1) as u32, solver, info)
         }
-        (_, _, Val::Symbolic(_)) => Err(ExecError::SymbolicLength("subrange_internal", info)),
-        (_, Val::Symbolic(_), _) => Err(ExecError::SymbolicLength("subrange_internal", info)),
+        (bits, high, Val::Symbolic(low)) => {
+            let low = symbolic_length(low, "subrange_internal", solver, info)?;
+            subrange_internal(bits, high, low, solver, info)
+        }
+        (bits, Val::Symbolic(high), low) => {
+            let high = symbolic_length(high, "subrange_internal", solver, info)?;
+            subrange_internal(bits, high, low, solver, info)
+        }
         (bits, high, low) => {
             Err(ExecError::Type(format!("subrange_internal {:?} {:?} {:?}", &bits, &high, &low), info))
         }
@@ -1166,7 +1275,9 @@ fn sail_truncate_lsb<B: BV>(
             let bits_length = segments_length(segments, solver, info)?;
             mixed_bits_slice(segments, bits_length, bits_length - len as u32, len as u32, solver, info)
         }
-        (_, Val::Symbolic(_)) => Err(ExecError::SymbolicLength("sail_truncateLSB", info)),
+        (bits, Val::Symbolic(len)) => {
+            sail_truncate_lsb(bits, symbolic_length(len, "sail_truncateLSB", solver, info)?, solver, info)
+        }
         (bits, len) => Err(ExecError::Type(format!("sail_truncateLSB {:?} {:?}", &bits, &len), info)),
     }
 }
@@ -1201,19 +1312,33 @@ fn sail_signed<B: BV>(bits: Val<B>, solver: &mut Solver<B>, info: SourceLoc) ->
     }
 }
 
+/// Convert a 128-bit shift amount into a bitvector of `length` bits
+/// for use with the SMT shift operations. Shifts of `length` or more
+/// are clamped to `length`, as truncating them could otherwise turn
+/// a large shift into a small one.
+fn smt_shift_amount(length: u32, shift: Exp) -> Exp {
+    if length < 128 {
+        let clamp = smt_i128(length as i128);
+        let in_range = Exp::Bvult(Box::new(shift.clone()), Box::new(clamp.clone()));
+        Exp::Ite(
+            Box::new(in_range),
+            Box::new(Exp::Extract(length - 1, 0, Box::new(shift))),
+            Box::new(Exp::Extract(length - 1, 0, Box::new(clamp))),
+        )
+    } else if length > 128 {
+        Exp::ZeroExtend(length - 128, Box::new(shift))
+    } else {
+        shift
+    }
+}
+
 fn shiftr<B: BV>(bits: Val<B>, shift: Val<B>, solver: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
     // We could support (MixedBits, I128) explicitly, if necessary
     let bits = replace_mixed_bits(bits, solver, info)?;
     match (bits, shift) {
         (Val::Symbolic(x), Val::Symbolic(y)) => match solver.length(x) {
             Some(length) => {
-                let shift = if length < 128 {
-                    Exp::Extract(length - 1, 0, Box::new(Exp::Var(y)))
-                } else if length > 128 {
-                    Exp::ZeroExtend(length - 128, Box::new(Exp::Var(y)))
-                } else {
-                    Exp::Var(y)
-                };
+                let shift = smt_shift_amount(length, Exp::Var(y));
                 solver.define_const(Exp::Bvlshr(Box::new(Exp::Var(x)), Box::new(shift)), info).into()
             }
             None => Err(ExecError::Type(format!("shiftr {:?} {:?}", &x, &y), info)),
@@ -1221,22 +1346,13 @@ fn shiftr<B: BV>(bits: Val<B>, shift: Val<B>, solver: &mut Solver<B>, info: Sour
         (Val::Symbolic(x), Val::I128(0)) => Ok(Val::Symbolic(x)),
         (Val::Symbolic(x), Val::I128(y)) => match solver.length(x) {
             Some(length) => {
-                let shift = if length < 128 {
-                    Exp::Extract(length - 1, 0, Box::new(smt_i128(y)))
-                } else if length > 128 {
-                    Exp::ZeroExtend(length - 128, Box::new(smt_i128(y)))
-                } else {
-                    smt_i128(y)
-                };
+                let shift = smt_shift_amount(length, smt_i128(y));
                 solver.define_const(Exp::Bvlshr(Box::new(Exp::Var(x)), Box::new(shift)), info).into()
             }
             None => Err(ExecError::Type(format!("shiftr {:?} {:?}", &x, &y), info)),
         },
         (Val::Bits(x), Val::Symbolic(y)) => solver
-            .define_const(
-                Exp::Bvlshr(Box::new(smt_sbits(x)), Box::new(Exp::Extract(x.len() - 1, 0, Box::new(Exp::Var(y))))),
-                info,
-            )
+            .define_const(Exp::Bvlshr(Box::new(smt_sbits(x)), Box::new(smt_shift_amount(x.len(), Exp::Var(y)))), info)
             .into(),
         (Val::Bits(x), Val::I128(y)) => Ok(Val::Bits(x.shiftr(y))),
         (bits, shift) => Err(ExecError::Type(format!("shiftr {:?} {:?}", &bits, &shift), info)),
@@ -1254,13 +1370,7 @@ fn arith_shiftr<B: BV>(
     match (bits, shift) {
         (Val::Symbolic(x), Val::Symbolic(y)) => match solver.length(x) {
             Some(length) => {
-                let shift = if length < 128 {
-                    Exp::Extract(length - 1, 0, Box::new(Exp::Var(y)))
-                } else if length > 128 {
-                    Exp::ZeroExtend(length - 128, Box::new(Exp::Var(y)))
-                } else {
-                    Exp::Var(y)
-                };
+                let shift = smt_shift_amount(length, Exp::Var(y));
                 solver.define_const(Exp::Bvashr(Box::new(Exp::Var(x)), Box::new(shift)), info).into()
             }
             None => Err(ExecError::Type(format!("arith_shiftr {:?} {:?}", &x, &y), info)),
@@ -1268,22 +1378,13 @@ fn arith_shiftr<B: BV>(
         (Val::Symbolic(x), Val::I128(0)) => Ok(Val::Symbolic(x)),
         (Val::Symbolic(x), Val::I128(y)) => match solver.length(x) {
             Some(length) => {
-                let shift = if length < 128 {
-                    Exp::Extract(length - 1, 0, Box::new(smt_i128(y)))
-                } else if length > 128 {
-                    Exp::ZeroExtend(length - 128, Box::new(smt_i128(y)))
-                } else {
-                    smt_i128(y)
-                };
+                let shift = smt_shift_amount(length, smt_i128(y));
                 solver.define_const(Exp::Bvashr(Box::new(Exp::Var(x)), Box::new(shift)), info).into()
             }
             None => Err(ExecError::Type(format!("arith_shiftr {:?} {:?}", &x, &y), info)),
         },
         (Val::Bits(x), Val::Symbolic(y)) => solver
-            .define_const(
-                Exp::Bvashr(Box::new(smt_sbits(x)), Box::new(Exp::Extract(x.len() - 1, 0, Box::new(Exp::Var(y))))),
-                info,
-            )
+            .define_const(Exp::Bvashr(Box::new(smt_sbits(x)), Box::new(smt_shift_amount(x.len(), Exp::Var(y)))), info)
             .into(),
         (Val::Bits(x), Val::I128(y)) => Ok(Val::Bits(x.arith_shiftr(y))),
         (bits, shift) => Err(ExecError::Type(format!("arith_shiftr {:?} {:?}", &bits, &shift), info)),
@@ -1296,13 +1397,7 @@ fn shiftl<B: BV>(bits: Val<B>, len: Val<B>, solver: &mut Solver<B>, info: Source
     match (bits, len) {
         (Val::Symbolic(x), Val::Symbolic(y)) => match solver.length(x) {
             Some(length) => {
-                let shift = if length < 128 {
-                    Exp::Extract(length - 1, 0, Box::new(Exp::Var(y)))
-                } else if length > 128 {
-                    Exp::ZeroExtend(length - 128, Box::new(Exp::Var(y)))
-                } else {
-                    Exp::Var(y)
-                };
+                let shift = smt_shift_amount(length, Exp::Var(y));
                 solver.define_const(Exp::Bvshl(Box::new(Exp::Var(x)), Box::new(shift)), info).into()
             }
             None => Err(ExecError::Type(format!("shiftl {:?} {:?}", &x, &y), info)),
@@ -1310,22 +1405,13 @@ fn shiftl<B: BV>(bits: Val<B>, len: Val<B>, solver: &mut Solver<B>, info: Source
         (Val::Symbolic(x), Val::I128(0)) => Ok(Val::Symbolic(x)),
         (Val::Symbolic(x), Val::I128(y)) => match solver.length(x) {
             Some(length) => {
-                let shift = if length < 128 {
-                    Exp::Extract(length - 1, 0, Box::new(smt_i128(y)))
-                } else if length > 128 {
-                    Exp::ZeroExtend(length - 128, Box::new(smt_i128(y)))
-                } else {
-                    smt_i128(y)
-                };
+                let shift = smt_shift_amount(length, smt_i128(y));
                 solver.define_const(Exp::Bvshl(Box::new(Exp::Var(x)), Box::new(shift)), info).into()
             }
             None => Err(ExecError::Type(format!("shiftl {:?} {:?}", &x, &y), info)),
         },
         (Val::Bits(x), Val::Symbolic(y)) => solver
-            .define_const(
-                Exp::Bvshl(Box::new(smt_sbits(x)), Box::new(Exp::Extract(x.len() - 1, 0, Box::new(Exp::Var(y))))),
-                info,
-            )
+            .define_const(Exp::Bvshl(Box::new(smt_sbits(x)), Box::new(smt_shift_amount(x.len(), Exp::Var(y)))), info)
             .into(),
         (Val::Bits(x), Val::I128(y)) => Ok(Val::Bits(x.shiftl(y))),
         (bits, len) => Err(ExecError::Type(format!("shiftl {:?} {:?}", &bits, &len), info)),
@@ -1341,17 +1427,22 @@ pub fn shift_bits_right<B: BV>(
     // We could support (MixedBits, Bits) explicitly, if necessary
     let bits = replace_mixed_bits(bits, solver, info)?;
     let bits_len = length_bits(&bits, solver, info)?;
-    let shift_len = length_bits(&bits, solver, info)?;
+    let shift_len = length_bits(&shift, solver, info)?;
     match (&bits, &shift) {
         (Val::Symbolic(_), Val::Symbolic(_)) | (Val::Bits(_), Val::Symbolic(_)) | (Val::Symbolic(_), Val::Bits(_)) => {
-            let shift = if bits_len < shift_len {
-                Exp::Extract(bits_len - 1, 0, Box::new(smt_value(&shift)?))
+            // If the shift is wider than the bits, truncating it could
+            // turn a large shift into a small one, so do the shift at
+            // the width of the shift and truncate the result instead.
+            let exp = if bits_len < shift_len {
+                let bits = Exp::ZeroExtend(shift_len - bits_len, Box::new(smt_value(&bits)?));
+                Exp::Extract(bits_len - 1, 0, Box::new(Exp::Bvlshr(Box::new(bits), Box::new(smt_value(&shift)?))))
             } else if bits_len > shift_len {
-                Exp::ZeroExtend(bits_len - shift_len, Box::new(smt_value(&shift)?))
+                let shift = Exp::ZeroExtend(bits_len - shift_len, Box::new(smt_value(&shift)?));
+                Exp::Bvlshr(Box::new(smt_value(&bits)?), Box::new(shift))
             } else {
-                smt_value(&shift)?
+                Exp::Bvlshr(Box::new(smt_value(&bits)?), Box::new(smt_value(&shift)?))
             };
-            solver.define_const(Exp::Bvlshr(Box::new(smt_value(&bits)?), Box::new(shift)), info).into()
+            solver.define_const(exp, info).into()
         }
         (Val::Bits(x), Val::Bits(y)) => {
             let shift: u64 = (*y).try_into()?;
@@ -1370,17 +1461,22 @@ pub fn shift_bits_left<B: BV>(
     // We could support (MixedBits, Bits) explicitly, if necessary
     let bits = replace_mixed_bits(bits, solver, info)?;
     let bits_len = length_bits(&bits, solver, info)?;
-    let shift_len = length_bits(&bits, solver, info)?;
+    let shift_len = length_bits(&shift, solver, info)?;
     match (&bits, &shift) {
         (Val::Symbolic(_), Val::Symbolic(_)) | (Val::Bits(_), Val::Symbolic(_)) | (Val::Symbolic(_), Val::Bits(_)) => {
-            let shift = if bits_len < shift_len {
-                Exp::Extract(bits_len - 1, 0, Box::new(smt_value(&shift)?))
+            // If the shift is wider than the bits, truncating it could
+            // turn a large shift into a small one, so do the shift at
+            // the width of the shift and truncate the result instead.
+            let exp = if bits_len < shift_len {
+                let bits = Exp::ZeroExtend(shift_len - bits_len, Box::new(smt_value(&bits)?));
+                Exp::Extract(bits_len - 1, 0, Box::new(Exp::Bvshl(Box::new(bits), Box::new(smt_value(&shift)?))))
             } else if bits_len > shift_len {
-                Exp::ZeroExtend(bits_len - shift_len, Box::new(smt_value(&shift)?))
+                let shift = Exp::ZeroExtend(bits_len - shift_len, Box::new(smt_value(&shift)?));
+                Exp::Bvshl(Box::new(smt_value(&bits)?), Box::new(shift))
             } else {
-                smt_value(&shift)?
+                Exp::Bvshl(Box::new(smt_value(&bits)?), Box::new(smt_value(&shift)?))
             };
-            solver.define_const(Exp::Bvshl(Box::new(smt_value(&bits)?), Box::new(shift)), info).into()
+            solver.define_const(exp, info).into()
         }
         (Val::Bits(x), Val::Bits(y)) => {
             let shift: u64 = (*y).try_into()?;
@@ -1473,13 +1569,7 @@ pub(crate) fn vector_access<B: BV>(
     match (vec, n) {
         (Val::Symbolic(bits), Val::Symbolic(n)) => match solver.length(bits) {
             Some(length) => {
-                let shift = if length < 128 {
-                    Exp::Extract(length - 1, 0, Box::new(Exp::Var(n)))
-                } else if length > 128 {
-                    Exp::ZeroExtend(length - 128, Box::new(Exp::Var(n)))
-                } else {
-                    Exp::Var(n)
-                };
+                let shift = smt_shift_amount(length, Exp::Var(n));
                 solver
                     .define_const(
                         Exp::Extract(0, 0, Box::new(Exp::Bvlshr(Box::new(Exp::Var(bits)), Box::new(shift)))),
@@ -1491,13 +1581,7 @@ pub(crate) fn vector_access<B: BV>(
         },
         (Val::Symbolic(bits), Val::I128(n)) => match solver.length(bits) {
             Some(length) => {
-                let shift = if length < 128 {
-                    Exp::Extract(length - 1, 0, Box::new(smt_i128(n)))
-                } else if length > 128 {
-                    Exp::ZeroExtend(length - 128, Box::new(smt_i128(n)))
-                } else {
-                    smt_i128(n)
-                };
+                let shift = smt_shift_amount(length, smt_i128(n));
                 solver
                     .define_const(
                         Exp::Extract(0, 0, Box::new(Exp::Bvlshr(Box::new(Exp::Var(bits)), Box::new(shift)))),
@@ -1508,7 +1592,7 @@ pub(crate) fn vector_access<B: BV>(
             None => Err(ExecError::Type(format!("vector_access {:?} {:?}", &bits, &n), info)),
         },
         (Val::Bits(bits), Val::Symbolic(n)) => {
-            let shift = Exp::Extract(bits.len() - 1, 0, Box::new(Exp::Var(n)));
+            let shift = smt_shift_amount(bits.len(), Exp::Var(n));
             solver
                 .define_const(
                     Exp::Extract(0, 0, Box::new(Exp::Bvlshr(Box::new(smt_sbits(bits)), Box::new(shift)))),
@@ -1751,38 +1835,41 @@ pub(crate) fn op_set_slice<B: BV>(
     let update = replace_mixed_bits(update, solver, info)?;
     let bits_length = length_bits(&bits, solver, info)?;
     let update_length = length_bits(&update, solver, info)?;
+    // set_slice! expects a 128-bit index
+    let var_n = |n| Exp::ZeroExtend(64, Box::new(Exp::Var(n)));
+    let int_n = |n: i64| smt_i128(n.into());
     match (bits, n, update) {
         (Val::Symbolic(bits), Val::Symbolic(n), Val::Symbolic(update)) => {
-            set_slice!(bits_length, update_length, Exp::Var(bits), Exp::Var(n), Exp::Var(update), solver, info)
+            set_slice!(bits_length, update_length, Exp::Var(bits), var_n(n), Exp::Var(update), solver, info)
         }
         (Val::Symbolic(bits), Val::Symbolic(n), Val::Bits(update)) => {
-            set_slice!(bits_length, update_length, Exp::Var(bits), Exp::Var(n), smt_sbits(update), solver, info)
+            set_slice!(bits_length, update_length, Exp::Var(bits), var_n(n), smt_sbits(update), solver, info)
         }
         (Val::Symbolic(bits), Val::I64(n), Val::Symbolic(update)) => {
             if n == 0 {
                 set_slice_n0!(bits_length, update_length, Exp::Var(bits), Exp::Var(update), solver, info)
             } else {
-                set_slice!(bits_length, update_length, Exp::Var(bits), smt_i64(n), Exp::Var(update), solver, info)
+                set_slice!(bits_length, update_length, Exp::Var(bits), int_n(n), Exp::Var(update), solver, info)
             }
         }
         (Val::Symbolic(bits), Val::I64(n), Val::Bits(update)) => {
             if n == 0 {
                 set_slice_n0!(bits_length, update_length, Exp::Var(bits), smt_sbits(update), solver, info)
             } else {
-                set_slice!(bits_length, update_length, Exp::Var(bits), smt_i64(n), smt_sbits(update), solver, info)
+                set_slice!(bits_length, update_length, Exp::Var(bits), int_n(n), smt_sbits(update), solver, info)
             }
         }
         (Val::Bits(bits), Val::Symbolic(n), Val::Symbolic(update)) => {
-            set_slice!(bits_length, update_length, smt_sbits(bits), Exp::Var(n), Exp::Var(update), solver, info)
+            set_slice!(bits_length, update_length, smt_sbits(bits), var_n(n), Exp::Var(update), solver, info)
         }
         (Val::Bits(bits), Val::Symbolic(n), Val::Bits(update)) => {
-            set_slice!(bits_length, update_length, smt_sbits(bits), Exp::Var(n), smt_sbits(update), solver, info)
+            set_slice!(bits_length, update_length, smt_sbits(bits), var_n(n), smt_sbits(update), solver, info)
         }
         (Val::Bits(bits), Val::I64(n), Val::Symbolic(update)) => {
             if n == 0 {
                 set_slice_n0!(bits_length, update_length, smt_sbits(bits), Exp::Var(update), solver, info)
             } else {
-                set_slice!(bits_length, update_length, smt_sbits(bits), smt_i64(n), Exp::Var(update), solver, info)
+                set_slice!(bits_length, update_length, smt_sbits(bits), int_n(n), Exp::Var(update), solver, info)
             }
         }
         (Val::Bits(bits), Val::I64(n), Val::Bits(update)) => Ok(Val::Bits(bits.set_slice(n as u32, update))),
@@ -1892,7 +1979,9 @@ fn get_slice_int_internal<B: BV>(
             },
             _ => Err(ExecError::Type(format!("get_slice_int {:?}", &length), info)),
         },
-        Val::Symbolic(_) => Err(ExecError::SymbolicLength("get_slice_int", info)),
+        Val::Symbolic(length) => {
+            get_slice_int_internal(symbolic_length(length, "get_slice_int", solver, info)?, n, from, solver, info)
+        }
         _ => Err(ExecError::Type(format!("get_slice_int length is {:?}", &length), info)),
     }
 }
@@ -1915,17 +2004,45 @@ fn unimplemented<B: BV>(
     Err(ExecError::Unimplemented)
 }
 
-fn eq_string<B: BV>(lhs: Val<B>, rhs: Val<B>, _: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
+/// Convert a string argument to an SMT expression if either it or
+/// another argument is symbolic (see
+/// [crate::smt::SolverOptions::symbolic_strings]).
+fn smt_string<B: BV>(s: &Val<B>, primop: &str, info: SourceLoc) -> Result<Exp, ExecError> {
+    match s {
+        Val::String(s) => Ok(Exp::String(s.clone())),
+        Val::Symbolic(v) => Ok(Exp::Var(*v)),
+        _ => Err(ExecError::Type(format!("{} {:?}", primop, s), info)),
+    }
+}
+
+/// Like [smt_string], but for integer arguments to string primops.
+fn smt_string_index<B: BV>(n: &Val<B>, primop: &str, info: SourceLoc) -> Result<Exp, ExecError> {
+    match n {
+        Val::I128(n) => Ok(smt_i128(*n)),
+        Val::Symbolic(v) => Ok(Exp::Var(*v)),
+        _ => Err(ExecError::Type(format!("{} {:?}", primop, n), info)),
+    }
+}
+
+fn eq_string<B: BV>(lhs: Val<B>, rhs: Val<B>, solver: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
     match (lhs, rhs) {
         (Val::String(lhs), Val::String(rhs)) => Ok(Val::Bool(lhs == rhs)),
-        (lhs, rhs) => Err(ExecError::Type(format!("eq_string {:?} {:?}", &lhs, &rhs), info)),
+        (lhs, rhs) => {
+            let lhs = smt_string(&lhs, "eq_string", info)?;
+            let rhs = smt_string(&rhs, "eq_string", info)?;
+            solver.define_const(Exp::Eq(Box::new(lhs), Box::new(rhs)), info).into()
+        }
     }
 }
 
-fn concat_str<B: BV>(lhs: Val<B>, rhs: Val<B>, _: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
+fn concat_str<B: BV>(lhs: Val<B>, rhs: Val<B>, solver: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
     match (lhs, rhs) {
         (Val::String(lhs), Val::String(rhs)) => Ok(Val::String(format!("{}{}", lhs, rhs))),
-        (lhs, rhs) => Err(ExecError::Type(format!("concat_str {:?} {:?}", &lhs, &rhs), info)),
+        (lhs, rhs) => {
+            let lhs = smt_string(&lhs, "concat_str", info)?;
+            let rhs = smt_string(&rhs, "concat_str", info)?;
+            solver.define_const(Exp::StrConcat(Box::new(lhs), Box::new(rhs)), info).into()
+        }
     }
 }
 
@@ -1945,9 +2062,13 @@ fn dec_str<B: BV>(n: Val<B>, _: &mut Solver<B>, info: SourceLoc) -> Result<Val<B
     }
 }
 
-// Strings can never be symbolic
-fn undefined_string<B: BV>(_: Val<B>, _: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
-    Ok(Val::Poison)
+// Strings are only symbolic when using the SMT string theory
+fn undefined_string<B: BV>(_: Val<B>, solver: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
+    if solver.symbolic_strings() {
+        solver.declare_const(Ty::String, info).into()
+    } else {
+        Ok(Val::Poison)
+    }
 }
 
 fn string_to_i128<B: BV>(s: Val<B>, _: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
@@ -1980,37 +2101,158 @@ fn eq_anything<B: BV>(lhs: Val<B>, rhs: Val<B>, solver: &mut Solver<B>, info: So
         (Val::I128(lhs), Val::I128(rhs)) => Ok(Val::Bool(lhs == rhs)),
         (Val::I64(lhs), Val::I64(rhs)) => Ok(Val::Bool(lhs == rhs)),
         (Val::Struct(lhs), Val::Struct(rhs)) => {
-            let mut vars = vec![];
+            let mut results = vec![];
             for (k, lhs_v) in lhs {
                 let rhs_v = match rhs.get(&k) {
                     Some(v) => v,
                     None => return Err(ExecError::Type("eq_anything None".to_string(), info)),
                 };
-                let result = eq_anything(lhs_v, rhs_v.clone(), solver, info)?;
-                match result {
-                    Val::Bool(true) => (),
-                    Val::Bool(false) => return Ok(Val::Bool(false)),
-                    Val::Symbolic(r) => vars.push(r),
-                    _ => return Err(ExecError::Type(format!("eq_anything {:?}", &result), info)),
-                }
+                results.push(eq_anything(lhs_v, rhs_v.clone(), solver, info)?)
             }
-            match vars.pop() {
-                None => Ok(Val::Bool(true)),
-                Some(init) => {
-                    let exp = vars
-                        .iter()
-                        .map(|v| Exp::Var(*v))
-                        .fold(Exp::Var(init), |e1, e2| Exp::And(Box::new(e1), Box::new(e2)));
-                    solver.define_const(exp, info).into()
-                }
+            all_true(results, solver, info)
+        }
+
+        (Val::Ctor(lhs_ctor, lhs), Val::Ctor(rhs_ctor, rhs)) => {
+            if lhs_ctor == rhs_ctor {
+                eq_anything(*lhs, *rhs, solver, info)
+            } else {
+                Ok(Val::Bool(false))
+            }
+        }
+        (Val::SymbolicCtor(tag, ctors), Val::Ctor(ctor, value))
+        | (Val::Ctor(ctor, value), Val::SymbolicCtor(tag, ctors)) => {
+            let member = match ctors.iter().position(|(ctor_b, _)| ctor == *ctor_b) {
+                Some(member) => member,
+                None => return Err(ExecError::Type(format!("eq_anything {:?} {:?}", ctor, &ctors), info)),
+            };
+            let tag_eq = symbolic_ctor_is(tag, member, ctors.len(), solver, info)?;
+            let payload_eq = eq_anything(ctors[member].1.clone(), *value, solver, info)?;
+            all_true(vec![tag_eq, payload_eq], solver, info)
+        }
+        (Val::SymbolicCtor(lhs_tag, lhs_ctors), Val::SymbolicCtor(rhs_tag, rhs_ctors)) => {
+            let mut results = vec![];
+            for (member, (ctor, lhs)) in lhs_ctors.iter().enumerate() {
+                let rhs_member = match rhs_ctors.iter().position(|(ctor_b, _)| ctor == ctor_b) {
+                    Some(member) => member,
+                    None => return Err(ExecError::Type(format!("eq_anything {:?} {:?}", ctor, &rhs_ctors), info)),
+                };
+                let rhs = &rhs_ctors[rhs_member].1;
+                let lhs_tag_eq = symbolic_ctor_is(lhs_tag, member, lhs_ctors.len(), solver, info)?;
+                let rhs_tag_eq = symbolic_ctor_is(rhs_tag, rhs_member, rhs_ctors.len(), solver, info)?;
+                let payload_eq = eq_anything(lhs.clone(), rhs.clone(), solver, info)?;
+                results.push(all_true(vec![lhs_tag_eq, rhs_tag_eq, payload_eq], solver, info)?)
             }
+            any_true(results, solver, info)
+        }
+
+        (Val::List(lhs), Val::List(rhs)) => {
+            if lhs.len() == rhs.len() {
+                elements_eq(&lhs, &rhs, solver, info)
+            } else {
+                Ok(Val::Bool(false))
+            }
+        }
+        // A symbolic list of length len consists of the last len
+        // elements of its vector, see [crate::executor::symbolic]
+        (Val::SymbolicList(len, xs), Val::List(ys)) | (Val::List(ys), Val::SymbolicList(len, xs)) => {
+            if ys.len() > xs.len() {
+                return Ok(Val::Bool(false));
+            }
+            let len_eq = symbolic_list_has_len(len, ys.len(), solver, info)?;
+            let elements_eq = elements_eq(&xs[xs.len() - ys.len()..], &ys, solver, info)?;
+            all_true(vec![len_eq, elements_eq], solver, info)
+        }
+        (Val::SymbolicList(lhs_len, xs), Val::SymbolicList(rhs_len, ys)) => {
+            let mut results = vec![];
+            for n in 0..=min(xs.len(), ys.len()) {
+                let lhs_len_eq = symbolic_list_has_len(lhs_len, n, solver, info)?;
+                let rhs_len_eq = symbolic_list_has_len(rhs_len, n, solver, info)?;
+                let elements_eq = elements_eq(&xs[xs.len() - n..], &ys[ys.len() - n..], solver, info)?;
+                results.push(all_true(vec![lhs_len_eq, rhs_len_eq, elements_eq], solver, info)?)
+            }
+            any_true(results, solver, info)
         }
 
         (lhs, rhs) => Err(ExecError::Type(format!("eq_anything {:?} {:?}", &lhs, &rhs), info)),
     }
 }
 
+/// Combine boolean values with a binary SMT operation. A concrete
+/// `absorbing` value decides the result immediately, and concrete
+/// values otherwise have no effect.
+fn combine_bools<B: BV>(
+    results: Vec<Val<B>>,
+    absorbing: bool,
+    op: fn(Box<Exp>, Box<Exp>) -> Exp,
+    solver: &mut Solver<B>,
+    info: SourceLoc,
+) -> Result<Val<B>, ExecError> {
+    let mut vars = vec![];
+    for result in results {
+        match result {
+            Val::Bool(b) if b == absorbing => return Ok(Val::Bool(absorbing)),
+            Val::Bool(_) => (),
+            Val::Symbolic(r) => vars.push(r),
+            _ => return Err(ExecError::Type(format!("eq_anything {:?}", &result), info)),
+        }
+    }
+    match vars.pop() {
+        None => Ok(Val::Bool(!absorbing)),
+        Some(init) => {
+            let exp = vars.iter().map(|v| Exp::Var(*v)).fold(Exp::Var(init), |e1, e2| op(Box::new(e1), Box::new(e2)));
+            solver.define_const(exp, info).into()
+        }
+    }
+}
+
+fn all_true<B: BV>(results: Vec<Val<B>>, solver: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
+    combine_bools(results, false, Exp::And, solver, info)
+}
+
+fn any_true<B: BV>(results: Vec<Val<B>>, solver: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
+    combine_bools(results, true, Exp::Or, solver, info)
+}
+
+fn elements_eq<B: BV>(
+    xs: &[Val<B>],
+    ys: &[Val<B>],
+    solver: &mut Solver<B>,
+    info: SourceLoc,
+) -> Result<Val<B>, ExecError> {
+    let results = xs
+        .iter()
+        .zip(ys.iter())
+        .map(|(x, y)| eq_anything(x.clone(), y.clone(), solver, info))
+        .collect::<Result<_, _>>()?;
+    all_true(results, solver, info)
+}
+
+fn symbolic_ctor_is<B: BV>(
+    tag: Sym,
+    member: usize,
+    ctors: usize,
+    solver: &mut Solver<B>,
+    info: SourceLoc,
+) -> Result<Val<B>, ExecError> {
+    let member = Exp::Enum(EnumMember { enum_id: solver.get_enum(ctors), member });
+    solver.define_const(Exp::Eq(Box::new(Exp::Var(tag)), Box::new(member)), info).into()
+}
+
+fn symbolic_list_has_len<B: BV>(
+    len: Sym,
+    n: usize,
+    solver: &mut Solver<B>,
+    info: SourceLoc,
+) -> Result<Val<B>, ExecError> {
+    solver.define_const(Exp::Eq(Box::new(Exp::Var(len)), Box::new(bits64(n as u64, 64))), info).into()
+}
+
 fn neq_anything<B: BV>(lhs: Val<B>, rhs: Val<B>, solver: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
+    let is_compound =
+        |v: &Val<B>| matches!(v, Val::Ctor(..) | Val::SymbolicCtor(..) | Val::List(_) | Val::SymbolicList(..));
+    if is_compound(&lhs) || is_compound(&rhs) {
+        return not_bool(eq_anything(lhs, rhs, solver, info)?, solver, info);
+    }
     match (replace_mixed_bits(lhs, solver, info)?, replace_mixed_bits(rhs, solver, info)?) {
         (Val::Symbolic(lhs), Val::Symbolic(rhs)) => {
             solver.define_const(Exp::Neq(Box::new(Exp::Var(lhs)), Box::new(Exp::Var(rhs))), info).into()
@@ -2038,34 +2280,62 @@ fn neq_anything<B: BV>(lhs: Val<B>, rhs: Val<B>, solver: &mut Solver<B>, info: S
 fn string_startswith<B: BV>(
     s: Val<B>,
     prefix: Val<B>,
-    _: &mut Solver<B>,
+    solver: &mut Solver<B>,
     info: SourceLoc,
 ) -> Result<Val<B>, ExecError> {
     match (s, prefix) {
         (Val::String(s), Val::String(prefix)) => Ok(Val::Bool(s.starts_with(&prefix))),
-        other => Err(ExecError::Type(format!("string_startswith {:?}", &other), info)),
+        (s, prefix) => {
+            let s = smt_string(&s, "string_startswith", info)?;
+            let prefix = smt_string(&prefix, "string_startswith", info)?;
+            solver.define_const(Exp::StrPrefixof(Box::new(prefix), Box::new(s)), info).into()
+        }
     }
 }
 
-fn string_length<B: BV>(s: Val<B>, _: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
-    if let Val::String(s) = s {
-        Ok(Val::I128(s.len() as i128))
-    } else {
-        Err(ExecError::Type(format!("string_length {:?}", &s), info))
+// As in the SMT string theory, lengths and indices count characters
+// rather than bytes. Indices are unsigned in the SMT encoding, so a
+// negative index is treated as being past the end of the string.
+fn string_length<B: BV>(s: Val<B>, solver: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
+    match s {
+        Val::String(s) => Ok(Val::I128(s.chars().count() as i128)),
+        Val::Symbolic(v) => solver.define_const(Exp::StrLen(Box::new(Exp::Var(v))), info).into(),
+        _ => Err(ExecError::Type(format!("string_length {:?}", &s), info)),
     }
 }
 
-fn string_drop<B: BV>(s: Val<B>, n: Val<B>, _: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
+fn string_drop<B: BV>(s: Val<B>, n: Val<B>, solver: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
     match (s, n) {
-        (Val::String(s), Val::I128(n)) => Ok(Val::String(s.get((n as usize)..).unwrap_or("").to_string())),
-        other => Err(ExecError::Type(format!("string_drop {:?}", &other), info)),
+        (Val::String(s), Val::I128(n)) => {
+            if n < 0 {
+                Ok(Val::String(String::new()))
+            } else {
+                Ok(Val::String(s.chars().skip(n as usize).collect()))
+            }
+        }
+        (s, n) => {
+            let s = smt_string(&s, "string_drop", info)?;
+            let n = smt_string_index(&n, "string_drop", info)?;
+            let len = Exp::StrLen(Box::new(s.clone()));
+            solver.define_const(Exp::StrSubstr(Box::new(s), Box::new(n), Box::new(len)), info).into()
+        }
     }
 }
 
-fn string_take<B: BV>(s: Val<B>, n: Val<B>, _: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
+fn string_take<B: BV>(s: Val<B>, n: Val<B>, solver: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
     match (s, n) {
-        (Val::String(s), Val::I128(n)) => Ok(Val::String(s.get(..(n as usize)).unwrap_or(&s).to_string())),
-        other => Err(ExecError::Type(format!("string_take {:?}", &other), info)),
+        (Val::String(s), Val::I128(n)) => {
+            if n < 0 {
+                Ok(Val::String(s))
+            } else {
+                Ok(Val::String(s.chars().take(n as usize).collect()))
+            }
+        }
+        (s, n) => {
+            let s = smt_string(&s, "string_take", info)?;
+            let n = smt_string_index(&n, "string_take", info)?;
+            solver.define_const(Exp::StrSubstr(Box::new(s), Box::new(smt_i128(0)), Box::new(n)), info).into()
+        }
     }
 }
 
@@ -2113,73 +2383,77 @@ fn string_of_int<B: BV>(n: Val<B>, _: &mut Solver<B>, info: SourceLoc) -> Result
     }
 }
 
-fn putchar<B: BV>(_c: Val<B>, _: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
-    //if let Val::I128(c) = c {
-    //    eprintln!("Stdout: {}", char::from(c as u8))
-    //}
+/// The print builtins record their output as [Event::Print] events,
+/// rather than writing to stdout or stderr directly.
+fn add_print_event<B: BV>(
+    stderr: bool,
+    output: Vec<PrintPart<B>>,
+    solver: &mut Solver<B>,
+) -> Result<Val<B>, ExecError> {
+    solver.add_event(Event::Print { stderr, output });
     Ok(Val::Unit)
 }
 
-fn print<B: BV>(_message: Val<B>, _: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
-    //if let Val::String(message) = message {
-    //    eprintln!("Stdout: {}", message)
-    //}
-    Ok(Val::Unit)
+fn newline<B: BV>() -> PrintPart<B> {
+    PrintPart::String(Val::String("\n".to_string()))
 }
 
-fn prerr<B: BV>(_message: Val<B>, _: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
-    //if let Val::String(message) = message {
-    //    eprintln!("Stderr: {}", message)
-    //}
-    Ok(Val::Unit)
+fn putchar<B: BV>(c: Val<B>, solver: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
+    let c = match c {
+        Val::I128(c) => PrintPart::String(Val::String(char::from(c as u8).to_string())),
+        c => PrintPart::Int(c),
+    };
+    add_print_event(false, vec![c], solver)
+}
+
+fn print<B: BV>(message: Val<B>, solver: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
+    add_print_event(false, vec![PrintPart::String(message)], solver)
+}
+
+fn prerr<B: BV>(message: Val<B>, solver: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
+    add_print_event(true, vec![PrintPart::String(message)], solver)
 }
 
 fn print_string<B: BV>(
-    _prefix: Val<B>,
-    _message: Val<B>,
-    _: &mut Solver<B>,
+    prefix: Val<B>,
+    message: Val<B>,
+    solver: &mut Solver<B>,
     _: SourceLoc,
 ) -> Result<Val<B>, ExecError> {
-    Ok(Val::Unit)
+    add_print_event(false, vec![PrintPart::String(prefix), PrintPart::String(message), newline()], solver)
 }
 
 fn prerr_string<B: BV>(
-    _prefix: Val<B>,
-    _message: Val<B>,
-    _: &mut Solver<B>,
+    prefix: Val<B>,
+    message: Val<B>,
+    solver: &mut Solver<B>,
     _: SourceLoc,
 ) -> Result<Val<B>, ExecError> {
-    Ok(Val::Unit)
+    add_print_event(true, vec![PrintPart::String(prefix), PrintPart::String(message), newline()], solver)
 }
 
-fn print_int<B: BV>(_prefix: Val<B>, _n: Val<B>, _: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
-    Ok(Val::Unit)
+fn print_int<B: BV>(prefix: Val<B>, n: Val<B>, solver: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
+    add_print_event(false, vec![PrintPart::String(prefix), PrintPart::Int(n), newline()], solver)
 }
 
-fn prerr_int<B: BV>(_prefix: Val<B>, _n: Val<B>, _: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
-    Ok(Val::Unit)
+fn prerr_int<B: BV>(prefix: Val<B>, n: Val<B>, solver: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
+    add_print_event(true, vec![PrintPart::String(prefix), PrintPart::Int(n), newline()], solver)
 }
 
-fn print_endline<B: BV>(_message: Val<B>, _: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
-    Ok(Val::Unit)
+fn print_endline<B: BV>(message: Val<B>, solver: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
+    add_print_event(false, vec![PrintPart::String(message), newline()], solver)
 }
 
-fn prerr_endline<B: BV>(_message: Val<B>, _: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
-    Ok(Val::Unit)
+fn prerr_endline<B: BV>(message: Val<B>, solver: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
+    add_print_event(true, vec![PrintPart::String(message), newline()], solver)
 }
 
-fn print_bits<B: BV>(_message: Val<B>, _bits: Val<B>, _: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
-    //if let Val::String(message) = message {
-    //    eprintln!("Stdout: {}{:?}", message, bits)
-    //}
-    Ok(Val::Unit)
+fn print_bits<B: BV>(message: Val<B>, bits: Val<B>, solver: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
+    add_print_event(false, vec![PrintPart::String(message), PrintPart::Bits(bits), newline()], solver)
 }
 
-fn prerr_bits<B: BV>(_message: Val<B>, _bits: Val<B>, _: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
-    //if let Val::String(message) = message {
-    //    eprintln!("Stderr: {}{:?}", message, bits)
-    //}
-    Ok(Val::Unit)
+fn prerr_bits<B: BV>(message: Val<B>, bits: Val<B>, solver: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
+    add_print_event(true, vec![PrintPart::String(message), PrintPart::Bits(bits), newline()], solver)
 }
 
 fn undefined_bitvector<B: BV>(sz: Val<B>, solver: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
@@ -2248,7 +2522,7 @@ fn zero_if<B: BV>(condition: Val<B>, solver: &mut Solver<B>, info: SourceLoc) ->
     }
 }
 
-fn cons<B: BV>(x: Val<B>, xs: Val<B>, _: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
+fn cons<B: BV>(x: Val<B>, xs: Val<B>, solver: &mut Solver<B>, info: SourceLoc) -> Result<Val<B>, ExecError> {
     match xs {
         /* TODO: Make this not a hack */
         Val::Poison => Ok(Val::List(vec![x])),
@@ -2256,6 +2530,12 @@ fn cons<B: BV>(x: Val<B>, xs: Val<B>, _: &mut Solver<B>, info: SourceLoc) -> Res
             xs.push(x);
             Ok(Val::List(xs))
         }
+        Val::SymbolicList(len, mut xs) => {
+            // The list must be padded so its elements stay the last len elements
+            let len = solver.define_const(Exp::Bvadd(Box::new(Exp::Var(len)), Box::new(bits64(1, 64))), info);
+            xs.push(x);
+            Ok(Val::SymbolicList(len, xs))
+        }
         _ => Err(ExecError::Type(format!("cons {:?}", &xs), info)),
     }
 }
@@ -2268,6 +2548,7 @@ pub fn smt_value<B: BV>(v: &Val<B>) -> Result<Exp, ExecError> {
         Val::Bits(bv) => smt_sbits(*bv),
         Val::Bool(b) => Exp::Bool(*b),
         Val::Enum(e) => Exp::Enum(*e),
+        Val::String(s) => Exp::String(s.clone()),
         Val::Symbolic(v) => Exp::Var(*v),
         _ => return Err(ExecError::Type(format!("smt_value {:?}", &v), SourceLoc::unknown())),
     })
@@ -2310,7 +2591,7 @@ fn read_mem<B: BV>(
     frame: &mut LocalFrame<B>,
     _: SourceLoc,
 ) -> Result<Val<B>, ExecError> {
-    frame.memory().read(args[0].clone(), args[2].clone(), args[3].clone(), solver, false)
+    frame.memory_mut().read(args[0].clone(), args[2].clone(), args[3].clone(), solver, false)
 }
 
 fn read_memt<B: BV>(
@@ -2319,7 +2600,7 @@ fn read_memt<B: BV>(
     frame: &mut LocalFrame<B>,
     _: SourceLoc,
 ) -> Result<Val<B>, ExecError> {
-    frame.memory().read(args[0].clone(), args[1].clone(), args[2].clone(), solver, true)
+    frame.memory_mut().read(args[0].clone(), args[1].clone(), args[2].clone(), solver, true)
 }
 
 fn bad_read<B: BV>(_: Val<B>, _: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
@@ -2370,21 +2651,13 @@ fn get_verbosity<B: BV>(_: Val<B>, _: &mut Solver<B>, _: SourceLoc) -> Result<Va
     Ok(Val::Bits(B::zeros(64)))
 }
 
-fn sleeping<B: BV>(_: Val<B>, _solver: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
-    // let sym = solver.fresh();
-    // solver.add(Def::DeclareConst(sym, Ty::Bool));
-    // solver.add_event(Event::Sleeping(sym));
-    // Ok(Val::Symbolic(sym))
-    Ok(Val::Bool(false))
-}
-
 fn wakeup_request<B: BV>(_: Val<B>, solver: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
     solver.add_event(Event::WakeupRequest);
     Ok(Val::Unit)
 }
 
 fn sleep_request<B: BV>(_: Val<B>, solver: &mut Solver<B>, _: SourceLoc) -> Result<Val<B>, ExecError> {
-    solver.add_event(Event::WakeupRequest);
+    solver.add_event(Event::SleepRequest);
     Ok(Val::Unit)
 }
 
@@ -2638,7 +2911,6 @@ pub fn unary_primops<B: BV>() -> HashMap<String, Unary<B>> {
     primops.insert("cycle_count".to_string(), cycle_count as Unary<B>);
     primops.insert("get_cycle_count".to_string(), get_cycle_count as Unary<B>);
     primops.insert("sail_get_verbosity".to_string(), get_verbosity as Unary<B>);
-    primops.insert("sleeping".to_string(), sleeping as Unary<B>);
     primops.insert("sleep_request".to_string(), sleep_request as Unary<B>);
     primops.insert("wakeup_request".to_string(), wakeup_request as Unary<B>);
     primops.insert("platform_instr_announce".to_string(), instr_announce as Unary<B>);
@@ -2768,14 +3040,80 @@ pub struct Primops<B> {
     pub unary: HashMap<String, Unary<B>>,
     pub binary: HashMap<String, Binary<B>>,
     pub variadic: HashMap<String, Variadic<B>>,
+    pub closures: HashMap<String, Closure<B>>,
 }
 
 impl<B: BV> Default for Primops<B> {
     fn default() -> Self {
-        Primops { unary: unary_primops(), binary: binary_primops(), variadic: variadic_primops() }
+        Primops {
+            unary: unary_primops(),
+            binary: binary_primops(),
+            variadic: variadic_primops(),
+            closures: HashMap::new(),
+        }
+    }
+}
+
+/// Additional primops can be registered before the architecture is
+/// initialized with [crate::init::initialize_architecture_with_primops],
+/// which will use them for any `val` declarations with a matching
+/// extern name. Registering a primop replaces any existing primop
+/// with the same name, regardless of its arity. Primops which need
+/// to access memory should be [Variadic] or closures, as they are
+/// passed the current [LocalFrame].
+impl<B: BV> Primops<B> {
+    fn remove(&mut self, name: &str) {
+        self.unary.remove(name);
+        self.binary.remove(name);
+        self.variadic.remove(name);
+        self.closures.remove(name);
+    }
+
+    pub fn register_unary(&mut self, name: &str, f: Unary<B>) -> &mut Self {
+        self.remove(name);
+        self.unary.insert(name.to_string(), f);
+        self
+    }
+
+    pub fn register_binary(&mut self, name: &str, f: Binary<B>) -> &mut Self {
+        self.remove(name);
+        self.binary.insert(name.to_string(), f);
+        self
+    }
+
+    pub fn register_variadic(&mut self, name: &str, f: Variadic<B>) -> &mut Self {
+        self.remove(name);
+        self.variadic.insert(name.to_string(), f);
+        self
+    }
+
+    /// Register a primop which captures some state, such as a handle
+    /// to a device model. Unlike the other primops, closures are not
+    /// inserted into the IR, but are called by the executor whenever
+    /// it reaches a call to a function with a matching extern name.
+    pub fn register_closure<F>(&mut self, name: &str, f: F) -> &mut Self
+    where
+        F: 'static
+            + Send
+            + Sync
+            + Fn(Vec<Val<B>>, &mut Solver<B>, &mut LocalFrame<B>, SourceLoc) -> Result<Val<B>, ExecError>,
+    {
+        self.remove(name);
+        self.closures.insert(name.to_string(), Arc::new(f));
+        self
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.unary.contains_key(name)
+            || self.binary.contains_key(name)
+            || self.variadic.contains_key(name)
+            || self.closures.contains_key(name)
     }
 }
 
+#[cfg(test)]
+mod properties;
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -2852,7 +3190,7 @@ mod tests {
             SourceLoc::unknown(),
         );
 
-        assert!(solver.check_sat() == SmtResult::Sat);
+        assert!(solver.check_sat(SourceLoc::unknown()) == SmtResult::Sat);
         Ok(())
     }
 }
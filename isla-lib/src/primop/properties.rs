@@ -0,0 +1,537 @@
+// BSD 2-Clause License
+//
+// Copyright (c) 2020 Alasdair Armstrong
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+// notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+// notice, this list of conditions and the following disclaimer in the
+// documentation and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Property tests checking that the concrete and symbolic
+//! implementations of each primop agree. For every primop we generate
+//! random concrete arguments and evaluate the primop on them. We then
+//! evaluate it again with some of the arguments replaced by SMT
+//! variables defined to be equal to the concrete values, and ask Z3
+//! whether the symbolic result can differ from the concrete one.
+//!
+//! Primops whose symbolic behaviour cannot be checked this way
+//! (because they have side effects, describe symbolic values as
+//! strings, or have no symbolic implementation) are listed in
+//! [UNCHECKED], so that new primops must be added to one list or the
+//! other.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::*;
+use crate::bitvector::b129::B129;
+use crate::bitvector::b64::B64;
+use crate::executor::LocalFrame;
+use crate::ir::source_loc::SourceLoc;
+use crate::ir::Name;
+use crate::smt::{Config, Context, SmtResult};
+
+/// Number of random argument lists tried for each primop.
+const ITERATIONS: usize = 20;
+
+static UNCHECKED: &[&str] = &[
+    // Side effects, assertions and undefined values
+    "assume",
+    "optimistic_assert",
+    "pessimistic_assert",
+    "sail_putchar",
+    "print",
+    "prerr",
+    "print_endline",
+    "prerr_endline",
+    "print_string",
+    "prerr_string",
+    "print_int",
+    "prerr_int",
+    "print_bits",
+    "prerr_bits",
+    "undefined_bitvector",
+    "undefined_bool",
+    "undefined_int",
+    "undefined_nat",
+    "undefined_unit",
+    "undefined_string",
+    "undefined_range",
+    "undefined_vector",
+    "internal_pick",
+    "bad_read",
+    "bad_write",
+    "cycle_count",
+    "get_cycle_count",
+    "sail_get_verbosity",
+    "sleep_request",
+    "wakeup_request",
+    "platform_instr_announce",
+    "platform_branch_announce",
+    "platform_barrier",
+    "platform_read_mem",
+    "platform_read_memt",
+    "platform_write_mem",
+    "platform_write_memt",
+    "platform_write_mem_ea",
+    "platform_cache_maintenance",
+    "platform_cache_maintenance_extra",
+    "elf_entry",
+    "monomorphize",
+    "mark_register",
+    "mark_register_pair",
+    // Symbolic arguments are described by name, rather than by value
+    "hex_str",
+    "dec_str",
+    "string_of_bits",
+    "decimal_string_of_bits",
+    "string_of_int",
+    // Symbolic lists are only created by the executor
+    "cons",
+    // No symbolic implementation
+    "%string->%i",
+    "pow_int",
+    "%string->%real",
+    "neg_real",
+    "mult_real",
+    "sub_real",
+    "add_real",
+    "div_real",
+    "sqrt_real",
+    "abs_real",
+    "round_down",
+    "round_up",
+    "to_real",
+    "eq_real",
+    "lt_real",
+    "gt_real",
+    "lteq_real",
+    "gteq_real",
+    "real_power",
+    "print_real",
+    "prerr_real",
+    "undefined_real",
+];
+
+/// An argument to a primop, and whether it may be replaced by a
+/// symbolic value. Some arguments, such as the lengths of bitvectors,
+/// must always be concrete.
+type Arg<B> = (Val<B>, bool);
+
+type Generator<B> = fn(&mut StdRng) -> Vec<Arg<B>>;
+
+fn gen_len<B: BV>(rng: &mut StdRng, max: u32) -> u32 {
+    let max = std::cmp::min(max, B::MAX_WIDTH);
+    match rng.gen_range(0, 4) {
+        0 => 1,
+        1 => max,
+        _ => rng.gen_range(1, max + 1),
+    }
+}
+
+fn gen_bv<B: BV>(rng: &mut StdRng, len: u32) -> B {
+    let chunk = |rng: &mut StdRng, len: u32| {
+        let bits: u64 = rng.gen();
+        B::new(if len == 64 { bits } else { bits & ((1 << len) - 1) }, len)
+    };
+    match rng.gen_range(0, 5) {
+        0 => B::zeros(len),
+        1 => B::ones(len),
+        _ => {
+            let mut bv = chunk(rng, std::cmp::min(len, 64));
+            while bv.len() < len {
+                let next = chunk(rng, std::cmp::min(len - bv.len(), 64));
+                bv = bv.append(next).unwrap()
+            }
+            bv
+        }
+    }
+}
+
+fn gen_bits<B: BV>(rng: &mut StdRng, len: u32) -> Val<B> {
+    Val::Bits(gen_bv(rng, len))
+}
+
+fn gen_int(rng: &mut StdRng) -> i128 {
+    match rng.gen_range(0, 6) {
+        0 => rng.gen_range(-8, 8),
+        1 => i128::from(rng.gen::<i32>()),
+        2 => i128::from(rng.gen::<i64>()),
+        3 => [i128::from(i64::MIN), i128::from(i64::MAX), i128::MAX, i128::MIN + 1][rng.gen_range(0, 4)],
+        _ => rng.gen(),
+    }
+}
+
+/// Strings are drawn from a small alphabet, including some non-ASCII
+/// characters, so that prefixes and repeated characters are likely.
+fn gen_string(rng: &mut StdRng) -> String {
+    const ALPHABET: &[char] = &['a', 'b', '0', ' ', '\\', '\u{e9}', '\u{3bb}', '\u{1f600}'];
+    let len = rng.gen_range(0, 6);
+    (0..len).map(|_| ALPHABET[rng.gen_range(0, ALPHABET.len())]).collect()
+}
+
+fn two_strings<B: BV>(rng: &mut StdRng) -> Vec<Arg<B>> {
+    vec![(Val::String(gen_string(rng)), true), (Val::String(gen_string(rng)), true)]
+}
+
+fn string_and_index<B: BV>(rng: &mut StdRng) -> Vec<Arg<B>> {
+    vec![(Val::String(gen_string(rng)), true), (Val::I128(rng.gen_range(-2, 8)), true)]
+}
+
+/// Arguments for operations on two bitvectors of the same length.
+fn same_bits<B: BV>(rng: &mut StdRng) -> Vec<Arg<B>> {
+    let len = gen_len::<B>(rng, B::MAX_WIDTH);
+    vec![(gen_bits(rng, len), true), (gen_bits(rng, len), true)]
+}
+
+fn one_bits<B: BV>(rng: &mut StdRng) -> Vec<Arg<B>> {
+    let len = gen_len::<B>(rng, B::MAX_WIDTH);
+    vec![(gen_bits(rng, len), true)]
+}
+
+fn two_ints<B: BV>(rng: &mut StdRng) -> Vec<Arg<B>> {
+    vec![(Val::I128(gen_int(rng)), true), (Val::I128(gen_int(rng)), true)]
+}
+
+fn divide<B: BV>(rng: &mut StdRng) -> Vec<Arg<B>> {
+    let divisor = match gen_int(rng) {
+        0 => 1,
+        n => n,
+    };
+    vec![(Val::I128(gen_int(rng)), true), (Val::I128(divisor), true)]
+}
+
+fn bools<B: BV>(rng: &mut StdRng) -> Vec<Arg<B>> {
+    vec![(Val::Bool(rng.gen()), true), (Val::Bool(rng.gen()), true)]
+}
+
+fn bits_and_shift<B: BV>(rng: &mut StdRng) -> Vec<Arg<B>> {
+    let len = gen_len::<B>(rng, B::MAX_WIDTH);
+    vec![(gen_bits(rng, len), true), (Val::I128(rng.gen_range(0, i128::from(len) + 2)), true)]
+}
+
+fn bits_and_length<B: BV>(rng: &mut StdRng, longer: bool) -> Vec<Arg<B>> {
+    let len = gen_len::<B>(rng, B::MAX_WIDTH);
+    let new_len = if longer { rng.gen_range(len, B::MAX_WIDTH + 9) } else { rng.gen_range(1, len + 1) };
+    vec![(gen_bits(rng, len), true), (Val::I128(i128::from(new_len)), false)]
+}
+
+fn properties<B: BV>() -> Vec<(&'static str, Generator<B>)> {
+    vec![
+        ("not", |rng| vec![(Val::Bool(rng.gen()), true)]),
+        ("and_bool", bools),
+        ("strict_and_bool", bools),
+        ("or_bool", bools),
+        ("strict_or_bool", bools),
+        ("eq_bool", bools),
+        ("one_if", |rng| vec![(Val::Bool(rng.gen()), true)]),
+        ("zero_if", |rng| vec![(Val::Bool(rng.gen()), true)]),
+        ("%i64->%i", |rng| vec![(Val::I64(rng.gen()), true)]),
+        ("%i->%i64", |rng| vec![(Val::I128(i128::from(rng.gen::<i64>())), true)]),
+        ("neg_int", |rng| vec![(Val::I128(gen_int(rng)), true)]),
+        ("abs_int", |rng| vec![(Val::I128(gen_int(rng)), true)]),
+        ("pow2", |rng| vec![(Val::I128(rng.gen_range(0, 127)), true)]),
+        ("eq_int", two_ints),
+        ("lteq", two_ints),
+        ("gteq", two_ints),
+        ("lt", two_ints),
+        ("gt", two_ints),
+        ("add_int", two_ints),
+        ("sub_int", two_ints),
+        ("mult_int", two_ints),
+        ("max_int", two_ints),
+        ("min_int", two_ints),
+        ("sub_nat", |rng| {
+            vec![(Val::I128(i128::from(rng.gen::<i64>())), true), (Val::I128(i128::from(rng.gen::<i64>())), true)]
+        }),
+        ("tdiv_int", divide),
+        ("tmod_int", divide),
+        ("ediv_int", divide),
+        ("emod_int", divide),
+        ("shl_int", |rng| vec![(Val::I128(gen_int(rng)), true), (Val::I128(rng.gen_range(0, 128)), true)]),
+        ("shr_int", |rng| vec![(Val::I128(gen_int(rng)), true), (Val::I128(rng.gen_range(0, 128)), true)]),
+        ("shl_mach_int", |rng| vec![(Val::I64(rng.gen()), true), (Val::I64(rng.gen_range(0, 64)), true)]),
+        ("shr_mach_int", |rng| vec![(Val::I64(rng.gen()), true), (Val::I64(rng.gen_range(0, 64)), true)]),
+        ("bit_to_bool", |rng| vec![(gen_bits(rng, 1), true)]),
+        ("eq_bit", |rng| vec![(gen_bits(rng, 1), true), (gen_bits(rng, 1), true)]),
+        ("not_bits", one_bits),
+        ("length", one_bits),
+        ("count_leading_zeros", one_bits),
+        ("sail_unsigned", |rng| {
+            let len = gen_len::<B>(rng, 127);
+            vec![(gen_bits(rng, len), true)]
+        }),
+        ("sail_signed", |rng| {
+            let len = gen_len::<B>(rng, 127);
+            vec![(gen_bits(rng, len), true)]
+        }),
+        ("zeros", |rng| vec![(Val::I128(i128::from(rng.gen_range(1, B::MAX_WIDTH + 9))), false)]),
+        ("ones", |rng| vec![(Val::I128(i128::from(rng.gen_range(1, B::MAX_WIDTH + 9))), false)]),
+        ("eq_bits", same_bits),
+        ("neq_bits", same_bits),
+        ("eq_anything", same_bits),
+        ("xor_bits", same_bits),
+        ("or_bits", same_bits),
+        ("and_bits", same_bits),
+        ("add_bits", same_bits),
+        ("sub_bits", same_bits),
+        ("add_bits_int", |rng| {
+            let len = gen_len::<B>(rng, 128);
+            vec![(gen_bits(rng, len), true), (Val::I128(gen_int(rng)), true)]
+        }),
+        ("sub_bits_int", |rng| {
+            let len = gen_len::<B>(rng, 128);
+            vec![(gen_bits(rng, len), true), (Val::I128(gen_int(rng)), true)]
+        }),
+        ("align_bits", |rng| {
+            let len = gen_len::<B>(rng, 64);
+            vec![(gen_bits(rng, len), true), (Val::I128(1 << rng.gen_range(0, len)), false)]
+        }),
+        ("zero_extend", |rng| bits_and_length(rng, true)),
+        ("sign_extend", |rng| bits_and_length(rng, true)),
+        ("sail_truncate", |rng| bits_and_length(rng, false)),
+        ("sail_truncateLSB", |rng| bits_and_length(rng, false)),
+        ("replicate_bits", |rng| {
+            let len = gen_len::<B>(rng, 8);
+            vec![(gen_bits(rng, len), true), (Val::I128(rng.gen_range(1, i128::from(B::MAX_WIDTH / len) + 3)), false)]
+        }),
+        ("shiftr", bits_and_shift),
+        ("shiftl", bits_and_shift),
+        ("arith_shiftr", bits_and_shift),
+        ("shift_bits_right", |rng| {
+            let len = gen_len::<B>(rng, B::MAX_WIDTH);
+            let shift_len = gen_len::<B>(rng, 64);
+            let shift = B::new(rng.gen_range(0, u64::from(len) + 2) & (u64::MAX >> (64 - shift_len)), shift_len);
+            vec![(gen_bits(rng, len), true), (Val::Bits(shift), true)]
+        }),
+        ("shift_bits_left", |rng| {
+            let len = gen_len::<B>(rng, B::MAX_WIDTH);
+            let shift_len = gen_len::<B>(rng, 64);
+            let shift = B::new(rng.gen_range(0, u64::from(len) + 2) & (u64::MAX >> (64 - shift_len)), shift_len);
+            vec![(gen_bits(rng, len), true), (Val::Bits(shift), true)]
+        }),
+        ("append", |rng| {
+            let len1 = gen_len::<B>(rng, B::MAX_WIDTH);
+            let len2 = gen_len::<B>(rng, B::MAX_WIDTH);
+            vec![(gen_bits(rng, len1), true), (gen_bits(rng, len2), true)]
+        }),
+        ("append_64", |rng| vec![(gen_bits(rng, 64), true), (gen_bits(rng, 64), true)]),
+        ("vector_access", |rng| {
+            let len = gen_len::<B>(rng, B::MAX_WIDTH);
+            vec![(gen_bits(rng, len), true), (Val::I128(rng.gen_range(0, i128::from(len))), true)]
+        }),
+        ("slice", |rng| {
+            let len = gen_len::<B>(rng, B::MAX_WIDTH);
+            let from = rng.gen_range(0, len);
+            let slice_len = rng.gen_range(1, len - from + 1);
+            vec![
+                (gen_bits(rng, len), true),
+                (Val::I128(i128::from(from)), true),
+                (Val::I128(i128::from(slice_len)), false),
+            ]
+        }),
+        ("vector_subrange", |rng| {
+            let len = gen_len::<B>(rng, B::MAX_WIDTH);
+            let low = rng.gen_range(0, len);
+            let high = rng.gen_range(low, len);
+            vec![(gen_bits(rng, len), true), (Val::I128(i128::from(high)), false), (Val::I128(i128::from(low)), false)]
+        }),
+        ("vector_update", |rng| {
+            let len = gen_len::<B>(rng, B::MAX_WIDTH);
+            vec![
+                (gen_bits(rng, len), true),
+                (Val::I128(rng.gen_range(0, i128::from(len))), true),
+                (gen_bits(rng, 1), true),
+            ]
+        }),
+        ("vector_update_subrange", |rng| {
+            let len = gen_len::<B>(rng, B::MAX_WIDTH);
+            let low = rng.gen_range(0, len);
+            let high = rng.gen_range(low, len);
+            vec![
+                (gen_bits(rng, len), true),
+                (Val::I128(i128::from(high)), false),
+                (Val::I128(i128::from(low)), true),
+                (gen_bits(rng, high - low + 1), true),
+            ]
+        }),
+        ("bitvector_update", |rng| {
+            let len = gen_len::<B>(rng, B::MAX_WIDTH);
+            let n = rng.gen_range(0, len);
+            let update_len = rng.gen_range(1, len - n + 1);
+            vec![(gen_bits(rng, len), true), (Val::I64(i64::from(n)), true), (gen_bits(rng, update_len), true)]
+        }),
+        ("set_slice", |rng| {
+            let len = gen_len::<B>(rng, B::MAX_WIDTH);
+            let n = rng.gen_range(0, len);
+            let update_len = rng.gen_range(1, len - n + 1);
+            vec![
+                (Val::I128(i128::from(len)), false),
+                (Val::I128(i128::from(update_len)), false),
+                (gen_bits(rng, len), true),
+                (Val::I128(i128::from(n)), true),
+                (gen_bits(rng, update_len), true),
+            ]
+        }),
+        ("get_slice_int", |rng| {
+            let len = gen_len::<B>(rng, 128);
+            let from = rng.gen_range(0, 128 - len + 1);
+            vec![
+                (Val::I128(i128::from(len)), false),
+                (Val::I128(gen_int(rng)), true),
+                (Val::I128(i128::from(from)), true),
+            ]
+        }),
+        ("set_slice_int", |rng| {
+            let len = gen_len::<B>(rng, 127);
+            let n = rng.gen_range(0, 128 - len);
+            vec![
+                (Val::I128(i128::from(len)), false),
+                (Val::I128(gen_int(rng)), true),
+                (Val::I128(i128::from(n)), true),
+                (gen_bits(rng, len), true),
+            ]
+        }),
+        ("eq_string", two_strings),
+        ("concat_str", two_strings),
+        ("string_startswith", |rng| {
+            let s = gen_string(rng);
+            let prefix = if rng.gen() { s.chars().take(rng.gen_range(0, 3)).collect() } else { gen_string(rng) };
+            vec![(Val::String(s), true), (Val::String(prefix), true)]
+        }),
+        ("string_length", |rng| vec![(Val::String(gen_string(rng)), true)]),
+        ("string_drop", string_and_index),
+        ("string_take", string_and_index),
+        ("ite", |rng| {
+            let len = gen_len::<B>(rng, B::MAX_WIDTH);
+            vec![(Val::Bool(rng.gen()), true), (gen_bits(rng, len), true), (gen_bits(rng, len), true)]
+        }),
+    ]
+}
+
+fn call<B: BV>(
+    primops: &Primops<B>,
+    name: &str,
+    mut args: Vec<Val<B>>,
+    solver: &mut Solver<B>,
+) -> Result<Val<B>, ExecError> {
+    let info = SourceLoc::unknown();
+    if let Some(f) = primops.unary.get(name) {
+        f(args.remove(0), solver, info)
+    } else if let Some(f) = primops.binary.get(name) {
+        let rhs = args.pop().unwrap();
+        f(args.pop().unwrap(), rhs, solver, info)
+    } else {
+        let f = primops.variadic.get(name).unwrap();
+        f(args, solver, &mut LocalFrame::new(Name::from_u32(0), &[], None, &[]), info)
+    }
+}
+
+/// Evaluate a primop on concrete arguments, then with the arguments
+/// selected by `mask` made symbolic, and check that Z3 cannot find a
+/// case where the results differ.
+fn check<B: BV>(primops: &Primops<B>, name: &str, args: &[Arg<B>], mask: usize) -> Result<(), String> {
+    let info = SourceLoc::unknown();
+    let ctx = Context::new(Config::new());
+    let mut solver = Solver::<B>::new(&ctx);
+    let describe = |msg: String| format!("{} {:?} (symbolic {:b}): {}", name, args, mask, msg);
+
+    let concrete = args.iter().map(|(arg, _)| arg.clone()).collect();
+    let expected = call(primops, name, concrete, &mut solver).map_err(|e| describe(format!("concrete {:?}", e)))?;
+
+    let mut symbolic = Vec::new();
+    for (i, (arg, may_be_symbolic)) in args.iter().enumerate() {
+        if *may_be_symbolic && (mask >> i) & 1 == 1 {
+            let exp = smt_value(arg).map_err(|e| describe(format!("argument {:?}", e)))?;
+            symbolic.push(Val::Symbolic(solver.define_const(exp, info)))
+        } else {
+            symbolic.push(arg.clone())
+        }
+    }
+    let result = call(primops, name, symbolic, &mut solver).map_err(|e| describe(format!("symbolic {:?}", e)))?;
+    let result = replace_mixed_bits(result, &mut solver, info).map_err(|e| describe(format!("{:?}", e)))?;
+
+    // Zero-length bitvectors cannot be represented in the solver
+    if let (Val::Bits(bv1), Val::Bits(bv2)) = (&expected, &result) {
+        if bv1.is_empty() || bv2.is_empty() {
+            return if bv1 == bv2 { Ok(()) } else { Err(describe(format!("got {:?}, expected {:?}", bv2, bv1))) };
+        }
+    }
+
+    let expected_exp = smt_value(&expected).map_err(|e| describe(format!("result {:?}", e)))?;
+    let result_exp = smt_value(&result).map_err(|e| describe(format!("result {:?}", e)))?;
+    solver.add(Def::Assert(Exp::Neq(Box::new(result_exp), Box::new(expected_exp))));
+    match solver.check_sat(SourceLoc::unknown()) {
+        SmtResult::Unsat => Ok(()),
+        SmtResult::Sat => Err(describe(format!("symbolic result can differ from {:?}", expected))),
+        SmtResult::Unknown => Err(describe("solver returned unknown".to_string())),
+    }
+}
+
+fn check_all<B: BV>(seed: u64) -> Vec<String> {
+    let primops = Primops::<B>::default();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut failures = Vec::new();
+    for (name, generator) in properties::<B>() {
+        for _ in 0..ITERATIONS {
+            let args = generator(&mut rng);
+            // Always check with every argument symbolic, as well as a
+            // random mix of concrete and symbolic arguments.
+            let all = (1 << args.len()) - 1;
+            for mask in &[all, rng.gen_range(0, all + 1)] {
+                if let Err(msg) = check(&primops, name, &args, *mask) {
+                    failures.push(msg)
+                }
+            }
+        }
+    }
+    failures
+}
+
+#[test]
+fn primops_accounted_for() {
+    let primops = Primops::<B64>::default();
+    let checked: Vec<&str> = properties::<B64>().iter().map(|(name, _)| *name).collect();
+    let mut names: Vec<&String> =
+        primops.unary.keys().chain(primops.binary.keys()).chain(primops.variadic.keys()).collect();
+    names.sort();
+    for name in names {
+        assert!(
+            checked.contains(&name.as_str()) ^ UNCHECKED.contains(&name.as_str()),
+            "{} must be in exactly one of properties or UNCHECKED",
+            name
+        )
+    }
+}
+
+#[test]
+fn concrete_matches_symbolic_b64() {
+    let failures = check_all::<B64>(0x15_1a);
+    assert!(failures.is_empty(), "{}", failures.join("\n"))
+}
+
+#[test]
+fn concrete_matches_symbolic_b129() {
+    let failures = check_all::<B129>(0x15_1a);
+    assert!(failures.is_empty(), "{}", failures.join("\n"))
+}
@@ -38,9 +38,10 @@ use std::path::PathBuf;
 
 use crate::bitvector::{write_bits64, BV};
 use crate::ir::{source_loc::SourceLoc, BitsSegment, Name, Symtab, Val, HAVE_EXCEPTION};
+use crate::primop::model_i128;
 use crate::smt::smtlib::*;
 use crate::smt::Event::*;
-use crate::smt::{Accessor, Event, Sym};
+use crate::smt::{Accessor, Event, Model, PrintPart, Sym};
 use crate::zencode;
 
 /// `renumber_event` Renumbers all the symbolic variables in an event such that multiple event
@@ -79,7 +80,12 @@ pub fn renumber_event<B>(event: &mut Event<B>, i: u32, total: u32) {
             renumber_val(address, i, total);
             renumber_val(extra_data, i, total);
         }
-        Cycle | SleepRequest | WakeupRequest | MarkReg { .. } | Function { .. } => (),
+        Print { output, .. } => output.iter_mut().for_each(|part| renumber_val(part.value_mut(), i, total)),
+        Watch(report) => {
+            renumber_val(&mut report.address, i, total);
+            renumber_val(&mut report.value, i, total);
+        }
+        Cycle | SleepRequest | WakeupRequest | MarkReg { .. } | Function { .. } | AssumeUnknown { .. } => (),
     }
 }
 
@@ -105,6 +111,14 @@ fn renumber_val<B>(val: &mut Val<B>, i: u32, total: u32) {
         List(vals) | Vector(vals) => vals.iter_mut().for_each(|val| renumber_val(val, i, total)),
         Struct(fields) => fields.iter_mut().for_each(|(_, val)| renumber_val(val, i, total)),
         Ctor(_, val) => renumber_val(val, i, total),
+        SymbolicCtor(tag, ctors) => {
+            *tag = Sym { id: (tag.id * total) + i };
+            ctors.iter_mut().for_each(|(_, val)| renumber_val(val, i, total))
+        }
+        SymbolicList(len, vals) => {
+            *len = Sym { id: (len.id * total) + i };
+            vals.iter_mut().for_each(|val| renumber_val(val, i, total))
+        }
     }
 }
 
@@ -127,10 +141,14 @@ fn uses_in_exp(uses: &mut HashMap<Sym, u32>, exp: &Exp) {
         Var(v) => {
             uses.insert(*v, uses.get(&v).unwrap_or(&0) + 1);
         }
-        Bits(_) | Bits64(_) | Enum(_) | Bool(_) => (),
-        Not(exp) | Bvnot(exp) | Bvneg(exp) | Extract(_, _, exp) | ZeroExtend(_, exp) | SignExtend(_, exp) => {
-            uses_in_exp(uses, exp)
-        }
+        Bits(_) | Bits64(_) | Enum(_) | Bool(_) | String(_) => (),
+        Not(exp)
+        | Bvnot(exp)
+        | Bvneg(exp)
+        | Extract(_, _, exp)
+        | ZeroExtend(_, exp)
+        | SignExtend(_, exp)
+        | StrLen(exp) => uses_in_exp(uses, exp),
         Eq(lhs, rhs)
         | Neq(lhs, rhs)
         | And(lhs, rhs)
@@ -160,7 +178,9 @@ fn uses_in_exp(uses: &mut HashMap<Sym, u32>, exp: &Exp) {
         | Bvshl(lhs, rhs)
         | Bvlshr(lhs, rhs)
         | Bvashr(lhs, rhs)
-        | Concat(lhs, rhs) => {
+        | Concat(lhs, rhs)
+        | StrConcat(lhs, rhs)
+        | StrPrefixof(lhs, rhs) => {
             uses_in_exp(uses, lhs);
             uses_in_exp(uses, rhs)
         }
@@ -169,6 +189,11 @@ fn uses_in_exp(uses: &mut HashMap<Sym, u32>, exp: &Exp) {
             uses_in_exp(uses, then_exp);
             uses_in_exp(uses, else_exp)
         }
+        StrSubstr(s, offset, len) => {
+            uses_in_exp(uses, s);
+            uses_in_exp(uses, offset);
+            uses_in_exp(uses, len)
+        }
         App(f, args) => {
             uses.insert(*f, uses.get(&f).unwrap_or(&0) + 1);
             for arg in args {
@@ -208,6 +233,14 @@ fn uses_in_value<B>(uses: &mut HashMap<Sym, u32>, val: &Val<B>) {
         List(vals) | Vector(vals) => vals.iter().for_each(|val| uses_in_value(uses, val)),
         Struct(fields) => fields.iter().for_each(|(_, val)| uses_in_value(uses, val)),
         Ctor(_, val) => uses_in_value(uses, val),
+        SymbolicCtor(tag, ctors) => {
+            uses.insert(*tag, uses.get(tag).unwrap_or(&0) + 1);
+            ctors.iter().for_each(|(_, val)| uses_in_value(uses, val))
+        }
+        SymbolicList(len, vals) => {
+            uses.insert(*len, uses.get(len).unwrap_or(&0) + 1);
+            vals.iter().for_each(|val| uses_in_value(uses, val))
+        }
     }
 }
 
@@ -384,6 +417,12 @@ fn calculate_uses<B, E: Borrow<Event<B>>>(events: &[E]) -> HashMap<Sym, u32> {
             WakeupRequest => (),
             SleepRequest => (),
             Function { .. } => (),
+            AssumeUnknown { .. } => (),
+            Print { output, .. } => output.iter().for_each(|part| uses_in_value(&mut uses, part.value())),
+            Watch(report) => {
+                uses_in_value(&mut uses, &report.address);
+                uses_in_value(&mut uses, &report.value)
+            }
         }
     }
 
@@ -446,6 +485,12 @@ fn calculate_required_uses<B, E: Borrow<Event<B>>>(events: &[E]) -> HashMap<Sym,
             WakeupRequest => (),
             SleepRequest => (),
             Function { .. } => (),
+            AssumeUnknown { .. } => (),
+            Print { output, .. } => output.iter().for_each(|part| uses_in_value(&mut uses, part.value())),
+            Watch(report) => {
+                uses_in_value(&mut uses, &report.address);
+                uses_in_value(&mut uses, &report.value)
+            }
         }
     }
 
@@ -936,9 +981,42 @@ fn write_exp(buf: &mut dyn Write, exp: &Exp, opts: &WriteOpts, enums: &[usize])
             }
             write!(buf, ")")
         }
+        String(s) => write_string(buf, s),
+        StrConcat(lhs, rhs) => write_binop(buf, "str.++", lhs, rhs, opts, enums),
+        StrPrefixof(lhs, rhs) => write_binop(buf, "str.prefixof", lhs, rhs, opts, enums),
+        StrLen(exp) => {
+            write!(buf, "((_ int2bv 128) ")?;
+            write_unop(buf, "str.len", exp, opts, enums)?;
+            write!(buf, ")")
+        }
+        StrSubstr(s, offset, len) => {
+            write!(buf, "(str.substr ")?;
+            write_exp(buf, s, opts, enums)?;
+            write!(buf, " ")?;
+            write_unop(buf, "bv2nat", offset, opts, enums)?;
+            write!(buf, " ")?;
+            write_unop(buf, "bv2nat", len, opts, enums)?;
+            write!(buf, ")")
+        }
     }
 }
 
+/// Write an SMT-LIB string literal, where double quotes are escaped
+/// by doubling them, and backslashes and non-printable characters
+/// use the `\u{...}` escape.
+fn write_string(buf: &mut dyn Write, s: &str) -> std::io::Result<()> {
+    write!(buf, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(buf, "\"\"")?,
+            '\\' => write!(buf, "\\u{{5c}}")?,
+            ' '..='~' => write!(buf, "{}", c)?,
+            _ => write!(buf, "\\u{{{:x}}}", c as u32)?,
+        }
+    }
+    write!(buf, "\"")
+}
+
 fn write_unop(buf: &mut dyn Write, op: &str, exp: &Exp, opts: &WriteOpts, enums: &[usize]) -> std::io::Result<()> {
     write!(buf, "({} ", op)?;
     write_exp(buf, exp, opts, enums)?;
@@ -1125,6 +1203,29 @@ pub fn write_events_with_opts<B: BV>(
             SleepRequest => write!(buf, "\n{}  (sleep-request)", indent),
 
             WakeupRequest => write!(buf, "\n{}  (wake-request)", indent),
+
+            AssumeUnknown { sat, .. } => {
+                write!(buf, "\n{}  (assume-unknown {})", indent, if *sat { "sat" } else { "unsat" })
+            }
+
+            Print { stderr, output } => {
+                write!(buf, "\n{}  ({} ", indent, if *stderr { "prerr" } else { "print" })?;
+                write_string(buf, &render_print(output, symtab, None))?;
+                write!(buf, ")")
+            }
+
+            Watch(report) => write!(
+                buf,
+                "\n{}  (watch \"{}\" {} |{}| {} {} {}{})",
+                indent,
+                report.path,
+                if report.write { "write" } else { "read" },
+                report.kind,
+                report.address.to_string(symtab),
+                report.bytes,
+                report.value.to_string(symtab),
+                if report.definite { "" } else { " possibly" }
+            ),
         })?
     }
     if !(opts.just_smt || opts.prefix) {
@@ -1133,6 +1234,80 @@ pub fn write_events_with_opts<B: BV>(
     Ok(())
 }
 
+fn render_int<B: BV>(n: &Val<B>, symtab: &Symtab, model: Option<&mut Model<B>>) -> String {
+    match (n, model) {
+        (Val::I128(n), _) => n.to_string(),
+        (Val::I64(n), _) => n.to_string(),
+        (Val::Symbolic(v), Some(model)) => match model.get_var(*v) {
+            Ok(Some(exp)) => match model_i128(&exp) {
+                Some(value) => value.to_string(),
+                None => n.to_string(symtab),
+            },
+            _ => n.to_string(symtab),
+        },
+        _ => n.to_string(symtab),
+    }
+}
+
+fn render_bits<B: BV>(bits: &Val<B>, symtab: &Symtab, model: Option<&mut Model<B>>) -> String {
+    match (bits, model) {
+        (Val::Symbolic(v), Some(model)) => {
+            let mut buf = Vec::new();
+            match model.get_var(*v) {
+                Ok(Some(Exp::Bits64(bv))) => write_bits64(&mut buf, bv.lower_u64(), bv.len()).unwrap(),
+                Ok(Some(Exp::Bits(bv))) => write_bits(&mut buf, &bv).unwrap(),
+                _ => return bits.to_string(symtab),
+            }
+            String::from_utf8(buf).unwrap()
+        }
+        _ => bits.to_string(symtab),
+    }
+}
+
+fn render_string<B: BV>(s: &Val<B>, symtab: &Symtab, model: Option<&mut Model<B>>) -> String {
+    match (s, model) {
+        (Val::String(s), _) => s.clone(),
+        (Val::Symbolic(v), Some(model)) => match model.get_var(*v) {
+            Ok(Some(Exp::String(s))) => s,
+            _ => s.to_string(symtab),
+        },
+        _ => s.to_string(symtab),
+    }
+}
+
+/// Render the output of a Sail print builtin, using the model (if
+/// given) to find values for any symbolic parts.
+pub fn render_print<B: BV>(output: &[PrintPart<B>], symtab: &Symtab, mut model: Option<&mut Model<B>>) -> String {
+    let mut rendered = String::new();
+    for part in output {
+        let model = model.as_deref_mut();
+        match part {
+            PrintPart::String(s) => rendered.push_str(&render_string(s, symtab, model)),
+            PrintPart::Int(n) => rendered.push_str(&render_int(n, symtab, model)),
+            PrintPart::Bits(bits) => rendered.push_str(&render_bits(bits, symtab, model)),
+        }
+    }
+    rendered
+}
+
+/// Render all the output from the [Event::Print] events in a trace
+/// (which should be in execution order), using the model (if given)
+/// to find values for any symbolic parts. Output to stdout and
+/// stderr is interleaved.
+pub fn render_output<B: BV, E: Borrow<Event<B>>>(
+    events: &[E],
+    symtab: &Symtab,
+    mut model: Option<&mut Model<B>>,
+) -> String {
+    let mut rendered = String::new();
+    for event in events {
+        if let Print { output, .. } = event.borrow() {
+            rendered.push_str(&render_print(output, symtab, model.as_deref_mut()))
+        }
+    }
+    rendered
+}
+
 pub fn write_events<B: BV>(buf: &mut dyn Write, events: &[Event<B>], symtab: &Symtab) {
     write_events_with_opts(buf, events, symtab, &WriteOpts::default()).unwrap()
 }
@@ -1173,6 +1348,50 @@ mod tests {
     use crate::bitvector::b64::B64;
     use crate::ir::source_loc::SourceLoc;
 
+    #[test]
+    fn render_concrete_print() {
+        let symtab = Symtab::new();
+        let output: Vec<PrintPart<B64>> = vec![
+            PrintPart::String(Val::String("x = ".to_string())),
+            PrintPart::Int(Val::I128(-3)),
+            PrintPart::String(Val::String(", y = ".to_string())),
+            PrintPart::Bits(Val::Bits(B64::new(0x2a, 8))),
+        ];
+        assert_eq!(render_print(&output, &symtab, None), "x = -3, y = #x2a");
+
+        let events: Vec<Event<B64>> = vec![
+            Event::Print { stderr: false, output: output[0..2].to_vec() },
+            Event::WakeupRequest,
+            Event::Print { stderr: true, output: output[2..].to_vec() },
+        ];
+        assert_eq!(render_output(&events, &symtab, None), "x = -3, y = #x2a");
+
+        let mut buf = Vec::new();
+        write_events(&mut buf, &events[2..], &symtab);
+        assert!(String::from_utf8(buf).unwrap().contains("(prerr \", y = #x2a\")"))
+    }
+
+    #[test]
+    fn render_symbolic_print() {
+        use crate::smt::{Config, Context, SmtResult, Solver};
+
+        let symtab = Symtab::new();
+        let mut cfg = Config::new();
+        cfg.set_param_value("model", "true");
+        let ctx = Context::new(cfg);
+        let mut solver = Solver::<B64>::new(&ctx);
+        let n = solver.declare_const(Ty::BitVec(128), SourceLoc::unknown());
+        let minus_three = Exp::SignExtend(64, Box::new(Exp::Bits64(B64::new(-3i64 as u64, 64))));
+        solver.add(Def::Assert(Exp::Eq(Box::new(Exp::Var(n)), Box::new(minus_three))));
+        assert!(solver.check_sat(SourceLoc::unknown()) == SmtResult::Sat);
+
+        let output: Vec<PrintPart<B64>> =
+            vec![PrintPart::String(Val::String("n = ".to_string())), PrintPart::Int(Val::Symbolic(n))];
+        let mut model = Model::new(&solver);
+        assert_eq!(render_print(&output, &symtab, Some(&mut model)), "n = -3");
+        assert_eq!(render_print(&output, &symtab, None), format!("n = v{}", n))
+    }
+
     #[test]
     fn break_forks_simple() {
         let events: Vec<Event<B64>> =
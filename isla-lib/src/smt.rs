@@ -48,13 +48,17 @@ use std::ffi::{CStr, CString};
 use std::fmt;
 use std::io::Write;
 use std::mem;
+use std::path::PathBuf;
 use std::ptr;
-use std::sync::Arc;
+use std::sync::atomic::{self, AtomicUsize};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use crate::bitvector::b64::B64;
 use crate::bitvector::BV;
 use crate::error::ExecError;
 use crate::ir::{source_loc::SourceLoc, EnumMember, Name, Symtab, Val};
+use crate::watch::WatchReport;
 use crate::zencode;
 
 /// A newtype wrapper for symbolic variables, which are `u32` under
@@ -127,6 +131,29 @@ impl Accessor {
     }
 }
 
+/// Part of the output of a Sail print builtin, which determines how
+/// the value is rendered.
+#[derive(Clone, Debug)]
+pub enum PrintPart<B> {
+    String(Val<B>),
+    Int(Val<B>),
+    Bits(Val<B>),
+}
+
+impl<B> PrintPart<B> {
+    pub fn value(&self) -> &Val<B> {
+        match self {
+            PrintPart::String(v) | PrintPart::Int(v) | PrintPart::Bits(v) => v,
+        }
+    }
+
+    pub fn value_mut(&mut self) -> &mut Val<B> {
+        match self {
+            PrintPart::String(v) | PrintPart::Int(v) | PrintPart::Bits(v) => v,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Event<B> {
     Smt(Def, SourceLoc),
@@ -174,6 +201,23 @@ pub enum Event<B> {
     Sleeping(Sym),
     SleepRequest,
     WakeupRequest,
+    /// An unknown result from the solver was assumed to be either
+    /// satisfiable or unsatisfiable according to the
+    /// [UnknownPolicy], so the path containing this event may not be
+    /// sound.
+    AssumeUnknown {
+        sat: bool,
+        info: SourceLoc,
+    },
+    /// Output from the Sail print and prerr builtins. This is
+    /// recorded in the trace rather than printed, so the output of
+    /// each path can be kept separate.
+    Print {
+        stderr: bool,
+        output: Vec<PrintPart<B>>,
+    },
+    /// A memory access touched a watchpoint, see [crate::watch].
+    Watch(WatchReport<B>),
 }
 
 impl<B: BV> Event<B> {
@@ -257,6 +301,14 @@ impl<B: BV> Event<B> {
         matches!(self, Event::CacheOp { .. })
     }
 
+    pub fn is_assume_unknown(&self) -> bool {
+        matches!(self, Event::AssumeUnknown { .. })
+    }
+
+    pub fn is_print(&self) -> bool {
+        matches!(self, Event::Print { .. })
+    }
+
     pub fn has_memory_kind(&self, mk: &str) -> bool {
         match self {
             Event::WriteMem { kind, .. } | Event::ReadMem { kind, .. } => mk == *kind,
@@ -348,6 +400,11 @@ impl<B: BV> Trace<B> {
         Checkpoint { num: self.checkpoints, trace: tail, next_var }
     }
 
+    /// The events added since the most recent checkpoint, oldest first
+    pub fn recent(&self) -> &[Event<B>] {
+        &self.head
+    }
+
     pub fn to_vec<'a>(&'a self) -> Vec<&'a Event<B>> {
         let mut vec: Vec<&'a Event<B>> = Vec::new();
 
@@ -368,15 +425,87 @@ impl<B: BV> Trace<B> {
     }
 }
 
+/// Determines how an `unknown` result from the solver (e.g. due to
+/// a timeout or resource limit being hit) is treated during symbolic
+/// execution.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnknownPolicy {
+    /// Raise [ExecError::Z3Unknown], killing the path
+    #[default]
+    Error,
+    /// Assume the query was satisfiable, and continue
+    Feasible,
+    /// Assume the query was unsatisfiable
+    Infeasible,
+}
+
+impl std::str::FromStr for UnknownPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(UnknownPolicy::Error),
+            "sat" | "feasible" => Ok(UnknownPolicy::Feasible),
+            "unsat" | "infeasible" => Ok(UnknownPolicy::Infeasible),
+            _ => Err(format!("Unknown result policy must be one of error, sat, or unsat, not {}", s)),
+        }
+    }
+}
+
+/// Where and when to log the queries made to the solver. Each
+/// logged query is written as a standalone SMTLIB file, so it can be
+/// reproduced outside of isla.
+#[derive(Clone, Debug)]
+pub struct QueryLog {
+    pub directory: PathBuf,
+    /// Only queries which take at least this long are logged
+    pub threshold: Duration,
+    /// The source files of the architecture, used to print the
+    /// location that caused each query
+    pub files: Arc<Vec<String>>,
+}
+
+/// Resource limits for each query made to the solver, along with
+/// the policy for handling queries that exceed them.
+#[derive(Clone, Debug, Default)]
+pub struct SolverOptions {
+    /// Timeout for each query in milliseconds
+    pub timeout: Option<u64>,
+    /// Z3 resource limit for each query
+    pub rlimit: Option<u64>,
+    pub unknown_policy: UnknownPolicy,
+    pub query_log: Option<QueryLog>,
+    /// The maximum number of values a symbolic bitvector length may
+    /// take before it is treated as an error. Execution forks for
+    /// each possible value. If unset, symbolic lengths are always
+    /// reported as [ExecError::SymbolicLength].
+    pub length_cases: Option<usize>,
+    /// Represent strings using the SMT string theory, so they can be
+    /// symbolic.
+    pub symbolic_strings: bool,
+}
+
 /// Config is a wrapper around the `Z3_config` type from the C
 /// API. `Z3_del_config` is called when it is dropped.
 pub struct Config {
     z3_cfg: Z3_config,
+    unknown_policy: UnknownPolicy,
+    query_log: Option<QueryLog>,
+    length_cases: Option<usize>,
+    symbolic_strings: bool,
 }
 
 impl Config {
     pub fn new() -> Self {
-        unsafe { Config { z3_cfg: Z3_mk_config() } }
+        unsafe {
+            Config {
+                z3_cfg: Z3_mk_config(),
+                unknown_policy: UnknownPolicy::default(),
+                query_log: None,
+                length_cases: None,
+                symbolic_strings: false,
+            }
+        }
     }
 }
 
@@ -398,6 +527,19 @@ impl Config {
         let value = CString::new(value).unwrap();
         unsafe { Z3_set_param_value(self.z3_cfg, id.as_ptr(), value.as_ptr()) }
     }
+
+    pub fn set_options(&mut self, options: &SolverOptions) {
+        if let Some(timeout) = options.timeout {
+            self.set_param_value("timeout", &timeout.to_string())
+        }
+        if let Some(rlimit) = options.rlimit {
+            self.set_param_value("rlimit", &rlimit.to_string())
+        }
+        self.unknown_policy = options.unknown_policy;
+        self.query_log = options.query_log.clone();
+        self.length_cases = options.length_cases;
+        self.symbolic_strings = options.symbolic_strings
+    }
 }
 
 pub fn global_set_param_value(id: &str, value: &str) {
@@ -409,11 +551,23 @@ pub fn global_set_param_value(id: &str, value: &str) {
 /// Context is a wrapper around `Z3_context`.
 pub struct Context {
     z3_ctx: Z3_context,
+    unknown_policy: UnknownPolicy,
+    query_log: Option<QueryLog>,
+    length_cases: Option<usize>,
+    symbolic_strings: bool,
 }
 
 impl Context {
-    pub fn new(cfg: Config) -> Self {
-        unsafe { Context { z3_ctx: Z3_mk_context_rc(cfg.z3_cfg) } }
+    pub fn new(mut cfg: Config) -> Self {
+        unsafe {
+            Context {
+                z3_ctx: Z3_mk_context_rc(cfg.z3_cfg),
+                unknown_policy: cfg.unknown_policy,
+                query_log: cfg.query_log.take(),
+                length_cases: cfg.length_cases,
+                symbolic_strings: cfg.symbolic_strings,
+            }
+        }
     }
 
     fn error(&self) -> ExecError {
@@ -533,6 +687,11 @@ impl<'ctx> Sort<'ctx> {
                     Z3_inc_ref(ctx.z3_ctx, Z3_sort_to_ast(ctx.z3_ctx, z3_sort));
                     Sort { z3_sort, ctx }
                 }
+                Ty::String => {
+                    let z3_sort = Z3_mk_string_sort(ctx.z3_ctx);
+                    Z3_inc_ref(ctx.z3_ctx, Z3_sort_to_ast(ctx.z3_ctx, z3_sort));
+                    Sort { z3_sort, ctx }
+                }
             }
         }
     }
@@ -591,6 +750,43 @@ impl<'ctx> Clone for Ast<'ctx> {
     }
 }
 
+/// Z3 interprets escape sequences in string literals, so any
+/// backslashes and non-printable characters are written using the
+/// `\u{...}` escape.
+fn escape_z3_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\u{5c}"),
+            ' '..='~' => escaped.push(c),
+            _ => escaped.push_str(&format!("\\u{{{:x}}}", c as u32)),
+        }
+    }
+    escaped
+}
+
+/// The inverse of [escape_z3_string] for strings in models.
+fn unescape_z3_string(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find("\\u{") {
+        unescaped.push_str(&rest[..i]);
+        let escape = &rest[i + 3..];
+        match escape.find('}').and_then(|j| Some((u32::from_str_radix(&escape[..j], 16).ok()?, j))) {
+            Some((code, j)) => {
+                unescaped.push(std::char::from_u32(code).unwrap_or(std::char::REPLACEMENT_CHARACTER));
+                rest = &escape[j + 1..]
+            }
+            None => {
+                unescaped.push_str(&rest[i..i + 3]);
+                rest = escape
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
 macro_rules! z3_unary_op {
     ($i:ident, $arg:ident) => {
         unsafe {
@@ -854,6 +1050,53 @@ impl<'ctx> Ast<'ctx> {
         }
     }
 
+    fn mk_string(ctx: &'ctx Context, s: &str) -> Self {
+        unsafe {
+            let s = CString::new(escape_z3_string(s)).unwrap();
+            let z3_ast = Z3_mk_string(ctx.z3_ctx, s.as_ptr());
+            Z3_inc_ref(ctx.z3_ctx, z3_ast);
+            Ast { z3_ast, ctx }
+        }
+    }
+
+    fn mk_str_concat(&self, rhs: &Ast<'ctx>) -> Self {
+        unsafe {
+            let args = [self.z3_ast, rhs.z3_ast];
+            let z3_ast = Z3_mk_seq_concat(self.ctx.z3_ctx, 2, args.as_ptr());
+            Z3_inc_ref(self.ctx.z3_ctx, z3_ast);
+            Ast { z3_ast, ctx: self.ctx }
+        }
+    }
+
+    fn mk_str_prefixof(&self, s: &Ast<'ctx>) -> Self {
+        z3_binary_op!(Z3_mk_seq_prefix, self, s)
+    }
+
+    /// The length of a string as a 128-bit bitvector
+    fn mk_str_len(&self) -> Self {
+        let len = z3_unary_op!(Z3_mk_seq_length, self);
+        unsafe {
+            let z3_ast = Z3_mk_int2bv(self.ctx.z3_ctx, 128, len.z3_ast);
+            Z3_inc_ref(self.ctx.z3_ctx, z3_ast);
+            Ast { z3_ast, ctx: self.ctx }
+        }
+    }
+
+    /// A substring, where the offset and length are bitvectors
+    fn mk_str_substr(&self, offset: &Ast<'ctx>, len: &Ast<'ctx>) -> Self {
+        unsafe {
+            let offset = Z3_mk_bv2int(self.ctx.z3_ctx, offset.z3_ast, false);
+            Z3_inc_ref(self.ctx.z3_ctx, offset);
+            let len = Z3_mk_bv2int(self.ctx.z3_ctx, len.z3_ast, false);
+            Z3_inc_ref(self.ctx.z3_ctx, len);
+            let z3_ast = Z3_mk_seq_extract(self.ctx.z3_ctx, self.z3_ast, offset, len);
+            Z3_inc_ref(self.ctx.z3_ctx, z3_ast);
+            Z3_dec_ref(self.ctx.z3_ctx, offset);
+            Z3_dec_ref(self.ctx.z3_ctx, len);
+            Ast { z3_ast, ctx: self.ctx }
+        }
+    }
+
     fn get_bool_value(&self) -> Option<bool> {
         unsafe {
             match Z3_get_bool_value(self.ctx.z3_ctx, self.z3_ast) {
@@ -893,6 +1136,7 @@ impl<'ctx> Drop for Ast<'ctx> {
 /// # use isla_lib::smt::smtlib::Def::*;
 /// # use isla_lib::smt::smtlib::*;
 /// # use isla_lib::smt::*;
+/// # use isla_lib::ir::source_loc::SourceLoc;
 /// # let x = Sym::from_u32(0);
 /// let cfg = Config::new();
 /// let ctx = Context::new(cfg);
@@ -902,7 +1146,7 @@ impl<'ctx> Drop for Ast<'ctx> {
 /// // (assert v0)
 /// solver.add(Assert(Var(x)));
 /// // (check-sat)
-/// assert!(solver.check_sat() == SmtResult::Sat)
+/// assert!(solver.check_sat(SourceLoc::unknown()) == SmtResult::Sat)
 /// ```
 ///
 /// The other thing the Solver type does is maintain a trace of
@@ -917,6 +1161,7 @@ impl<'ctx> Drop for Ast<'ctx> {
 /// # use isla_lib::smt::smtlib::Def::*;
 /// # use isla_lib::smt::smtlib::*;
 /// # use isla_lib::smt::*;
+/// # use isla_lib::ir::source_loc::SourceLoc;
 /// # let x = Sym::from_u32(0);
 /// let point = {
 ///     let cfg = Config::new();
@@ -930,7 +1175,7 @@ impl<'ctx> Drop for Ast<'ctx> {
 /// let cfg = Config::new();
 /// let ctx = Context::new(cfg);
 /// let mut solver = Solver::from_checkpoint(&ctx, point);
-/// assert!(solver.check_sat() == SmtResult::Unsat);
+/// assert!(solver.check_sat(SourceLoc::unknown()) == SmtResult::Unsat);
 pub struct Solver<'ctx, B> {
     trace: Trace<B>,
     next_var: u32,
@@ -939,12 +1184,16 @@ pub struct Solver<'ctx, B> {
     func_decls: HashMap<Sym, FuncDecl<'ctx>>,
     enums: Enums<'ctx>,
     enum_map: HashMap<usize, usize>,
+    statistics: SolverStatistics,
     z3_solver: Z3_solver,
     ctx: &'ctx Context,
 }
 
 impl<'ctx, B> Drop for Solver<'ctx, B> {
     fn drop(&mut self) {
+        if let Ok(mut total) = TOTAL_STATISTICS.lock() {
+            total.merge(&self.statistics)
+        }
         unsafe {
             Z3_solver_dec_ref(self.ctx.z3_ctx, self.z3_solver);
         }
@@ -962,6 +1211,7 @@ impl<'ctx, B> Drop for Solver<'ctx, B> {
 /// # use isla_lib::smt::smtlib::Def::*;
 /// # use isla_lib::smt::smtlib::*;
 /// # use isla_lib::smt::*;
+/// # use isla_lib::ir::source_loc::SourceLoc;
 /// # let x = Sym::from_u32(0);
 /// let mut cfg = Config::new();
 /// cfg.set_param_value("model", "true");
@@ -969,7 +1219,7 @@ impl<'ctx, B> Drop for Solver<'ctx, B> {
 /// let mut solver = Solver::<B64>::new(&ctx);
 /// solver.add(DeclareConst(x, Ty::BitVec(4)));
 /// solver.add(Assert(Bvsgt(Box::new(Var(x)), Box::new(Bits(vec![false,false,true,false])))));
-/// assert!(solver.check_sat() == SmtResult::Sat);
+/// assert!(solver.check_sat(SourceLoc::unknown()) == SmtResult::Sat);
 /// let mut model = Model::new(&solver);
 /// let var0 = model.get_var(x).unwrap().unwrap();
 /// ```
@@ -1040,20 +1290,31 @@ impl<'ctx, B: BV> Model<'ctx, B> {
             None => return Err(ExecError::Type(format!("Unbound variable {:?}", &var), SourceLoc::unknown())),
             Some(ast) => ast.clone(),
         };
-        self.get_ast(var_ast)
+        self.get_ast(var_ast, false)
+    }
+
+    /// Like `get_var`, except if the model leaves the variable (or
+    /// any variable it is defined in terms of) unconstrained, an
+    /// arbitrary value is chosen for it.
+    pub fn get_var_completed(&mut self, var: Sym) -> Result<Option<Exp>, ExecError> {
+        let var_ast = match self.solver.decls.get(&var) {
+            None => return Err(ExecError::Type(format!("Unbound variable {:?}", &var), SourceLoc::unknown())),
+            Some(ast) => ast.clone(),
+        };
+        self.get_ast(var_ast, true)
     }
 
     pub fn get_exp(&mut self, exp: &Exp) -> Result<Option<Exp>, ExecError> {
         let ast = self.solver.translate_exp(exp);
-        self.get_ast(ast)
+        self.get_ast(ast, false)
     }
 
     // Requiring the model to be mutable as I expect Z3 will alter the underlying data
-    fn get_ast(&mut self, var_ast: Ast) -> Result<Option<Exp>, ExecError> {
+    fn get_ast(&mut self, var_ast: Ast, model_completion: bool) -> Result<Option<Exp>, ExecError> {
         unsafe {
             let z3_ctx = self.ctx.z3_ctx;
             let mut z3_ast: Z3_ast = ptr::null_mut();
-            if !Z3_model_eval(z3_ctx, self.z3_model, var_ast.z3_ast, false, &mut z3_ast) {
+            if !Z3_model_eval(z3_ctx, self.z3_model, var_ast.z3_ast, model_completion, &mut z3_ast) {
                 return Err(self.ctx.error());
             }
             Z3_inc_ref(z3_ctx, z3_ast);
@@ -1078,6 +1339,13 @@ impl<'ctx, B: BV> Model<'ctx, B> {
             } else if sort_kind == SortKind::Bool || sort_kind == SortKind::BV {
                 // Model did not need to assign an interpretation to this variable
                 Ok(None)
+            } else if Z3_is_string_sort(z3_ctx, sort) {
+                if Z3_is_string(z3_ctx, z3_ast) {
+                    let s = CStr::from_ptr(Z3_get_string(z3_ctx, z3_ast));
+                    Ok(Some(Exp::String(unescape_z3_string(&s.to_string_lossy()))))
+                } else {
+                    Ok(None)
+                }
             } else if sort_kind == SortKind::Datatype {
                 let func_decl = Z3_get_app_decl(z3_ctx, Z3_to_app(z3_ctx, z3_ast));
                 Z3_inc_ref(z3_ctx, Z3_func_decl_to_ast(z3_ctx, func_decl));
@@ -1138,6 +1406,70 @@ impl SmtResult {
     }
 }
 
+/// Statistics about the queries made by a solver. As each path is
+/// executed using a fresh solver restored from a checkpoint, these
+/// cover the queries made by a single path since it forked.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SolverStatistics {
+    pub queries: u32,
+    pub unknowns: u32,
+    pub total_time: Duration,
+    pub max_time: Duration,
+}
+
+impl SolverStatistics {
+    const fn zero() -> Self {
+        SolverStatistics {
+            queries: 0,
+            unknowns: 0,
+            total_time: Duration::from_secs(0),
+            max_time: Duration::from_secs(0),
+        }
+    }
+
+    pub fn merge(&mut self, other: &SolverStatistics) {
+        self.queries += other.queries;
+        self.unknowns += other.unknowns;
+        self.total_time += other.total_time;
+        self.max_time = std::cmp::max(self.max_time, other.max_time)
+    }
+
+    fn record(&mut self, result: SmtResult, elapsed: Duration) {
+        self.queries += 1;
+        if result == Unknown {
+            self.unknowns += 1
+        }
+        self.total_time += elapsed;
+        self.max_time = std::cmp::max(self.max_time, elapsed)
+    }
+}
+
+impl fmt::Display for SolverStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} queries ({} unknown), total {}ms, slowest {}ms",
+            self.queries,
+            self.unknowns,
+            self.total_time.as_millis(),
+            self.max_time.as_millis()
+        )
+    }
+}
+
+/// Statistics for every solver which has been dropped, which is
+/// updated as each path finishes.
+static TOTAL_STATISTICS: Mutex<SolverStatistics> = Mutex::new(SolverStatistics::zero());
+
+/// The combined statistics for all the solvers used so far, across
+/// all threads.
+pub fn total_statistics() -> SolverStatistics {
+    *TOTAL_STATISTICS.lock().unwrap()
+}
+
+/// Used to give each logged query a unique file name
+static QUERY_COUNT: AtomicUsize = AtomicUsize::new(0);
+
 static QFAUFBV_STR: &[u8] = b"qfaufbv\0";
 
 impl<'ctx, B: BV> Solver<'ctx, B> {
@@ -1167,6 +1499,7 @@ impl<'ctx, B: BV> Solver<'ctx, B> {
                 func_decls: HashMap::new(),
                 enums: Enums::new(ctx),
                 enum_map: HashMap::new(),
+                statistics: SolverStatistics::default(),
             }
         }
     }
@@ -1240,6 +1573,13 @@ impl<'ctx, B: BV> Solver<'ctx, B> {
                 let exps_ast: Vec<_> = exps.iter().map(|exp| self.translate_exp(exp)).collect();
                 Ast::mk_distinct(self.ctx, &exps_ast)
             }
+            String(s) => Ast::mk_string(self.ctx, s),
+            StrConcat(lhs, rhs) => Ast::mk_str_concat(&self.translate_exp(lhs), &self.translate_exp(rhs)),
+            StrPrefixof(lhs, rhs) => Ast::mk_str_prefixof(&self.translate_exp(lhs), &self.translate_exp(rhs)),
+            StrLen(exp) => Ast::mk_str_len(&self.translate_exp(exp)),
+            StrSubstr(s, offset, len) => {
+                Ast::mk_str_substr(&self.translate_exp(s), &self.translate_exp(offset), &self.translate_exp(len))
+            }
         }
     }
 
@@ -1358,6 +1698,10 @@ impl<'ctx, B: BV> Solver<'ctx, B> {
         self.cycles
     }
 
+    pub fn set_cycle_count(&mut self, cycles: i128) {
+        self.cycles = cycles
+    }
+
     fn add_event_internal(&mut self, event: &Event<B>) {
         if let Event::Smt(def, _) = event {
             self.add_internal(def)
@@ -1410,10 +1754,17 @@ impl<'ctx, B: BV> Solver<'ctx, B> {
         solver
     }
 
-    pub fn check_sat_with(&mut self, exp: &Exp) -> SmtResult {
-        let ast = self.translate_exp(exp);
-        unsafe {
-            let result = Z3_solver_check_assumptions(self.ctx.z3_ctx, self.z3_solver, 1, &ast.z3_ast);
+    /// Check satisfiability, optionally under an additional
+    /// assumption, recording statistics about the query and logging
+    /// it if required by the solver's context.
+    fn check(&mut self, assumption: Option<&Exp>, info: SourceLoc) -> SmtResult {
+        let assumption_ast = assumption.map(|exp| self.translate_exp(exp));
+        let start_time = Instant::now();
+        let result = unsafe {
+            let result = match &assumption_ast {
+                Some(ast) => Z3_solver_check_assumptions(self.ctx.z3_ctx, self.z3_solver, 1, &ast.z3_ast),
+                None => Z3_solver_check(self.ctx.z3_ctx, self.z3_solver),
+            };
             if result == Z3_L_TRUE {
                 Sat
             } else if result == Z3_L_FALSE {
@@ -1421,22 +1772,168 @@ impl<'ctx, B: BV> Solver<'ctx, B> {
             } else {
                 Unknown
             }
+        };
+        let elapsed = start_time.elapsed();
+        self.statistics.record(result, elapsed);
+
+        let ctx = self.ctx;
+        if let Some(query_log) = &ctx.query_log {
+            if elapsed >= query_log.threshold {
+                self.log_query(query_log, assumption_ast.as_ref(), result, elapsed, info)
+            }
+        }
+
+        result
+    }
+
+    /// Write the current assertions, along with any assumption, as
+    /// a standalone SMTLIB benchmark.
+    fn log_query(
+        &self,
+        query_log: &QueryLog,
+        assumption: Option<&Ast>,
+        result: SmtResult,
+        elapsed: Duration,
+        info: SourceLoc,
+    ) {
+        let n = QUERY_COUNT.fetch_add(1, atomic::Ordering::Relaxed);
+        let path = query_log.directory.join(format!("query_{}_{}.smt2", std::process::id(), n));
+        let files: Vec<&str> = query_log.files.iter().map(String::as_str).collect();
+
+        let benchmark = unsafe {
+            let z3_ctx = self.ctx.z3_ctx;
+            let assertions = Z3_solver_get_assertions(z3_ctx, self.z3_solver);
+            Z3_ast_vector_inc_ref(z3_ctx, assertions);
+            let assertions_vec: Vec<Z3_ast> =
+                (0..Z3_ast_vector_size(z3_ctx, assertions)).map(|i| Z3_ast_vector_get(z3_ctx, assertions, i)).collect();
+            let formula = match assumption {
+                Some(ast) => ast.z3_ast,
+                None => Z3_mk_true(z3_ctx),
+            };
+            let status = CString::new(match result {
+                Sat => "sat",
+                Unsat => "unsat",
+                Unknown => "unknown",
+            })
+            .unwrap();
+            let empty = CString::new("").unwrap();
+            let s = Z3_benchmark_to_smtlib_string(
+                z3_ctx,
+                empty.as_ptr(),
+                empty.as_ptr(),
+                status.as_ptr(),
+                empty.as_ptr(),
+                assertions_vec.len() as u32,
+                assertions_vec.as_ptr(),
+                formula,
+            );
+            let benchmark = CStr::from_ptr(s).to_string_lossy().into_owned();
+            Z3_ast_vector_dec_ref(z3_ctx, assertions);
+            benchmark
+        };
+
+        let write_query = || -> std::io::Result<()> {
+            let mut file = std::fs::File::create(&path)?;
+            writeln!(file, "; result: {:?}", result)?;
+            writeln!(file, "; time: {}ms", elapsed.as_millis())?;
+            writeln!(file, "; location: {}", info.location_string(&files))?;
+            file.write_all(benchmark.as_bytes())
+        };
+
+        if let Err(e) = write_query() {
+            eprintln!("Failed to log solver query to {}: {}", path.display(), e)
         }
     }
 
+    /// Check satisfiability assuming `exp`. The source location is
+    /// recorded if the query is logged.
+    pub fn check_sat_with(&mut self, exp: &Exp, info: SourceLoc) -> SmtResult {
+        self.check(Some(exp), info)
+    }
+
+    pub fn statistics(&self) -> &SolverStatistics {
+        &self.statistics
+    }
+
     pub fn trace(&self) -> &Trace<B> {
         &self.trace
     }
 
-    pub fn check_sat(&mut self) -> SmtResult {
-        unsafe {
-            let result = Z3_solver_check(self.ctx.z3_ctx, self.z3_solver);
-            if result == Z3_L_TRUE {
-                Sat
-            } else if result == Z3_L_FALSE {
-                Unsat
-            } else {
-                Unknown
+    /// Decide whether a query for which the solver returned unknown
+    /// should be treated as satisfiable, according to the
+    /// [UnknownPolicy] of the solver's context. When the result is
+    /// assumed an `AssumeUnknown` event is added to the trace, so the
+    /// path can be flagged in any output.
+    pub fn resolve_unknown(&mut self, info: SourceLoc) -> Result<bool, ExecError> {
+        let sat = match self.ctx.unknown_policy {
+            UnknownPolicy::Error => return Err(ExecError::Z3Unknown),
+            UnknownPolicy::Feasible => true,
+            UnknownPolicy::Infeasible => false,
+        };
+        self.add_event(Event::AssumeUnknown { sat, info });
+        Ok(sat)
+    }
+
+    /// Like `check_sat_with(exp).is_sat()`, but handles unknown
+    /// results using `resolve_unknown`.
+    pub fn is_sat_with(&mut self, exp: &Exp, info: SourceLoc) -> Result<bool, ExecError> {
+        match self.check(Some(exp), info) {
+            Sat => Ok(true),
+            Unsat => Ok(false),
+            Unknown => self.resolve_unknown(info),
+        }
+    }
+
+    /// Like `check_sat(SourceLoc::unknown()).is_sat()`, but handles unknown results using
+    /// `resolve_unknown`.
+    pub fn is_sat(&mut self, info: SourceLoc) -> Result<bool, ExecError> {
+        match self.check(None, info) {
+            Sat => Ok(true),
+            Unsat => Ok(false),
+            Unknown => self.resolve_unknown(info),
+        }
+    }
+
+    /// Check satisfiability. The source location is recorded if the
+    /// query is logged.
+    pub fn check_sat(&mut self, info: SourceLoc) -> SmtResult {
+        self.check(None, info)
+    }
+
+    /// The maximum number of cases to split a symbolic length into,
+    /// if enabled for the solver's context.
+    pub fn length_cases(&self) -> Option<usize> {
+        self.ctx.length_cases
+    }
+
+    /// Whether strings can be symbolic in the solver's context.
+    pub fn symbolic_strings(&self) -> bool {
+        self.ctx.symbolic_strings
+    }
+
+    /// Find every value `v` can take in the current state, by
+    /// repeatedly asking for a model which excludes the values found
+    /// so far. Returns `None` if there are more than `limit` values,
+    /// or if the solver cannot decide whether there are any more, as
+    /// there is then no model to take the next value from.
+    pub fn possible_values(&mut self, v: Sym, limit: usize, info: SourceLoc) -> Result<Option<Vec<Exp>>, ExecError> {
+        let mut values: Vec<Exp> = Vec::new();
+        loop {
+            let exclude = values
+                .iter()
+                .map(|value| Exp::Neq(Box::new(Exp::Var(v)), Box::new(value.clone())))
+                .fold(Exp::Bool(true), |exclude, neq| Exp::And(Box::new(exclude), Box::new(neq)));
+            match self.check(Some(&exclude), info) {
+                Sat => (),
+                Unsat => return Ok(Some(values)),
+                Unknown => return Ok(None),
+            }
+            if values.len() == limit {
+                return Ok(None);
+            }
+            match Model::new(self).get_var_completed(v)? {
+                Some(value) => values.push(value),
+                None => return Ok(None),
             }
         }
     }
@@ -1493,6 +1990,7 @@ mod tests {
     use super::Def::*;
     use super::Exp::*;
     use super::*;
+    use std::string::String;
 
     macro_rules! bv {
         ( $bv_string:expr ) => {{
@@ -1520,7 +2018,7 @@ mod tests {
         let ctx = Context::new(cfg);
         let mut solver = Solver::<B64>::new(&ctx);
         solver.add(Assert(Eq(Box::new(bv!("0110")), Box::new(bv!("1001")))));
-        assert!(solver.check_sat() == Unsat);
+        assert!(solver.check_sat(SourceLoc::unknown()) == Unsat);
     }
 
     #[test]
@@ -1538,7 +2036,7 @@ mod tests {
         solver.add(Assert(Eq(Box::new(var(2)), Box::new(var(3)))));
         let big_bv = Box::new(SignExtend(251, Box::new(Bits(vec![true, false, false, true, false, true]))));
         solver.add(Assert(Eq(Box::new(var(4)), big_bv)));
-        assert!(solver.check_sat() == Sat);
+        assert!(solver.check_sat(SourceLoc::unknown()) == Sat);
         let (v0, v2, v3, v4);
         {
             let mut model = Model::new(&solver);
@@ -1552,7 +2050,7 @@ mod tests {
         solver.add(Assert(Eq(Box::new(var(2)), Box::new(v2))));
         solver.add(Assert(Eq(Box::new(var(3)), Box::new(v3))));
         solver.add(Assert(Eq(Box::new(var(4)), Box::new(v4))));
-        match solver.check_sat() {
+        match solver.check_sat(SourceLoc::unknown()) {
             Sat => (),
             _ => panic!("Round-trip failed, trace {:?}", solver.trace()),
         }
@@ -1569,7 +2067,7 @@ mod tests {
         let v1 = solver.declare_const(Ty::Enum(e), SourceLoc::unknown());
         let v2 = solver.declare_const(Ty::Enum(e), SourceLoc::unknown());
         solver.assert_eq(Var(v0), Var(v1));
-        assert!(solver.check_sat() == Sat);
+        assert!(solver.check_sat(SourceLoc::unknown()) == Sat);
         let (m0, m1) = {
             let mut model = Model::new(&solver);
             assert!(model.get_var(v2).unwrap().is_none());
@@ -1577,7 +2075,7 @@ mod tests {
         };
         solver.assert_eq(Var(v0), m0);
         solver.assert_eq(Var(v1), m1);
-        match solver.check_sat() {
+        match solver.check_sat(SourceLoc::unknown()) {
             Sat => (),
             _ => panic!("Round-trip failed, trace {:?}", solver.trace()),
         }
@@ -1596,7 +2094,7 @@ mod tests {
             .add(Assert(Eq(Box::new(App(Sym::from_u32(0), vec![bv!("10"), bv!("0110")])), Box::new(bv!("01011011")))));
         solver.add(Assert(Eq(Box::new(App(Sym::from_u32(0), vec![var(2), bv!("0110")])), Box::new(var(1)))));
         solver.add(Assert(Eq(Box::new(var(2)), Box::new(bv!("10")))));
-        assert!(solver.check_sat() == Sat);
+        assert!(solver.check_sat(SourceLoc::unknown()) == Sat);
         let mut model = Model::new(&solver);
         let val = model.get_var(Sym::from_u32(1)).unwrap().unwrap();
         assert!(match val {
@@ -1605,6 +2103,68 @@ mod tests {
         });
     }
 
+    #[test]
+    fn solver_limits() {
+        let mut cfg = Config::new();
+        cfg.set_options(&SolverOptions { timeout: Some(10000), rlimit: Some(100000000), ..SolverOptions::default() });
+        let ctx = Context::new(cfg);
+        let mut solver = Solver::<B64>::new(&ctx);
+        solver.add(DeclareConst(Sym::from_u32(0), Ty::BitVec(4)));
+        let query = Eq(Box::new(var(0)), Box::new(Bits64(B64::new(0b0110, 4))));
+        assert!(solver.is_sat_with(&query, SourceLoc::unknown()).unwrap());
+    }
+
+    #[test]
+    fn unknown_policy() {
+        let mut cfg = Config::new();
+        cfg.set_options(&SolverOptions { unknown_policy: UnknownPolicy::Feasible, ..SolverOptions::default() });
+        let ctx = Context::new(cfg);
+        let mut solver = Solver::<B64>::new(&ctx);
+        assert!(solver.resolve_unknown(SourceLoc::unknown()).unwrap());
+        assert!(solver.trace().to_vec().iter().any(|event| event.is_assume_unknown()));
+
+        let ctx = Context::new(Config::new());
+        let mut solver = Solver::<B64>::new(&ctx);
+        assert!(matches!(solver.resolve_unknown(SourceLoc::unknown()), Err(ExecError::Z3Unknown)));
+        assert!(solver.trace().to_vec().is_empty());
+
+        assert_eq!("unsat".parse(), Ok(UnknownPolicy::Infeasible));
+        assert!("maybe".parse::<UnknownPolicy>().is_err())
+    }
+
+    #[test]
+    fn query_log() {
+        let directory = std::env::temp_dir().join(format!("isla_query_log_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let query_log =
+            QueryLog { directory: directory.clone(), threshold: Duration::from_millis(0), files: Arc::new(Vec::new()) };
+        let mut cfg = Config::new();
+        cfg.set_options(&SolverOptions { query_log: Some(query_log), ..SolverOptions::default() });
+        let ctx = Context::new(cfg);
+        let mut solver = Solver::<B64>::new(&ctx);
+        solver.add(DeclareConst(Sym::from_u32(0), Ty::BitVec(4)));
+        let query = Eq(Box::new(var(0)), Box::new(Bits64(B64::new(0b0110, 4))));
+        assert!(solver.check_sat_with(&query, SourceLoc::unknown()) == Sat);
+        solver.add(Assert(query));
+        assert!(solver.check_sat(SourceLoc::unknown()) == Sat);
+        assert_eq!(solver.statistics().queries, 2);
+
+        let logged: Vec<String> = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(logged.len(), 2);
+        assert!(logged.iter().all(|query| query.starts_with("; result: Sat") && query.contains("(check-sat)")));
+        assert!(logged.iter().all(|query| query.contains("(declare-fun")));
+
+        // Other tests run concurrently, so the total can only be
+        // bounded from below
+        let before = total_statistics().queries;
+        drop(solver);
+        assert!(total_statistics().queries >= before + 2)
+    }
+
     #[test]
     fn array() {
         let cfg = Config::new();
@@ -1619,6 +2179,52 @@ mod tests {
             )),
             Box::new(bv!("0101")),
         )));
-        assert!(solver.check_sat() == Unsat);
+        assert!(solver.check_sat(SourceLoc::unknown()) == Unsat);
+    }
+
+    #[test]
+    fn strings() {
+        let mut cfg = Config::new();
+        cfg.set_param_value("model", "true");
+        let ctx = Context::new(cfg);
+        let mut solver = Solver::<B64>::new(&ctx);
+        let prefix = "ld\\\"\u{e9}";
+        let v0 = solver.declare_const(Ty::String, SourceLoc::unknown());
+        solver.add(Assert(StrPrefixof(Box::new(Exp::String(prefix.to_string())), Box::new(Var(v0)))));
+        solver.assert_eq(StrLen(Box::new(Var(v0))), bits64(8, 128));
+        let substr = StrSubstr(Box::new(Var(v0)), Box::new(bits64(5, 128)), Box::new(bits64(3, 128)));
+        solver.assert_eq(substr, Exp::String("x0 ".to_string()));
+        assert!(solver.check_sat(SourceLoc::unknown()) == Sat);
+        let m0 = Model::new(&solver).get_var(v0).unwrap().unwrap();
+        match &m0 {
+            Exp::String(s) => assert_eq!(s, &format!("{}x0 ", prefix)),
+            _ => panic!("Expected a string, got {:?}", m0),
+        }
+        solver.assert_eq(Var(v0), m0);
+        match solver.check_sat(SourceLoc::unknown()) {
+            Sat => (),
+            _ => panic!("Round-trip failed, trace {:?}", solver.trace()),
+        }
+    }
+
+    #[test]
+    fn possible_values() {
+        let mut cfg = Config::new();
+        cfg.set_param_value("model", "true");
+        let ctx = Context::new(cfg);
+        let mut solver = Solver::<B64>::new(&ctx);
+        let v0 = solver.declare_const(Ty::BitVec(4), SourceLoc::unknown());
+        solver.add(Assert(Bvult(Box::new(Var(v0)), Box::new(bits64(3, 4)))));
+        let values = solver.possible_values(v0, 3, SourceLoc::unknown()).unwrap().unwrap();
+        let mut values: Vec<u64> = values
+            .iter()
+            .map(|value| match value {
+                Bits64(bv) => bv.lower_u64(),
+                _ => panic!("Expected a bitvector, got {:?}", value),
+            })
+            .collect();
+        values.sort_unstable();
+        assert_eq!(values, vec![0, 1, 2]);
+        assert!(solver.possible_values(v0, 2, SourceLoc::unknown()).unwrap().is_none())
     }
 }
@@ -47,6 +47,7 @@ pub enum Ty {
     BitVec(u32),
     Enum(usize),
     Array(Box<Ty>, Box<Ty>),
+    String,
 }
 
 impl fmt::Display for Ty {
@@ -63,6 +64,7 @@ impl fmt::Display for Ty {
                 codom.fmt(f)?;
                 write!(f, ")")
             }
+            String => write!(f, "String"),
         }
     }
 }
@@ -115,6 +117,13 @@ pub enum Exp {
     Select(Box<Exp>, Box<Exp>),
     Store(Box<Exp>, Box<Exp>, Box<Exp>),
     Distinct(Vec<Exp>),
+    // Operations from the SMT string theory. The integer argument and
+    // result types are 128-bit bitvectors, as for Sail integers.
+    String(String),
+    StrConcat(Box<Exp>, Box<Exp>),
+    StrPrefixof(Box<Exp>, Box<Exp>),
+    StrLen(Box<Exp>),
+    StrSubstr(Box<Exp>, Box<Exp>, Box<Exp>),
 }
 
 #[allow(clippy::needless_range_loop)]
@@ -311,10 +320,14 @@ impl Exp {
     {
         use Exp::*;
         match self {
-            Var(_) | Bits(_) | Bits64(_) | Enum { .. } | Bool(_) => (),
-            Not(exp) | Bvnot(exp) | Bvneg(exp) | Extract(_, _, exp) | ZeroExtend(_, exp) | SignExtend(_, exp) => {
-                exp.modify(f)
-            }
+            Var(_) | Bits(_) | Bits64(_) | Enum { .. } | Bool(_) | String(_) => (),
+            Not(exp)
+            | Bvnot(exp)
+            | Bvneg(exp)
+            | Extract(_, _, exp)
+            | ZeroExtend(_, exp)
+            | SignExtend(_, exp)
+            | StrLen(exp) => exp.modify(f),
             Eq(lhs, rhs)
             | Neq(lhs, rhs)
             | And(lhs, rhs)
@@ -344,7 +357,9 @@ impl Exp {
             | Bvshl(lhs, rhs)
             | Bvlshr(lhs, rhs)
             | Bvashr(lhs, rhs)
-            | Concat(lhs, rhs) => {
+            | Concat(lhs, rhs)
+            | StrConcat(lhs, rhs)
+            | StrPrefixof(lhs, rhs) => {
                 lhs.modify(f);
                 rhs.modify(f);
             }
@@ -353,6 +368,11 @@ impl Exp {
                 then_exp.modify(f);
                 else_exp.modify(f)
             }
+            StrSubstr(s, offset, len) => {
+                s.modify(f);
+                offset.modify(f);
+                len.modify(f)
+            }
             App(_, args) => {
                 for exp in args {
                     exp.modify(f)
@@ -384,10 +404,14 @@ impl Exp {
         use Exp::*;
         f(self);
         match self {
-            Var(_) | Bits(_) | Bits64(_) | Enum { .. } | Bool(_) => (),
-            Not(exp) | Bvnot(exp) | Bvneg(exp) | Extract(_, _, exp) | ZeroExtend(_, exp) | SignExtend(_, exp) => {
-                exp.modify(f)
-            }
+            Var(_) | Bits(_) | Bits64(_) | Enum { .. } | Bool(_) | String(_) => (),
+            Not(exp)
+            | Bvnot(exp)
+            | Bvneg(exp)
+            | Extract(_, _, exp)
+            | ZeroExtend(_, exp)
+            | SignExtend(_, exp)
+            | StrLen(exp) => exp.modify(f),
             Eq(lhs, rhs)
             | Neq(lhs, rhs)
             | And(lhs, rhs)
@@ -417,7 +441,9 @@ impl Exp {
             | Bvshl(lhs, rhs)
             | Bvlshr(lhs, rhs)
             | Bvashr(lhs, rhs)
-            | Concat(lhs, rhs) => {
+            | Concat(lhs, rhs)
+            | StrConcat(lhs, rhs)
+            | StrPrefixof(lhs, rhs) => {
                 lhs.modify(f);
                 rhs.modify(f);
             }
@@ -426,6 +452,11 @@ impl Exp {
                 then_exp.modify(f);
                 else_exp.modify(f)
             }
+            StrSubstr(s, offset, len) => {
+                s.modify(f);
+                offset.modify(f);
+                len.modify(f)
+            }
             App(_, args) => {
                 for exp in args {
                     exp.modify(f)
@@ -487,10 +518,14 @@ impl Exp {
                     }
                 }
             }
-            Bits(_) | Bits64(_) | Enum { .. } | Bool(_) => (),
-            Not(exp) | Bvnot(exp) | Bvneg(exp) | Extract(_, _, exp) | ZeroExtend(_, exp) | SignExtend(_, exp) => {
-                exp.subst_once_in_place(substs)
-            }
+            Bits(_) | Bits64(_) | Enum { .. } | Bool(_) | String(_) => (),
+            Not(exp)
+            | Bvnot(exp)
+            | Bvneg(exp)
+            | Extract(_, _, exp)
+            | ZeroExtend(_, exp)
+            | SignExtend(_, exp)
+            | StrLen(exp) => exp.subst_once_in_place(substs),
             Eq(lhs, rhs)
             | Neq(lhs, rhs)
             | And(lhs, rhs)
@@ -520,7 +555,9 @@ impl Exp {
             | Bvshl(lhs, rhs)
             | Bvlshr(lhs, rhs)
             | Bvashr(lhs, rhs)
-            | Concat(lhs, rhs) => {
+            | Concat(lhs, rhs)
+            | StrConcat(lhs, rhs)
+            | StrPrefixof(lhs, rhs) => {
                 lhs.subst_once_in_place(substs);
                 rhs.subst_once_in_place(substs);
             }
@@ -529,6 +566,11 @@ impl Exp {
                 then_exp.subst_once_in_place(substs);
                 else_exp.subst_once_in_place(substs)
             }
+            StrSubstr(s, offset, len) => {
+                s.subst_once_in_place(substs);
+                offset.subst_once_in_place(substs);
+                len.subst_once_in_place(substs)
+            }
             App(_, args) => {
                 for exp in args {
                     exp.subst_once_in_place(substs)
@@ -573,7 +615,8 @@ impl Exp {
             | Bvsge(_, _)
             | Bvugt(_, _)
             | Bvsgt(_, _)
-            | Distinct(_) => Some(Ty::Bool),
+            | Distinct(_)
+            | StrPrefixof(_, _) => Some(Ty::Bool),
             Bvnot(exp) | Bvneg(exp) => exp.infer(tcx, ftcx),
             Extract(i, j, _) => Some(Ty::BitVec((i - j) + 1)),
             ZeroExtend(ext, exp) | SignExtend(ext, exp) => match exp.infer(tcx, ftcx) {
@@ -608,6 +651,8 @@ impl Exp {
                 _ => None,
             },
             Store(array, _, _) => array.infer(tcx, ftcx),
+            String(_) | StrConcat(_, _) | StrSubstr(_, _, _) => Some(Ty::String),
+            StrLen(_) => Some(Ty::BitVec(128)),
         }
     }
 }
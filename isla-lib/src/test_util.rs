@@ -0,0 +1,79 @@
+// BSD 2-Clause License
+//
+// Copyright (c) 2020 Alasdair Armstrong
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+// notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+// notice, this list of conditions and the following disclaimer in the
+// documentation and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Fixtures shared by the unit tests in this crate.
+
+use crate::bitvector::b64::B64;
+use crate::config::ISAConfig;
+use crate::ir::{Def, Symtab};
+use crate::ir_parser;
+use crate::lexer::Lexer;
+
+/// Parse IR from a string, panicking if it is malformed.
+pub fn parse_ir(ir: &str) -> Vec<Def<String, B64>> {
+    ir_parser::IrParser::new().parse(Lexer::new(ir)).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// A minimal ISA config using `pc` as the program counter, and the
+/// given TOML inline table of default register values,
+/// e.g. `{ R = "0x03" }`.
+pub fn isa_config(symtab: &Symtab, pc: &str, defaults: &str) -> ISAConfig<B64> {
+    let toml = format!(
+        r#"
+            pc = "{pc}"
+            ifetch = "{pc}"
+            read_exclusives = []
+            write_exclusives = []
+            assembler = "as"
+            objdump = "objdump"
+            linker = "ld"
+            [mmu]
+            page_table_base = "0x300000"
+            page_size = "4096"
+            s2_page_table_base = "0x200000"
+            s2_page_size = "4096"
+            [threads]
+            base = "0x400000"
+            top = "0x500000"
+            stride = "0x10000"
+            [symbolic_addrs]
+            base = "0x1000000"
+            top = "0x2000000"
+            stride = "0x10000"
+            [reads]
+            [writes]
+            [cache_ops]
+            [registers]
+            defaults = {defaults}
+        "#,
+        pc = pc,
+        defaults = defaults
+    );
+    ISAConfig::parse(&toml, symtab).unwrap()
+}
@@ -0,0 +1,402 @@
+// BSD 2-Clause License
+//
+// Copyright (c) 2019, 2020 Alasdair Armstrong
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without
+// modification, are permitted provided that the following conditions are
+// met:
+//
+// 1. Redistributions of source code must retain the above copyright
+// notice, this list of conditions and the following disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright
+// notice, this list of conditions and the following disclaimer in the
+// documentation and/or other materials provided with the distribution.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT
+// HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT
+// LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE,
+// DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY
+// THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT
+// (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+// OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Memory watchpoints. A watchpoint is either a range of addresses
+//! or an SMT constraint on addresses, and every memory read or write
+//! which touches it produces a [WatchReport]. Reports are logged
+//! under the [crate::log::WATCH] flag as they happen, and recorded in
+//! the trace as [Event::Watch] events so they can be output with each
+//! path. Accesses at symbolic addresses are reported if they could
+//! touch the watchpoint.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use crate::bitvector::BV;
+use crate::error::ExecError;
+use crate::executor::{Backtrace, PathId};
+use crate::ir::source_loc::SourceLoc;
+use crate::ir::*;
+use crate::memory::{Address, DEFAULT_ADDRESS_WIDTH};
+use crate::smt::smtlib::{bits64, Exp, Ty};
+use crate::smt::{Event, Solver, Sym};
+use crate::smt_parser;
+use crate::zencode;
+
+#[derive(Clone, Debug)]
+pub enum WatchTarget {
+    Range(Range<Address>),
+    /// An SMT constraint in which `addr` stands for the address of
+    /// each byte accessed, e.g. `(bvult addr 0x0000000000001000)`.
+    /// Any literals must be as wide as addresses. The constraint is
+    /// parsed once for addresses of `width` bits, see
+    /// [Watchpoint::for_address_width].
+    Constraint {
+        source: String,
+        width: u32,
+        constraint: Exp,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct Watchpoint {
+    pub target: WatchTarget,
+    pub reads: bool,
+    pub writes: bool,
+}
+
+fn parse_address(addr: &str) -> Result<Address, String> {
+    if let Some(hex) = addr.strip_prefix("0x") { u64::from_str_radix(hex, 16) } else { addr.parse::<u64>() }
+        .map_err(|_| format!("Could not parse watchpoint address {}", addr))
+}
+
+/// The variable standing for `addr` in a parsed constraint, which
+/// is replaced by the address of each byte before it is used.
+fn addr_var() -> Sym {
+    Sym::from_u32(u32::MAX)
+}
+
+fn parse_constraint(source: &str, width: u32) -> Result<Exp, String> {
+    let mut lookup = |loc| match loc {
+        Loc::Id(id) if id == zencode::encode("addr") => Ok(Exp::Var(addr_var())),
+        _ => Err("Watchpoint constraints may only refer to addr".to_string()),
+    };
+    let constraint = smt_parser::ExpParser::new()
+        .parse(&mut lookup, source)
+        .map_err(|e| format!("Could not parse watchpoint constraint {}: {}", source, e))?;
+    let tcx: HashMap<Sym, Ty> = std::iter::once((addr_var(), Ty::BitVec(width))).collect();
+    match constraint.infer(&tcx, &HashMap::new()) {
+        Some(Ty::Bool) => Ok(constraint),
+        _ => Err(format!("Watchpoint constraint {} is not a boolean", source)),
+    }
+}
+
+/// A concrete address as an SMT bitvector of the given width, or
+/// `None` if it does not fit rather than truncating it.
+fn address_exp(address: Address, width: u32) -> Option<Exp> {
+    if width >= 64 || address >> width == 0 {
+        Some(bits64(address, width))
+    } else {
+        None
+    }
+}
+
+/// Watchpoints are written as `<address>[..<address>][:r|w|rw]`,
+/// where a single address watches one byte, or as
+/// `(<constraint>)[:r|w|rw]` where the constraint is over `addr` (see
+/// [WatchTarget::Constraint]). By default both reads and writes are
+/// watched.
+impl FromStr for Watchpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (target, access) = match s.rsplit_once(':') {
+            Some((target, access)) => (target, access),
+            None => (s, "rw"),
+        };
+        let (reads, writes) = match access {
+            "r" => (true, false),
+            "w" => (false, true),
+            "rw" => (true, true),
+            _ => return Err(format!("Watchpoint access must be r, w, or rw, not {}", access)),
+        };
+        if target.starts_with('(') {
+            let width = DEFAULT_ADDRESS_WIDTH;
+            let constraint = parse_constraint(target, width)?;
+            let target = WatchTarget::Constraint { source: target.to_string(), width, constraint };
+            return Ok(Watchpoint { target, reads, writes });
+        }
+        let range = match target.split_once("..") {
+            Some((start, end)) => parse_address(start)?..parse_address(end)?,
+            None => {
+                let addr = parse_address(target)?;
+                addr..(addr + 1)
+            }
+        };
+        if range.start >= range.end {
+            return Err(format!("Watchpoint range {} is empty", s));
+        }
+        Ok(Watchpoint { target: WatchTarget::Range(range), reads, writes })
+    }
+}
+
+impl Watchpoint {
+    /// Parse any constraint for addresses of the given width, which
+    /// should be the memory's address width. Constraints are parsed
+    /// for [DEFAULT_ADDRESS_WIDTH] bit addresses otherwise.
+    pub fn for_address_width(self, width: u32) -> Result<Self, String> {
+        match self.target {
+            WatchTarget::Constraint { source, .. } => {
+                let constraint = parse_constraint(&source, width)?;
+                Ok(Watchpoint { target: WatchTarget::Constraint { source, width, constraint }, ..self })
+            }
+            WatchTarget::Range(_) => Ok(self),
+        }
+    }
+
+    /// Whether the byte at an address of the given width is watched
+    fn watches_byte(&self, byte: Exp, width: u32) -> Result<Exp, ExecError> {
+        match &self.target {
+            WatchTarget::Range(range) => {
+                let above = match address_exp(range.start, width) {
+                    Some(start) => Exp::Bvuge(Box::new(byte.clone()), Box::new(start)),
+                    None => return Ok(Exp::Bool(false)),
+                };
+                match address_exp(range.end, width) {
+                    Some(end) => {
+                        let below = Exp::Bvult(Box::new(byte), Box::new(end));
+                        Ok(Exp::And(Box::new(above), Box::new(below)))
+                    }
+                    None => Ok(above),
+                }
+            }
+            WatchTarget::Constraint { source, width: constraint_width, constraint } => {
+                if *constraint_width != width {
+                    return Err(ExecError::Type(
+                        format!("Watchpoint constraint {} applied to a {} bit address", source, width),
+                        SourceLoc::unknown(),
+                    ));
+                }
+                let mut constraint = constraint.clone();
+                constraint.modify(&|exp: &mut Exp| {
+                    if matches!(exp, Exp::Var(v) if *v == addr_var()) {
+                        *exp = byte.clone()
+                    }
+                });
+                Ok(constraint)
+            }
+        }
+    }
+
+    /// Returns `Some(true)` if an access definitely touches the
+    /// watchpoint, `Some(false)` if it might, and `None` if it
+    /// cannot.
+    fn touches<B: BV>(&self, address: &Val<B>, bytes: u32, solver: &mut Solver<B>) -> Result<Option<bool>, ExecError> {
+        match (&self.target, address) {
+            (WatchTarget::Range(range), Val::Bits(address)) if address.len() <= 64 => {
+                let address = address.lower_u64();
+                let touches = address < range.end && address.saturating_add(u64::from(bytes)) > range.start;
+                Ok(if touches { Some(true) } else { None })
+            }
+            (_, Val::Bits(_)) | (_, Val::Symbolic(_)) => {
+                let width = crate::primop::length_bits(address, solver, SourceLoc::unknown())?;
+                let address = crate::primop::smt_value(address)?;
+                let touches = (0..u64::from(bytes))
+                    .map(|i| {
+                        let byte = Exp::Bvadd(Box::new(address.clone()), Box::new(bits64(i, width)));
+                        self.watches_byte(byte, width)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?
+                    .drain(..)
+                    .reduce(|lhs, rhs| Exp::Or(Box::new(lhs), Box::new(rhs)))
+                    .unwrap_or(Exp::Bool(false));
+                if !solver.is_sat_with(&touches, SourceLoc::unknown())? {
+                    Ok(None)
+                } else {
+                    let misses = Exp::Not(Box::new(touches));
+                    Ok(Some(!solver.is_sat_with(&misses, SourceLoc::unknown())?))
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WatchReport<B> {
+    /// The path the access was made on, at the point it was made
+    pub path: PathId,
+    /// The Sail backtrace, innermost function first
+    pub backtrace: Vec<String>,
+    pub write: bool,
+    /// The name of the read or write kind of the access
+    pub kind: String,
+    pub address: Val<B>,
+    pub bytes: u32,
+    /// The value read or written
+    pub value: Val<B>,
+    /// Whether the access definitely touches the watchpoint rather
+    /// than possibly touching it
+    pub definite: bool,
+}
+
+impl<B: BV> fmt::Display for WatchReport<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Watchpoint: path {} {} {} {} bytes at {:?}{}: {:?}",
+            self.path,
+            if self.write { "write" } else { "read" },
+            self.kind,
+            self.bytes,
+            self.address,
+            if self.definite { "" } else { " (possibly)" },
+            self.value
+        )?;
+        for function in &self.backtrace {
+            write!(f, "\n  in {}", function)?
+        }
+        Ok(())
+    }
+}
+
+/// Decode the member of an enumeration such as `read_kind` or
+/// `write_kind` into its name.
+fn kind_name<B: BV>(kind: &Val<B>, enum_name: &str, shared_state: &SharedState<B>) -> String {
+    if let Val::Enum(EnumMember { member, .. }) = kind {
+        let members = shared_state.symtab.get(&zencode::encode(enum_name)).and_then(|id| shared_state.enums.get(&id));
+        if let Some(members) = members {
+            for name in members {
+                if shared_state.enum_members.get(name).map(|(pos, _)| pos) == Some(member) {
+                    return zencode::decode(shared_state.symtab.to_str(*name));
+                }
+            }
+        }
+    }
+    kind.to_string(&shared_state.symtab)
+}
+
+/// Check a sequence of events, produced by a single step of
+/// execution, against the watchpoints in the shared state, returning
+/// a report for each event which touches a watchpoint.
+pub fn check_events<B: BV>(
+    events: &[Event<B>],
+    path: &PathId,
+    function_name: Name,
+    backtrace: &Backtrace,
+    shared_state: &SharedState<B>,
+    solver: &mut Solver<B>,
+) -> Result<Vec<WatchReport<B>>, ExecError> {
+    let mut reports = Vec::new();
+
+    for event in events {
+        let (write, kind, address, bytes, value) = match event {
+            Event::ReadMem { value, read_kind, address, bytes, .. } => {
+                (false, kind_name(read_kind, "read_kind", shared_state), address, *bytes, value.clone())
+            }
+            Event::WriteMem { write_kind, address, data, bytes, .. } => {
+                (true, kind_name(write_kind, "write_kind", shared_state), address, *bytes, data.clone())
+            }
+            _ => continue,
+        };
+
+        for watchpoint in &shared_state.watchpoints {
+            if (write && !watchpoint.writes) || (!write && !watchpoint.reads) {
+                continue;
+            }
+            if let Some(definite) = watchpoint.touches(address, bytes, solver)? {
+                let backtrace = std::iter::once(function_name)
+                    .chain(backtrace.iter().rev().map(|(f, _)| *f))
+                    .map(|f| zencode::decode(shared_state.symtab.to_str(f)))
+                    .collect();
+                reports.push(WatchReport {
+                    path: path.clone(),
+                    backtrace,
+                    write,
+                    kind,
+                    address: address.clone(),
+                    bytes,
+                    value,
+                    definite,
+                });
+                break;
+            }
+        }
+    }
+
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::bitvector::b64::B64;
+    use crate::smt::smtlib::{Def, Ty};
+    use crate::smt::{Config, Context};
+
+    fn is_range(watchpoint: Result<Watchpoint, String>, expected: Range<Address>, access: (bool, bool)) -> bool {
+        matches!(
+            watchpoint,
+            Ok(Watchpoint { target: WatchTarget::Range(range), reads, writes })
+                if range == expected && (reads, writes) == access
+        )
+    }
+
+    #[test]
+    fn parse_watchpoints() {
+        assert!(is_range("0x1000".parse(), 0x1000..0x1001, (true, true)));
+        assert!(is_range("0x1000..0x1008:w".parse(), 0x1000..0x1008, (false, true)));
+        assert!(is_range("16..32:r".parse(), 16..32, (true, false)));
+        assert!("0x1000:x".parse::<Watchpoint>().is_err());
+        assert!("0x1000..0x1000".parse::<Watchpoint>().is_err());
+
+        let constraint = "(bvult addr 0x0000000000001000)";
+        assert!(matches!(
+            format!("{}:r", constraint).parse(),
+            Ok(Watchpoint { target: WatchTarget::Constraint { source, width: 64, .. }, reads: true, writes: false })
+                if source == constraint
+        ));
+        assert!("(bvult x 0x0000000000001000)".parse::<Watchpoint>().is_err());
+        assert!("(bvadd addr 0x0000000000001000)".parse::<Watchpoint>().is_err())
+    }
+
+    #[test]
+    fn touches() {
+        let ctx = Context::new(Config::new());
+        let mut solver = Solver::<B64>::new(&ctx);
+
+        let watchpoint: Watchpoint = "0x1000..0x1008".parse().unwrap();
+        assert_eq!(watchpoint.touches(&Val::Bits(B64::new(0xFFC, 64)), 8, &mut solver).unwrap(), Some(true));
+        assert_eq!(watchpoint.touches(&Val::Bits(B64::new(0x1008, 64)), 8, &mut solver).unwrap(), None);
+
+        // Ranges which do not fit in the address are never touched, rather than being truncated
+        let watchpoint: Watchpoint = "0x100..0x108".parse().unwrap();
+        let addr = solver.declare_const(Ty::BitVec(8), SourceLoc::unknown());
+        assert_eq!(watchpoint.touches(&Val::Symbolic(addr), 1, &mut solver).unwrap(), None);
+
+        let watchpoint: Watchpoint = "(= (bvand addr 0x0000000000000fff) 0x0000000000000000):w".parse().unwrap();
+        assert_eq!(watchpoint.touches(&Val::Bits(B64::new(0x2000, 64)), 1, &mut solver).unwrap(), Some(true));
+        assert_eq!(watchpoint.touches(&Val::Bits(B64::new(0x2001, 64)), 1, &mut solver).unwrap(), None);
+
+        let addr = solver.declare_const(Ty::BitVec(64), SourceLoc::unknown());
+        assert_eq!(watchpoint.touches(&Val::Symbolic(addr), 4, &mut solver).unwrap(), Some(false));
+        solver.add(Def::Assert(Exp::Eq(Box::new(Exp::Var(addr)), Box::new(bits64(0x2FFE, 64)))));
+        assert_eq!(watchpoint.touches(&Val::Symbolic(addr), 4, &mut solver).unwrap(), Some(true));
+
+        // Constraints are parsed for the address width
+        let watchpoint: Watchpoint =
+            "(bvuge addr 0x8000)".parse::<Watchpoint>().unwrap().for_address_width(16).unwrap();
+        assert_eq!(watchpoint.touches(&Val::Bits(B64::new(0x8000, 16)), 1, &mut solver).unwrap(), Some(true));
+        assert_eq!(watchpoint.touches(&Val::Bits(B64::new(0x7000, 16)), 1, &mut solver).unwrap(), None);
+        assert!(watchpoint.touches(&Val::Bits(B64::new(0x8000, 64)), 1, &mut solver).is_err())
+    }
+}
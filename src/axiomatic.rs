@@ -48,21 +48,35 @@ use isla_axiomatic::page_table::{name_initial_walk_bitvectors, VirtualAddress};
 use isla_axiomatic::run_litmus;
 use isla_axiomatic::run_litmus::LitmusRunOpts;
 use isla_cat::cat;
-use isla_lib::bitvector::{BV, b64::B64};
+use isla_lib::bitvector::b129::B129;
+use isla_lib::bitvector::b64::B64;
+use isla_lib::bitvector::bn::{B2048, B256, B4096};
+use isla_lib::bitvector::BV;
 use isla_lib::config::ISAConfig;
-use isla_lib::init::{initialize_architecture, Initialized};
+use isla_lib::init::{initialize_architecture_with_primops, Initialized};
+use isla_lib::ir::optimize;
 use isla_lib::ir::*;
 use isla_lib::log;
+use isla_lib::primop::Primops;
 
 mod opts;
-use opts::CommonOpts;
+use opts::{Bits, CommonOpts};
+
+const DEFAULT_BITS: Bits = Bits::B64;
 
 use std::sync::atomic::{AtomicBool, Ordering};
 
 static FAILURE: AtomicBool = AtomicBool::new(false);
 
 fn main() {
-    let code = isla_main();
+    let code = match opts::bits(DEFAULT_BITS) {
+        Bits::B64 => isla_main::<B64>(),
+        Bits::B129 => isla_main::<B129>(),
+        Bits::B256 => isla_main::<B256>(),
+        Bits::B2048 => isla_main::<B2048>(),
+        Bits::B4096 => isla_main::<B4096>(),
+    };
+    opts::report_solver_statistics();
     unsafe { isla_lib::smt::finalize_solver() };
     process::exit(code)
 }
@@ -126,11 +140,12 @@ impl<'a, A> Iterator for GroupIndex<'a, A> {
     }
 }
 
-fn isla_main() -> i32 {
+fn isla_main<B: BV>() -> i32 {
     use AxResult::*;
     let now = Instant::now();
 
     let mut opts = opts::common_opts();
+    opts::bits_opt(&mut opts, DEFAULT_BITS);
     opts.optopt("t", "test", "A litmus test (.litmus or .toml), or a file containing a list of tests", "<path>");
     opts.optopt("", "footprint-config", "load custom config for footprint analysis", "<file>");
     opts.optopt("", "thread-groups", "number threads per group", "<n>");
@@ -141,6 +156,7 @@ fn isla_main() -> i32 {
     opts.optflag("", "armv8-page-tables", "Automatically set up ARMv8 page tables");
     opts.optflag("", "merge-translations", "Merge consecutive translate events into a single event");
     opts.optflag("e", "exhaustive", "Attempt to exhaustively enumerate all possible rf combinations");
+    opts.optflag("", "hide-output", "Do not print output from the Sail print builtins");
     opts.optmulti("", "extra-smt", "additional SMT appended to each candidate", "<file>");
     opts.optopt("", "check-sat-using", "Use z3 tactic for checking satisfiablity", "tactic");
     opts.optopt("", "latex", "generate latex version of input file", "<latex id>");
@@ -204,24 +220,33 @@ fn isla_main() -> i32 {
         "Open graphviz dot files in default image viewer. Implies --temp-dot unless --dot is set.",
     );
     opts.optopt("", "refs", "references to compare output with", "<path>");
-    opts.optopt(
-        "",
-        "cache",
-        "A directory to cache intermediate results. The default is TMPDIR if set, otherwise /tmp",
-        "<path>",
-    );
 
     let mut hasher = Sha256::new();
-    let (matches, orig_arch) = opts::parse::<B64>(&mut hasher, &opts);
+    let (matches, orig_arch) = opts::parse::<B>(&mut hasher, &opts);
+
+    // Litmus tests reset and initialise registers separately for each
+    // thread, so registers cannot be treated as constants
+    if let Some(passes) = matches.opt_str("optimize") {
+        if optimize::parse_passes(&passes).unwrap_or_default().contains(&optimize::Pass::ConstantPropagation) {
+            eprintln!("The const-prop optimisation cannot be used with litmus tests, as they set registers per thread");
+            process::exit(1)
+        }
+    }
+    if matches.opt_present("specialize") {
+        eprintln!("The --specialize option cannot be used with litmus tests, as they set registers per thread");
+        process::exit(1)
+    }
+
+    let primops = Primops::default();
     let CommonOpts { num_threads, mut arch, symtab, isa_config } =
-        opts::parse_with_arch(&mut hasher, &opts, &matches, &orig_arch);
+        opts::parse_with_arch(&mut hasher, &opts, &matches, &orig_arch, &primops);
 
     // Huge hack, just load an entirely separate copy of the architecture for footprint analysis
     let CommonOpts { num_threads: _, arch: mut farch, symtab: fsymtab, isa_config: _ } =
-        opts::parse_with_arch(&mut hasher, &opts, &matches, &orig_arch);
+        opts::parse_with_arch(&mut hasher, &opts, &matches, &orig_arch, &primops);
 
     let Initialized { regs, lets, shared_state } =
-        initialize_architecture(&mut arch, symtab, &isa_config, AssertionMode::Optimistic);
+        initialize_architecture_with_primops(&mut arch, symtab, &isa_config, AssertionMode::Optimistic, &primops);
 
     let footprint_config = if let Some(file) = matches.opt_str("footprint-config") {
         match ISAConfig::from_file(&mut hasher, file, &fsymtab) {
@@ -241,8 +266,13 @@ fn isla_main() -> i32 {
         &isa_config
     };
 
-    let Initialized { regs: fregs, lets: flets, shared_state: fshared_state } =
-        initialize_architecture(&mut farch, fsymtab, &footprint_config, AssertionMode::Optimistic);
+    let Initialized { regs: fregs, lets: flets, shared_state: fshared_state } = initialize_architecture_with_primops(
+        &mut farch,
+        fsymtab,
+        footprint_config,
+        AssertionMode::Optimistic,
+        &primops,
+    );
 
     let arch_hash = hasher.result();
     log!(log::VERBOSE, &format!("Archictecture + config hash: {:x}", arch_hash));
@@ -251,6 +281,7 @@ fn isla_main() -> i32 {
     let use_ifetch = matches.opt_present("ifetch");
     let armv8_page_tables = matches.opt_present("armv8-page-tables");
     let merge_translations = matches.opt_present("merge-translations");
+    let hide_output = matches.opt_present("hide-output");
 
     let graph_all_events = matches.opt_present("graph-show-all-trace-events");
     let compact = ! matches.opt_present("graph-fixed-layout");
@@ -425,7 +456,7 @@ fn isla_main() -> i32 {
                         fs::read_to_string(&litmus_file).expect("Failed to read test file")
                     };
 
-                    let litmus = match Litmus::parse(&litmus, symtab, isa_config) {
+                    let litmus = match Litmus::parse_in_directory(&litmus, litmus_file.parent(), symtab, isa_config) {
                         Ok(litmus) => litmus,
                         Err(msg) => {
                             eprintln!("Failed to parse litmus file: {}\n{}", litmus_file.display(), msg);
@@ -468,7 +499,7 @@ fn isla_main() -> i32 {
                         force_show_events: graph_force_show_events.map(|s| s.split(",").map(String::from).collect()),
                     };
 
-                    let run_info = run_litmus::smt_output_per_candidate::<B64, _, _, ()>(
+                    let run_info = run_litmus::smt_output_per_candidate::<B, _, _, ()>(
                         &format!("g{}t{}", group_id, i),
                         &opts,
                         &litmus,
@@ -486,6 +517,16 @@ fn isla_main() -> i32 {
                         check_sat_using,
                         cache,
                         &|exec, memory, all_addrs, footprints, z3_output| {
+                            if !hide_output {
+                                let stdout = std::io::stdout();
+                                let mut handle = stdout.lock();
+                                for (tid, output) in exec.thread_output.iter().enumerate() {
+                                    if !output.is_empty() {
+                                        write!(handle, "{} thread {} output:\n{}", litmus.name, tid, output).unwrap()
+                                    }
+                                }
+                            }
+
                             let mut names = HashMap::new();
 
                             // collect names from translation-table-walks for each VA
@@ -501,7 +542,7 @@ fn isla_main() -> i32 {
 
                             // collect names for each IPA/PA variable in the pagetable
                             for (name, val) in all_addrs {
-                                names.insert(B64::new(*val, 64), name.clone());
+                                names.insert(B::new(*val, 64), name.clone());
                             }
 
                             if z3_output.starts_with("sat") {
@@ -542,18 +583,21 @@ fn isla_main() -> i32 {
 
                     let ref_result = refs.get(&litmus.name);
 
-                    if let Err(msg) = run_info {
-                        println!("{:?}", msg);
-                        print_results(&litmus.name, now, &[Error(None, "".to_string())], ref_result);
-                        continue;
-                    }
+                    let run_info = match run_info {
+                        Ok(run_info) => run_info,
+                        Err(msg) => {
+                            println!("{:?}", msg);
+                            print_results(&litmus.name, now, &[Error(None, "".to_string())], ref_result, false);
+                            continue;
+                        }
+                    };
 
                     let mut results: Vec<AxResult> = Vec::new();
                     while let Ok(result) = result_queue.pop() {
                         results.push(result)
                     }
 
-                    print_results(&litmus.name, now, &results, ref_result);
+                    print_results(&litmus.name, now, &results, ref_result, run_info.assumed_unknown);
 
                     if let Some(dot_path) = dot_path {
                         for (i, allowed) in results.iter().enumerate() {
@@ -598,7 +642,13 @@ fn isla_main() -> i32 {
     }
 }
 
-fn print_results(name: &str, start_time: Instant, results: &[AxResult], expected: Option<&AxResult>) {
+fn print_results(
+    name: &str,
+    start_time: Instant,
+    results: &[AxResult],
+    expected: Option<&AxResult>,
+    assumed_unknown: bool,
+) {
     if results.is_empty() {
         let prefix = format!("{} no executions {}", name, start_time.elapsed().as_millis());
         println!("{:.<100} \x1b[95m\x1b[1merror\x1b[0m", prefix);
@@ -623,7 +673,10 @@ fn print_results(name: &str, start_time: Instant, results: &[AxResult], expected
         };
     }
 
-    let count = format!("{} of {}", results.iter().filter(|result| result.is_allowed()).count(), results.len());
+    let mut count = format!("{} of {}", results.iter().filter(|result| result.is_allowed()).count(), results.len());
+    if assumed_unknown {
+        count.push_str(", assuming unknown solver results")
+    }
 
     let prefix = if let Some(reference) = expected {
         format!(
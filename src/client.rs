@@ -37,17 +37,23 @@ use std::process::exit;
 use std::sync::Arc;
 
 use isla_axiomatic::litmus::assemble_instruction;
-use isla_lib::bitvector::{b64::B64, BV};
+use isla_lib::bitvector::b129::B129;
+use isla_lib::bitvector::b64::B64;
+use isla_lib::bitvector::bn::{B2048, B256, B4096};
+use isla_lib::bitvector::BV;
 use isla_lib::config::ISAConfig;
 use isla_lib::executor;
 use isla_lib::executor::{LocalFrame, TaskState};
-use isla_lib::init::{initialize_architecture, Initialized};
+use isla_lib::init::{initialize_architecture_with_primops, Initialized};
 use isla_lib::ir::*;
+use isla_lib::primop::Primops;
 use isla_lib::simplify::write_events;
 use isla_lib::smt::Event;
 
 mod opts;
-use opts::CommonOpts;
+use opts::{Bits, CommonOpts};
+
+const DEFAULT_BITS: Bits = Bits::B64;
 
 enum Answer<'a> {
     Error,
@@ -104,13 +110,13 @@ fn write_answer<W: Write>(writer: &mut W, message: Answer) -> std::io::Result<()
     }
 }
 
-fn execute_opcode(
+fn execute_opcode<B: BV>(
     stream: &mut UnixStream,
-    opcode: B64,
+    opcode: B,
     num_threads: usize,
-    shared_state: &SharedState<B64>,
-    register_state: &Bindings<B64>,
-    letbindings: &Bindings<B64>,
+    shared_state: &SharedState<B>,
+    register_state: &Bindings<B>,
+    letbindings: &Bindings<B>,
 ) -> std::io::Result<Result<(), String>> {
     let function_id = shared_state.symtab.lookup("zisla_client");
     let (args, _, instrs) = shared_state.functions.get(&function_id).unwrap();
@@ -138,7 +144,7 @@ fn execute_opcode(
         match queue.pop() {
             Ok(Ok((_, result, mut events))) => {
                 let mut buf = Vec::new();
-                let events: Vec<Event<B64>> = events.drain(..).rev().collect();
+                let events: Vec<Event<B>> = events.drain(..).rev().collect();
                 write_events(&mut buf, &events, &shared_state.symtab);
                 write_answer(stream, Answer::Trace(result, &buf))?;
             }
@@ -151,13 +157,13 @@ fn execute_opcode(
     })
 }
 
-fn interact(
+fn interact<B: BV>(
     stream: &mut UnixStream,
     num_threads: usize,
-    shared_state: &SharedState<B64>,
-    register_state: &Bindings<B64>,
-    letbindings: &Bindings<B64>,
-    isa_config: &ISAConfig<B64>,
+    shared_state: &SharedState<B>,
+    register_state: &Bindings<B>,
+    letbindings: &Bindings<B>,
+    isa_config: &ISAConfig<B>,
 ) -> std::io::Result<Result<(), String>> {
     Ok(loop {
         // The parsing done here should match IslaServer.string_of_request of ReadDwarf
@@ -177,7 +183,7 @@ fn interact(
             ["execute", instruction] => {
                 // Protocol : Send StartTraces then any number of Trace then StopTraces
                 if let Ok(opcode) = u32::from_str_radix(&instruction, 16) {
-                    let opcode = B64::from_u32(opcode);
+                    let opcode = B::from_u32(opcode);
                     match execute_opcode(stream, opcode, num_threads, shared_state, register_state, letbindings)? {
                         Ok(()) => continue,
                         Err(msg) => {
@@ -196,7 +202,7 @@ fn interact(
                 if let Ok(bytes) = assemble_instruction(&instruction, &isa_config) {
                     let mut opcode: [u8; 4] = Default::default();
                     opcode.copy_from_slice(&bytes);
-                    let opcode = B64::from_u32(u32::from_le_bytes(opcode));
+                    let opcode = B::from_u32(u32::from_le_bytes(opcode));
                     match execute_opcode(stream, opcode, num_threads, shared_state, register_state, letbindings)? {
                         Ok(()) => continue,
                         Err(msg) => {
@@ -216,22 +222,31 @@ fn interact(
 }
 
 fn main() {
-    let code = isla_main();
+    let code = match opts::bits(DEFAULT_BITS) {
+        Bits::B64 => isla_main::<B64>(),
+        Bits::B129 => isla_main::<B129>(),
+        Bits::B256 => isla_main::<B256>(),
+        Bits::B2048 => isla_main::<B2048>(),
+        Bits::B4096 => isla_main::<B4096>(),
+    };
+    opts::report_solver_statistics();
     unsafe { isla_lib::smt::finalize_solver() };
     exit(code)
 }
 
-fn isla_main() -> i32 {
+fn isla_main<B: BV>() -> i32 {
     let mut opts = opts::common_opts();
+    opts::bits_opt(&mut opts, DEFAULT_BITS);
     opts.reqopt("", "socket", "connect to server at location", "<path>");
 
     let mut hasher = Sha256::new();
-    let (matches, arch) = opts::parse(&mut hasher, &opts);
+    let (matches, arch) = opts::parse::<B>(&mut hasher, &opts);
+    let primops = Primops::default();
     let CommonOpts { num_threads, mut arch, symtab, isa_config } =
-        opts::parse_with_arch(&mut hasher, &opts, &matches, &arch);
+        opts::parse_with_arch(&mut hasher, &opts, &matches, &arch, &primops);
 
     let Initialized { regs, lets, shared_state } =
-        initialize_architecture(&mut arch, symtab, &isa_config, AssertionMode::Optimistic);
+        initialize_architecture_with_primops(&mut arch, symtab, &isa_config, AssertionMode::Optimistic, &primops);
 
     let socket_path = matches.opt_str("socket").unwrap();
     let mut stream = match UnixStream::connect(&socket_path) {
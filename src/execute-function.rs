@@ -39,14 +39,18 @@ use std::sync::Arc;
 use std::time::Instant;
 
 use isla_lib::bitvector::b129::B129;
+use isla_lib::bitvector::b64::B64;
+use isla_lib::bitvector::bn::{B2048, B256, B4096};
 use isla_lib::bitvector::BV;
 use isla_lib::error::ExecError;
 use isla_lib::executor;
 use isla_lib::executor::{reset_registers, Backtrace, LocalFrame, TaskState};
-use isla_lib::init::{initialize_architecture, Initialized};
+use isla_lib::init::{initialize_architecture_with_primops, Initialized};
 use isla_lib::ir::source_loc::SourceLoc;
 use isla_lib::ir::*;
 use isla_lib::lexer::Lexer;
+use isla_lib::memory::image;
+use isla_lib::primop::Primops;
 use isla_lib::smt;
 use isla_lib::smt::smtlib::Exp;
 use isla_lib::smt::{Event, Model, SmtResult, Solver};
@@ -56,10 +60,19 @@ use isla_lib::{log, log_from};
 use isla_lib::{simplify, simplify::WriteOpts};
 
 mod opts;
-use opts::CommonOpts;
+use opts::{Bits, CommonOpts};
+
+const DEFAULT_BITS: Bits = Bits::B129;
 
 fn main() {
-    let code = isla_main();
+    let code = match opts::bits(DEFAULT_BITS) {
+        Bits::B64 => isla_main::<B64>(),
+        Bits::B129 => isla_main::<B129>(),
+        Bits::B256 => isla_main::<B256>(),
+        Bits::B2048 => isla_main::<B2048>(),
+        Bits::B4096 => isla_main::<B4096>(),
+    };
+    opts::report_solver_statistics();
     unsafe { isla_lib::smt::finalize_solver() };
     exit(code)
 }
@@ -79,20 +92,26 @@ fn parse_function_names<B>(names: Vec<String>, shared_state: &SharedState<B>) ->
 }
 
 #[allow(clippy::mutex_atomic)]
-fn isla_main() -> i32 {
+fn isla_main<B: BV>() -> i32 {
     let mut opts = opts::common_opts();
+    opts::bits_opt(&mut opts, DEFAULT_BITS);
     opts.optopt("", "linear", "rewrite function into linear form", "<id>");
     opts.optflag("", "optimistic", "assume assertions succeed");
     opts.optflag("t", "traces", "print execution traces for successful executions");
     opts.optflag("", "error-traces", "print execution traces for paths that fail");
     opts.optflag("s", "simplify", "simplify function traces");
     opts.optflag("m", "model", "query SMT model to fill in variables");
+    opts.optflag("", "hide-output", "do not print output from the Sail print builtins");
+    opts.optmulti("", "load", "load a raw binary, Intel HEX, or S-record memory image", "<file>[@<address>]");
+    opts.optflag("", "sequential", "make writes to memory visible to later reads on the same path");
+    opts.optflag("", "array-memory", "represent memory as an SMT array, resolving symbolic addresses precisely");
     opts.optmulti("k", "stop-fn", "stop executions early if they reach this function", "<function name>");
 
     let mut hasher = Sha256::new();
-    let (matches, arch) = opts::parse::<B129>(&mut hasher, &opts);
+    let (matches, arch) = opts::parse::<B>(&mut hasher, &opts);
+    let primops = Primops::default();
     let CommonOpts { num_threads, mut arch, symtab, isa_config } =
-        opts::parse_with_arch(&mut hasher, &opts, &matches, &arch);
+        opts::parse_with_arch(&mut hasher, &opts, &matches, &arch, &primops);
 
     if matches.free.is_empty() {
         eprintln!("No function given");
@@ -104,7 +123,7 @@ fn isla_main() -> i32 {
         if matches.opt_present("optimistic") { AssertionMode::Optimistic } else { AssertionMode::Pessimistic };
 
     let Initialized { regs, lets, shared_state } =
-        initialize_architecture(&mut arch, symtab, &isa_config, assertion_mode);
+        initialize_architecture_with_primops(&mut arch, symtab, &isa_config, assertion_mode, &primops);
 
     let stop_functions = parse_function_names(matches.opt_strs("stop-fn"), &shared_state);
     let function_id = shared_state.symtab.lookup(&function_name);
@@ -145,6 +164,30 @@ fn isla_main() -> i32 {
     let task_state = TaskState::new();
 
     frame.add_lets(&lets).add_regs(&regs);
+    frame.memory_mut().set_address_width(isa_config.address_width);
+    frame.memory_mut().set_access_checks(isa_config.access_checks.clone());
+    for region in &isa_config.device_regions {
+        frame.memory_mut().add_region(region.clone())
+    }
+    for tag_region in &isa_config.tag_regions {
+        frame.memory_mut().add_tag_region(tag_region.clone())
+    }
+    for arg in matches.opt_strs("load") {
+        match image::load_image_argument(&arg) {
+            Ok(image) => frame.memory_mut().add_image(&image),
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1)
+            }
+        }
+    }
+    if matches.opt_present("sequential") {
+        frame.memory_mut().set_sequential(true);
+    }
+    if matches.opt_present("array-memory") {
+        let address_space = frame.memory().address_space();
+        frame.memory_mut().add_symbolic_array_region(address_space);
+    }
 
     // We don't call model initialisation in execute-function, so do register reset here.
     reset_registers(0, &mut frame, &task_state, &shared_state, &mut solver, SourceLoc::unknown())
@@ -156,20 +199,21 @@ fn isla_main() -> i32 {
     let traces = matches.opt_present("traces");
     let error_traces = matches.opt_present("error-traces");
     let models = matches.opt_present("model");
-    let collecting = Arc::new((SegQueue::new(), traces | error_traces, models));
+    let output = !matches.opt_present("hide-output");
+    let collecting = Arc::new((SegQueue::new(), traces | error_traces, models, output));
     let now = Instant::now();
     executor::start_multi(num_threads, None, vec![task], &shared_state, collecting.clone(), &model_collector);
 
     eprintln!("Execution took: {}ms", now.elapsed().as_millis());
 
-    let (queue, _, _) = collecting.as_ref();
+    let (queue, _, _, _) = collecting.as_ref();
 
     let write_events = |mut events, handle: &mut dyn Write| {
         if matches.opt_present("simplify") {
             simplify::hide_initialization(&mut events);
             simplify::remove_unused(&mut events);
         }
-        let events: Vec<Event<B129>> = events.drain(..).rev().collect();
+        let events: Vec<Event<B>> = events.drain(..).rev().collect();
         let write_opts = WriteOpts { define_enum: !matches.opt_present("simplify"), ..WriteOpts::default() };
         simplify::write_events_with_opts(handle, &events, &shared_state.symtab, &write_opts).unwrap();
     };
@@ -178,19 +222,25 @@ fn isla_main() -> i32 {
 
     loop {
         match queue.pop() {
-            Ok(Ok((_, result, events))) => {
+            Ok(Ok((_, result, assumed_unknown, output, events))) => {
                 let stdout = std::io::stdout();
                 let mut handle = stdout.lock();
-                writeln!(handle, "Result: {}", result.to_string(&shared_state.symtab)).unwrap();
+                write!(handle, "Result: {}", result.to_string(&shared_state.symtab)).unwrap();
+                if assumed_unknown {
+                    write!(handle, " (assuming unknown solver results)").unwrap();
+                }
+                writeln!(handle).unwrap();
+                write!(handle, "{}", output).unwrap();
                 if traces {
                     write_events(events, &mut handle);
                 }
             }
             // Error during execution
-            Ok(Err((msg, events))) => {
+            Ok(Err((msg, output, events))) => {
                 let stdout = std::io::stdout();
                 let mut handle = stdout.lock();
                 writeln!(handle, "{}", msg).unwrap();
+                write!(handle, "{}", output).unwrap();
                 if error_traces {
                     write_events(events, &mut handle);
                 }
@@ -219,17 +269,37 @@ fn concrete_value<B: BV>(model: &mut Model<B>, val: &Val<B>) -> Val<B> {
         Val::Symbolic(v) => match model.get_var(*v) {
             Ok(Some(Exp::Bits64(bv))) => Val::Bits(B::new(bv.lower_u64(), bv.len())),
             Ok(Some(Exp::Bits(bs))) => Val::Bits(bits_to_bv(&bs)),
+            Ok(Some(Exp::String(s))) => Val::String(s),
             _ => val.clone(),
         },
         Val::Vector(vec) => Val::Vector(vec.iter().map(|v| concrete_value(model, v)).collect()),
         Val::List(vec) => Val::List(vec.iter().map(|v| concrete_value(model, v)).collect()),
         Val::Struct(map) => Val::Struct(map.iter().map(|(k, v)| (*k, concrete_value(model, v))).collect()),
         Val::Ctor(n, v) => Val::Ctor(*n, Box::new(concrete_value(model, v))),
+        Val::SymbolicCtor(tag, ctors) => match model.get_var_completed(*tag) {
+            Ok(Some(Exp::Enum(e))) => {
+                let (n, v) = &ctors[e.member];
+                Val::Ctor(*n, Box::new(concrete_value(model, v)))
+            }
+            _ => val.clone(),
+        },
+        Val::SymbolicList(len, vec) => match model.get_var_completed(*len) {
+            Ok(Some(Exp::Bits64(len))) => {
+                // The head of the list is the last element of the vector
+                let skip = vec.len().saturating_sub(len.lower_u64() as usize);
+                Val::List(vec[skip..].iter().map(|v| concrete_value(model, v)).collect())
+            }
+            _ => val.clone(),
+        },
         _ => val.clone(),
     }
 }
 
-type AllTraceValueQueue<B> = SegQueue<Result<(usize, Val<B>, Vec<Event<B>>), (String, Vec<Event<B>>)>>;
+/// The boolean is true for paths where an unknown solver result was
+/// assumed to be sat or unsat. Each path also carries the rendered
+/// output from any Sail print builtins it called (empty if hidden).
+type AllTraceValueQueue<B> =
+    SegQueue<Result<(usize, Val<B>, bool, String, Vec<Event<B>>), (String, String, Vec<Event<B>>)>>;
 
 fn model_collector<'ir, B: BV>(
     tid: usize,
@@ -237,21 +307,32 @@ fn model_collector<'ir, B: BV>(
     result: Result<(Val<B>, LocalFrame<'ir, B>), (ExecError, Backtrace)>,
     shared_state: &SharedState<'ir, B>,
     mut solver: Solver<B>,
-    (collected, trace, models): &(AllTraceValueQueue<B>, bool, bool),
+    (collected, trace, models, output): &(AllTraceValueQueue<B>, bool, bool, bool),
 ) {
     let events: Vec<Event<B>> = if *trace { solver.trace().to_vec().drain(..).cloned().collect() } else { vec![] };
+    let assumed_unknown = solver.trace().to_vec().iter().any(|event| event.is_assume_unknown());
+    let prints: Vec<Event<B>> = if *output {
+        solver.trace().to_vec().drain(..).rev().filter(|event| event.is_print()).cloned().collect()
+    } else {
+        vec![]
+    };
+    let render = |model: Option<&mut Model<B>>| simplify::render_output(&prints, &shared_state.symtab, model);
     match result {
-        Ok((val, _)) => {
-            if solver.check_sat() == SmtResult::Sat {
-                let val = if *models {
+        Ok((val, frame)) => {
+            if solver.check_sat(frame.source_loc()) == SmtResult::Sat {
+                let (val, output) = if *models {
                     let mut model = Model::new(&solver);
-                    concrete_value(&mut model, &val)
+                    (concrete_value(&mut model, &val), render(Some(&mut model)))
                 } else {
-                    val
+                    (val, render(None))
                 };
-                collected.push(Ok((task_id, val, events)))
+                collected.push(Ok((task_id, val, assumed_unknown, output, events)))
             } else {
-                collected.push(Err((format!("Got value {} but unsat?", val.to_string(&shared_state.symtab)), events)))
+                collected.push(Err((
+                    format!("Got value {} but unsat?", val.to_string(&shared_state.symtab)),
+                    render(None),
+                    events,
+                )))
             }
         }
         Err((ExecError::Dead, _)) => (),
@@ -260,11 +341,12 @@ fn model_collector<'ir, B: BV>(
             for (f, pc) in backtrace.iter().rev() {
                 log_from!(tid, log::VERBOSE, format!("  {} @ {}", shared_state.symtab.to_str(*f), pc));
             }
-            if solver.check_sat() == SmtResult::Sat {
-                let model = Model::new(&solver);
-                collected.push(Err((format!("Error {:?}\n{:?}", err, model), events)))
+            if solver.check_sat(err.source_loc()) == SmtResult::Sat {
+                let mut model = Model::new(&solver);
+                let output = if *models { render(Some(&mut model)) } else { render(None) };
+                collected.push(Err((format!("Error {:?}\n{:?}", err, model), output, events)))
             } else {
-                collected.push(Err((format!("Error {:?}\nno model", err), events)))
+                collected.push(Err((format!("Error {:?}\nno model", err), render(None), events)))
             }
         }
     }
@@ -42,13 +42,17 @@ use isla_axiomatic::footprint_analysis::footprint_analysis;
 use isla_axiomatic::litmus::assemble_instruction;
 use isla_axiomatic::page_table;
 use isla_axiomatic::page_table::setup::PageTableSetup;
-use isla_lib::bitvector::{b129::B129, BV};
+use isla_lib::bitvector::b129::B129;
+use isla_lib::bitvector::b64::B64;
+use isla_lib::bitvector::bn::{B2048, B256, B4096};
+use isla_lib::bitvector::BV;
 use isla_lib::executor;
 use isla_lib::executor::{LocalFrame, TaskState};
-use isla_lib::init::{initialize_architecture, Initialized};
+use isla_lib::init::{initialize_architecture_with_primops, Initialized};
 use isla_lib::ir::source_loc::SourceLoc;
 use isla_lib::ir::*;
-use isla_lib::memory::Memory;
+use isla_lib::memory::{image, Memory};
+use isla_lib::primop::Primops;
 use isla_lib::simplify;
 use isla_lib::simplify::{EventTree, WriteOpts};
 use isla_lib::smt;
@@ -57,10 +61,19 @@ use isla_lib::smt_parser;
 use isla_lib::zencode;
 
 mod opts;
-use opts::CommonOpts;
+use opts::{Bits, CommonOpts};
+
+const DEFAULT_BITS: Bits = Bits::B129;
 
 fn main() {
-    let code = isla_main();
+    let code = match opts::bits(DEFAULT_BITS) {
+        Bits::B64 => isla_main::<B64>(),
+        Bits::B129 => isla_main::<B129>(),
+        Bits::B256 => isla_main::<B256>(),
+        Bits::B2048 => isla_main::<B2048>(),
+        Bits::B4096 => isla_main::<B4096>(),
+    };
+    opts::report_solver_statistics();
     unsafe { isla_lib::smt::finalize_solver() };
     exit(code)
 }
@@ -70,21 +83,21 @@ pub fn hex_bytes(s: &str) -> Result<Vec<u8>, std::num::ParseIntError> {
 }
 
 #[derive(Clone, Debug)]
-enum InstructionSegment {
-    Concrete(B129),
+enum InstructionSegment<B> {
+    Concrete(B),
     Symbolic(String, u32),
 }
 
-impl std::fmt::Display for InstructionSegment {
+impl<B: BV> std::fmt::Display for InstructionSegment<B> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            InstructionSegment::Concrete(bv) => bv.fmt(f),
+            InstructionSegment::Concrete(bv) => std::fmt::Display::fmt(bv, f),
             InstructionSegment::Symbolic(s, _) => s.fmt(f),
         }
     }
 }
 
-fn instruction_to_string(opcode: &[InstructionSegment]) -> String {
+fn instruction_to_string<B: BV>(opcode: &[InstructionSegment<B>]) -> String {
     let mut s = "".to_string();
     for seg in opcode {
         s += &format!("{} ", seg);
@@ -92,7 +105,7 @@ fn instruction_to_string(opcode: &[InstructionSegment]) -> String {
     s
 }
 
-fn instruction_to_val(opcode: &[InstructionSegment], matches: &Matches, solver: &mut Solver<B129>) -> Val<B129> {
+fn instruction_to_val<B: BV>(opcode: &[InstructionSegment<B>], matches: &Matches, solver: &mut Solver<B>) -> Val<B> {
     match opcode {
         [InstructionSegment::Concrete(bv)] => Val::Bits(*bv),
         _ => {
@@ -138,7 +151,7 @@ fn instruction_to_val(opcode: &[InstructionSegment], matches: &Matches, solver:
     }
 }
 
-fn opcode_bytes(opcode: Vec<u8>, little_endian: bool) -> B129 {
+fn opcode_bytes<B: BV>(opcode: Vec<u8>, little_endian: bool) -> B {
     if opcode.len() > 8 {
         eprintln!("Currently instructions greater than 8 bytes in length are not supported");
         exit(1);
@@ -146,17 +159,18 @@ fn opcode_bytes(opcode: Vec<u8>, little_endian: bool) -> B129 {
 
     if opcode.len() == 2 {
         let opcode: Box<[u8; 2]> = opcode.into_boxed_slice().try_into().unwrap();
-        B129::from_u16(if little_endian { u16::from_le_bytes(*opcode) } else { u16::from_be_bytes(*opcode) })
+        B::from_u16(if little_endian { u16::from_le_bytes(*opcode) } else { u16::from_be_bytes(*opcode) })
     } else if opcode.len() == 4 {
         let opcode: Box<[u8; 4]> = opcode.into_boxed_slice().try_into().unwrap();
-        B129::from_u32(if little_endian { u32::from_le_bytes(*opcode) } else { u32::from_be_bytes(*opcode) })
+        B::from_u32(if little_endian { u32::from_le_bytes(*opcode) } else { u32::from_be_bytes(*opcode) })
     } else {
-        B129::from_bytes(&opcode)
+        B::from_bytes(&opcode)
     }
 }
 
-fn isla_main() -> i32 {
+fn isla_main<B: BV>() -> i32 {
     let mut opts = opts::common_opts();
+    opts::bits_opt(&mut opts, DEFAULT_BITS);
     opts.reqopt("i", "instruction", "display footprint of instruction", "<instruction>");
     opts.optopt("e", "endianness", "instruction encoding endianness (default: little)", "big/little");
     opts.optflag("d", "dependency", "view instruction dependency info");
@@ -166,23 +180,26 @@ fn isla_main() -> i32 {
     opts.optflag("t", "tree", "combine traces into tree");
     opts.optopt("f", "function", "use a custom footprint function", "<identifer>");
     opts.optflag("c", "continue-on-error", "continue generating traces upon encountering an error");
+    opts.optflag("", "hide-output", "do not print output from the Sail print builtins");
     opts.optopt("", "source", "Sail source code directory for .ir file", "<path>");
     opts.optopt("", "armv8-page-tables", "set up page tables with provided constraints", "<constraints>");
     opts.optflag("", "create-memory-regions", "create default memory regions");
+    opts.optmulti("", "load", "load a raw binary, Intel HEX, or S-record memory image", "<file>[@<address>]");
     opts.optflag("", "partial", "parse instruction as binary with unknown bits");
     opts.optmulti("", "instruction-constraint", "add constraint on variables in a partial instruction", "<constraint>");
 
     let mut hasher = Sha256::new();
-    let (matches, arch) = opts::parse(&mut hasher, &opts);
+    let (matches, arch) = opts::parse::<B>(&mut hasher, &opts);
     if !matches.free.is_empty() {
         eprintln!("Unexpected arguments: {}", matches.free.join(" "));
         exit(1)
     }
+    let primops = Primops::default();
     let CommonOpts { num_threads, mut arch, symtab, isa_config } =
-        opts::parse_with_arch(&mut hasher, &opts, &matches, &arch);
+        opts::parse_with_arch(&mut hasher, &opts, &matches, &arch, &primops);
 
     let Initialized { regs, lets, shared_state } =
-        initialize_architecture(&mut arch, symtab, &isa_config, AssertionMode::Optimistic);
+        initialize_architecture_with_primops(&mut arch, symtab, &isa_config, AssertionMode::Optimistic, &primops);
 
     let little_endian = match matches.opt_str("endianness").as_deref() {
         Some("little") | None => true,
@@ -195,11 +212,11 @@ fn isla_main() -> i32 {
 
     let instruction = matches.opt_str("instruction").unwrap();
 
-    let opcode: Vec<InstructionSegment> = if matches.opt_present("partial") {
+    let opcode: Vec<InstructionSegment<B>> = if matches.opt_present("partial") {
         instruction
             .split_ascii_whitespace()
             .map(|s| {
-                B129::from_str(&format!("0b{}", s))
+                B::from_str(&format!("0b{}", s))
                     .map(InstructionSegment::Concrete)
                     .or_else(|| {
                         let mut it = s.split(':');
@@ -236,6 +253,23 @@ fn isla_main() -> i32 {
     eprintln!("opcode: {}", instruction_to_string(&opcode));
 
     let mut memory = Memory::new();
+    memory.set_address_width(isa_config.address_width);
+    memory.set_access_checks(isa_config.access_checks.clone());
+    for region in &isa_config.device_regions {
+        memory.add_region(region.clone())
+    }
+    for tag_region in &isa_config.tag_regions {
+        memory.add_tag_region(tag_region.clone())
+    }
+    for arg in matches.opt_strs("load") {
+        match image::load_image_argument(&arg) {
+            Ok(image) => memory.add_image(&image),
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1)
+            }
+        }
+    }
 
     let PageTableSetup { memory_checkpoint, .. } = if let Some(setup) = matches.opt_str("armv8-page-tables") {
         let lexer = page_table::setup_lexer::SetupLexer::new(&setup);
@@ -291,12 +325,23 @@ fn isla_main() -> i32 {
 
     let mut paths = Vec::new();
     let rk_ifetch = shared_state.enum_member(isa_config.ifetch_read_kind).expect("Invalid ifetch read kind");
-    let mut evtree: Option<EventTree<B129>> = None;
+    let mut evtree: Option<EventTree<B>> = None;
+    let mut assumed_unknown_paths = 0;
 
     loop {
-        match queue.pop() {
+        let result = queue.pop();
+        if let Ok(Ok((_, events))) = &result {
+            if events.iter().any(|ev| ev.is_assume_unknown()) {
+                assumed_unknown_paths += 1
+            }
+            if !matches.opt_present("hide-output") {
+                let prints: Vec<&Event<B>> = events.iter().rev().filter(|ev| ev.is_print()).collect();
+                print!("{}", simplify::render_output(&prints, &shared_state.symtab, None))
+            }
+        }
+        match result {
             Ok(Ok((_, mut events))) if matches.opt_present("dependency") => {
-                let mut events: EvPath<B129> = events
+                let mut events: EvPath<B> = events
                     .drain(..)
                     .rev()
                     .filter(|ev| {
@@ -312,7 +357,7 @@ fn isla_main() -> i32 {
                 paths.push(events)
             }
             Ok(Ok((_, mut events))) if matches.opt_present("tree") => {
-                let events: Vec<Event<B129>> = events.drain(..).rev().collect();
+                let events: Vec<Event<B>> = events.drain(..).rev().collect();
                 if let Some(ref mut evtree) = evtree {
                     evtree.add_events(&events)
                 } else {
@@ -320,6 +365,7 @@ fn isla_main() -> i32 {
                 }
             }
             Ok(Ok((_, mut events))) => {
+                let assumed_unknown = events.iter().any(|ev| ev.is_assume_unknown());
                 if matches.opt_present("simplify") {
                     simplify::hide_initialization(&mut events);
                     if matches.opt_present("simplify-registers") {
@@ -331,9 +377,12 @@ fn isla_main() -> i32 {
                     simplify::commute_extract(&mut events);
                     simplify::eval(&mut events);
                 }
-                let events: Vec<Event<B129>> = events.drain(..).rev().collect();
+                let events: Vec<Event<B>> = events.drain(..).rev().collect();
                 let stdout = std::io::stdout();
                 let mut handle = stdout.lock();
+                if assumed_unknown {
+                    writeln!(handle, "; assuming unknown solver results").unwrap()
+                }
                 let write_opts = WriteOpts {
                     define_enum: !matches.opt_present("simplify"),
                     source_directory: matches.opt_str("source").map(PathBuf::from),
@@ -353,6 +402,10 @@ fn isla_main() -> i32 {
         }
     }
 
+    if assumed_unknown_paths > 0 {
+        eprintln!("{} paths assumed unknown solver results", assumed_unknown_paths)
+    }
+
     if matches.opt_present("tree") {
         if let Some(ref evtree) = evtree {
             let stdout = std::io::stdout();
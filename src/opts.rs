@@ -30,23 +30,33 @@
 
 use getopts::{Matches, Options};
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
-use std::fs::File;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
 use std::io::prelude::*;
+use std::path::PathBuf;
 use std::process::exit;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use isla_lib::bitvector::BV;
 use isla_lib::config::ISAConfig;
 use isla_lib::ir;
+use isla_lib::ir::checker;
 use isla_lib::ir::linearize;
+use isla_lib::ir::optimize;
+use isla_lib::ir::pretty;
+use isla_lib::ir::specialize;
 use isla_lib::ir::*;
 use isla_lib::ir_parser;
 use isla_lib::lexer;
 use isla_lib::log;
+use isla_lib::primop::Primops;
+use isla_lib::smt;
 use isla_lib::smt::smtlib;
 use isla_lib::smt_parser;
 use isla_lib::value_parser;
+use isla_lib::watch::Watchpoint;
 use isla_lib::zencode;
 
 fn tool_name() -> Option<String> {
@@ -56,6 +66,18 @@ fn tool_name() -> Option<String> {
     }
 }
 
+/// Set by the --solver-stats flag
+static SOLVER_STATS: AtomicBool = AtomicBool::new(false);
+
+/// Print the combined statistics for every SMT solver query made by
+/// the tool, if requested with --solver-stats. Should be called just
+/// before the tool exits.
+pub fn report_solver_statistics() {
+    if SOLVER_STATS.load(Ordering::Relaxed) {
+        eprintln!("Solver statistics: {}", smt::total_statistics())
+    }
+}
+
 pub fn print_usage(opts: &Options, code: i32) -> ! {
     let tool = match tool_name() {
         Some(name) => name,
@@ -66,21 +88,112 @@ pub fn print_usage(opts: &Options, code: i32) -> ! {
     exit(code)
 }
 
+/// The concrete bitvector representations that can be chosen with
+/// the `--bits` option. See [isla_lib::bitvector] for details.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Bits {
+    B64,
+    B129,
+    B256,
+    B2048,
+    B4096,
+}
+
+/// Add the `--bits` option, for tools which can use any concrete
+/// bitvector representation.
+pub fn bits_opt(opts: &mut Options, default: Bits) {
+    let default = match default {
+        Bits::B64 => "64",
+        Bits::B129 => "129",
+        Bits::B256 => "256",
+        Bits::B2048 => "2048",
+        Bits::B4096 => "4096",
+    };
+    opts.optopt(
+        "",
+        "bits",
+        &format!("maximum width of concrete bitvectors: 64, 129, 256, 2048, or 4096 (default {})", default),
+        "<width>",
+    );
+}
+
+/// Returns the bitvector representation chosen with `--bits`. As this
+/// determines the type used to parse the rest of the options, it is
+/// found before they are parsed.
+pub fn bits(default: Bits) -> Bits {
+    let args: Vec<String> = std::env::args().collect();
+    let mut width = None;
+    for (i, arg) in args.iter().enumerate() {
+        if arg == "--" {
+            break;
+        } else if arg == "--bits" {
+            width = args.get(i + 1).cloned()
+        } else if let Some(w) = arg.strip_prefix("--bits=") {
+            width = Some(w.to_string())
+        }
+    }
+    match width.as_deref() {
+        None => default,
+        Some("64") => Bits::B64,
+        Some("129") => Bits::B129,
+        Some("256") => Bits::B256,
+        Some("2048") => Bits::B2048,
+        Some("4096") => Bits::B4096,
+        Some(width) => {
+            eprintln!("Could not parse --bits option: {} (expected 64, 129, 256, 2048, or 4096)", width);
+            exit(1)
+        }
+    }
+}
+
 pub fn common_opts() -> Options {
     let mut opts = Options::new();
     opts.optopt("T", "threads", "use this many worker threads", "<n>");
     opts.reqopt("A", "arch", "load architecture file", "<file>");
     opts.optopt("C", "config", "load custom config for architecture", "<file>");
+    opts.optflag("", "no-check-ir", "skip the static checks on the architecture IR");
     opts.optmulti("R", "register", "set a register, via the reset_registers builtin", "<register>=<value>");
     opts.optmulti("I", "initial", "set a register in the initial state", "<register>=<value>");
     opts.optflag("h", "help", "print this help message");
     opts.optflag("", "verbose", "print verbose output");
     opts.optopt("D", "debug", "set debugging flags", "<flags>");
     opts.optmulti("", "probe", "trace specified function calls or location assignments", "<id>");
+    opts.optmulti(
+        "",
+        "watch",
+        "report memory reads (r) and writes (w) in a range, or satisfying a constraint on addr",
+        "<start>[..<end>][:r|w|rw] or (<constraint>)[:r|w|rw]",
+    );
     opts.optmulti("L", "linearize", "rewrite function into linear form", "<id>");
     opts.optflag("", "test-linearize", "test that linearization rewrite has been performed correctly");
+    opts.optflag("", "linearize-auto", "automatically linearize suitable functions");
+    opts.optflag(
+        "",
+        "no-test-linearize-auto",
+        "skip testing that automatically linearized functions are equivalent to the originals",
+    );
+    opts.optopt("", "unroll", "maximum number of loop iterations to unroll when linearizing", "<n>");
+    opts.optflag("", "specialize", "partially evaluate the architecture using the config register defaults");
+    opts.optopt(
+        "",
+        "cache",
+        "A directory to cache intermediate results. The default is TMPDIR if set, otherwise /tmp",
+        "<path>",
+    );
+    opts.optopt("O", "optimize", "optimise the IR (inline, const-prop, dead-code, or all)", "<passes>");
+    opts.optflag("", "test-optimize", "test that optimisation passes preserve function behaviour");
+    opts.optopt("", "dump-ir", "write the IR to a file after any rewrites, such as --linearize", "<file>");
     opts.optmulti("", "debug-id", "print the name of an interned identifier (for debugging)", "<name id>");
     opts.optmulti("", "reset-constraint", "property to enforce at the reset_registers builtin", "<constraint>");
+    opts.optopt("", "solver-timeout", "timeout for each SMT solver query", "<milliseconds>");
+    opts.optopt("", "solver-rlimit", "resource limit for each SMT solver query", "<n>");
+    opts.optopt("", "solver-memory", "maximum memory used by the SMT solver", "<megabytes>");
+    opts.optopt("", "unknown", "treat unknown SMT solver results as an error (default), sat, or unsat", "<policy>");
+    opts.optopt("", "dump-queries", "write slow SMT solver queries to files in a directory", "<directory>");
+    opts.optopt("", "dump-threshold", "minimum time for a query to be written by --dump-queries", "<milliseconds>");
+    opts.optopt("", "length-cases", "fork on symbolic bitvector lengths with at most this many values", "<n>");
+    opts.optflag("", "symbolic-strings", "allow symbolic strings using the SMT string theory");
+    opts.optflag("", "solver-stats", "print statistics about the SMT solver queries before exiting");
     opts
 }
 
@@ -143,13 +256,16 @@ pub fn parse<B>(hasher: &mut Sha256, opts: &Options) -> (Matches, Vec<Def<String
         print_usage(opts, 0)
     }
 
+    SOLVER_STATS.store(matches.opt_present("solver-stats"), Ordering::Relaxed);
+
     let debug_opts = matches.opt_str("debug").unwrap_or_else(|| "".to_string());
     let logging_flags = (if matches.opt_present("verbose") { log::VERBOSE } else { 0u32 })
         | (if debug_opts.contains('f') { log::FORK } else { 0u32 })
         | (if debug_opts.contains('m') { log::MEMORY } else { 0u32 })
         | (if debug_opts.contains('l') { log::LITMUS } else { 0u32 })
         | (if debug_opts.contains('g') { log::GRAPH } else { 0u32 })
-        | (if debug_opts.contains('p') { log::PROBE } else { 0u32 });
+        | (if debug_opts.contains('p') { log::PROBE } else { 0u32 })
+        | (if debug_opts.contains('w') || matches.opt_present("watch") { log::WATCH } else { 0u32 });
     log::set_flags(logging_flags);
 
     let arch = {
@@ -193,11 +309,15 @@ pub fn reset_from_string<B: BV>(arg: String, symtab: &Symtab) -> (Loc<Name>, Res
     )
 }
 
+/// Apply the common options to the architecture. Any user-defined
+/// primitives the tool registers in `primops` should be passed here,
+/// so the linearizer and optimiser treat calls to them correctly.
 pub fn parse_with_arch<'ir, B: BV>(
     hasher: &mut Sha256,
     opts: &Options,
     matches: &Matches,
     arch: &'ir [Def<String, B>],
+    primops: &Primops<B>,
 ) -> CommonOpts<'ir, B> {
     let num_threads = match matches.opt_get_default("threads", num_cpus::get()) {
         Ok(t) => t,
@@ -210,6 +330,21 @@ pub fn parse_with_arch<'ir, B: BV>(
     let mut symtab = Symtab::new();
     let mut arch = symtab.intern_defs(&arch);
 
+    // Problems found by the checker might be in code that is never
+    // executed, so they are reported as warnings rather than errors.
+    if !matches.opt_present("no-check-ir") {
+        let errors = checker::check_defs(&arch, &symtab);
+        if !errors.is_empty() {
+            for error in &errors {
+                eprintln!("Warning: {}", error.describe(&symtab))
+            }
+            eprintln!(
+                "Warning: found {} problems in the architecture (use --no-check-ir to skip these checks)",
+                errors.len()
+            )
+        }
+    }
+
     let mut isa_config = if let Some(file) = matches.opt_str("config") {
         match ISAConfig::from_file(hasher, file, &symtab) {
             Ok(isa_config) => isa_config,
@@ -242,6 +377,16 @@ pub fn parse_with_arch<'ir, B: BV>(
         }
     });
 
+    matches.opt_strs("watch").iter().for_each(|arg| {
+        match arg.parse::<Watchpoint>().and_then(|watchpoint| watchpoint.for_address_width(isa_config.address_width)) {
+            Ok(watchpoint) => isa_config.watchpoints.push(watchpoint),
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1)
+            }
+        }
+    });
+
     // Sometimes our debug output prints interned identifiers which
     // are just wrapped u32 numbers (as the code printing may not have
     // access to the symbol table). This flag allows us to print their
@@ -279,72 +424,175 @@ pub fn parse_with_arch<'ir, B: BV>(
         }
     });
 
-    #[rustfmt::skip]
+    // Functions which are probed, traced, or where execution should
+    // stop (the -k option of isla-execute-function) must still be
+    // called, so they are never specialized away, linearized
+    // automatically, or inlined.
+    let mut keep: HashSet<Name> = isa_config.probes.union(&isa_config.trace_functions).copied().collect();
+    if matches.opt_defined("stop-fn") {
+        for f in matches.opt_strs("stop-fn") {
+            if let Some(id) = symtab.get(&zencode::encode(&f)).or_else(|| symtab.get(&f)) {
+                keep.insert(id);
+            }
+        }
+    }
+
+    if matches.opt_present("specialize") {
+        let cache = matches.opt_str("cache").map(PathBuf::from).unwrap_or_else(std::env::temp_dir);
+        if let Err(e) = fs::create_dir_all(&cache) {
+            eprintln!("Failed to create cache directory {}: {}", cache.display(), e);
+            exit(1)
+        }
+        specialize::specialize_cached(&mut arch, &isa_config, &keep, hasher, &cache)
+    }
+
+    let unroll_bound = match matches.opt_get_default("unroll", linearize::DEFAULT_UNROLL_BOUND) {
+        Ok(bound) => bound,
+        Err(f) => {
+            eprintln!("Could not parse --unroll option: {}", f);
+            print_usage(opts, 1)
+        }
+    };
+
+    let mut linearize_targets: Vec<(Name, bool)> = Vec::new();
     matches.opt_strs("linearize").iter().for_each(|id| {
-        if let Some(target) = symtab.get(&zencode::encode(&id)) {
-            let mut arg_tys: Option<&[Ty<Name>]> = None;
-            let mut ret_ty: Option<&Ty<Name>> = None;
- 
-            let mut rewrites = HashMap::new();
-
-            for def in arch.iter() {
-                match def {
-                    Def::Val(f, tys, ty) if *f == target => {
-                        arg_tys = Some(tys);
-                        ret_ty = Some(ty)
-                    }
- 
-                    Def::Fn(f, args, body) if *f == target => {
-                        if let (Some(arg_tys), Some(ret_ty)) = (arg_tys, ret_ty) {
-                            let rewritten_body = linearize::linearize(body.to_vec(), &Ty::Bool, &mut symtab);
- 
-                            if matches.opt_present("test-linearize") {
-                                let success = linearize::self_test(
-                                    num_threads,
-                                    arch.clone(),
-                                    symtab.clone(),
-                                    &isa_config,
-                                    args,
-                                    arg_tys,
-                                    ret_ty,
-                                    body.to_vec(),
-                                    rewritten_body.to_vec()
-                                );
-                                if success {
-                                    log!(log::VERBOSE, &format!("Successfully proved linearization of {} equivalent", id))
-                                } else {
-                                    eprintln!("Failed to linearize {}", id);
-                                    exit(1)
-                                }
-                            }
- 
-                            rewrites.insert(*f, rewritten_body);
-                        } else {
-                            eprintln!("Found function body before type signature when processing -L/--linearize option for function {}", id);
-                            exit(1)
-                        }
-                    }
- 
-                    _ => (),
-                }
+        if let Some(target) = symtab.get(&zencode::encode(id)) {
+            linearize_targets.push((target, true))
+        } else {
+            eprintln!("Function {} could not be found when processing -L/--linearize option", id)
+        }
+    });
+    if matches.opt_present("linearize-auto") {
+        for target in linearize::candidates(&arch, &keep, primops) {
+            if !linearize_targets.iter().any(|(f, _)| *f == target) {
+                linearize_targets.push((target, false))
             }
+        }
+    }
 
-            for def in arch.iter_mut() {
-                if let Def::Fn(f, _, body) = def {
-                    if *f == target {
-                        *body = rewrites.remove(f).unwrap()
+    // Functions selected explicitly with -L must be linearized
+    // successfully, whereas automatically chosen functions are
+    // skipped if they cannot be. Automatically chosen functions are
+    // always checked for equivalence unless --no-test-linearize-auto
+    // is given.
+    let mut rewrites = HashMap::new();
+    for (target, explicit) in linearize_targets {
+        let id = zencode::decode(symtab.to_str(target));
+        let test = if explicit {
+            matches.opt_present("test-linearize")
+        } else {
+            !matches.opt_present("no-test-linearize-auto")
+        };
+        match linearize::linearize_function(&arch, target, unroll_bound, &mut symtab) {
+            Some(rewritten_body) => {
+                if test {
+                    let success = linearize::self_test_function(
+                        num_threads,
+                        arch.clone(),
+                        symtab.clone(),
+                        &isa_config,
+                        primops,
+                        target,
+                        rewritten_body.clone(),
+                    );
+                    if success {
+                        log!(log::VERBOSE, &format!("Successfully proved linearization of {} equivalent", id))
+                    } else if explicit {
+                        eprintln!("Failed to linearize {}", id);
+                        exit(1)
+                    } else {
+                        log!(log::VERBOSE, &format!("Skipping linearization of {} as it is not equivalent", id));
+                        continue;
                     }
                 }
+                rewrites.insert(target, rewritten_body);
             }
-        } else {
-            eprintln!("Function {} could not be found when processing -L/--linearize option", id)
+            None if explicit => {
+                eprintln!("Function {} could not be linearized", id);
+                exit(1)
+            }
+            None => (),
         }
-    });
+    }
+    if matches.opt_present("linearize-auto") {
+        log!(log::VERBOSE, &format!("Linearized {} functions", rewrites.len()))
+    }
+    for def in arch.iter_mut() {
+        if let Def::Fn(f, _, body) = def {
+            if let Some(rewritten_body) = rewrites.remove(f) {
+                *body = rewritten_body
+            }
+        }
+    }
+
+    if let Some(passes) = matches.opt_str("optimize") {
+        let passes = optimize::parse_passes(&passes).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            exit(1)
+        });
+        let self_test = if matches.opt_present("test-optimize") { Some(num_threads) } else { None };
+        if let Err(e) = optimize::optimize(&mut arch, &mut symtab, &isa_config, primops, &keep, &passes, self_test) {
+            eprintln!("{}", e);
+            exit(1)
+        }
+    }
+
+    if let Some(file) = matches.opt_str("dump-ir") {
+        let result = File::create(&file).and_then(|mut buf| pretty::write_ir(&mut buf, &arch, &symtab));
+        if let Err(e) = result {
+            eprintln!("Could not write IR to {}: {}", file, e);
+            exit(1)
+        }
+    }
 
     for constraint in matches.opt_strs("reset-constraint") {
         check_constraint(&constraint, &symtab);
         isa_config.reset_constraints.push(constraint);
     }
 
+    let solver_limit = |opt| match matches.opt_get::<u64>(opt) {
+        Ok(limit) => limit,
+        Err(f) => {
+            eprintln!("Could not parse --{} option: {}", opt, f);
+            print_usage(opts, 1)
+        }
+    };
+    if let Some(timeout) = solver_limit("solver-timeout") {
+        isa_config.solver_options.timeout = Some(timeout)
+    }
+    if let Some(rlimit) = solver_limit("solver-rlimit") {
+        isa_config.solver_options.rlimit = Some(rlimit)
+    }
+    if let Some(cases) = solver_limit("length-cases") {
+        isa_config.solver_options.length_cases = Some(cases as usize)
+    }
+    if matches.opt_present("symbolic-strings") {
+        isa_config.solver_options.symbolic_strings = true
+    }
+    if let Some(memory) = solver_limit("solver-memory") {
+        smt::global_set_param_value("memory_max_size", &memory.to_string())
+    }
+    if let Some(policy) = matches.opt_str("unknown") {
+        match policy.parse() {
+            Ok(policy) => isa_config.solver_options.unknown_policy = policy,
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1)
+            }
+        }
+    }
+    if let Some(directory) = matches.opt_str("dump-queries") {
+        let directory = PathBuf::from(directory);
+        if let Err(e) = fs::create_dir_all(&directory) {
+            eprintln!("Could not create directory {}: {}", directory.display(), e);
+            exit(1)
+        }
+        isa_config.solver_options.query_log = Some(smt::QueryLog {
+            directory,
+            threshold: Duration::from_millis(solver_limit("dump-threshold").unwrap_or(0)),
+            files: Arc::new(symtab.files().iter().map(|f| f.to_string()).collect()),
+        })
+    }
+
     CommonOpts { num_threads, arch, symtab, isa_config }
 }
@@ -32,12 +32,18 @@ use std::error::Error;
 use std::fs::File;
 use std::io::Write;
 
-use isla_lib::bitvector::{b64::B64, BV};
+use isla_lib::bitvector::b129::B129;
+use isla_lib::bitvector::b64::B64;
+use isla_lib::bitvector::bn::{B2048, B256, B4096};
+use isla_lib::bitvector::BV;
 use isla_lib::ir;
 use isla_lib::ir::{Def, Name, Symtab};
+use isla_lib::primop::Primops;
 
 mod opts;
-use opts::CommonOpts;
+use opts::{Bits, CommonOpts};
+
+const DEFAULT_BITS: Bits = Bits::B64;
 
 fn write_output<B: BV>(output: &str, arch: Vec<Def<Name, B>>, symtab: &Symtab) -> Result<(), Box<dyn Error>> {
     let mut arch_file = File::create(format!("{}.irx", output))?;
@@ -50,16 +56,31 @@ fn write_output<B: BV>(output: &str, arch: Vec<Def<Name, B>>, symtab: &Symtab) -
 }
 
 fn main() {
+    match opts::bits(DEFAULT_BITS) {
+        Bits::B64 => isla_main::<B64>(),
+        Bits::B129 => isla_main::<B129>(),
+        Bits::B256 => isla_main::<B256>(),
+        Bits::B2048 => isla_main::<B2048>(),
+        Bits::B4096 => isla_main::<B4096>(),
+    }
+}
+
+fn isla_main<B: BV>() {
     let mut opts = opts::common_opts();
+    opts::bits_opt(&mut opts, DEFAULT_BITS);
     opts.reqopt("o", "output", "output name for processed architecture and symbol table info", "<file>");
 
     let mut hasher = Sha256::new();
-    let (matches, arch) = opts::parse::<B64>(&mut hasher, &opts);
-    let CommonOpts { arch, symtab, .. } = opts::parse_with_arch(&mut hasher, &opts, &matches, &arch);
+    let (matches, arch) = opts::parse::<B>(&mut hasher, &opts);
+    let primops = Primops::default();
+    let CommonOpts { arch, symtab, .. } = opts::parse_with_arch(&mut hasher, &opts, &matches, &arch, &primops);
 
     let output = matches.opt_str("output").unwrap();
 
-    if let Err(e) = write_output(&output, arch, &symtab) {
+    let result = write_output(&output, arch, &symtab);
+    opts::report_solver_statistics();
+
+    if let Err(e) = result {
         eprintln!("Error: {}", e);
         std::process::exit(1)
     }
@@ -29,42 +29,56 @@
 
 use sha2::{Digest, Sha256};
 use std::process::exit;
-use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use isla_lib::bitvector::b129::B129;
+use isla_lib::bitvector::b64::B64;
+use isla_lib::bitvector::bn::{B2048, B256, B4096};
+use isla_lib::bitvector::BV;
 use isla_lib::executor;
 use isla_lib::executor::{LocalFrame, TaskState};
-use isla_lib::init::{initialize_architecture, Initialized};
+use isla_lib::init::{initialize_architecture_with_primops, Initialized};
 use isla_lib::ir::*;
+use isla_lib::primop::Primops;
 use isla_lib::zencode;
 
 mod opts;
-use opts::CommonOpts;
+use opts::{Bits, CommonOpts};
+
+const DEFAULT_BITS: Bits = Bits::B129;
 
 fn main() {
-    let code = isla_main();
+    let code = match opts::bits(DEFAULT_BITS) {
+        Bits::B64 => isla_main::<B64>(),
+        Bits::B129 => isla_main::<B129>(),
+        Bits::B256 => isla_main::<B256>(),
+        Bits::B2048 => isla_main::<B2048>(),
+        Bits::B4096 => isla_main::<B4096>(),
+    };
+    opts::report_solver_statistics();
     unsafe { isla_lib::smt::finalize_solver() };
     exit(code)
 }
 
 #[allow(clippy::mutex_atomic)]
-fn isla_main() -> i32 {
+fn isla_main<B: BV>() -> i32 {
     let mut opts = opts::common_opts();
+    opts::bits_opt(&mut opts, DEFAULT_BITS);
     opts.reqopt("p", "property", "check property in architecture", "<id>");
     opts.optopt("", "linear", "rewrite function into linear form", "<id>");
     opts.optflag("", "optimistic", "assume assertions succeed");
 
     let mut hasher = Sha256::new();
-    let (matches, arch) = opts::parse::<B129>(&mut hasher, &opts);
+    let (matches, arch) = opts::parse::<B>(&mut hasher, &opts);
+    let primops = Primops::default();
     let CommonOpts { num_threads, mut arch, symtab, isa_config } =
-        opts::parse_with_arch(&mut hasher, &opts, &matches, &arch);
+        opts::parse_with_arch(&mut hasher, &opts, &matches, &arch, &primops);
 
     let assertion_mode =
         if matches.opt_present("optimistic") { AssertionMode::Optimistic } else { AssertionMode::Pessimistic };
 
     let Initialized { regs, lets, shared_state } =
-        initialize_architecture(&mut arch, symtab, &isa_config, assertion_mode);
+        initialize_architecture_with_primops(&mut arch, symtab, &isa_config, assertion_mode, &primops);
 
     let property = zencode::encode(&matches.opt_str("property").unwrap());
 
@@ -72,12 +86,16 @@ fn isla_main() -> i32 {
     let (args, _, instrs) = shared_state.functions.get(&function_id).unwrap();
     let task_state = TaskState::new();
     let task = LocalFrame::new(function_id, args, None, instrs).add_lets(&lets).add_regs(&regs).task(0, &task_state);
-    let result = Arc::new(AtomicBool::new(true));
+    let result = Arc::new(executor::AllUnsat::new());
 
     executor::start_multi(num_threads, None, vec![task], &shared_state, result.clone(), &executor::all_unsat_collector);
 
-    if result.load(Ordering::Acquire) {
-        println!("ok");
+    if result.all_unsat() {
+        if result.assumed_unknown() {
+            println!("ok (assuming unknown solver results)")
+        } else {
+            println!("ok")
+        }
         0
     } else {
         println!("fail");